@@ -0,0 +1,19 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::env;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+fn main() {
+    // NOTE: honor SOURCE_DATE_EPOCH for reproducible builds
+    let timestamp = match env::var("SOURCE_DATE_EPOCH") {
+        Ok(timestamp) => timestamp,
+        Err(_) => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default()
+            .to_string(),
+    };
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rustc-env=CATERPILLAR_BUILD_TIMESTAMP={}", timestamp);
+}
@@ -0,0 +1,93 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::fs::create_dir_all;
+use std::fs::read_to_string;
+use std::fs::write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use semver::Version;
+
+use crate::error::Error;
+
+/// A persistent list of update bundle versions, that are known to be bad
+///
+/// The blocklist is stored in a file with one version per line.
+#[derive(Clone, Debug)]
+pub struct Blocklist {
+    path: PathBuf,
+    versions: Vec<Version>,
+}
+
+impl Blocklist {
+    /// Read a Blocklist from a file
+    ///
+    /// If the file does not exist, an empty Blocklist is returned.
+    /// Lines that can not be parsed as version are ignored.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let versions = if path.exists() {
+            read_to_string(path)?
+                .lines()
+                .filter_map(|line| Version::parse(line.trim()).ok())
+                .collect()
+        } else {
+            vec![]
+        };
+
+        Ok(Blocklist {
+            path: path.into(),
+            versions,
+        })
+    }
+
+    /// Return whether a version is blocked
+    pub fn contains(&self, version: &Version) -> bool {
+        self.versions.contains(version)
+    }
+
+    /// Add a version to the Blocklist and persist it
+    pub fn add(&mut self, version: &Version) -> Result<(), Error> {
+        if self.contains(version) {
+            return Ok(());
+        }
+        self.versions.push(version.clone());
+
+        if let Some(parent) = self.path.parent() {
+            create_dir_all(parent)?;
+        }
+        write(
+            &self.path,
+            self.versions
+                .iter()
+                .map(|version| format!("{}\n", version))
+                .collect::<String>(),
+        )?;
+        println!("Added version {} to blocklist {:?}", version, self.path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use testdir::testdir;
+    use testresult::TestResult;
+
+    #[rstest]
+    fn test_blocklist() -> TestResult {
+        let path = testdir!().join("state").join("blocklist");
+        let version = Version::new(1, 0, 0);
+
+        let mut blocklist = Blocklist::read(&path)?;
+        assert!(!blocklist.contains(&version));
+        blocklist.add(&version)?;
+        blocklist.add(&version)?;
+
+        let blocklist = Blocklist::read(&path)?;
+        assert!(blocklist.contains(&version));
+        assert!(!blocklist.contains(&Version::new(2, 0, 0)));
+        assert_eq!(read_to_string(&path)?, "1.0.0\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::Parser;
+use clap::Subcommand;
+use clap::ValueEnum;
+use zbus::ConnectionBuilder;
+
+/// Detect and apply RAUC update bundles during boot
+#[derive(Debug, Parser)]
+#[command(about, version)]
+pub struct Cli {
+    /// The configuration file to read instead of /etc/caterpillar/caterpillar.toml
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// The bus on which the D-Bus interface is provided ("system", "session" or the address of a bus)
+    #[arg(long, value_name = "BUS")]
+    pub bus: Option<Bus>,
+    /// Do not search for updates non-interactively on start (overrides the auto_search configuration option)
+    #[arg(long)]
+    pub no_autorun: bool,
+    /// The level of messages to log ("error" only logs errors and warnings)
+    #[arg(long, value_enum, default_value_t = LogLevel::Info)]
+    pub log_level: LogLevel,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// The mode in which the application runs
+///
+/// Without a command, the application provides its D-Bus interface until it is done.
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum Command {
+    /// Search for and install an update once, without providing the D-Bus interface (e.g. in an initramfs)
+    RunOnce {
+        /// Do not reboot after an update has been installed
+        #[arg(long)]
+        no_reboot: bool,
+    },
+    /// Monitor a running instance in a terminal UI (e.g. on headless systems over SSH)
+    Monitor,
+    /// Confirm the boot of a newly installed slot and mark it as good in RAUC (e.g. from a systemd unit late in boot)
+    ConfirmBoot,
+}
+
+/// The bus on which the D-Bus interface of the application is provided
+///
+/// The services used by the application (e.g. RAUC and UDisks2) are always accessed on the system bus.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Bus {
+    System,
+    Session,
+    /// The address of a bus (e.g. "unix:path=/run/caterpillar/bus")
+    Address(String),
+}
+
+/// The system bus is used by default (the session bus, if the mock backends are used, as they are provided on it)
+impl Default for Bus {
+    fn default() -> Self {
+        if cfg!(feature = "mock-backends") {
+            Bus::Session
+        } else {
+            Bus::System
+        }
+    }
+}
+
+impl Bus {
+    /// Return a ConnectionBuilder for the bus
+    pub fn connection_builder(&self) -> zbus::Result<ConnectionBuilder<'static>> {
+        match self {
+            Bus::System => ConnectionBuilder::system(),
+            Bus::Session => ConnectionBuilder::session(),
+            Bus::Address(address) => ConnectionBuilder::address(address.as_str()),
+        }
+    }
+}
+
+impl FromStr for Bus {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "system" => Bus::System,
+            "session" => Bus::Session,
+            address => Bus::Address(address.to_string()),
+        })
+    }
+}
+
+/// The level of messages to log
+///
+/// Informational messages are written to stdout, while errors and warnings are written to stderr.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum LogLevel {
+    /// Only log errors and warnings
+    Error,
+    /// Log all messages
+    Info,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("system", Bus::System)]
+    #[case("session", Bus::Session)]
+    #[case(
+        "unix:path=/run/caterpillar/bus",
+        Bus::Address("unix:path=/run/caterpillar/bus".to_string())
+    )]
+    fn test_bus_from_str(#[case] input: &str, #[case] bus: Bus) {
+        assert_eq!(Bus::from_str(input), Ok(bus));
+    }
+
+    #[rstest]
+    fn test_cli_parse() {
+        let cli = Cli::parse_from(["caterpillar"]);
+        assert_eq!(cli.config, None);
+        assert_eq!(cli.bus, None);
+        assert!(!cli.no_autorun);
+        assert_eq!(cli.log_level, LogLevel::Info);
+
+        let cli = Cli::parse_from([
+            "caterpillar",
+            "--config",
+            "/tmp/caterpillar.toml",
+            "--bus",
+            "session",
+            "--no-autorun",
+            "--log-level",
+            "error",
+        ]);
+        assert_eq!(cli.config, Some(PathBuf::from("/tmp/caterpillar.toml")));
+        assert_eq!(cli.bus, Some(Bus::Session));
+        assert!(cli.no_autorun);
+        assert_eq!(cli.log_level, LogLevel::Error);
+        assert_eq!(cli.command, None);
+
+        let cli = Cli::parse_from(["caterpillar", "run-once", "--no-reboot"]);
+        assert_eq!(cli.command, Some(Command::RunOnce { no_reboot: true }));
+
+        let cli = Cli::parse_from(["caterpillar", "--bus", "session", "monitor"]);
+        assert_eq!(cli.bus, Some(Bus::Session));
+        assert_eq!(cli.command, Some(Command::Monitor));
+
+        let cli = Cli::parse_from(["caterpillar", "confirm-boot"]);
+        assert_eq!(cli.command, Some(Command::ConfirmBoot));
+    }
+}
@@ -0,0 +1,50 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::error::Error;
+
+/// Return the time (in seconds since the epoch) at which caterpillar has been built
+pub fn build_timestamp() -> i64 {
+    env!("CATERPILLAR_BUILD_TIMESTAMP")
+        .parse()
+        .unwrap_or_default()
+}
+
+/// Return the current system time in seconds since the epoch
+///
+/// A system time before the epoch is returned as a negative value.
+pub fn now() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(error) => -(error.duration().as_secs() as i64),
+    }
+}
+
+/// Check whether a time (in seconds since the epoch) is plausible
+///
+/// A time is plausible if it is not earlier than the build timestamp of caterpillar or a configured `floor` (in
+/// seconds since the epoch), whichever is later.
+pub fn check_clock(time: i64, floor: i64) -> Result<(), Error> {
+    let floor = floor.max(build_timestamp());
+    if time < floor {
+        Err(Error::ImplausibleClock(time, floor))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(0, 0, false)]
+    #[case(now(), 0, true)]
+    #[case(now(), i64::MAX, false)]
+    fn test_check_clock(#[case] time: i64, #[case] floor: i64, #[case] plausible: bool) {
+        assert_eq!(check_clock(time, floor).is_ok(), plausible);
+    }
+}
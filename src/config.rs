@@ -1,36 +1,1177 @@
 // SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
 // SPDX-License-Identifier: Apache-2.0 OR MIT
-use config::{Config, ConfigError, File};
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
 
-pub const DEVICE_REGEX: &str = "^/org/freedesktop/UDisks2/block_devices/sd[a-z]{1}[1-9]{1}[0-9]*?$";
+use config::{Config, ConfigError, File, FileFormat};
+use glob::Pattern;
+use nix::unistd::geteuid;
+use regex::Regex;
+use semver::Version;
+use serde::Deserialize;
+use serde::Deserializer;
+
+use crate::cli::Cli;
+use crate::cli::Command;
+use crate::device::compile_patterns;
+use crate::device::default_partition_types;
+use crate::device::ReleaseAction;
+use crate::error::Error;
+use crate::power::PowerCheck;
+use crate::rauc::BundleSort;
+use crate::reboot::RebootMethod;
+use crate::report::ReporterKind;
+use crate::share::NetworkShare;
+use crate::window::InstallWindow;
+
+pub const DEVICE_REGEX: &str =
+    "^/org/freedesktop/UDisks2/block_devices/(sd[a-z]{1}[1-9]{1}[0-9]*?|sr[0-9]+)$";
+
+/// The name of the systemd credential, that provides configuration
+pub const CREDENTIAL_NAME: &str = "caterpillar.toml";
+
+/// The options, whose values are redacted in the effective configuration
+pub const REDACTED_OPTIONS: &[&str] = &["hawkbit_token"];
+
+/// Return the path of the configuration file provided as systemd credential in an Option
+///
+/// The credential is looked up in `directory` (i.e. the `CREDENTIALS_DIRECTORY` set by systemd, see systemd.exec(5)).
+fn credential_file(directory: Option<&Path>) -> Option<PathBuf> {
+    directory
+        .map(|directory| directory.join(CREDENTIAL_NAME))
+        .filter(|path| path.is_file())
+}
 
 /// Read the configuration for the application
 ///
 /// This uses built-in defaults, which can be overridden with an optional configuration file found in /etc/caterpillar/caterpillar.toml
-pub async fn read_config() -> Result<Config, ConfigError> {
-    Config::builder()
-        .set_default("autorun", true)?
+/// (or the required configuration file at `path`), an optional configuration file provided as systemd credential (see
+/// [`CREDENTIAL_NAME`]) and environment variables (in that order).
+pub async fn read_config(path: Option<&Path>) -> Result<Config, ConfigError> {
+    let builder = Config::builder()
+        .set_default("anti_rollback", false)?
+        .set_default("authorized_group", "")?
+        .set_default("auto_install", true)?
+        .set_default("auto_reboot", true)?
+        .set_default("auto_search", true)?
+        .set_default("blocklist_failed", true)?
+        .set_default("battery_threshold", 30.0)?
         .set_default("bundle_extension", "raucb")?
+        .set_default("bundle_filename_regex", "")?
+        .set_default("bundle_sort", "version")?
+        .set_default("channel", "")?
+        .set_default("check_space", true)?
+        .set_default("clock_floor", 0)?
+        .set_default("compatible_aliases", HashMap::<String, Vec<String>>::new())?
+        .set_default("defer_on_implausible_clock", false)?
         .set_default("device_regex", DEVICE_REGEX)?
+        .set_default("drive_models", Vec::<String>::new())?
+        .set_default("drive_serial_prefixes", Vec::<String>::new())?
+        .set_default("drive_vendors", Vec::<String>::new())?
+        .set_default("email_from", "caterpillar@localhost")?
+        .set_default("email_relay", "localhost:25")?
+        .set_default("email_to", Vec::<String>::new())?
+        .set_default("forward_to_instance", false)?
+        .set_default("hawkbit_cache_dir", "/var/cache/caterpillar/hawkbit")?
+        .set_default("hawkbit_controller_id", "")?
+        .set_default("hawkbit_tenant", "DEFAULT")?
+        .set_default("hawkbit_token", "")?
+        .set_default("hawkbit_url", "")?
+        .set_default("ignore_patterns", Vec::<String>::new())?
+        .set_default("inbox_dirs", Vec::<String>::new())?
+        .set_default("install_window", "")?
+        .set_default("kexec_cmdline", "")?
+        .set_default("kexec_initrd", "")?
+        .set_default("kexec_kernel", "boot/vmlinuz")?
+        .set_default("kexec_mount_dir", "/run/caterpillar/kexec")?
+        .set_default("kill_blocking_processes", false)?
+        .set_default("loop_images", Vec::<String>::new())?
+        .set_default("min_version", "")?
+        .set_default("mount_options", "rw,nosuid,nodev,noexec")?
+        .set_default("mount_timeout", 60)?
+        .set_default("network_shares", Vec::<String>::new())?
+        .set_default("otlp_endpoint", "")?
         .set_default("override_dir", "override")?
-        .add_source(File::with_name("/etc/caterpillar/caterpillar").required(false))
+        .set_default("partition_labels", Vec::<String>::new())?
+        .set_default("partition_types", default_partition_types())?
+        .set_default("power_check", "disabled")?
+        .set_default("private_mounts", false)?
+        .set_default("quarantine_threshold", 3)?
+        .set_default("reboot_delay", 0)?
+        .set_default("reboot_method", "logind")?
+        .set_default("recovery_slot_class", "")?
+        .set_default("reinstall_current_version", false)?
+        .set_default("release_action", "none")?
+        .set_default("reporters", vec!["wall"])?
+        .set_default("sandbox", false)?
+        .set_default("search_debounce", 5)?
+        .set_default("search_depth", 1)?
+        .set_default("search_dir", "")?
+        .set_default("search_max_files", 10000)?
+        .set_default("search_timeout", 120)?
+        .set_default("sftp_cache_dir", "/var/cache/caterpillar/sftp")?
+        .set_default("sftp_cache_max_size", 0)?
+        .set_default("sftp_dir", "/")?
+        .set_default("sftp_host", "")?
+        .set_default("sftp_identity", "/etc/caterpillar/id_ed25519")?
+        .set_default("sftp_known_hosts", "/etc/caterpillar/known_hosts")?
+        .set_default("sftp_port", 22)?
+        .set_default("sftp_user", "caterpillar")?
+        .set_default("share_dir", "/run/caterpillar/shares")?
+        .set_default("shred_override_bundles", false)?
+        .set_default("soft_reboot", false)?
+        .set_default("stage_bandwidth", 0)?
+        .set_default("stage_dir", "")?
+        .set_default("stage_idle_io", false)?
+        .set_default("state_dir", "/var/lib/caterpillar")?
+        .set_default("tls_ca", "")?
+        .set_default("tls_cert", "")?
+        .set_default("tls_key", "")?
+        .set_default("tmp_dir", "/tmp")?
+        .set_default("verify_bundles", true)?
+        .set_default("watch_inbox_dirs", true)?
+        .set_default("write_result_report", false)?
+        .add_source(match path {
+            Some(path) => File::from(path).required(true),
+            None => File::with_name("/etc/caterpillar/caterpillar").required(false),
+        });
+    let credentials_directory = std::env::var_os("CREDENTIALS_DIRECTORY").map(PathBuf::from);
+    let builder = match credential_file(credentials_directory.as_deref()) {
+        Some(path) => {
+            println!("Reading configuration from credential {}", path.display());
+            builder.add_source(File::from(path).format(FileFormat::Toml))
+        }
+        None => builder,
+    };
+    builder
         .add_source(config::Environment::with_prefix("CATERPILLAR"))
         .build()
 }
 
+/// Return the effective configuration of a Config as strings
+///
+/// Non-empty values of options in [`REDACTED_OPTIONS`] are replaced with "<redacted>".
+fn effective_config(config: &Config) -> Result<BTreeMap<String, String>, ConfigError> {
+    config
+        .collect()?
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value.try_deserialize::<serde_json::Value>()? {
+                serde_json::Value::String(value) => value,
+                value => value.to_string(),
+            };
+            if REDACTED_OPTIONS.contains(&key.as_str()) && !value.is_empty() {
+                Ok((key, "<redacted>".to_string()))
+            } else {
+                Ok((key, value))
+            }
+        })
+        .collect()
+}
+
+/// Deserialize a type implementing FromStr from a string
+fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    String::deserialize(deserializer)?
+        .parse::<T>()
+        .map_err(serde::de::Error::custom)
+}
+
+/// Deserialize a list of a type implementing FromStr from a list of strings
+fn vec_from_str<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|input| input.parse::<T>().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// Deserialize an optional type implementing FromStr from a string, that is empty if unset
+fn optional_from_str<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let input = String::deserialize(deserializer)?;
+    if input.is_empty() {
+        Ok(None)
+    } else {
+        input
+            .parse::<T>()
+            .map(Some)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The typed configuration of the application
+///
+/// The configuration is read using [`read_config`] and validated when it is created.
+/// All options are documented in the configuration file shipped with caterpillar.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CaterpillarConfig {
+    /// The effective configuration in its string representation (see [`CaterpillarConfig::effective`])
+    #[serde(skip)]
+    effective: BTreeMap<String, String>,
+    anti_rollback: bool,
+    authorized_group: String,
+    auto_install: bool,
+    auto_reboot: bool,
+    auto_search: bool,
+    /// the deprecated predecessor of auto_search, auto_install and auto_reboot
+    #[serde(default)]
+    autorun: Option<String>,
+    battery_threshold: f64,
+    blocklist_failed: bool,
+    bundle_extension: String,
+    bundle_filename_regex: String,
+    /// the compiled bundle_filename_regex
+    #[serde(skip)]
+    compiled_bundle_filename_regex: Option<Regex>,
+    #[serde(deserialize_with = "from_str")]
+    bundle_sort: BundleSort,
+    channel: String,
+    check_space: bool,
+    clock_floor: i64,
+    compatible_aliases: HashMap<String, Vec<String>>,
+    defer_on_implausible_clock: bool,
+    device_regex: String,
+    drive_models: Vec<String>,
+    drive_serial_prefixes: Vec<String>,
+    drive_vendors: Vec<String>,
+    email_from: String,
+    email_relay: String,
+    email_to: Vec<String>,
+    forward_to_instance: bool,
+    hawkbit_cache_dir: PathBuf,
+    hawkbit_controller_id: String,
+    hawkbit_tenant: String,
+    hawkbit_token: String,
+    hawkbit_url: String,
+    ignore_patterns: Vec<String>,
+    /// the compiled ignore_patterns
+    #[serde(skip)]
+    compiled_ignore_patterns: Vec<Pattern>,
+    inbox_dirs: Vec<PathBuf>,
+    #[serde(deserialize_with = "optional_from_str")]
+    install_window: Option<InstallWindow>,
+    kexec_cmdline: String,
+    kexec_initrd: PathBuf,
+    kexec_kernel: PathBuf,
+    kexec_mount_dir: PathBuf,
+    kill_blocking_processes: bool,
+    loop_images: Vec<PathBuf>,
+    #[serde(deserialize_with = "optional_from_str")]
+    min_version: Option<Version>,
+    mount_options: String,
+    mount_timeout: u64,
+    network_shares: Vec<NetworkShare>,
+    otlp_endpoint: String,
+    override_dir: PathBuf,
+    partition_labels: Vec<String>,
+    partition_types: Vec<String>,
+    #[serde(deserialize_with = "from_str")]
+    power_check: PowerCheck,
+    private_mounts: bool,
+    quarantine_threshold: u32,
+    reboot_delay: u64,
+    #[serde(deserialize_with = "from_str")]
+    reboot_method: RebootMethod,
+    recovery_slot_class: String,
+    reinstall_current_version: bool,
+    #[serde(deserialize_with = "from_str")]
+    release_action: ReleaseAction,
+    #[serde(deserialize_with = "vec_from_str")]
+    reporters: Vec<ReporterKind>,
+    sandbox: bool,
+    search_debounce: u64,
+    search_depth: usize,
+    search_dir: PathBuf,
+    search_max_files: usize,
+    search_timeout: u64,
+    sftp_cache_dir: PathBuf,
+    sftp_cache_max_size: u64,
+    sftp_dir: PathBuf,
+    sftp_host: String,
+    sftp_identity: PathBuf,
+    sftp_known_hosts: PathBuf,
+    sftp_port: u16,
+    sftp_user: String,
+    share_dir: PathBuf,
+    shred_override_bundles: bool,
+    soft_reboot: bool,
+    stage_bandwidth: u64,
+    stage_dir: PathBuf,
+    stage_idle_io: bool,
+    state_dir: PathBuf,
+    tls_ca: PathBuf,
+    tls_cert: PathBuf,
+    tls_key: PathBuf,
+    tmp_dir: PathBuf,
+    verify_bundles: bool,
+    watch_inbox_dirs: bool,
+    write_result_report: bool,
+}
+
+impl CaterpillarConfig {
+    /// Read and validate the configuration of the application, taking command line arguments into account
+    pub async fn from_cli(cli: &Cli) -> Result<Self, Error> {
+        let mut builder = Config::builder().add_source(read_config(cli.config.as_deref()).await?);
+        if cli.no_autorun {
+            builder = builder.set_override("auto_search", false)?;
+        }
+        // in standalone mode, searching for and installing updates is requested explicitly
+        if let Some(Command::RunOnce { .. }) = cli.command {
+            builder = builder
+                .set_override("auto_search", false)?
+                .set_override("auto_install", false)?;
+        }
+        let mut config = Self::from_config(builder.build()?)?;
+        if !geteuid().is_root() {
+            for option in config.restrict_to_unprivileged() {
+                eprintln!(
+                    "The configuration option {} requires root privileges and has been disabled",
+                    option
+                );
+            }
+        }
+        if config.sandbox() {
+            for option in config.restrict_to_sandbox() {
+                eprintln!(
+                    "The configuration option {} requires mounting filesystems, which is not possible in the sandbox and has been disabled",
+                    option
+                );
+            }
+        }
+        Ok(config)
+    }
+
+    /// Disable all features mounting filesystems directly and return the names of the affected configuration options
+    ///
+    /// Sandboxed processes (see [`crate::sandbox`]) are not able to mount filesystems, so that network shares can not be
+    /// mounted using mount(8) and the new slot can not be mounted for a kexec or a soft-reboot.
+    pub fn restrict_to_sandbox(&mut self) -> Vec<&'static str> {
+        let mut options = vec![];
+        if !self.network_shares.is_empty() {
+            self.network_shares.clear();
+            options.push("network_shares");
+        }
+        if self.soft_reboot {
+            self.soft_reboot = false;
+            options.push("soft_reboot");
+        }
+        if self.reboot_method == RebootMethod::Kexec {
+            self.reboot_method = RebootMethod::Systemd;
+            options.push("reboot_method");
+        }
+        options
+    }
+
+    /// Disable all features requiring root privileges and return the names of the affected configuration options
+    ///
+    /// When running as an unprivileged system user, devices are only mounted and set up using UDisks2, update bundles
+    /// are installed using RAUC and the system is rebooted using logind or the systemd manager, all of which authorize
+    /// the D-Bus calls (e.g. using polkit).
+    /// Terminating the processes of other users, mounting network shares using mount(8), loading kernels using kexec(8)
+    /// and mounting the new slot for a soft-reboot or a reboot using the reboot(2) system call are not possible.
+    pub fn restrict_to_unprivileged(&mut self) -> Vec<&'static str> {
+        let mut options = vec![];
+        if self.kill_blocking_processes {
+            self.kill_blocking_processes = false;
+            options.push("kill_blocking_processes");
+        }
+        if !self.network_shares.is_empty() {
+            self.network_shares.clear();
+            options.push("network_shares");
+        }
+        if self.soft_reboot {
+            self.soft_reboot = false;
+            options.push("soft_reboot");
+        }
+        match self.reboot_method {
+            RebootMethod::Kexec => {
+                self.reboot_method = RebootMethod::Systemd;
+                options.push("reboot_method");
+            }
+            RebootMethod::Syscall => {
+                self.reboot_method = RebootMethod::Logind;
+                options.push("reboot_method");
+            }
+            RebootMethod::Logind | RebootMethod::Systemd => {}
+        }
+        options
+    }
+
+    /// Create a CaterpillarConfig from a Config and validate it
+    pub fn from_config(config: Config) -> Result<Self, Error> {
+        let effective = effective_config(&config)?;
+        let mut config: CaterpillarConfig = config.try_deserialize()?;
+        config.effective = effective;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Validate the configuration
+    ///
+    /// Ensures that the device_regex, bundle_filename_regex and ignore_patterns compile and that the bundle_extension is
+    /// not empty.
+    /// The deprecated autorun option is mapped onto auto_search, auto_install and auto_reboot, so that a system, that
+    /// opted out of non-interactive updates, does not install updates and reboot after upgrading.
+    fn validate(&mut self) -> Result<(), Error> {
+        if let Some(autorun) = self.autorun.take() {
+            let autorun = match autorun.to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => true,
+                "false" | "no" | "off" | "0" => false,
+                _ => {
+                    return Err(Error::InvalidConfig(
+                        "autorun".to_string(),
+                        format!(
+                            "{} is not a boolean (the option is deprecated, use auto_search, auto_install and auto_reboot instead)",
+                            autorun
+                        ),
+                    ))
+                }
+            };
+            eprintln!(
+                "Warning: The configuration option autorun is deprecated, use auto_search, auto_install and auto_reboot instead"
+            );
+            // explicitly disabled options (e.g. using --no-autorun) are not enabled again
+            self.auto_search &= autorun;
+            self.auto_install &= autorun;
+            self.auto_reboot &= autorun;
+        }
+        if let Err(error) = Regex::new(&self.device_regex) {
+            return Err(Error::InvalidConfig(
+                "device_regex".to_string(),
+                error.to_string(),
+            ));
+        }
+        if !self.bundle_filename_regex.is_empty() {
+            self.compiled_bundle_filename_regex =
+                Some(Regex::new(&self.bundle_filename_regex).map_err(|error| {
+                    Error::InvalidConfig("bundle_filename_regex".to_string(), error.to_string())
+                })?);
+        }
+        if !self.channel.is_empty()
+            && !matches!(
+                Path::new(&self.channel).components().collect::<Vec<_>>()[..],
+                [Component::Normal(_)]
+            )
+        {
+            return Err(Error::InvalidConfig(
+                "channel".to_string(),
+                "must be the name of a single directory".to_string(),
+            ));
+        }
+        if !self
+            .search_dir
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(Error::InvalidConfig(
+                "search_dir".to_string(),
+                "must be a relative path without parent directories".to_string(),
+            ));
+        }
+        if self.bundle_extension.is_empty() {
+            return Err(Error::InvalidConfig(
+                "bundle_extension".to_string(),
+                "must not be empty".to_string(),
+            ));
+        }
+        if self.partition_types.is_empty() {
+            return Err(Error::InvalidConfig(
+                "partition_types".to_string(),
+                "must not be empty".to_string(),
+            ));
+        }
+        if self.tls_cert.as_os_str().is_empty() != self.tls_key.as_os_str().is_empty() {
+            return Err(Error::InvalidConfig(
+                "tls_cert".to_string(),
+                "must be set together with tls_key".to_string(),
+            ));
+        }
+        self.compiled_ignore_patterns =
+            compile_patterns(&self.ignore_patterns).map_err(|error| {
+                Error::InvalidConfig("ignore_patterns".to_string(), error.to_string())
+            })?;
+        if self.reporters.contains(&ReporterKind::Email) && self.email_to.is_empty() {
+            return Err(Error::InvalidConfig(
+                "email_to".to_string(),
+                "must not be empty if the email reporter is used".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether to refuse update bundles with a build timestamp older than the one of the newest installed update bundle
+    pub fn anti_rollback(&self) -> bool {
+        self.anti_rollback
+    }
+
+    /// The optional group, whose members (besides root) are authorized to call the D-Bus methods changing the state
+    pub fn authorized_group(&self) -> Option<&str> {
+        if self.authorized_group.is_empty() {
+            None
+        } else {
+            Some(&self.authorized_group)
+        }
+    }
+
+    /// Whether to install an update non-interactively, if one is found on first start
+    pub fn auto_install(&self) -> bool {
+        self.auto_install
+    }
+
+    /// Whether to reboot non-interactively after an update has been installed on first start
+    pub fn auto_reboot(&self) -> bool {
+        self.auto_reboot
+    }
+
+    /// Whether to search for updates non-interactively on first start
+    pub fn auto_search(&self) -> bool {
+        self.auto_search
+    }
+
+    /// The battery charge (in percent) below which the power check applies
+    pub fn battery_threshold(&self) -> f64 {
+        self.battery_threshold
+    }
+
+    /// Whether to add the version of an update bundle to the blocklist, if its installation fails
+    pub fn blocklist_failed(&self) -> bool {
+        self.blocklist_failed
+    }
+
+    /// The file extension of update bundles
+    pub fn bundle_extension(&self) -> &str {
+        &self.bundle_extension
+    }
+
+    /// The optional regular expression, that file names of update bundles must match
+    pub fn bundle_filename_regex(&self) -> Option<&Regex> {
+        self.compiled_bundle_filename_regex.as_ref()
+    }
+
+    /// The order in which compatible update bundles are offered, of which the first is selected for installation
+    pub fn bundle_sort(&self) -> BundleSort {
+        self.bundle_sort
+    }
+
+    /// The optional channel, whose directory on media is searched for update bundles
+    pub fn channel(&self) -> Option<&str> {
+        if self.channel.is_empty() {
+            None
+        } else {
+            Some(&self.channel)
+        }
+    }
+
+    /// Whether to check for sufficient space before installing an update bundle
+    pub fn check_space(&self) -> bool {
+        self.check_space
+    }
+
+    /// The earliest plausible system time in seconds since the epoch
+    pub fn clock_floor(&self) -> i64 {
+        self.clock_floor
+    }
+
+    /// The additionally accepted compatibles of update bundles per system compatible
+    pub fn compatible_aliases(&self) -> &HashMap<String, Vec<String>> {
+        &self.compatible_aliases
+    }
+
+    /// Whether to defer the installation of update bundles while the system time is implausible
+    pub fn defer_on_implausible_clock(&self) -> bool {
+        self.defer_on_implausible_clock
+    }
+
+    /// The regular expression used to match block devices
+    pub fn device_regex(&self) -> &str {
+        &self.device_regex
+    }
+
+    /// The drive models of which block devices are considered
+    pub fn drive_models(&self) -> &[String] {
+        &self.drive_models
+    }
+
+    /// The serial prefixes of drives of which block devices are considered
+    pub fn drive_serial_prefixes(&self) -> &[String] {
+        &self.drive_serial_prefixes
+    }
+
+    /// The drive vendors of which block devices are considered
+    pub fn drive_vendors(&self) -> &[String] {
+        &self.drive_vendors
+    }
+
+    /// The sender address of the mails sent by the email reporter
+    pub fn email_from(&self) -> &str {
+        &self.email_from
+    }
+
+    /// The SMTP relay (host and port) used by the email reporter
+    pub fn email_relay(&self) -> &str {
+        &self.email_relay
+    }
+
+    /// The recipient addresses of the mails sent by the email reporter
+    pub fn email_to(&self) -> &[String] {
+        &self.email_to
+    }
+
+    /// Whether to forward the search for updates to an already running instance
+    pub fn forward_to_instance(&self) -> bool {
+        self.forward_to_instance
+    }
+
+    /// The local directory, to which update bundles are downloaded from the hawkBit server
+    pub fn hawkbit_cache_dir(&self) -> &Path {
+        &self.hawkbit_cache_dir
+    }
+
+    /// The optional ID of the device on the hawkBit server (if None, the machine ID is used)
+    pub fn hawkbit_controller_id(&self) -> Option<&str> {
+        if self.hawkbit_controller_id.is_empty() {
+            None
+        } else {
+            Some(&self.hawkbit_controller_id)
+        }
+    }
+
+    /// The tenant on the hawkBit server
+    pub fn hawkbit_tenant(&self) -> &str {
+        &self.hawkbit_tenant
+    }
+
+    /// The effective configuration (defaults, configuration file and environment merged) as strings
+    ///
+    /// Strings are provided as is, while all other values are provided in their JSON representation.
+    /// The values of options in [`REDACTED_OPTIONS`] are redacted.
+    pub fn effective(&self) -> &BTreeMap<String, String> {
+        &self.effective
+    }
+
+    /// The optional target security token, with which the device authenticates on the hawkBit server
+    pub fn hawkbit_token(&self) -> Option<&str> {
+        if self.hawkbit_token.is_empty() {
+            None
+        } else {
+            Some(&self.hawkbit_token)
+        }
+    }
+
+    /// The optional URL of the hawkBit server, that is polled for deployments
+    pub fn hawkbit_url(&self) -> Option<&str> {
+        if self.hawkbit_url.is_empty() {
+            None
+        } else {
+            Some(&self.hawkbit_url)
+        }
+    }
+
+    /// The compiled glob patterns for file names of update bundles, that are ignored
+    pub fn ignore_patterns(&self) -> &[Pattern] {
+        &self.compiled_ignore_patterns
+    }
+
+    /// The local directories, that are searched for update bundles
+    pub fn inbox_dirs(&self) -> &[PathBuf] {
+        &self.inbox_dirs
+    }
+
+    /// The optional daily window of local time, in which updates are installed automatically
+    pub fn install_window(&self) -> Option<InstallWindow> {
+        self.install_window
+    }
+
+    /// The optional kernel command line used when rebooting using kexec
+    pub fn kexec_cmdline(&self) -> Option<&str> {
+        if self.kexec_cmdline.is_empty() {
+            None
+        } else {
+            Some(&self.kexec_cmdline)
+        }
+    }
+
+    /// The optional path of the initrd (relative to the root of a slot) used when rebooting using kexec
+    pub fn kexec_initrd(&self) -> Option<&Path> {
+        if self.kexec_initrd.as_os_str().is_empty() {
+            None
+        } else {
+            Some(&self.kexec_initrd)
+        }
+    }
+
+    /// The path of the kernel (relative to the root of a slot) used when rebooting using kexec
+    pub fn kexec_kernel(&self) -> &Path {
+        &self.kexec_kernel
+    }
+
+    /// The directory to which the device of a slot is mounted when rebooting using kexec
+    pub fn kexec_mount_dir(&self) -> &Path {
+        &self.kexec_mount_dir
+    }
+
+    /// Whether processes using a busy mountpoint are terminated before unmounting it lazily
+    pub fn kill_blocking_processes(&self) -> bool {
+        self.kill_blocking_processes
+    }
+
+    /// The image files (e.g. squashfs), that are set up as loop devices and searched for update bundles
+    pub fn loop_images(&self) -> &[PathBuf] {
+        &self.loop_images
+    }
+
+    /// The optional minimum version of update bundles, below which they are refused
+    pub fn min_version(&self) -> Option<&Version> {
+        self.min_version.as_ref()
+    }
+
+    /// The options used for mounting block devices
+    pub fn mount_options(&self) -> &str {
+        &self.mount_options
+    }
+
+    /// The time (in seconds) after which mounting devices is considered stuck (0 disables the deadline)
+    pub fn mount_timeout(&self) -> u64 {
+        self.mount_timeout
+    }
+
+    /// The network shares, that are searched for update bundles
+    pub fn network_shares(&self) -> &[NetworkShare] {
+        &self.network_shares
+    }
+
+    /// The endpoint of the OTLP collector, to which traces of update cycles are exported
+    pub fn otlp_endpoint(&self) -> &str {
+        &self.otlp_endpoint
+    }
+
+    /// The name of the directory in which override updates are searched for
+    pub fn override_dir(&self) -> &Path {
+        &self.override_dir
+    }
+
+    /// The labels of GPT partitions, of which filesystems are mounted
+    pub fn partition_labels(&self) -> &[String] {
+        &self.partition_labels
+    }
+
+    /// The partition type identifiers of which filesystems are mounted
+    pub fn partition_types(&self) -> &[String] {
+        &self.partition_types
+    }
+
+    /// The power state check before installing an update bundle
+    pub fn power_check(&self) -> PowerCheck {
+        self.power_check
+    }
+
+    /// Whether to only use block devices mounted by caterpillar itself
+    pub fn private_mounts(&self) -> bool {
+        self.private_mounts
+    }
+
+    /// The number of consecutive failed installations, after which an update bundle is quarantined
+    pub fn quarantine_threshold(&self) -> u32 {
+        self.quarantine_threshold
+    }
+
+    /// The grace period (in seconds) before rebooting the system
+    pub fn reboot_delay(&self) -> u64 {
+        self.reboot_delay
+    }
+
+    /// The method used for rebooting the system
+    pub fn reboot_method(&self) -> RebootMethod {
+        self.reboot_method
+    }
+
+    /// The optional slot class of a dedicated recovery slot, which is not updated by regular update bundles
+    pub fn recovery_slot_class(&self) -> Option<&str> {
+        if self.recovery_slot_class.is_empty() {
+            None
+        } else {
+            Some(&self.recovery_slot_class)
+        }
+    }
+
+    /// Whether update bundles with a version equal to the current system version are offered (e.g. for repairing a slot)
+    pub fn reinstall_current_version(&self) -> bool {
+        self.reinstall_current_version
+    }
+
+    /// The action performed on the drive of the source device after an installation
+    pub fn release_action(&self) -> ReleaseAction {
+        self.release_action
+    }
+
+    /// The kinds of Reporters notified of the lifecycle events of update cycles
+    pub fn reporters(&self) -> &[ReporterKind] {
+        &self.reporters
+    }
+
+    /// Whether to sandbox the daemon using Landlock and seccomp
+    pub fn sandbox(&self) -> bool {
+        self.sandbox
+    }
+
+    /// The interval (in seconds) within which requests to search for updates are coalesced
+    pub fn search_debounce(&self) -> u64 {
+        self.search_debounce
+    }
+
+    /// The depth of directories in which update bundles are searched for
+    pub fn search_depth(&self) -> usize {
+        self.search_depth
+    }
+
+    /// The optional directory (relative to the mountpoint of media), in which update bundles are searched
+    pub fn search_dir(&self) -> Option<&Path> {
+        if self.search_dir.as_os_str().is_empty() {
+            None
+        } else {
+            Some(&self.search_dir)
+        }
+    }
+
+    /// The maximum number of files considered when searching for update bundles
+    pub fn search_max_files(&self) -> usize {
+        self.search_max_files
+    }
+
+    /// The time (in seconds) after which searching for updates is considered stuck (0 disables the deadline)
+    pub fn search_timeout(&self) -> u64 {
+        self.search_timeout
+    }
+
+    /// The local directory, to which update bundles are downloaded from the SFTP server
+    pub fn sftp_cache_dir(&self) -> &Path {
+        &self.sftp_cache_dir
+    }
+
+    /// The maximum size (in bytes) of the files in the SFTP cache directory besides the current candidate
+    pub fn sftp_cache_max_size(&self) -> u64 {
+        self.sftp_cache_max_size
+    }
+
+    /// The remote directory on the SFTP server, in which update bundles are searched for
+    pub fn sftp_dir(&self) -> &Path {
+        &self.sftp_dir
+    }
+
+    /// The host of the SFTP server (empty if unused)
+    pub fn sftp_host(&self) -> &str {
+        &self.sftp_host
+    }
+
+    /// The private key used for authenticating on the SFTP server
+    pub fn sftp_identity(&self) -> &Path {
+        &self.sftp_identity
+    }
+
+    /// The known hosts file, against which the host key of the SFTP server is verified
+    pub fn sftp_known_hosts(&self) -> &Path {
+        &self.sftp_known_hosts
+    }
+
+    /// The port of the SFTP server
+    pub fn sftp_port(&self) -> u16 {
+        self.sftp_port
+    }
+
+    /// The user used for authenticating on the SFTP server
+    pub fn sftp_user(&self) -> &str {
+        &self.sftp_user
+    }
+
+    /// The directory below which network shares are mounted
+    pub fn share_dir(&self) -> &Path {
+        &self.share_dir
+    }
+
+    /// Whether to overwrite and remove override update bundles after they have been installed
+    pub fn shred_override_bundles(&self) -> bool {
+        self.shred_override_bundles
+    }
+
+    /// Whether to soft-reboot instead of rebooting after an update bundle has been installed
+    pub fn soft_reboot(&self) -> bool {
+        self.soft_reboot
+    }
+
+    /// The maximum bandwidth (in bytes per second) for copying update bundles to the staging directory (unlimited, if 0)
+    pub fn stage_bandwidth(&self) -> u64 {
+        self.stage_bandwidth
+    }
+
+    /// The optional local directory, to which update bundles are copied and from which they are installed
+    pub fn stage_dir(&self) -> Option<&Path> {
+        if self.stage_dir.as_os_str().is_empty() {
+            None
+        } else {
+            Some(&self.stage_dir)
+        }
+    }
+
+    /// Whether to copy update bundles to the staging directory using the idle I/O priority class
+    pub fn stage_idle_io(&self) -> bool {
+        self.stage_idle_io
+    }
+
+    /// The directory in which persistent state is kept
+    pub fn state_dir(&self) -> &Path {
+        &self.state_dir
+    }
+
+    /// The optional certificate authority used by RAUC for verifying HTTPS servers of streamed update bundles
+    pub fn tls_ca(&self) -> Option<&Path> {
+        if self.tls_ca.as_os_str().is_empty() {
+            None
+        } else {
+            Some(&self.tls_ca)
+        }
+    }
+
+    /// The optional client certificate used by RAUC for authenticating with HTTPS servers of streamed update bundles
+    pub fn tls_cert(&self) -> Option<&Path> {
+        if self.tls_cert.as_os_str().is_empty() {
+            None
+        } else {
+            Some(&self.tls_cert)
+        }
+    }
+
+    /// The optional private key of the client certificate
+    pub fn tls_key(&self) -> Option<&Path> {
+        if self.tls_key.as_os_str().is_empty() {
+            None
+        } else {
+            Some(&self.tls_key)
+        }
+    }
+
+    /// The temporary directory, which is checked for available space
+    pub fn tmp_dir(&self) -> &Path {
+        &self.tmp_dir
+    }
+
+    /// Whether update bundles are verified by RAUC before they are offered
+    pub fn verify_bundles(&self) -> bool {
+        self.verify_bundles
+    }
+
+    /// Whether the inbox directories are watched, so that update bundles written to them are found immediately
+    pub fn watch_inbox_dirs(&self) -> bool {
+        self.watch_inbox_dirs
+    }
+
+    /// Whether a result report is written onto the block device an update bundle has been installed from
+    pub fn write_result_report(&self) -> bool {
+        self.write_result_report
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use crate::macros;
+    use clap::Parser;
     use rstest::rstest;
+    use testdir::testdir;
+    use testresult::TestResult;
 
     #[tokio::test]
     #[rstest]
     async fn test_read_config() {
-        let config = read_config().await.unwrap();
+        let config = read_config(None).await.unwrap();
         let device_regex_string = config.get_string("device_regex").unwrap();
         assert!(macros::regex_once!(device_regex_string)
             .is_match("/org/freedesktop/UDisks2/block_devices/sda1"));
+        assert!(macros::regex_once!(device_regex_string)
+            .is_match("/org/freedesktop/UDisks2/block_devices/sr0"));
+    }
+
+    #[tokio::test]
+    #[rstest]
+    async fn test_caterpillar_config() -> TestResult {
+        let config = CaterpillarConfig::from_config(read_config(None).await?)?;
+        assert!(!config.anti_rollback());
+        assert_eq!(config.authorized_group(), None);
+        assert_eq!(config.min_version(), None);
+        assert_eq!(config.channel(), None);
+        assert_eq!(config.search_dir(), None);
+        assert!(!config.private_mounts());
+        assert!(!config.sandbox());
+        assert_eq!(config.email_relay(), "localhost:25");
+        assert!(config.email_to().is_empty());
+        assert_eq!(
+            config.hawkbit_cache_dir(),
+            Path::new("/var/cache/caterpillar/hawkbit")
+        );
+        assert_eq!(config.hawkbit_controller_id(), None);
+        assert_eq!(config.hawkbit_tenant(), "DEFAULT");
+        assert_eq!(config.hawkbit_token(), None);
+        assert_eq!(config.hawkbit_url(), None);
+        assert!(config.auto_install());
+        assert!(config.auto_reboot());
+        assert!(config.auto_search());
+        assert!(config.watch_inbox_dirs());
+        assert!(!config.write_result_report());
+        assert!(config.verify_bundles());
+        assert!(!config.kill_blocking_processes());
+        assert!(!config.reinstall_current_version());
+        assert_eq!(config.stage_dir(), None);
+        assert_eq!(config.stage_bandwidth(), 0);
+        assert!(!config.stage_idle_io());
+        assert_eq!(config.bundle_extension(), "raucb");
+        assert_eq!(config.bundle_sort(), BundleSort::Version);
+        assert_eq!(config.mount_options(), "rw,nosuid,nodev,noexec");
+        assert_eq!(config.power_check(), PowerCheck::Disabled);
+        assert_eq!(config.reboot_method(), RebootMethod::Logind);
+        assert_eq!(config.release_action(), ReleaseAction::None);
+        assert_eq!(config.reporters(), &[ReporterKind::Wall]);
+        assert_eq!(config.partition_types(), default_partition_types());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case(false, false)]
+    #[case(true, true)]
+    async fn test_caterpillar_config_autorun(
+        #[case] autorun: bool,
+        #[case] enabled: bool,
+    ) -> TestResult {
+        let config = CaterpillarConfig::from_config(
+            Config::builder()
+                .add_source(read_config(None).await?)
+                .set_override("autorun", autorun)?
+                .build()?,
+        )?;
+        assert_eq!(config.auto_search(), enabled);
+        assert_eq!(config.auto_install(), enabled);
+        assert_eq!(config.auto_reboot(), enabled);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[rstest]
+    async fn test_caterpillar_config_effective() -> TestResult {
+        let config = CaterpillarConfig::from_config(
+            Config::builder()
+                .add_source(read_config(None).await?)
+                .set_override("hawkbit_token", "secret")?
+                .set_override("battery_threshold", 25.5)?
+                .build()?,
+        )?;
+        let effective = config.effective();
+        assert_eq!(effective["auto_search"], "true");
+        assert_eq!(effective["battery_threshold"], "25.5");
+        assert_eq!(effective["bundle_extension"], "raucb");
+        assert_eq!(effective["hawkbit_token"], "<redacted>");
+        assert_eq!(effective["reporters"], r#"["wall"]"#);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[rstest]
+    async fn test_caterpillar_config_from_cli() -> TestResult {
+        let config = CaterpillarConfig::from_cli(&Cli::parse_from(["caterpillar"])).await?;
+        assert!(config.auto_search());
+        let config =
+            CaterpillarConfig::from_cli(&Cli::parse_from(["caterpillar", "--no-autorun"])).await?;
+        assert!(!config.auto_search());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[rstest]
+    async fn test_restrict_to_unprivileged() -> TestResult {
+        let mut config = CaterpillarConfig::from_config(read_config(None).await?)?;
+        assert!(config.restrict_to_unprivileged().is_empty());
+
+        config.kill_blocking_processes = true;
+        config.soft_reboot = true;
+        config.reboot_method = RebootMethod::Kexec;
+        assert_eq!(
+            config.restrict_to_unprivileged(),
+            vec!["kill_blocking_processes", "soft_reboot", "reboot_method"]
+        );
+        assert!(!config.kill_blocking_processes());
+        assert!(!config.soft_reboot());
+        assert_eq!(config.reboot_method(), RebootMethod::Systemd);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[rstest]
+    async fn test_restrict_to_sandbox() -> TestResult {
+        let mut config = CaterpillarConfig::from_config(read_config(None).await?)?;
+        assert!(config.restrict_to_sandbox().is_empty());
+
+        config.soft_reboot = true;
+        config.reboot_method = RebootMethod::Syscall;
+        assert_eq!(config.restrict_to_sandbox(), vec!["soft_reboot"]);
+        assert_eq!(config.reboot_method(), RebootMethod::Syscall);
+        config.reboot_method = RebootMethod::Kexec;
+        assert_eq!(config.restrict_to_sandbox(), vec!["reboot_method"]);
+        assert_eq!(config.reboot_method(), RebootMethod::Systemd);
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_credential_file() -> TestResult {
+        let directory = testdir!();
+        assert_eq!(credential_file(None), None);
+        assert_eq!(credential_file(Some(&directory)), None);
+        std::fs::write(directory.join(CREDENTIAL_NAME), "auto_reboot = false")?;
+        assert_eq!(
+            credential_file(Some(&directory)),
+            Some(directory.join(CREDENTIAL_NAME))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[rstest]
+    #[case("device_regex", "(")]
+    #[case("bundle_extension", "")]
+    #[case("bundle_filename_regex", "^myproduct-(")]
+    #[case("ignore_patterns", "[")]
+    #[case("power_check", "maybe")]
+    #[case("autorun", "maybe")]
+    #[case("bundle_sort", "size")]
+    #[case("install_window", "2am-5am")]
+    #[case("tls_cert", "/etc/caterpillar/client.crt")]
+    #[case("channel", "../stable")]
+    #[case("search_dir", "/caterpillar")]
+    #[case("min_version", "1.0")]
+    async fn test_caterpillar_config_invalid(#[case] key: &str, #[case] value: &str) -> TestResult {
+        let mut builder = Config::builder().add_source(read_config(None).await?);
+        builder = if key == "ignore_patterns" {
+            builder.set_override(key, vec![value])?
+        } else {
+            builder.set_override(key, value)?
+        };
+        assert!(CaterpillarConfig::from_config(builder.build()?).is_err());
+        Ok(())
     }
 }
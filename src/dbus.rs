@@ -1,165 +1,430 @@
 // SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
 // SPDX-License-Identifier: Apache-2.0 OR MIT
+use async_std::fs::remove_file;
 use async_std::fs::rename;
+use async_std::stream::StreamExt;
 use async_std::sync::RwLock;
-use config::Config;
 use event_listener::Event;
 use semver::Version;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::spawn;
 use tokio::sync::mpsc::channel;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::mpsc::Sender;
+use tokio::task::spawn_blocking;
 use tokio::task::JoinHandle;
 use tokio::time::sleep;
+use tokio::time::timeout;
 use tokio::time::Duration;
+use zbus::fdo::Properties;
 use zbus::names::BusName;
 use zbus::names::InterfaceName;
 use zbus::Connection;
+use zbus::MessageHeader;
+use zbus::ObjectServer;
 use zbus::SignalContext;
 use zbus_macros::dbus_interface;
+use zvariant::Array;
 use zvariant::ObjectPath;
+use zvariant::OwnedObjectPath;
 use zvariant::Type;
+use zvariant::Value;
 
-use crate::config::read_config;
+use crate::blocklist::Blocklist;
+use crate::clock::check_clock;
+use crate::clock::now;
+use crate::config::CaterpillarConfig;
+use crate::device::is_read_only;
+use crate::device::BundleSearch;
 use crate::device::Device;
+use crate::device::DriveFilter;
+use crate::device::ReleaseAction;
+use crate::device::SourceKind;
 use crate::device::UdisksInfo;
 use crate::error::Error;
-use crate::proxy::login1::ManagerProxy;
+#[cfg(feature = "hawkbit")]
+use crate::hawkbit::HawkbitClient;
+#[cfg(feature = "hawkbit")]
+use crate::hawkbit::HawkbitReporter;
+#[cfg(feature = "hawkbit")]
+use crate::hawkbit::DEFAULT_POLLING_INTERVAL;
+use crate::inbox::InboxWatcher;
+use crate::machine::Machine;
+use crate::outcome::append_result_report;
+use crate::outcome::UpdateResult;
+use crate::peer::authorize;
+use crate::power::check_power;
+use crate::proxy::rauc::InstallerProxy;
+use crate::quarantine::Quarantine;
+use crate::rauc::await_installation;
+use crate::rauc::mark_booted_good;
+use crate::rauc::rauc_operation;
+use crate::rauc::receive_rauc_owner_changed;
+use crate::rauc::shred;
+use crate::rauc::BundleImage;
+use crate::rauc::BundleSort;
 use crate::rauc::RaucInfo;
+use crate::rauc::TlsOptions;
 use crate::rauc::UpdateBundle;
+use crate::reboot::reboot_backend;
+use crate::reboot::RebootBackend;
+use crate::reboot::SoftReboot;
+use crate::report::Reporters;
+use crate::rollback::RollbackFloor;
+use crate::sftp::SftpSource;
+use crate::stage::stage_bundle;
+use crate::statemachine::download_progress;
+use crate::statemachine::install_progress;
+use crate::statemachine::next;
+use crate::statemachine::stage_progress;
+use crate::statemachine::Action;
+use crate::statemachine::Context;
+use crate::statemachine::State;
+use crate::telemetry;
+use crate::telemetry::Span;
+use crate::window::InstallWindow;
 
-/// State of the application
-#[derive(Clone, Debug, strum::Display, strum::EnumString, PartialEq)]
-#[non_exhaustive]
-pub enum State {
-    #[strum(to_string = "done")]
-    Done(bool, usize),
-    #[strum(to_string = "idle")]
-    Idle(bool, usize),
-    #[strum(to_string = "init")]
-    Init,
-    #[strum(to_string = "mounted")]
-    Mounted(bool, usize),
-    #[strum(to_string = "mounting")]
-    Mounting(bool, usize),
-    #[strum(to_string = "noupdatefound")]
-    NoUpdateFound(bool, usize),
-    #[strum(to_string = "searching")]
-    Searching(bool, usize),
-    #[strum(to_string = "skip")]
-    Skip(bool, usize),
-    #[strum(to_string = "unmounted")]
-    Unmounted(bool, usize, bool),
-    #[strum(to_string = "unmounting")]
-    Unmounting(bool, usize, bool),
-    #[strum(to_string = "updated")]
-    Updated(bool, usize, bool),
-    #[strum(to_string = "updatefound")]
-    UpdateFound(bool, usize),
-    #[strum(to_string = "updating")]
-    Updating(bool, usize),
-}
-
-impl State {
-    /// Return whether the system has been updated successfully
-    ///
-    /// Since users may choose not to reboot right after update, this indicator helps in distinguishing whether to allow another update attempt.
-    pub fn get_updated(&self) -> bool {
-        match self {
-            State::Init => false,
-            State::Done(updated, _)
-            | State::UpdateFound(updated, _)
-            | State::Idle(updated, _)
-            | State::Mounting(updated, _)
-            | State::Mounted(updated, _)
-            | State::NoUpdateFound(updated, _)
-            | State::Searching(updated, _)
-            | State::Skip(updated, _)
-            | State::Unmounting(updated, _, _)
-            | State::Unmounted(updated, _, _)
-            | State::Updating(updated, _)
-            | State::Updated(updated, _, _) => updated.to_owned(),
-        }
-    }
-
-    /// Return the iteration the program is currently in
-    ///
-    /// An iteration is defined by how often [`State::Unmounted`] has been reached
-    pub fn get_iteration(&self) -> usize {
-        match self {
-            State::Init => 0,
-            State::Done(_, iteration)
-            | State::UpdateFound(_, iteration)
-            | State::Idle(_, iteration)
-            | State::Mounting(_, iteration)
-            | State::Mounted(_, iteration)
-            | State::NoUpdateFound(_, iteration)
-            | State::Searching(_, iteration)
-            | State::Skip(_, iteration)
-            | State::Unmounting(_, iteration, _)
-            | State::Unmounted(_, iteration, _)
-            | State::Updating(_, iteration)
-            | State::Updated(_, iteration, _) => iteration.to_owned(),
-        }
-    }
-
-    /// Return whether the system is marked for reboot
-    ///
-    /// A system is marked for reboot, if reboot has been selected as action after successful update
-    pub fn get_marked_for_reboot(&self) -> bool {
-        match self {
-            State::Init
-            | State::Done(_, _)
-            | State::UpdateFound(_, _)
-            | State::Idle(_, _)
-            | State::Mounting(_, _)
-            | State::Mounted(_, _)
-            | State::NoUpdateFound(_, _)
-            | State::Searching(_, _)
-            | State::Updating(_, _)
-            | State::Skip(_, _) => false,
-            State::Unmounting(_, _, reboot)
-            | State::Unmounted(_, _, reboot)
-            | State::Updated(_, _, reboot) => reboot.to_owned(),
+/// The time to wait for devices to be unmounted when shutting down
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The name of the file in the state directory, in which the outcome of the most recent installation is persisted
+const LAST_UPDATE_RESULT_FILE: &str = "last_update_result";
+
+/// The version of the D-Bus API of the application
+///
+/// The version is incremented whenever the D-Bus interfaces change (e.g. new methods or signals, or changed structs).
+pub const API_VERSION: u32 = 21;
+
+/// The name of the versioned D-Bus interface of the application
+///
+/// The number in the name is only incremented for incompatible changes (e.g. changed structs or signals), so that
+/// clients of the previous interface can be migrated, while the compatible changes are tracked by [`API_VERSION`].
+pub const INTERFACE: &str = "de.sleepmap.Caterpillar1";
+
+/// A State as it is presented over D-BUS
+///
+/// A state is represented by its name (see [`State`]), whether the system has been updated, the iteration the program is
+/// in and whether the system is marked for reboot.
+#[derive(Debug, Deserialize, PartialEq, Serialize, Type)]
+struct StateInfo {
+    state: String,
+    updated: bool,
+    iteration: u32,
+    reboot: bool,
+}
+
+impl From<&State> for StateInfo {
+    fn from(state: &State) -> Self {
+        Self {
+            state: state.to_string(),
+            updated: state.get_updated(),
+            iteration: u32::try_from(state.get_iteration()).unwrap_or(u32::MAX),
+            reboot: state.get_marked_for_reboot(),
+        }
+    }
+}
+
+/// Health information on the application
+///
+/// The information is used for health probes and does not require any scanning.
+#[derive(Clone, Debug)]
+pub struct Health {
+    started: Instant,
+    last_scan: Option<i64>,
+    last_error: Option<String>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            last_scan: None,
+            last_error: None,
+        }
+    }
+}
+
+/// The status of the application as it is presented over D-BUS
+///
+/// A status is represented by the uptime of the application in seconds, the name of the state (see [`State`]), the time
+/// of the last completed search for updates in seconds since the epoch (0 if none has completed yet) and the last error
+/// that occurred (empty if none occurred).
+#[derive(Debug, Deserialize, PartialEq, Serialize, Type)]
+struct StatusInfo {
+    uptime: u64,
+    state: String,
+    last_scan: i64,
+    last_error: String,
+}
+
+impl StatusInfo {
+    /// Create a StatusInfo from the Health of the application and its State
+    pub fn new(health: &Health, state: &State) -> Self {
+        Self {
+            uptime: health.started.elapsed().as_secs(),
+            state: state.to_string(),
+            last_scan: health.last_scan.unwrap_or_default(),
+            last_error: health.last_error.clone().unwrap_or_default(),
         }
     }
 }
 
 /// An Update as it is presented over D-BUS
 ///
-/// An update is represented by the (file) name, current (old) version of the system, the (new) version of the update
-/// and whether the update is forced.
+/// An update is represented by the (file) name, current (old) version of the system, the (new) version of the update,
+/// whether the update is forced, the compatible of the update, its size in bytes, the device path and mountpoint of the
+/// device it has been found on and the slot classes the update targets (comma separated).
 #[derive(Debug, Deserialize, PartialEq, Serialize, Type)]
 struct Update {
     name: String,
     old_version: String,
     new_version: String,
     force: bool,
+    compatible: String,
+    size: u64,
+    device: String,
+    mountpoint: String,
+    slot_classes: String,
 }
 
 impl Update {
-    /// Create an Update from an UpdateBundle and the current system version
-    pub fn from_bundle(bundle: &UpdateBundle, current_version: &Version) -> Self {
+    /// Create an Update from an UpdateBundle, the current system version and the optional Device it has been found on
+    pub fn from_bundle(
+        bundle: &UpdateBundle,
+        current_version: &Version,
+        device: Option<&Device>,
+    ) -> Self {
         Self {
             name: bundle.path(),
             old_version: current_version.to_string(),
             new_version: bundle.version().to_string(),
             force: bundle.is_override(),
+            compatible: bundle.compatible().to_string(),
+            size: bundle.size(),
+            device: device
+                .map(|device| device.device_path())
+                .unwrap_or_default(),
+            mountpoint: device
+                .and_then(|device| device.mountpoint())
+                .map(|mountpoint| mountpoint.display().to_string())
+                .unwrap_or_default(),
+            slot_classes: bundle.slot_classes().join(","),
+        }
+    }
+}
+
+/// A Device as it is exported on D-Bus
+///
+/// Each Device found during the last search for updates is exported as a child object of `/de/sleepmap/Caterpillar`.
+/// The information on the Device reflects the time of the search.
+pub struct DeviceObject {
+    device_path: String,
+    mountpoint: String,
+    bundles: Vec<String>,
+    override_bundles: Vec<String>,
+}
+
+impl From<&Device> for DeviceObject {
+    fn from(device: &Device) -> Self {
+        Self {
+            device_path: device.device_path(),
+            mountpoint: device
+                .mountpoint()
+                .map(|mountpoint| mountpoint.display().to_string())
+                .unwrap_or_default(),
+            bundles: device
+                .bundles()
+                .unwrap_or_default()
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect(),
+            override_bundles: device
+                .override_bundles()
+                .unwrap_or_default()
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect(),
+        }
+    }
+}
+
+#[dbus_interface(name = "de.sleepmap.Caterpillar.Device")]
+impl DeviceObject {
+    /// The path of the device (e.g. "/dev/sda1"), directory or URL of the network share
+    #[dbus_interface(property)]
+    async fn device_path(&self) -> String {
+        self.device_path.clone()
+    }
+
+    /// The mountpoint of the device (empty if it was not mounted)
+    #[dbus_interface(property)]
+    async fn mountpoint(&self) -> String {
+        self.mountpoint.clone()
+    }
+
+    /// The paths of potential update bundles found on the device
+    #[dbus_interface(property)]
+    async fn bundles(&self) -> Vec<String> {
+        self.bundles.clone()
+    }
+
+    /// The paths of potential override update bundles found on the device
+    #[dbus_interface(property)]
+    async fn override_bundles(&self) -> Vec<String> {
+        self.override_bundles.clone()
+    }
+}
+
+/// An UpdateBundle as it is exported on D-Bus
+///
+/// Each UpdateBundle found during the last search for updates is exported as a child object of `/de/sleepmap/Caterpillar`.
+/// The object at index 0 is the update selected by default.
+pub struct UpdateObject {
+    index: usize,
+    path: String,
+    version: String,
+    compatible: String,
+    size: u64,
+    is_override: bool,
+    slot_classes: Vec<String>,
+    signer_common_name: String,
+    signer_fingerprint: String,
+    signer_not_before: i64,
+    signer_not_after: i64,
+}
+
+impl UpdateObject {
+    /// Create a new UpdateObject from an UpdateBundle and its index in the list of found UpdateBundles
+    pub fn new(index: usize, bundle: &UpdateBundle) -> Self {
+        Self {
+            index,
+            path: bundle.path(),
+            version: bundle.version().to_string(),
+            compatible: bundle.compatible().to_string(),
+            size: bundle.size(),
+            is_override: bundle.is_override(),
+            slot_classes: bundle.slot_classes().to_vec(),
+            signer_common_name: bundle
+                .signer()
+                .map(|signer| signer.common_name().to_string())
+                .unwrap_or_default(),
+            signer_fingerprint: bundle
+                .signer()
+                .map(|signer| signer.fingerprint().to_string())
+                .unwrap_or_default(),
+            signer_not_before: bundle
+                .signer()
+                .map(|signer| signer.not_before())
+                .unwrap_or_default(),
+            signer_not_after: bundle
+                .signer()
+                .map(|signer| signer.not_after())
+                .unwrap_or_default(),
         }
     }
 }
 
+#[dbus_interface(name = "de.sleepmap.Caterpillar.Update")]
+impl UpdateObject {
+    /// Trigger the installation of the update
+    ///
+    /// The parameter to this method provides information on whether to reboot afterwards (b).
+    /// Returns the ID of the transaction (t).
+    async fn install(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: MessageHeader<'_>,
+        #[zbus(object_server)] object_server: &ObjectServer,
+        reboot: bool,
+    ) -> zbus::fdo::Result<u64> {
+        let caterpillar = object_server
+            .interface::<_, Caterpillar>("/de/sleepmap/Caterpillar")
+            .await?;
+        let caterpillar = caterpillar.get().await;
+        caterpillar.authorize(connection, &header).await?;
+        caterpillar
+            .install_update_at(self.index, true, reboot)
+            .await
+    }
+
+    /// The absolute path of the update bundle
+    #[dbus_interface(property)]
+    async fn path(&self) -> String {
+        self.path.clone()
+    }
+
+    /// The version of the update bundle
+    #[dbus_interface(property)]
+    async fn version(&self) -> String {
+        self.version.clone()
+    }
+
+    /// The compatible of the update bundle
+    #[dbus_interface(property)]
+    async fn compatible(&self) -> String {
+        self.compatible.clone()
+    }
+
+    /// The size of the update bundle in bytes
+    #[dbus_interface(property)]
+    async fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Whether the update bundle is an override
+    #[dbus_interface(property)]
+    async fn is_override(&self) -> bool {
+        self.is_override
+    }
+
+    /// The slot classes targeted by the images of the update bundle
+    #[dbus_interface(property)]
+    async fn slot_classes(&self) -> Vec<String> {
+        self.slot_classes.clone()
+    }
+
+    /// The common name of the subject of the signing certificate of the update bundle (empty, if unknown)
+    #[dbus_interface(property)]
+    async fn signer_common_name(&self) -> String {
+        self.signer_common_name.clone()
+    }
+
+    /// The hex encoded SHA-256 fingerprint of the signing certificate of the update bundle (empty, if unknown)
+    #[dbus_interface(property)]
+    async fn signer_fingerprint(&self) -> String {
+        self.signer_fingerprint.clone()
+    }
+
+    /// The start of the validity of the signing certificate in seconds since the epoch (0, if unknown)
+    #[dbus_interface(property)]
+    async fn signer_not_before(&self) -> i64 {
+        self.signer_not_before
+    }
+
+    /// The end of the validity of the signing certificate in seconds since the epoch (0, if unknown)
+    #[dbus_interface(property)]
+    async fn signer_not_after(&self) -> i64 {
+        self.signer_not_after
+    }
+}
+
 /// The state of the application
+#[derive(Clone)]
 pub struct StateHandle {
     state: Arc<RwLock<State>>,
     done: Arc<Event>,
     sender: Option<Sender<State>>,
-    thread: Option<JoinHandle<Result<(), Error>>>,
+    thread: Option<Arc<JoinHandle<Result<(), Error>>>>,
 }
 
 impl StateHandle {
@@ -186,25 +451,506 @@ impl StateHandle {
     }
 }
 
+/// The executor of the state machine
+///
+/// The executor receives requested States, computes transitions using [`next`] and carries out the resulting Actions.
+pub struct StateExecutor {
+    connection: Connection,
+    /// the current connection to the system bus, which replaces `connection` once it has been reconnected
+    system_connection: Arc<RwLock<Connection>>,
+    /// the connection on which the application is served (see [`Caterpillar::serve_on`])
+    bus_connection: Arc<RwLock<Option<Connection>>>,
+    sender: Sender<State>,
+    state: Arc<RwLock<State>>,
+    done: Arc<Event>,
+    devices: Arc<RwLock<Vec<Device>>>,
+    updates: Arc<RwLock<Vec<UpdateBundle>>>,
+    rauc_info: Arc<RwLock<RaucInfo>>,
+    auto_install: bool,
+    auto_reboot: bool,
+    clock_floor: i64,
+    defer_on_implausible_clock: bool,
+    release_action: ReleaseAction,
+    reboot_backend: Box<dyn RebootBackend + Send + Sync>,
+    soft_reboot: Arc<AtomicBool>,
+    reboot_cancelled: Arc<AtomicBool>,
+    reboot_delay: u64,
+    inhibited: Arc<RwLock<Option<String>>>,
+    install_window: Option<InstallWindow>,
+    progress: Arc<RwLock<(i32, String)>>,
+    resume_install: bool,
+    trace: Arc<RwLock<Option<Span>>>,
+    transaction: Arc<AtomicU64>,
+    search_task: Arc<RwLock<Option<JoinHandle<zbus::fdo::Result<()>>>>>,
+    mount_timeout: u64,
+    search_timeout: u64,
+    kill_blocking_processes: bool,
+    reporters: Arc<Reporters>,
+}
+
+impl StateExecutor {
+    /// Return the connection used for signals and calls to the application itself
+    ///
+    /// This is the connection on which the application is served or the connection to the system bus, if it is not
+    /// served (e.g. when running once).
+    async fn bus_connection(&self) -> Connection {
+        self.bus_connection
+            .read()
+            .await
+            .clone()
+            .unwrap_or_else(|| self.connection.clone())
+    }
+
+    /// Receive requested States and act on them until the application is done
+    pub async fn run(mut self, mut receiver: Receiver<State>) -> Result<(), Error> {
+        let mut exit = false;
+        self.sender.send(State::Idle(false, 0)).await?;
+        if self.resume_install {
+            self.resume_install().await?;
+        }
+        let mut entered = Instant::now();
+        while !exit {
+            if let Ok(event) = receiver.try_recv() {
+                entered = Instant::now();
+                self.connection = self.system_connection.read().await.clone();
+                println!("Entering state: {}", &event);
+                let context = Context {
+                    auto_install: self.auto_install,
+                    auto_reboot: self.auto_reboot,
+                    clock_plausible: !matches!(event, State::UpdateFound(_, _))
+                        || self.clock_plausible(),
+                    inhibited: self.inhibited.read().await.is_some(),
+                    outside_install_window: !self.until_install_window().is_zero(),
+                    reboot_cancelled: self.reboot_cancelled.load(Ordering::SeqCst),
+                };
+                let previous_state = self.state.read_arc().await.clone();
+                let (state, actions) = next(&previous_state, event, &context);
+                {
+                    // update the state
+                    let mut state_write = self.state.write_arc().await;
+                    *state_write = state.clone();
+                }
+                for action in actions {
+                    exit |= self.execute(action, &state).await?;
+                }
+            } else {
+                let state = self.state.read_arc().await.clone();
+                if self
+                    .deadline(&state)
+                    .is_some_and(|deadline| entered.elapsed() > deadline)
+                {
+                    self.time_out(&state, entered.elapsed()).await?;
+                    entered = Instant::now();
+                }
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        Ok(())
+    }
+
+    /// Resume monitoring an installation, that RAUC has started before the application has been (re)started
+    ///
+    /// Once the installation is completed, the regular flow continues from [`State::Updated`] (rebooting if
+    /// `auto_reboot` is configured).
+    async fn resume_install(&self) -> Result<(), Error> {
+        println!("RAUC is installing an update bundle. Resume monitoring the installation...");
+        self.sender.send(State::Updating(false, 1)).await?;
+        let connection = self.connection.clone();
+        let sender = self.sender.clone();
+        let reboot = self.auto_reboot;
+        spawn(async move {
+            let state = match await_installation(&connection).await {
+                Ok(()) => State::Updated(false, 1, reboot),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    State::Unmounting(false, 1, false)
+                }
+            };
+            if let Err(error) = sender.send(state).await {
+                eprintln!("{}", error);
+            }
+        });
+        Ok(())
+    }
+
+    /// Return whether the system time is plausible, if installations are deferred on an implausible clock
+    fn clock_plausible(&self) -> bool {
+        if self.defer_on_implausible_clock {
+            match check_clock(now(), self.clock_floor) {
+                Ok(()) => true,
+                Err(error) => {
+                    eprintln!("Deferring installation: {}", error);
+                    false
+                }
+            }
+        } else {
+            true
+        }
+    }
+
+    /// Return the time after which a State is considered stuck (if any)
+    ///
+    /// Only States, in which the search for updates waits on external services (e.g. UDisks2 or RAUC), have a deadline.
+    /// [`State::Updating`] has none, as devices must not be unmounted while RAUC may still read from them.
+    fn deadline(&self, state: &State) -> Option<Duration> {
+        let seconds = match state {
+            State::Mounting(_, _) | State::Mounted(_, _) => self.mount_timeout,
+            State::Searching(_, _) => self.search_timeout,
+            _ => 0,
+        };
+        (seconds > 0).then(|| Duration::from_secs(seconds))
+    }
+
+    /// Recover from a stuck State
+    ///
+    /// The stuck State is signaled, the ongoing search is aborted, all devices are unmounted (on a best effort basis)
+    /// and the application returns to idle state.
+    async fn time_out(&self, state: &State, elapsed: Duration) -> Result<(), Error> {
+        eprintln!(
+            "Operation timed out after {}s in state {}",
+            elapsed.as_secs(),
+            state
+        );
+        let bus_connection = self.bus_connection().await;
+        Caterpillar::operation_timed_out(
+            &SignalContext::from_parts(
+                bus_connection.clone(),
+                ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+            ),
+            &state.to_string(),
+            elapsed.as_secs(),
+        )
+        .await?;
+
+        // the search holds the lock on the devices, while it is ongoing
+        if let Some(task) = self.search_task.write().await.take() {
+            task.abort();
+        }
+        {
+            let mut devices = self.devices.write_arc().await;
+            for device in devices.iter_mut().filter(|device| device.is_mounted()) {
+                eprintln!("Device {} is still mounted", device.device_path());
+                match timeout(
+                    Duration::from_secs(self.mount_timeout.max(1)),
+                    unmount_device(
+                        &self.connection,
+                        &bus_connection,
+                        device,
+                        self.kill_blocking_processes,
+                    ),
+                )
+                .await
+                {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(error)) => eprintln!("{}", error),
+                    Err(_) => eprintln!("Unmounting {} timed out", device.device_path()),
+                }
+            }
+            *devices = vec![];
+        }
+        devices_found_changed(&bus_connection, &[]).await?;
+        self.updates.write_arc().await.clear();
+        *self.trace.write().await = None;
+
+        self.sender
+            .send(State::Idle(state.get_updated(), state.get_iteration()))
+            .await?;
+        Ok(())
+    }
+
+    /// Return the time until the installation window opens (zero if no installation window is configured)
+    fn until_install_window(&self) -> Duration {
+        self.install_window
+            .map(|window| window.until_open_now())
+            .unwrap_or_default()
+    }
+
+    /// Announce a pending reboot to the Reporters and on D-Bus
+    async fn announce_reboot(&self) -> Result<(), Error> {
+        println!(
+            "System restarting in {}s to finish update",
+            self.reboot_delay
+        );
+        self.reporters
+            .reboot_pending(self.transaction.load(Ordering::SeqCst), self.reboot_delay)
+            .await;
+        Caterpillar::reboot_pending(
+            &SignalContext::from_parts(
+                self.bus_connection().await,
+                ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+            ),
+            self.reboot_delay,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Carry out an Action in a State
+    ///
+    /// Returns whether the application is done.
+    async fn execute(&self, action: Action, state: &State) -> Result<bool, Error> {
+        match action {
+            Action::EmitPropertiesChanged(previous_state) => {
+                *self.progress.write().await = state.get_progress();
+                // broadcast changes of the properties derived from the state
+                if let Err(error) = emit_properties_changed(
+                    &self.bus_connection().await,
+                    self.transaction.load(Ordering::SeqCst),
+                    &previous_state,
+                    state,
+                )
+                .await
+                {
+                    eprintln!("{}", error);
+                }
+            }
+            Action::SignalUpdateFound => {
+                let transaction = self.transaction.load(Ordering::SeqCst);
+                let updates = self.updates.read_arc().await;
+                signal_update_found(
+                    &self.bus_connection().await,
+                    transaction,
+                    &updates,
+                    &self.devices.read_arc().await,
+                    &self.rauc_info.read_arc().await,
+                )
+                .await?;
+                if let Some(bundle) = updates.first() {
+                    self.reporters.update_found(transaction, bundle).await;
+                }
+            }
+            Action::InstallUpdate => {
+                println!("Running in non-interactive mode. Install...");
+                request_install(&self.bus_connection().await, self.auto_reboot).await?;
+            }
+            Action::DeferInstall => {
+                let delay = self.until_install_window();
+                println!(
+                    "Outside of the installation window. Deferring installation for {}s...",
+                    delay.as_secs()
+                );
+                let connection = self.bus_connection().await;
+                Caterpillar::install_deferred(
+                    &SignalContext::from_parts(
+                        connection.clone(),
+                        ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+                    ),
+                    delay.as_secs(),
+                )
+                .await?;
+                let reboot = self.auto_reboot;
+                spawn(async move {
+                    sleep(delay).await;
+                    println!("Installation window opened. Install...");
+                    if let Err(error) = request_install(&connection, reboot).await {
+                        eprintln!("{}", error);
+                    }
+                });
+            }
+            Action::Unmount(updated) => {
+                let bus_connection = self.bus_connection().await;
+                let mut devices = self.devices.write_arc().await;
+                // the device from which an update has been installed
+                let source = if updated {
+                    let updates = self.updates.read_arc().await;
+                    updates.first().and_then(|update| {
+                        devices
+                            .iter()
+                            .position(|device| device.contains(Path::new(&update.path())))
+                    })
+                } else {
+                    None
+                };
+                // a device, that can not be unmounted, must not keep the state machine from progressing
+                for device in devices.iter_mut() {
+                    if device.is_mounted() {
+                        if let Err(error) = unmount_device(
+                            &self.connection,
+                            &bus_connection,
+                            device,
+                            self.kill_blocking_processes,
+                        )
+                        .await
+                        {
+                            eprintln!("{}", error);
+                        }
+                    }
+                }
+
+                // release the drive of the source device, so that it is safe to remove it
+                if let Some(device) = source.and_then(|index| devices.get(index)) {
+                    match device.release(&self.connection, self.release_action).await {
+                        Ok(true) => {
+                            Caterpillar::device_released(
+                                &SignalContext::from_parts(
+                                    bus_connection.clone(),
+                                    ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+                                ),
+                                &device.device_path(),
+                                &self.release_action.to_string(),
+                            )
+                            .await?;
+                        }
+                        Ok(false) => {}
+                        Err(error) => eprintln!("{}", error),
+                    }
+                }
+            }
+            Action::Reboot => {
+                let span = trace_span(&self.trace, "reboot").await;
+                span.set_attribute("caterpillar.reboot.delay", self.reboot_delay);
+                span.set_attribute(
+                    "caterpillar.reboot.soft",
+                    self.soft_reboot.load(Ordering::SeqCst),
+                );
+                // end the trace of the update cycle before rebooting
+                drop(span);
+                *self.trace.write().await = None;
+                telemetry::flush();
+                if self.reboot_delay > 0 {
+                    self.announce_reboot().await?;
+                    // the reboot may be cancelled during the grace period
+                    let started = Instant::now();
+                    let mut remaining = 0;
+                    while started.elapsed() < Duration::from_secs(self.reboot_delay)
+                        && !self.reboot_cancelled.load(Ordering::SeqCst)
+                    {
+                        // broadcast the remaining seconds once per second
+                        let seconds = self.reboot_delay - started.elapsed().as_secs();
+                        if seconds != remaining {
+                            remaining = seconds;
+                            if let Err(error) = Caterpillar::reboot_countdown(
+                                &SignalContext::from_parts(
+                                    self.bus_connection().await,
+                                    ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+                                ),
+                                remaining,
+                            )
+                            .await
+                            {
+                                eprintln!("{}", error);
+                            }
+                        }
+                        sleep(Duration::from_millis(100)).await;
+                    }
+                }
+                if self.reboot_cancelled.load(Ordering::SeqCst) {
+                    println!("Reboot cancelled");
+                    self.sender
+                        .send(State::Idle(state.get_updated(), state.get_iteration()))
+                        .await?;
+                    return Ok(false);
+                }
+                if self.soft_reboot.load(Ordering::SeqCst) {
+                    match SoftReboot.reboot(&self.connection).await {
+                        Ok(()) => return Ok(false),
+                        Err(error) => {
+                            eprintln!("{}", error);
+                            eprintln!("Falling back to a full reboot.");
+                        }
+                    }
+                }
+                self.reboot_backend.reboot(&self.connection).await?
+            }
+            Action::Reset => {
+                self.soft_reboot.store(false, Ordering::SeqCst);
+                self.reboot_cancelled.store(false, Ordering::SeqCst);
+                // end the trace of the update cycle
+                *self.trace.write().await = None;
+                // reset devices and updates lists
+                {
+                    let mut devices_write = self.devices.write_arc().await;
+                    *devices_write = vec![];
+                }
+                devices_found_changed(&self.bus_connection().await, &[]).await?;
+                {
+                    let mut updates_write = self.updates.write_arc().await;
+                    *updates_write = vec![];
+                }
+            }
+            Action::Request(state) => self.sender.send(state).await?,
+            Action::Done => {
+                self.done.notify(1);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
 /// The main application and D-Bus interface
 ///
 /// The struct unifies the configuration, connection to other D-BUS proxies, central state, found devices and updates.
 pub struct Caterpillar {
-    config: Config,
+    config: CaterpillarConfig,
     devices: Arc<RwLock<Vec<Device>>>,
     updates: Arc<RwLock<Vec<UpdateBundle>>>,
+    /// the cached information on RAUC and the status of its slots
+    rauc_info: Arc<RwLock<RaucInfo>>,
     state_handle: StateHandle,
+    health: Arc<RwLock<Health>>,
+    /// whether to soft-reboot after the installed update
+    soft_reboot: Arc<AtomicBool>,
+    /// whether the pending reboot after the installed update has been cancelled
+    reboot_cancelled: Arc<AtomicBool>,
+    /// the reason for which automatic searches and installations are inhibited
+    inhibited: Arc<RwLock<Option<String>>>,
+    /// the time at which the last search for updates has been started
+    last_search: Arc<RwLock<Option<Instant>>>,
+    /// the progress of the whole pipeline as percentage and message
+    progress: Arc<RwLock<(i32, String)>>,
+    /// the persisted outcome of the most recent attempt to install an update bundle
+    last_update_result: Arc<RwLock<Option<UpdateResult>>>,
+    /// the Reporters notified of the lifecycle events of update cycles
+    reporters: Arc<Reporters>,
+    /// whether an installation by RAUC has been ongoing on startup
+    resume_install: bool,
+    /// the root span of the trace of the current update cycle
+    trace: Arc<RwLock<Option<Span>>>,
+    /// the ID of the current transaction (a search for or an installation of updates)
+    transaction: Arc<AtomicU64>,
+    /// the background task of the ongoing search for updates
+    search_task: Arc<RwLock<Option<JoinHandle<zbus::fdo::Result<()>>>>>,
+    /// the connection to the system bus used by the state machine
+    system_connection: Arc<RwLock<Connection>>,
+    /// the connection on which the application is served (if any)
+    bus_connection: Arc<RwLock<Option<Connection>>>,
 }
 
 impl Caterpillar {
     /// Create a new Caterpillar instance
-    pub async fn new(done: Event) -> Result<Self, Error> {
+    pub async fn new(done: Event, config: CaterpillarConfig) -> Result<Self, Error> {
         println!("Initializing Caterpillar");
+        let last_update_result =
+            UpdateResult::read(&config.state_dir().join(LAST_UPDATE_RESULT_FILE));
+        #[allow(unused_mut)]
+        let mut reporters = Reporters::from_config(&config);
+        // report the outcome of deployments to the hawkBit server
+        #[cfg(feature = "hawkbit")]
+        if let Some(client) = hawkbit_client(&config) {
+            reporters.register(Box::new(HawkbitReporter::new(client)));
+        }
+        let reporters = Arc::new(reporters);
         let mut caterpillar = Self {
-            config: read_config().await?,
+            config,
             devices: Arc::new(RwLock::new(vec![])),
             updates: Arc::new(RwLock::new(vec![])),
+            rauc_info: Arc::new(RwLock::new(RaucInfo::default())),
             state_handle: StateHandle::new(done),
+            health: Arc::new(RwLock::new(Health::default())),
+            soft_reboot: Arc::new(AtomicBool::new(false)),
+            reboot_cancelled: Arc::new(AtomicBool::new(false)),
+            inhibited: Arc::new(RwLock::new(None)),
+            last_search: Arc::new(RwLock::new(None)),
+            progress: Arc::new(RwLock::new(State::Init.get_progress())),
+            last_update_result: Arc::new(RwLock::new(last_update_result)),
+            reporters,
+            resume_install: false,
+            trace: Arc::new(RwLock::new(None)),
+            transaction: Arc::new(AtomicU64::new(0)),
+            search_task: Arc::new(RwLock::new(None)),
+            system_connection: Arc::new(RwLock::new(system_connection().await?)),
+            bus_connection: Arc::new(RwLock::new(None)),
         };
         caterpillar.init().await?;
         Ok(caterpillar)
@@ -213,7 +959,7 @@ impl Caterpillar {
     /// Initialize the application's state handling
     async fn init(&mut self) -> Result<(), Error> {
         // state
-        let (sender, mut receiver): (Sender<State>, Receiver<State>) = channel(2);
+        let (sender, receiver): (Sender<State>, Receiver<State>) = channel(2);
         let state_sender = sender.clone();
         let state_lock = self.state_handle.state.clone();
         let done_lock = self.state_handle.done.clone();
@@ -223,408 +969,2340 @@ impl Caterpillar {
         let updates_lock = self.updates.clone();
 
         // config data
-        let autorun = self.config().get_bool("autorun")?;
+        let auto_install = self.config().auto_install();
+        let auto_reboot = self.config().auto_reboot();
+        let clock_floor = self.config().clock_floor();
+        let defer_on_implausible_clock = self.config().defer_on_implausible_clock();
+        let release_action = self.config().release_action();
+        let reboot_backend = reboot_backend(self.config());
 
         // test connections to other services
-        let connection = Connection::system().await?;
-        test_connections(&connection).await?;
+        let connection = self.system_connection.read().await.clone();
+        let rauc_info = test_connections(&connection, reboot_backend.as_ref()).await?;
+
+        // an installation may be ongoing, if the application has been restarted (e.g. after a crash)
+        self.resume_install = rauc_info.operation() == Some("installing");
+        *self.rauc_info.write().await = rauc_info;
 
         // start task that receives state changes, persists and acts on them
         self.state_handle.sender = Some(sender);
-        self.state_handle.thread = Some(spawn(async move {
-            let mut exit = false;
-            state_sender.send(State::Idle(false, 0)).await?;
-            while !exit {
-                if let Ok(state) = receiver.try_recv() {
-                    println!("Entering state: {}", &state);
-                    // let previous_state = state_lock.read_arc().await;
-                    {
-                        // update the state
-                        let mut state_write = state_lock.write_arc().await;
-                        *state_write = state;
-                    }
+        let executor = StateExecutor {
+            connection,
+            system_connection: self.system_connection.clone(),
+            bus_connection: self.bus_connection.clone(),
+            sender: state_sender,
+            state: state_lock,
+            done: done_lock,
+            devices: devices_lock,
+            updates: updates_lock,
+            rauc_info: self.rauc_info.clone(),
+            auto_install,
+            auto_reboot,
+            clock_floor,
+            defer_on_implausible_clock,
+            release_action,
+            reboot_backend,
+            soft_reboot: self.soft_reboot.clone(),
+            reboot_cancelled: self.reboot_cancelled.clone(),
+            reboot_delay: self.config().reboot_delay(),
+            inhibited: self.inhibited.clone(),
+            install_window: self.config().install_window(),
+            progress: self.progress.clone(),
+            resume_install: self.resume_install,
+            trace: self.trace.clone(),
+            transaction: self.transaction.clone(),
+            search_task: self.search_task.clone(),
+            mount_timeout: self.config().mount_timeout(),
+            search_timeout: self.config().search_timeout(),
+            reporters: self.reporters.clone(),
+            kill_blocking_processes: self.config().kill_blocking_processes(),
+        };
+        self.state_handle.thread = Some(Arc::new(spawn(executor.run(receiver))));
+        Ok(())
+    }
 
-                    // match against a clone of the state so we do not block
-                    let state_read = state_lock.read_arc().await.clone();
-                    match state_read {
-                        State::Init
-                        | State::Mounting(_, _)
-                        | State::Mounted(_, _)
-                        | State::Searching(_, _)
-                        | State::Updating(_, _) => {}
-                        State::Done(_, _) => {
-                            exit = true;
-                            done_lock.notify(1);
-                        }
-                        State::UpdateFound(_, iteration) => {
-                            let updates = updates_lock.read_arc().await;
-                            let connection = Connection::system().await?;
-                            let rauc_info = RaucInfo::new(&connection).await?;
-
-                            // signal that we have found an update
-                            println!("Signal over D-Bus, that an update is found");
-                            Caterpillar::update_found(
-                                &SignalContext::from_parts(
-                                    connection.to_owned(),
-                                    ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
-                                ),
-                                vec![Update::from_bundle(
-                                    &updates[0],
-                                    rauc_info.version().unwrap_or(&Version::new(0, 0, 0)),
-                                )],
-                            )
-                            .await?;
+    /// Return a new Caterpillar instance sharing the state (and state machine) of this one
+    ///
+    /// This allows serving the application on a new connection (e.g. after the bus has been restarted), while the
+    /// previous connection is dropped together with the instance served on it.
+    pub fn share(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            devices: self.devices.clone(),
+            updates: self.updates.clone(),
+            rauc_info: self.rauc_info.clone(),
+            state_handle: self.state_handle.clone(),
+            health: self.health.clone(),
+            soft_reboot: self.soft_reboot.clone(),
+            reboot_cancelled: self.reboot_cancelled.clone(),
+            inhibited: self.inhibited.clone(),
+            last_search: self.last_search.clone(),
+            progress: self.progress.clone(),
+            last_update_result: self.last_update_result.clone(),
+            reporters: self.reporters.clone(),
+            resume_install: self.resume_install,
+            trace: self.trace.clone(),
+            transaction: self.transaction.clone(),
+            search_task: self.search_task.clone(),
+            system_connection: self.system_connection.clone(),
+            bus_connection: self.bus_connection.clone(),
+        }
+    }
 
-                            // if this is the first iteration (i.e. boot) and configured to do so, install update and reboot
-                            if iteration == 1 && autorun {
-                                println!("Running in non-interactive mode. Install...");
-                                connection
-                                    .call_method(
-                                        Some(
-                                            BusName::try_from("de.sleepmap.Caterpillar")
-                                                .map_err(|x| Error::Default(x.to_string()))?,
-                                        ),
-                                        ObjectPath::try_from("/de/sleepmap/Caterpillar")
-                                            .map_err(|x| Error::Default(x.to_string()))?,
-                                        Some(
-                                            InterfaceName::try_from("de.sleepmap.Caterpillar")
-                                                .map_err(|x| Error::Default(x.to_string()))?,
-                                        ),
-                                        "InstallUpdate",
-                                        &(true, true),
-                                    )
-                                    .await?;
-                            }
-                        }
-                        State::NoUpdateFound(updated, iteration) => {
-                            state_sender
-                                .send(State::Unmounting(updated, iteration, false))
-                                .await?;
-                        }
-                        State::Idle(updated, iteration) => {
-                            {
-                                // increment our iteration
-                                let mut state_write = state_lock.write_arc().await;
-                                *state_write = State::Idle(updated, iteration + 1);
-                            }
-                        }
-                        State::Skip(updated, iteration) => {
-                            state_sender
-                                .send(State::Unmounting(updated, iteration, false))
-                                .await?;
-                        }
-                        State::Unmounting(updated, iteration, reboot) => {
-                            let connection = Connection::system().await?;
-                            let mut devices = devices_lock.write_arc().await;
-                            for device in devices.iter_mut() {
-                                if device.is_mounted() {
-                                    device.unmount_filesystem(&connection).await?;
-                                }
-                            }
-                            state_sender
-                                .send(State::Unmounted(updated, iteration, reboot))
-                                .await?;
-                        }
-                        State::Unmounted(updated, iteration, reboot) => {
-                            // if this is the first iteration, successfully updated and configured to do so, reboot
-                            if updated && ((iteration == 1 && autorun) || reboot) {
-                                let connection = Connection::system().await?;
-                                println!("Connecting to logind over dbus...");
-                                let login_proxy = ManagerProxy::new(&connection).await?;
-                                println!("Rebooting...");
-                                login_proxy.reboot(false).await?;
-                            // return to idle state if not updated or no reboot is wanted
-                            } else {
-                                state_sender.send(State::Idle(updated, iteration)).await?;
-                            }
+    /// Use the connection, on which the application is served, for its signals and calls to itself
+    ///
+    /// The services used by the application (e.g. RAUC and UDisks2) are always accessed on the system bus.
+    pub async fn serve_on(&self, connection: &Connection) {
+        *self.bus_connection.write().await = Some(connection.clone());
+    }
 
-                            // reset devices and updates lists
-                            {
-                                let mut devices_write = devices_lock.write_arc().await;
-                                *devices_write = vec![];
-                            }
-                            {
-                                let mut updates_write = updates_lock.write_arc().await;
-                                *updates_write = vec![];
-                            }
-                        }
-                        State::Updated(_, iteration, reboot) => {
-                            // mark ourselves as updated
-                            state_sender
-                                .send(State::Unmounting(true, iteration, reboot))
-                                .await?;
-                        }
-                    }
-                }
-                sleep(Duration::from_millis(100)).await;
-            }
-            Ok(())
-        }));
+    /// Return the connection used for signals and calls to the application itself
+    ///
+    /// This is the connection on which the application is served or a connection to the system bus, if it is not
+    /// served (e.g. when running once).
+    async fn bus_connection(&self) -> zbus::Result<Connection> {
+        match self.bus_connection.read().await.clone() {
+            Some(connection) => Ok(connection),
+            None => system_connection().await,
+        }
+    }
+
+    /// Resume on a new connection, on which the application is served (e.g. after the bus has been restarted)
+    ///
+    /// The connection to the system bus used by the state machine is replaced, signals are sent on the new connection
+    /// and the found devices and updates are exported on it again.
+    pub async fn reconnect(&self, server_connection: &Connection) -> Result<(), Error> {
+        *self.system_connection.write().await = system_connection().await?;
+        self.serve_on(server_connection).await;
+        export_devices(server_connection, &self.devices.read().await).await?;
+        export_updates(server_connection, &self.updates.read().await).await?;
         Ok(())
     }
 
     /// Return a reference to the application's configuration
-    pub fn config(&self) -> &Config {
+    pub fn config(&self) -> &CaterpillarConfig {
         &self.config
     }
 
-    /// Return a reference to the done Event of the application
-    pub fn done(&self) -> &Event {
+    /// Return whether an installation by RAUC has been ongoing on startup and is monitored instead of searching for updates
+    pub fn resume_install(&self) -> bool {
+        self.resume_install
+    }
+
+    /// Create the BundleSearch options used for searching update bundles on devices
+    pub fn bundle_search(&self) -> BundleSearch {
+        BundleSearch::from_config(&self.config)
+    }
+
+    /// Read the Blocklist of update bundle versions from the state directory
+    pub fn blocklist(&self) -> Result<Blocklist, Error> {
+        Blocklist::read(&self.config.state_dir().join("blocklist"))
+    }
+
+    /// Read the optional RollbackFloor of build timestamps from the state directory
+    ///
+    /// The RollbackFloor is only returned, if anti-rollback protection is enabled using the configuration.
+    pub fn rollback_floor(&self) -> Result<Option<RollbackFloor>, Error> {
+        if self.config.anti_rollback() {
+            Ok(Some(RollbackFloor::read(
+                &self.config.state_dir().join("rollback_floor"),
+            )?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Create the DriveFilter used for filtering block devices by their drive
+    pub fn drive_filter(&self) -> DriveFilter {
+        DriveFilter::from_config(&self.config)
+    }
+
+    /// Create the DeviceSearch used for finding, mounting and searching block devices from the configuration
+    pub fn device_search(&self) -> DeviceSearch {
+        DeviceSearch {
+            device_regex: self.config.device_regex().to_string(),
+            drive_filter: self.drive_filter(),
+            partition_labels: self.config.partition_labels().to_vec(),
+            partition_types: self.config.partition_types().to_vec(),
+            mount_options: self.config.mount_options().to_string(),
+            private_mounts: self.config.private_mounts(),
+            bundle_search: self.bundle_search(),
+        }
+    }
+
+    /// Create Devices for the configured inbox directories, loop images and network shares
+    ///
+    /// Inbox directories and loop images, that do not exist are skipped.
+    /// Network shares are mounted below the configured `share_dir`.
+    pub fn additional_devices(&self) -> Vec<Device> {
+        let mut devices = vec![];
+
+        for inbox_dir in self.config.inbox_dirs() {
+            if inbox_dir.is_dir() {
+                devices.push(Device::from_directory(inbox_dir));
+            } else {
+                eprintln!(
+                    "Skipping inbox directory {:?} as it is not a directory.",
+                    inbox_dir
+                );
+            }
+        }
+
+        for image in self.config.loop_images() {
+            if image.is_file() {
+                devices.push(Device::from_image(image));
+            } else {
+                eprintln!("Skipping loop image {:?} as it is not a file.", image);
+            }
+        }
+
+        for (index, share) in self.config.network_shares().iter().enumerate() {
+            devices.push(Device::from_share(
+                share.clone(),
+                &self.config.share_dir().join(index.to_string()),
+            ));
+        }
+
+        devices
+    }
+
+    /// Create the optional SftpSource from which update bundles are fetched
+    ///
+    /// If no SFTP host is configured, None is returned.
+    pub fn sftp_source(&self) -> Option<SftpSource> {
+        if self.config.sftp_host().is_empty() {
+            return None;
+        }
+
+        Some(SftpSource::from_config(&self.config))
+    }
+
+    /// Create the optional HawkbitClient with which deployments are fetched
+    ///
+    /// If no hawkBit server is configured, None is returned.
+    #[cfg(feature = "hawkbit")]
+    pub fn hawkbit_client(&self) -> Option<HawkbitClient> {
+        hawkbit_client(&self.config)
+    }
+
+    /// Read the Quarantine of update bundles, that repeatedly failed to install, from the state directory
+    pub fn quarantine(&self) -> Result<Quarantine, Error> {
+        Quarantine::read(
+            &self.config.state_dir().join("quarantine"),
+            self.config.quarantine_threshold(),
+        )
+    }
+
+    /// Create the BundleFilter used for selecting update bundles from the configuration and the persisted state
+    pub fn bundle_filter(&self) -> Result<BundleFilter, Error> {
+        Ok(BundleFilter {
+            blocklist: self.blocklist()?,
+            quarantine: self.quarantine()?,
+            rollback_floor: self.rollback_floor()?,
+            min_version: self.config.min_version().cloned(),
+            compatible_aliases: self.config.compatible_aliases().clone(),
+            recovery_slot_class: self.config.recovery_slot_class().map(String::from),
+            verify_bundles: self.config.verify_bundles(),
+            reinstall_current_version: self.config.reinstall_current_version(),
+            bundle_sort: self.config.bundle_sort(),
+        })
+    }
+
+    /// Return a reference to the done Event of the application
+    pub fn done(&self) -> &Event {
         &self.state_handle.done
     }
 
-    /// Return the optional UpdateBundle, that the application found
-    async fn get_update(&self) -> Option<UpdateBundle> {
+    /// Search for and install an update once, without providing the D-Bus interface
+    ///
+    /// This drives the state machine directly instead of awaiting requests on D-Bus, so that only RAUC, UDisks2 and the
+    /// reboot backend are required (e.g. in an initramfs or recovery system).
+    /// If `reboot` is true, the system is rebooted after the update has been installed.
+    /// Returns whether an update has been installed.
+    pub async fn run_once(&self, reboot: bool) -> Result<bool, Error> {
+        let connection = system_connection().await?;
+        let state = self
+            .wait_for_state(|state| matches!(state, State::Idle(_, iteration) if *iteration > 0))
+            .await?;
+
+        self.search(&connection, None).await?;
+        let state = self
+            .wait_for_state(|x| match x {
+                State::UpdateFound(_, _) => true,
+                State::Idle(_, iteration) => *iteration > state.get_iteration(),
+                _ => false,
+            })
+            .await?;
+        if let State::Idle(_, _) = state {
+            println!("No update found");
+            return Ok(false);
+        }
+
+        // the installation failed, if an error is recorded before the state machine returns to idle state
+        let last_error = self.health.read().await.last_error.clone();
+        self.install_update_at(0, true, reboot).await?;
+        loop {
+            match self.state_handle.read_state().await {
+                State::Done(_, _) => return Ok(true),
+                State::Idle(_, iteration) if iteration > state.get_iteration() => return Ok(true),
+                _ => {}
+            }
+            let error = self.health.read().await.last_error.clone();
+            if error != last_error {
+                return Err(Error::UpdateFailed(error.unwrap_or_default()));
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Wait until the state machine reaches a State for which `predicate` is true and return the State
+    async fn wait_for_state<F>(&self, predicate: F) -> Result<State, Error>
+    where
+        F: Fn(&State) -> bool,
+    {
+        loop {
+            let state = self.state_handle.read_state().await;
+            if predicate(&state) {
+                return Ok(state);
+            }
+            if self
+                .state_handle
+                .thread
+                .as_ref()
+                .map_or(true, |thread| thread.is_finished())
+            {
+                return Err(Error::Default(
+                    "The state machine stopped unexpectedly".to_string(),
+                ));
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Return the optional UpdateBundle at an index of the list of UpdateBundles, that the application found
+    ///
+    /// The UpdateBundle at index 0 is the one selected for update.
+    async fn get_update(&self, index: usize) -> Option<UpdateBundle> {
         self.updates
             .read()
             .await
-            .iter()
-            .last()
+            .get(index)
             .map(|bundle| bundle.to_owned())
     }
-}
 
-#[dbus_interface(name = "de.sleepmap.Caterpillar")]
-impl Caterpillar {
-    /// Trigger the search for an update
+    /// Trigger the installation of the UpdateBundle at an index of the list of found UpdateBundles
     ///
-    /// It is advised to subscribe to the `UpdateFound` signal before calling this method.
-    pub async fn search_for_update(&self) -> zbus::fdo::Result<()> {
-        println!("Search for update...");
+    /// If `update` is false, the update is skipped. If `reboot` is true, the system is rebooted after successful update.
+    /// Returns the ID of the transaction of the installation (or of the search, if the update is skipped).
+    pub async fn install_update_at(
+        &self,
+        index: usize,
+        update: bool,
+        reboot: bool,
+    ) -> zbus::fdo::Result<u64> {
         let state = self.state_handle.read_state().await;
-        match state {
-            State::Idle(updated, iteration) if !updated => {
+        let transaction = match state {
+            State::UpdateFound(updated, iteration) if !updated && update => {
+                if self.config.defer_on_implausible_clock() {
+                    check_clock(now(), self.config.clock_floor())
+                        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                }
                 let state_sender = self
                     .state_handle
                     .sender_clone()
                     .await
                     .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                let blocklist_failed = self.config.blocklist_failed();
+                let mut blocklist = self
+                    .blocklist()
+                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                let mut quarantine = self
+                    .quarantine()
+                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                let mut rollback_floor = self
+                    .rollback_floor()
+                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                let soft_reboot = self.config.soft_reboot();
+                let shred_override_bundles = self.config.shred_override_bundles();
+                let soft_reboot_lock = self.soft_reboot.clone();
+                let progress_lock = self.progress.clone();
+                let rauc_info_lock = self.rauc_info.clone();
+                let last_update_result_lock = self.last_update_result.clone();
+                let last_update_result_path = self.config.state_dir().join(LAST_UPDATE_RESULT_FILE);
+                let write_result_report = self.config.write_result_report();
                 let devices_lock = self.devices.clone();
-                let (device_regex, bundle_extension, override_dir) = (
-                    self.config
-                        .get_string("device_regex")
-                        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?,
-                    self.config
-                        .get_string("bundle_extension")
-                        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?,
-                    self.config
-                        .get_string("override_dir")
-                        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?,
+                let reporters = self.reporters.clone();
+                let tls = TlsOptions::new(
+                    self.config.tls_cert(),
+                    self.config.tls_key(),
+                    self.config.tls_ca(),
                 );
-                let updates_lock = self.updates.clone();
-                let connection = Connection::system().await?;
-
-                // run background task that mounts available devices and searches for compatible updates
-                spawn(async move {
-                    state_sender
-                        .send(State::Mounting(updated, iteration))
+                let stage_dir = self.config.stage_dir().map(Path::to_path_buf);
+                let (stage_bandwidth, stage_idle_io) =
+                    (self.config.stage_bandwidth(), self.config.stage_idle_io());
+                let (check_space, tmp_dir, power_check, battery_threshold) = (
+                    self.config.check_space(),
+                    self.config.tmp_dir().to_path_buf(),
+                    self.config.power_check(),
+                    self.config.battery_threshold(),
+                );
+                let trace = self.trace.clone();
+                let bus_connection = self.bus_connection().await?;
+                let bundle = match self.get_update(index).await {
+                    Some(bundle) => bundle,
+                    None => {
+                        return Err(zbus::fdo::Error::Failed(format!(
+                            "{}",
+                            Error::NoUpdateBundle
+                        )))
+                    }
+                };
+                if index > 0 {
+                    // the UpdateBundle to install is always the first in the list, which changes the UpdateObjects
+                    let mut updates = self.updates.write().await;
+                    updates.swap(0, index);
+                    export_updates(&bus_connection, &updates)
                         .await
                         .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
-                    let mut devices = devices_lock.write_arc().await;
-                    // setup the devices (mounts)
-                    *devices = mount_and_search_devices(
-                        &connection,
-                        &device_regex,
-                        &bundle_extension,
-                        &override_dir,
-                    )
-                    .await
-                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                }
+                let transaction = self.start_transaction();
+                spawn(record_error(
+                    bus_connection.clone(),
+                    self.health.clone(),
+                    transaction,
+                    async move {
+                        let span = trace_span(&trace, "install").await;
+                        span.set_attribute("caterpillar.transaction", transaction);
+                        span.set_attribute("caterpillar.bundle.path", bundle.path());
+                        span.set_attribute("caterpillar.bundle.version", bundle.version());
+                        span.set_attribute("caterpillar.bundle.compatible", bundle.compatible());
+                        println!(
+                            "Install update {} and {}reboot",
+                            &bundle,
+                            if reboot { "" } else { "do not " }
+                        );
+                        state_sender
+                            .send(State::Updating(updated, iteration))
+                            .await
+                            .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
 
-                    state_sender
-                        .send(State::Mounted(updated, iteration))
-                        .await
-                        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                        let connection = system_connection().await?;
+                        if let Err(error) = check_power(&connection, power_check, battery_threshold)
+                            .await
+                            .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))
+                        {
+                            eprintln!("{}", error);
+                            record_update_result(
+                                &bus_connection,
+                                &last_update_result_lock,
+                                &last_update_result_path,
+                                &bundle.version().to_string(),
+                                &Err(error.to_string()),
+                            )
+                            .await;
+                            abort_install(&state_sender, updated, iteration).await;
+                            return Err(error);
+                        }
 
-                    let mut updates = updates_lock.write_arc().await;
-                    let rauc_info = RaucInfo::new(&connection)
-                        .await
-                        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
-                    state_sender
-                        .send(State::Searching(updated, iteration))
-                        .await
-                        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
-                    // search for a compatible update bundle
-                    match get_update_bundle(&connection, &rauc_info, &devices)
-                        .await
-                        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?
-                    {
-                        Some(bundle) => {
-                            println!(
-                                "Found {}update {}",
-                                if bundle.is_override() {
-                                    "override "
-                                } else {
-                                    " "
-                                },
-                                bundle.path()
-                            );
-                            updates.push(bundle);
-                            state_sender
-                                .send(State::UpdateFound(updated, iteration))
+                        let rauc_info = rauc_info_lock.read_arc().await;
+                        if check_space {
+                            if let Err(error) = bundle
+                                .check_space(&connection, &rauc_info, &tmp_dir)
                                 .await
-                                .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                                .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))
+                            {
+                                eprintln!("{}", error);
+                                record_update_result(
+                                    &bus_connection,
+                                    &last_update_result_lock,
+                                    &last_update_result_path,
+                                    &bundle.version().to_string(),
+                                    &Err(error.to_string()),
+                                )
+                                .await;
+                                abort_install(&state_sender, updated, iteration).await;
+                                return Err(error);
+                            }
+                        }
+
+                        // bundles with an aliased compatible are only accepted if RAUC ignores their compatible
+                        let ignore_compatible = bundle.compatible() != rauc_info.compatible();
+                        if ignore_compatible {
+                            println!(
+                            "Installing update bundle {} with the aliased compatible {} (system compatible: {}), ignoring its compatible",
+                            bundle.path(),
+                            bundle.compatible(),
+                            rauc_info.compatible()
+                        );
                         }
-                        None => state_sender
-                            .send(State::NoUpdateFound(updated, iteration))
+                        let artifact_only = bundle.is_artifact_only(&rauc_info);
+                        let old_version = rauc_info.version().map(ToString::to_string);
+                        drop(rauc_info);
+                        // RAUC only fetches changed chunks of casync images, seeding from the active slot
+                        let seeded = bundle
+                            .images(&connection)
                             .await
-                            .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?,
-                    }
-                    Ok::<(), zbus::fdo::Error>(())
-                });
-                Ok(())
+                            .is_ok_and(|images| images.iter().any(BundleImage::is_casync));
+                        if seeded {
+                            println!(
+                            "Update bundle {} contains casync images seeded from the active slot",
+                            bundle.path()
+                        );
+                        }
+                        // install from a verified local copy, so that removing the source medium does not corrupt the slot
+                        let staged = if let Some(stage_dir) = stage_dir {
+                            let source = PathBuf::from(bundle.path());
+                            let copy = source.file_name().map(|name| stage_dir.join(name));
+                            let (progress_sender, progress_receiver) = channel(8);
+                            let stage_progress_task = spawn(watch_transfer_progress(
+                                bus_connection.clone(),
+                                progress_lock.clone(),
+                                transaction,
+                                progress_receiver,
+                            ));
+                            let result = spawn_blocking(move || {
+                                stage_bundle(
+                                    &source,
+                                    &stage_dir,
+                                    stage_bandwidth,
+                                    stage_idle_io,
+                                    transfer_reporter(progress_sender, stage_progress),
+                                )
+                            })
+                            .await;
+                            if let Ok(Err(error)) = stage_progress_task.await {
+                                eprintln!("{}", error);
+                            }
+                            match result
+                                .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))
+                                .and_then(|result| {
+                                    result.map_err(|x| zbus::fdo::Error::Failed(x.to_string()))
+                                }) {
+                                Ok(staged) => Some(staged),
+                                Err(error) => {
+                                    eprintln!("{}", error);
+                                    // a partial copy is left behind, if the staging task panicked
+                                    if let Some(copy) = copy.filter(|copy| copy.exists()) {
+                                        if let Err(error) = remove_file(&copy).await {
+                                            eprintln!(
+                                                "Unable to remove staged update bundle {}: {}",
+                                                copy.display(),
+                                                error
+                                            );
+                                        }
+                                    }
+                                    record_update_result(
+                                        &bus_connection,
+                                        &last_update_result_lock,
+                                        &last_update_result_path,
+                                        &bundle.version().to_string(),
+                                        &Err(error.to_string()),
+                                    )
+                                    .await;
+                                    abort_install(&state_sender, updated, iteration).await;
+                                    return Err(error);
+                                }
+                            }
+                        } else {
+                            None
+                        };
+                        let progress_task = spawn(watch_install_progress(
+                            connection.clone(),
+                            bus_connection.clone(),
+                            progress_lock,
+                            transaction,
+                        ));
+                        let result = match &staged {
+                            Some(staged) => {
+                                bundle
+                                    .relocated(staged)
+                                    .install(&connection, ignore_compatible, &tls)
+                                    .await
+                            }
+                            None => bundle.install(&connection, ignore_compatible, &tls).await,
+                        };
+                        progress_task.abort();
+                        if let Some(staged) = staged {
+                            if let Err(error) = remove_file(&staged).await {
+                                eprintln!(
+                                    "Unable to remove staged update bundle {}: {}",
+                                    staged.display(),
+                                    error
+                                );
+                            }
+                        }
+                        // the status of the slots changes with an installation
+                        if let Err(error) = refresh_rauc_info(&connection, &rauc_info_lock).await {
+                            eprintln!("{}", error);
+                        }
+                        span.set_attribute("caterpillar.bundle.seeded", seeded);
+                        if let Err(error) = &result {
+                            span.set_error(error);
+                        }
+                        let update_result = record_update_result(
+                            &bus_connection,
+                            &last_update_result_lock,
+                            &last_update_result_path,
+                            &bundle.version().to_string(),
+                            &result.as_ref().map(|_| ()).map_err(|x| x.to_string()),
+                        )
+                        .await;
+                        reporters
+                            .install_finished(transaction, &update_result)
+                            .await;
+                        // the devices are unmounted only after the installation task finished
+                        if write_result_report {
+                            report_result_on_device(
+                                &devices_lock.read_arc().await,
+                                &bundle.path(),
+                                old_version.as_deref(),
+                                &update_result,
+                            );
+                        }
+                        match result {
+                            Ok(()) => {
+                                if let Err(error) =
+                                    quarantine.reset(&bundle.path(), bundle.version())
+                                {
+                                    eprintln!("{}", error);
+                                }
+                                if let Some(rollback_floor) = rollback_floor.as_mut() {
+                                    if let Some(build_timestamp) =
+                                        bundle.build_timestamp(&connection).await
+                                    {
+                                        if let Err(error) = rollback_floor.raise(build_timestamp) {
+                                            eprintln!("{}", error);
+                                        }
+                                    }
+                                }
+                                // update bundles may request a soft-reboot using their manifest's meta data
+                                let soft_reboot = soft_reboot
+                                    || bundle
+                                        .meta(&connection, "caterpillar", "soft-reboot")
+                                        .await
+                                        .is_ok_and(|x| x.as_deref() == Some("true"));
+                                soft_reboot_lock.store(soft_reboot, Ordering::SeqCst);
+                                Caterpillar::update_installed(
+                                    &SignalContext::from_parts(
+                                        bus_connection.clone(),
+                                        ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+                                    ),
+                                    transaction,
+                                    &bundle.path(),
+                                    &bundle.version().to_string(),
+                                    seeded,
+                                )
+                                .await?;
+                                if bundle.is_override() && is_read_only(Path::new(&bundle.path())) {
+                                    println!(
+                                    "Not disabling override bundle {}, as it is located on a read-only filesystem.",
+                                    bundle.path()
+                                );
+                                } else if bundle.is_override() && shred_override_bundles {
+                                    println!("Removing override bundle {}", bundle.path());
+                                    let path = PathBuf::from(bundle.path());
+                                    if let Err(error) = spawn_blocking(move || shred(&path))
+                                        .await
+                                        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?
+                                        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))
+                                    {
+                                        eprintln!("{}", error);
+                                        return Err(error);
+                                    }
+                                } else if bundle.is_override() {
+                                    println!("Disabling override bundle {}", bundle.path());
+                                    if let Err(error) = rename(
+                                        bundle.path(),
+                                        format!("{}.installed", bundle.path()),
+                                    )
+                                    .await
+                                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))
+                                    {
+                                        eprintln!("{}", error);
+                                        return Err(error);
+                                    }
+                                }
+                                // artifacts are updated in place, so the system neither is updated nor rebooted
+                                let state = if artifact_only {
+                                    println!(
+                                        "Update bundle {} only updates artifacts. Not rebooting.",
+                                        bundle.path()
+                                    );
+                                    State::Unmounting(updated, iteration, false)
+                                } else {
+                                    State::Updated(updated, iteration, reboot)
+                                };
+                                state_sender
+                                    .send(state)
+                                    .await
+                                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                            }
+                            Err(error) => {
+                                if blocklist_failed {
+                                    if let Err(error) = blocklist.add(bundle.version()) {
+                                        eprintln!("{}", error);
+                                    }
+                                }
+                                // stop offering update bundles, that repeatedly fail to install
+                                match quarantine.add_failure(&bundle.path(), bundle.version()) {
+                                    Ok(true) => {
+                                        if let Err(error) = Caterpillar::bundle_quarantined(
+                                            &SignalContext::from_parts(
+                                                bus_connection.clone(),
+                                                ObjectPath::from_str_unchecked(
+                                                    "/de/sleepmap/Caterpillar",
+                                                ),
+                                            ),
+                                            &bundle.path(),
+                                            &bundle.version().to_string(),
+                                            quarantine.failures(&bundle.path(), bundle.version()),
+                                        )
+                                        .await
+                                        {
+                                            eprintln!("{}", error);
+                                        }
+                                    }
+                                    Ok(false) => {}
+                                    Err(error) => eprintln!("{}", error),
+                                }
+                                let error = zbus::fdo::Error::Failed(error.to_string());
+                                eprintln!("{}", error);
+                                abort_install(&state_sender, updated, iteration).await;
+                                return Err(error);
+                            }
+                        }
+                        Ok(())
+                    },
+                ));
+                transaction
             }
-            _ => Err(zbus::fdo::Error::AccessDenied(format!(
-                "Already in state {}",
-                state
-            ))),
+            State::NoUpdateFound(updated, iteration) | State::UpdateFound(updated, iteration)
+                if !update =>
+            {
+                let state_sender = self
+                    .state_handle
+                    .sender_clone()
+                    .await
+                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                state_sender
+                    .send(State::Skip(updated, iteration))
+                    .await
+                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                self.transaction.load(Ordering::SeqCst)
+            }
+            _ => {
+                if state.get_updated() {
+                    return Err(zbus::fdo::Error::Failed(format!(
+                        "{}",
+                        Error::WrongState(
+                            "System is updated already, waiting for reboot".to_string()
+                        )
+                    )));
+                } else {
+                    return Err(zbus::fdo::Error::Failed(format!(
+                        "{}",
+                        Error::WrongState(format!("{}", state))
+                    )));
+                }
+            }
+        };
+        Ok(transaction)
+    }
+
+    /// Authorize the sender of a method call, if the `authorized_group` configuration option is set
+    async fn authorize(
+        &self,
+        connection: &Connection,
+        header: &MessageHeader<'_>,
+    ) -> zbus::fdo::Result<()> {
+        match self.config.authorized_group() {
+            Some(group) => authorize(connection, header, group).await,
+            None => Ok(()),
         }
     }
 
-    /// Trigger the installation of an update
+    /// Start a new transaction and return its ID
+    ///
+    /// The ID is included in all signals related to the transaction, so that clients can correlate them with their
+    /// request.
+    fn start_transaction(&self) -> u64 {
+        self.transaction.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Search for an update on all devices or only on a single block `device`
     ///
-    /// The parameters to this method provide information on whether to update (b) and whether to reboot afterwards (b)
-    async fn install_update(&self, update: bool, reboot: bool) -> zbus::fdo::Result<()> {
+    /// Requests during an ongoing search, while a found update is pending installation or shortly after a search has
+    /// been started (see the `search_debounce` configuration option) are coalesced and do not start another search.
+    /// Callers of coalesced requests receive the result of the ongoing or last search (the `UpdateFound` signal is
+    /// emitted again, if an update is pending installation).
+    /// Requests during an installation are denied.
+    /// Returns the ID of the transaction of the search (that of the ongoing or last search, if the request is coalesced).
+    async fn search(
+        &self,
+        server_connection: &Connection,
+        device: Option<String>,
+    ) -> zbus::fdo::Result<u64> {
         let state = self.state_handle.read_state().await;
+        let debounced = self.last_search.read().await.map_or(false, |started| {
+            started.elapsed() < Duration::from_secs(self.config.search_debounce())
+        });
         match state {
-            State::UpdateFound(updated, iteration) if !updated && update => {
+            // late callers receive the result of the ongoing search
+            State::Mounting(_, _) | State::Mounted(_, _) | State::Searching(_, _) => {
+                println!("Already searching for updates. Coalescing request.");
+                Ok(self.transaction.load(Ordering::SeqCst))
+            }
+            // late callers receive the result of the last search, which is pending installation
+            State::UpdateFound(_, _) => {
+                println!("Already found an update. Coalescing request.");
+                let transaction = self.transaction.load(Ordering::SeqCst);
+                signal_update_found(
+                    server_connection,
+                    transaction,
+                    &self.updates.read_arc().await,
+                    &self.devices.read_arc().await,
+                    &self.rauc_info.read_arc().await,
+                )
+                .await
+                .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                Ok(transaction)
+            }
+            // late callers receive the result of the search, that is winding down
+            State::NoUpdateFound(false, _)
+            | State::Skip(false, _)
+            | State::Unmounting(false, _, _)
+            | State::Unmounted(false, _, _) => {
+                println!("Finishing a search for updates. Coalescing request.");
+                Ok(self.transaction.load(Ordering::SeqCst))
+            }
+            State::Idle(updated, _) if !updated && debounced => {
+                println!("Recently searched for updates. Coalescing request.");
+                Ok(self.transaction.load(Ordering::SeqCst))
+            }
+            State::Idle(updated, iteration) if !updated => {
+                *self.last_search.write().await = Some(Instant::now());
                 let state_sender = self
                     .state_handle
                     .sender_clone()
                     .await
                     .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
-                if let Some(bundle) = self.get_update().await {
-                    spawn(async move {
-                        println!(
-                            "Install update {} and {}reboot",
-                            &bundle,
-                            if reboot { "" } else { "do not " }
-                        );
+                let devices_lock = self.devices.clone();
+                let device_search = self.device_search();
+                // a search on a single block device does not include other sources
+                let (mut additional_devices, sftp_source) = match device {
+                    Some(_) => (vec![], None),
+                    None => (self.additional_devices(), self.sftp_source()),
+                };
+                #[cfg(feature = "hawkbit")]
+                let hawkbit_client = match device {
+                    Some(_) => None,
+                    None => self.hawkbit_client(),
+                };
+                let clock_floor = self.config.clock_floor();
+                let bundle_filter = self
+                    .bundle_filter()
+                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                let updates_lock = self.updates.clone();
+                let rauc_info_lock = self.rauc_info.clone();
+                let health = self.health.clone();
+                let progress_lock = self.progress.clone();
+                let connection = system_connection().await?;
+                let server_connection = server_connection.to_owned();
+
+                // start the trace of a new update cycle
+                let transaction = self.start_transaction();
+                let trace = Span::root("update_cycle");
+                trace.set_attribute("caterpillar.iteration", iteration);
+                trace.set_attribute("caterpillar.transaction", transaction);
+                if let Some(device) = &device {
+                    trace.set_attribute("caterpillar.device", device);
+                }
+                *self.trace.write().await = Some(trace);
+                let trace = self.trace.clone();
+
+                self.reporters.scan_started(transaction).await;
+
+                // run background task that mounts available devices and searches for compatible updates
+                let task = spawn(record_error(
+                    server_connection.clone(),
+                    health.clone(),
+                    transaction,
+                    async move {
+                        let span = trace_span(&trace, "scan").await;
                         state_sender
-                            .send(State::Updating(updated, iteration))
+                            .send(State::Mounting(updated, iteration))
                             .await
                             .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
 
-                        match bundle
-                            .install(&Connection::system().await?)
-                            .await
-                            .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))
-                        {
-                            Ok(()) => {
-                                if bundle.is_override() {
-                                    println!("Disabling override bundle {}", bundle.path());
-                                    if let Err(error) = rename(
-                                        bundle.path(),
-                                        format!("{}.installed", bundle.path()),
-                                    )
-                                    .await
-                                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))
-                                    {
-                                        eprintln!("{}", error);
-                                        return Err(error);
-                                    }
-                                }
-                                state_sender
-                                    .send(State::Updated(updated, iteration, reboot))
-                                    .await
-                                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
-                            }
-                            Err(error) => {
-                                eprintln!("{}", error);
-                                return Err(error);
-                            }
+                        // fetch the best update bundle candidate from an SFTP server
+                        if let Some(sftp_source) = sftp_source {
+                            let current = rauc_info_lock.read().await.version().cloned();
+                            let extension = device_search.bundle_search.extension().to_string();
+                            let sftp_blocklist = bundle_filter.blocklist.clone();
+                            let cache_dir = sftp_source.cache_dir().to_path_buf();
+                            let (progress_sender, progress_receiver) = channel(8);
+                            let progress_task = spawn(watch_transfer_progress(
+                                server_connection.clone(),
+                                progress_lock.clone(),
+                                transaction,
+                                progress_receiver,
+                            ));
+                            let result = spawn_blocking(move || {
+                                sftp_source.fetch(
+                                    &extension,
+                                    current.as_ref(),
+                                    &sftp_blocklist,
+                                    transfer_reporter(progress_sender, download_progress),
+                                )
+                            })
+                            .await;
+                            if let Ok(Err(error)) = progress_task.await {
+                                eprintln!("{}", error);
+                            }
+                            match result {
+                                Ok(Ok(Some(_))) => {
+                                    additional_devices.push(Device::from_directory(&cache_dir))
+                                }
+                                Ok(Ok(None)) => {}
+                                Ok(Err(error)) => eprintln!("{}", error),
+                                Err(error) => eprintln!("{}", error),
+                            }
+                        }
+
+                        // fetch the update bundle of a deployment assigned by a hawkBit server
+                        #[cfg(feature = "hawkbit")]
+                        if let Some(hawkbit_client) = hawkbit_client {
+                            let extension = device_search.bundle_search.extension().to_string();
+                            let cache_dir = hawkbit_client.cache_dir().to_path_buf();
+                            let (progress_sender, progress_receiver) = channel(8);
+                            let progress_task = spawn(watch_transfer_progress(
+                                server_connection.clone(),
+                                progress_lock.clone(),
+                                transaction,
+                                progress_receiver,
+                            ));
+                            let result = spawn_blocking(move || {
+                                hawkbit_client.fetch(
+                                    &extension,
+                                    transfer_reporter(progress_sender, download_progress),
+                                )
+                            })
+                            .await;
+                            if let Ok(Err(error)) = progress_task.await {
+                                eprintln!("{}", error);
+                            }
+                            match result {
+                                Ok(Ok(Some(_))) => {
+                                    additional_devices.push(Device::from_directory(&cache_dir))
+                                }
+                                Ok(Ok(None)) => {}
+                                Ok(Err(error)) => eprintln!("{}", error),
+                                Err(error) => eprintln!("{}", error),
+                            }
+                        }
+
+                        let mut devices = devices_lock.write_arc().await;
+                        // setup the devices (mounts)
+                        *devices = mount_and_search_devices(
+                            SearchContext {
+                                connection: &connection,
+                                bus_connection: &server_connection,
+                                health: &health,
+                                transaction,
+                                span: &span,
+                            },
+                            &device_search,
+                            additional_devices,
+                            device.as_deref(),
+                        )
+                        .await
+                        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                        // expose the devices on D-Bus
+                        if let Err(error) = export_devices(&server_connection, &devices).await {
+                            eprintln!("{}", error);
+                        }
+
+                        state_sender
+                            .send(State::Mounted(updated, iteration))
+                            .await
+                            .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+
+                        // signal an implausible system time, as it leads to failing bundle validation
+                        if let Err(Error::ImplausibleClock(time, floor)) =
+                            check_clock(now(), clock_floor)
+                        {
+                            eprintln!("Warning: {}", Error::ImplausibleClock(time, floor));
+                            Caterpillar::clock_implausible(
+                                &SignalContext::from_parts(
+                                    server_connection.clone(),
+                                    ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+                                ),
+                                time,
+                                floor,
+                            )
+                            .await?;
+                        }
+
+                        let mut updates = updates_lock.write_arc().await;
+                        let rauc_info = rauc_info_lock.read_arc().await;
+                        span.set_attribute("caterpillar.compatible", rauc_info.compatible());
+                        span.set_attribute("caterpillar.version", rauc_info.version_string());
+                        state_sender
+                            .send(State::Searching(updated, iteration))
+                            .await
+                            .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                        // search for a compatible update bundle
+                        *updates = get_update_bundles(
+                            SearchContext {
+                                connection: &connection,
+                                bus_connection: &server_connection,
+                                health: &health,
+                                transaction,
+                                span: &span,
+                            },
+                            &rauc_info,
+                            &devices,
+                            &bundle_filter,
+                        )
+                        .await
+                        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                        drop(rauc_info);
+                        health.write_arc().await.last_scan = Some(now());
+                        // expose the update bundles on D-Bus
+                        if let Err(error) = export_updates(&server_connection, &updates).await {
+                            eprintln!("{}", error);
+                        }
+                        match updates.first() {
+                            Some(bundle) => {
+                                println!(
+                                    "Found {}update {}",
+                                    if bundle.is_override() {
+                                        "override "
+                                    } else {
+                                        " "
+                                    },
+                                    bundle.path()
+                                );
+                                state_sender
+                                    .send(State::UpdateFound(updated, iteration))
+                                    .await
+                                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+                            }
+                            None => state_sender
+                                .send(State::NoUpdateFound(updated, iteration))
+                                .await
+                                .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?,
+                        }
+                        Ok::<(), zbus::fdo::Error>(())
+                    },
+                ));
+                *self.search_task.write().await = Some(task);
+                Ok(transaction)
+            }
+            _ => Err(zbus::fdo::Error::AccessDenied(format!(
+                "Already in state {}",
+                state
+            ))),
+        }
+    }
+
+    /// Unmount all devices and clear the lists of found devices and updates
+    ///
+    /// Returns the paths of all devices, that could not be unmounted.
+    async fn unmount_all(&self, server_connection: &Connection) -> Result<Vec<String>, Error> {
+        let connection = system_connection().await?;
+        let mut failed = vec![];
+        {
+            let mut devices = self.devices.write_arc().await;
+            for device in devices.iter_mut() {
+                if device.is_mounted() {
+                    if let Err(error) = unmount_device(
+                        &connection,
+                        server_connection,
+                        device,
+                        self.config().kill_blocking_processes(),
+                    )
+                    .await
+                    {
+                        eprintln!("{}", error);
+                        failed.push(device.device_path());
+                    }
+                }
+            }
+            *devices = vec![];
+        }
+        self.updates.write_arc().await.clear();
+        if let Err(error) = export_devices(server_connection, &[]).await {
+            eprintln!("{}", error);
+        }
+        if let Err(error) = export_updates(server_connection, &[]).await {
+            eprintln!("{}", error);
+        }
+        Ok(failed)
+    }
+
+    /// Prepare for exiting the application (e.g. when receiving SIGTERM)
+    ///
+    /// A pending decision on a found update is skipped by transitioning through [`State::Unmounting`], so that all
+    /// devices are unmounted, before the application exits.
+    /// Devices are left mounted, while RAUC carries out an operation (e.g. installs an update bundle), as RAUC may still
+    /// read from them.
+    pub async fn shutdown(&self, server_connection: &Connection) -> Result<(), Error> {
+        match rauc_operation(&system_connection().await?).await {
+            Ok(operation) if operation != "idle" => {
+                eprintln!("RAUC is {}. Not unmounting devices.", operation);
+                return Ok(());
+            }
+            Ok(_) => {}
+            Err(error) => eprintln!("{}", error),
+        }
+        match self.state_handle.read_state().await {
+            State::UpdateFound(updated, iteration) => {
+                self.state_handle
+                    .sender_clone()
+                    .await?
+                    .send(State::Unmounting(updated, iteration, false))
+                    .await?;
+                // wait for the state machine to unmount all devices and return to idle state
+                let started = Instant::now();
+                while !matches!(
+                    self.state_handle.read_state().await,
+                    State::Idle(_, _) | State::Done(_, _)
+                ) && started.elapsed() < SHUTDOWN_TIMEOUT
+                {
+                    sleep(Duration::from_millis(100)).await;
+                }
+            }
+            _ => {}
+        }
+
+        // unmount devices left mounted by an interrupted search
+        let failed = self.unmount_all(server_connection).await?;
+        if !failed.is_empty() {
+            eprintln!("Unable to unmount {}", failed.join(", "));
+        }
+        Ok(())
+    }
+}
+
+#[dbus_interface(name = "de.sleepmap.Caterpillar1")]
+impl Caterpillar {
+    /// Trigger the search for an update
+    ///
+    /// It is advised to subscribe to the `UpdateFound` signal before calling this method.
+    /// Requests during an ongoing search, while a found update is pending installation or shortly after a search has
+    /// been started (see the `search_debounce` configuration option) are coalesced and do not start another search, so
+    /// that several uncoordinated clients receive the same result.
+    /// Returns the ID of the transaction (t), which is included in all signals related to the search.
+    pub async fn search_for_update(
+        &self,
+        #[zbus(connection)] server_connection: &Connection,
+        #[zbus(header)] header: MessageHeader<'_>,
+    ) -> zbus::fdo::Result<u64> {
+        self.authorize(server_connection, &header).await?;
+        println!("Search for update...");
+        self.search(server_connection, None).await
+    }
+
+    /// Trigger the search for an update on a single block device (e.g. "/dev/sdb1")
+    ///
+    /// This method is meant to be called when a block device is added (e.g. from a udev rule or a systemd device unit).
+    /// Only the block device is mounted and searched for updates, which are broadcast using the `UpdateFound` signal.
+    /// The block device is ignored, if it does not match the configured filters or belongs to the running system.
+    /// Requests are coalesced in the same way as those of `SearchForUpdate`.
+    /// Returns the ID of the transaction (t).
+    /// Available since version 10 of the D-Bus API (see the `ApiVersion` property).
+    async fn trigger_device_added(
+        &self,
+        #[zbus(connection)] server_connection: &Connection,
+        #[zbus(header)] header: MessageHeader<'_>,
+        device: String,
+    ) -> zbus::fdo::Result<u64> {
+        self.authorize(server_connection, &header).await?;
+        println!("Search for update on device {}...", device);
+        self.search(server_connection, Some(device)).await
+    }
+
+    /// Trigger the installation of an update
+    ///
+    /// The parameters to this method provide information on whether to update (b) and whether to reboot afterwards (b).
+    /// Returns the ID of the transaction (t), which is included in all signals related to the installation.
+    async fn install_update(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: MessageHeader<'_>,
+        update: bool,
+        reboot: bool,
+    ) -> zbus::fdo::Result<u64> {
+        self.authorize(connection, &header).await?;
+        self.install_update_at(0, update, reboot).await
+    }
+
+    /// Re-query RAUC for the status of its slots
+    ///
+    /// Information on RAUC and its slots is cached on start and refreshed after each installation.
+    /// This method should be called, if the status of the slots has been changed otherwise (e.g. using
+    /// `rauc status mark-active`).
+    /// Available since version 17 of the D-Bus API (see the `ApiVersion` property).
+    async fn refresh_slot_status(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: MessageHeader<'_>,
+    ) -> zbus::fdo::Result<()> {
+        self.authorize(connection, &header).await?;
+        refresh_rauc_info(&system_connection().await?, &self.rauc_info)
+            .await
+            .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))
+    }
+
+    /// Return the internal state of Caterpillar in a structured form
+    ///
+    /// The state consists of the name of the state (s) (see the `State` property),
+    /// whether the system has been successfully updated (b),
+    /// the iteration the program is currently in (u)
+    /// and whether the system is marked for reboot (b).
+    async fn get_state(&self) -> StateInfo {
+        StateInfo::from(&self.state_handle.read_state().await)
+    }
+
+    /// Return the effective configuration of Caterpillar
+    ///
+    /// The configuration is provided as dict of option names and values (a{ss}), which merges the built-in defaults,
+    /// the configuration files and environment variables.
+    /// Strings are provided as is, while all other values are provided in their JSON representation.
+    /// Secrets (e.g. `hawkbit_token`) are redacted.
+    /// Available since version 20 of the D-Bus API (see the `ApiVersion` property).
+    async fn get_config(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: MessageHeader<'_>,
+    ) -> zbus::fdo::Result<HashMap<String, String>> {
+        self.authorize(connection, &header).await?;
+        Ok(self
+            .config()
+            .effective()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    /// Return the status of Caterpillar for health probes
+    ///
+    /// The status consists of the uptime in seconds (t),
+    /// the name of the state (s) (see the `State` property),
+    /// the time of the last completed search for updates in seconds since the epoch (x) (0 if none has completed yet)
+    /// and the last error that occurred (s) (see the `LastError` property).
+    /// Calling this method does not trigger any scanning.
+    async fn get_status(&self) -> StatusInfo {
+        StatusInfo::new(
+            &*self.health.read().await,
+            &self.state_handle.read_state().await,
+        )
+    }
+
+    /// The internal state of Caterpillar
+    ///
+    /// One of
+    /// - "done"
+    /// - "idle"
+    /// - "init"
+    /// - "mounted"
+    /// - "mounting"
+    /// - "noupdatefound"
+    /// - "searching"
+    /// - "skip"
+    /// - "unmounted"
+    /// - "unmounting"
+    /// - "updated"
+    /// - "updatefound"
+    /// - "updating"
+    #[dbus_interface(property)]
+    async fn state(&self) -> String {
+        format!("{}", self.state_handle.read_state().await)
+    }
+
+    /// Whether the system has been successfully updated
+    #[dbus_interface(property)]
+    async fn updated(&self) -> bool {
+        self.state_handle.read_state().await.get_updated()
+    }
+
+    /// Inhibit automatic searches and installations of updates for maintenance
+    ///
+    /// The parameter to this method provides the reason for the inhibition (s).
+    /// Explicit requests to search for or install updates are not affected.
+    /// Available since version 7 of the D-Bus API (see the `ApiVersion` property).
+    async fn inhibit(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: MessageHeader<'_>,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        reason: &str,
+    ) -> zbus::fdo::Result<()> {
+        self.authorize(connection, &header).await?;
+        println!("Inhibiting automatic updates: {}", reason);
+        *self.inhibited.write().await = Some(reason.to_string());
+        self.inhibited_changed(&ctxt).await?;
+        Ok(())
+    }
+
+    /// Remove the inhibition of automatic searches and installations of updates
+    ///
+    /// Available since version 7 of the D-Bus API (see the `ApiVersion` property).
+    async fn uninhibit(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: MessageHeader<'_>,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> zbus::fdo::Result<()> {
+        self.authorize(connection, &header).await?;
+        if self.inhibited.write().await.take().is_some() {
+            println!("No longer inhibiting automatic updates");
+            self.inhibited_changed(&ctxt).await?;
+        }
+        Ok(())
+    }
+
+    /// Cancel a pending reboot
+    ///
+    /// A reboot is pending, while an update is installed (if a reboot has been requested or `auto_reboot` applies) and
+    /// during the grace period before rebooting (see the `reboot_delay` configuration option).
+    /// Afterwards the system is not rebooted and caterpillar returns to idle state once the update has been installed.
+    /// Available since version 15 of the D-Bus API (see the `ApiVersion` property).
+    async fn cancel_reboot(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: MessageHeader<'_>,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> zbus::fdo::Result<()> {
+        self.authorize(connection, &header).await?;
+        match self.state_handle.read_state().await {
+            State::Updating(_, _)
+            | State::Updated(_, _, _)
+            | State::Unmounting(true, _, _)
+            | State::Unmounted(true, _, _) => {
+                println!("Cancelling the reboot");
+                self.reboot_cancelled.store(true, Ordering::SeqCst);
+                self.marked_for_reboot_changed(&ctxt).await?;
+                Caterpillar::reboot_cancelled(&ctxt, self.transaction.load(Ordering::SeqCst))
+                    .await?;
+                Ok(())
+            }
+            state => Err(zbus::fdo::Error::AccessDenied(format!(
+                "No reboot is pending in state {}",
+                state
+            ))),
+        }
+    }
+
+    /// Unmount all devices mounted by caterpillar and clear the lists of found devices and updates
+    ///
+    /// This is meant as an escape hatch for operators, if devices are left mounted (e.g. after a search).
+    /// If an update has been found, the decision on it is skipped and caterpillar returns to idle state.
+    /// Cleaning up is possible in any state (e.g. if an installation has been interrupted), but not while RAUC carries out
+    /// an operation (e.g. installs an update bundle).
+    /// Available since version 11 of the D-Bus API (see the `ApiVersion` property).
+    async fn cleanup(
+        &self,
+        #[zbus(connection)] server_connection: &Connection,
+        #[zbus(header)] header: MessageHeader<'_>,
+    ) -> zbus::fdo::Result<()> {
+        self.authorize(server_connection, &header).await?;
+        let operation = rauc_operation(&system_connection().await?)
+            .await
+            .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+        if operation != "idle" {
+            return Err(zbus::fdo::Error::AccessDenied(format!(
+                "{}",
+                Error::WrongState(format!("RAUC is {}", operation))
+            )));
+        }
+        let state = self.state_handle.read_state().await;
+
+        println!("Cleaning up...");
+        let failed = self
+            .unmount_all(server_connection)
+            .await
+            .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+
+        // return to idle state, instead of waiting for a decision on an update, that is no longer available, or for an
+        // installation, that RAUC is no longer carrying out
+        let next_state = match state {
+            State::UpdateFound(updated, iteration) => Some(State::Skip(updated, iteration)),
+            State::Updating(updated, iteration) => {
+                Some(State::Unmounting(updated, iteration, false))
+            }
+            _ => None,
+        };
+        if let Some(next_state) = next_state {
+            self.state_handle
+                .sender_clone()
+                .await
+                .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?
+                .send(next_state)
+                .await
+                .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(zbus::fdo::Error::Failed(format!(
+                "Unable to unmount {}",
+                failed.join(", ")
+            )))
+        }
+    }
+
+    /// Whether automatic searches and installations of updates are inhibited for maintenance
+    ///
+    /// Available since version 7 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(property)]
+    async fn inhibited(&self) -> bool {
+        self.inhibited.read().await.is_some()
+    }
+
+    /// The version of the D-Bus API
+    ///
+    /// The version is incremented whenever the D-Bus interfaces change.
+    #[dbus_interface(property)]
+    async fn api_version(&self) -> u32 {
+        API_VERSION
+    }
+
+    /// The version of the daemon
+    #[dbus_interface(property)]
+    async fn daemon_version(&self) -> String {
+        env!("CARGO_PKG_VERSION").to_string()
+    }
+
+    /// The devices found during the last search for updates
+    ///
+    /// Each device consists of the path of its exported object (o), the path of the device (s), its mountpoint (s)
+    /// and the number of potential update bundles found on it (u).
+    /// The list is empty, while devices are mounted.
+    /// Available since version 13 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(property)]
+    async fn devices_found(&self) -> Vec<(OwnedObjectPath, String, String, u32)> {
+        match self.devices.try_read() {
+            Some(devices) => devices_found(&devices),
+            None => vec![],
+        }
+    }
+
+    /// Whether the system has been marked for reboot when requesting the installation of an update
+    #[dbus_interface(property)]
+    async fn marked_for_reboot(&self) -> bool {
+        self.state_handle.read_state().await.get_marked_for_reboot()
+            && !self.reboot_cancelled.load(Ordering::SeqCst)
+    }
+
+    /// The outcome of the most recent attempt to install an update bundle
+    ///
+    /// The outcome consists of the version of the update bundle (s), whether it has been installed successfully (b), the
+    /// time of the attempt in seconds since the epoch (x) and the error that occurred (s) (empty on success).
+    /// The outcome is persisted in the state directory, so that it is available after a restart or reboot.
+    /// All members are empty (or 0), if no installation has been attempted yet.
+    #[dbus_interface(property)]
+    async fn last_update_result(&self) -> (String, bool, i64, String) {
+        self.last_update_result
+            .read()
+            .await
+            .as_ref()
+            .map(UpdateResult::to_tuple)
+            .unwrap_or_default()
+    }
+
+    /// The last error that occurred (e.g. a mount failure, an incompatible update bundle or a failed installation)
+    ///
+    /// The property is empty, if no error has occurred yet.
+    /// Available since version 9 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(property)]
+    async fn last_error(&self) -> String {
+        self.health
+            .read()
+            .await
+            .last_error
+            .clone()
+            .unwrap_or_default()
+    }
+
+    /// The progress of the whole pipeline (downloading, mounting, searching, verifying, staging and installing)
+    ///
+    /// The progress consists of the percentage (i) and a message describing the current phase (s).
+    /// While update bundles are downloaded or staged, the message contains the bytes transferred and the total bytes.
+    /// Available since version 8 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(property)]
+    async fn progress(&self) -> (i32, String) {
+        self.progress.read().await.clone()
+    }
+
+    /// The ID of the current transaction (0, if no transaction has been started yet)
+    ///
+    /// The property is changed together with the `Progress` property, so that the progress can be correlated with the
+    /// request, that started the transaction.
+    #[dbus_interface(property)]
+    async fn transaction(&self) -> u64 {
+        self.transaction.load(Ordering::SeqCst)
+    }
+
+    /// A signal, broadcasting information on found updates
+    ///
+    /// The update is returned in an array of length one.
+    /// The update information consists of the absolute filename (s),
+    /// the current version of the system (s),
+    /// the new version (s),
+    /// whether the update is an override (b),
+    /// the compatible of the update (s),
+    /// the size of the update in bytes (t),
+    /// the path of the device the update has been found on (s),
+    /// the mountpoint of that device (s)
+    /// and the slot classes targeted by the update (comma separated) (s)
+    #[dbus_interface(signal)]
+    async fn update_found(
+        ctxt: &SignalContext<'_>,
+        transaction: u64,
+        update: Vec<Update>,
+    ) -> zbus::Result<()>;
+
+    /// A signal, broadcasting that an update bundle has been installed successfully
+    ///
+    /// The signal provides the ID of the transaction (t), the absolute path (s) and the version (s) of the update bundle
+    /// and whether its casync images have been seeded from the active slot (b), so that only changed chunks have been fetched.
+    /// Seeding and the chunk store are configured in RAUC's system configuration.
+    #[dbus_interface(signal)]
+    async fn update_installed(
+        ctxt: &SignalContext<'_>,
+        transaction: u64,
+        bundle: &str,
+        version: &str,
+        seeded: bool,
+    ) -> zbus::Result<()>;
+
+    /// A signal, broadcasting that the boot of a newly installed slot has been confirmed
+    ///
+    /// The signal provides the name of the slot (s), that has been marked as good, and the version (s) it has been
+    /// installed with.
+    /// It is emitted by `caterpillar confirm-boot`.
+    /// Available since version 21 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(signal)]
+    async fn boot_confirmed(
+        ctxt: &SignalContext<'_>,
+        slot: &str,
+        version: &str,
+    ) -> zbus::Result<()>;
+
+    /// A signal, broadcasting that an error occurred
+    ///
+    /// The signal provides the ID of the transaction in which the error occurred (t) and the error message (s), which is
+    /// also exposed using the `LastError` property.
+    /// Available since version 9 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(signal)]
+    async fn error_occurred(
+        ctxt: &SignalContext<'_>,
+        transaction: u64,
+        error: &str,
+    ) -> zbus::Result<()>;
+
+    /// A signal, broadcasting that the system time is implausible
+    ///
+    /// The signal provides the current system time (x) and the earliest plausible time (x) in seconds since the epoch.
+    /// An implausible system time leads to failing validation of update bundles.
+    #[dbus_interface(signal)]
+    async fn clock_implausible(ctxt: &SignalContext<'_>, time: i64, floor: i64)
+        -> zbus::Result<()>;
+
+    /// A signal, broadcasting that an update bundle has been quarantined
+    ///
+    /// The signal provides the absolute path (s), the version (s) and the number of consecutive failed installations (u)
+    /// of the update bundle.
+    /// Quarantined update bundles are no longer offered.
+    #[dbus_interface(signal)]
+    async fn bundle_quarantined(
+        ctxt: &SignalContext<'_>,
+        bundle: &str,
+        version: &str,
+        failures: u32,
+    ) -> zbus::Result<()>;
+
+    /// A signal, broadcasting that a device has been mounted by caterpillar
+    ///
+    /// The signal provides the path of the device (s) and its mountpoint (s).
+    /// The device is busy until the DeviceUnmounted signal is emitted for it.
+    /// Available since version 14 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(signal)]
+    async fn device_mounted(
+        ctxt: &SignalContext<'_>,
+        device: &str,
+        mountpoint: &str,
+    ) -> zbus::Result<()>;
+
+    /// A signal, broadcasting the potential update bundles found on a device
+    ///
+    /// The signal provides the ID of the transaction of the search (t), the path of the device (s), the paths of the
+    /// update bundles (as) and the paths of the override update bundles (as) found on it.
+    /// It is emitted after each device has been searched and includes update bundles, that are rejected later on (e.g.
+    /// because they are not compatible), which allows for debugging why an update bundle has not been found.
+    /// Available since version 19 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(signal)]
+    async fn device_scanned(
+        ctxt: &SignalContext<'_>,
+        transaction: u64,
+        device: &str,
+        bundles: Vec<String>,
+        override_bundles: Vec<String>,
+    ) -> zbus::Result<()>;
+
+    /// A signal, broadcasting that a device has been unmounted by caterpillar
+    ///
+    /// The signal provides the path of the device (s) and its former mountpoint (s).
+    /// Available since version 14 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(signal)]
+    async fn device_unmounted(
+        ctxt: &SignalContext<'_>,
+        device: &str,
+        mountpoint: &str,
+    ) -> zbus::Result<()>;
+
+    /// A signal, broadcasting that the drive of a device has been released and can safely be removed
+    ///
+    /// The signal provides the path of the device (s) and the action used for releasing its drive (s).
+    /// The action is one of "eject" or "poweroff".
+    #[dbus_interface(signal)]
+    async fn device_released(
+        ctxt: &SignalContext<'_>,
+        device: &str,
+        action: &str,
+    ) -> zbus::Result<()>;
+
+    /// A signal, broadcasting that the installation of a found update is deferred until the installation window opens
+    ///
+    /// The signal provides the time until the installation window opens in seconds (t).
+    #[dbus_interface(signal)]
+    async fn install_deferred(ctxt: &SignalContext<'_>, delay: u64) -> zbus::Result<()>;
+
+    /// A signal, broadcasting that an operation did not finish in time
+    ///
+    /// The signal provides the name of the state (s) (see the `State` property) and the time spent in it in seconds (t).
+    /// Afterwards all devices are unmounted and caterpillar returns to idle state.
+    /// Installations never time out.
+    /// Available since version 12 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(signal)]
+    async fn operation_timed_out(
+        ctxt: &SignalContext<'_>,
+        state: &str,
+        duration: u64,
+    ) -> zbus::Result<()>;
+
+    /// A signal, broadcasting that the system is going to reboot after a grace period
+    ///
+    /// The signal provides the grace period in seconds (t).
+    /// Available since version 6 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(signal)]
+    async fn reboot_pending(ctxt: &SignalContext<'_>, delay: u64) -> zbus::Result<()>;
+
+    /// A signal, broadcasting the remaining time of the grace period before rebooting
+    ///
+    /// The signal provides the remaining time in seconds (t) and is emitted once per second after the RebootPending
+    /// signal, until the system is rebooted or the reboot is cancelled (see the `CancelReboot` method).
+    /// Available since version 16 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(signal)]
+    async fn reboot_countdown(ctxt: &SignalContext<'_>, remaining: u64) -> zbus::Result<()>;
+
+    /// A signal, confirming that a pending reboot has been cancelled
+    ///
+    /// The signal provides the ID of the transaction of the installed update (t).
+    #[dbus_interface(signal)]
+    async fn reboot_cancelled(ctxt: &SignalContext<'_>, transaction: u64) -> zbus::Result<()>;
+
+    /// A signal, broadcasting that the operation of RAUC has changed
+    ///
+    /// The signal provides the operation of RAUC (s) (e.g. "idle" or "installing").
+    /// It is also emitted, if RAUC is used by others (e.g. another agent installing an update bundle).
+    /// Available since version 18 of the D-Bus API (see the `ApiVersion` property).
+    #[dbus_interface(signal)]
+    async fn rauc_operation_changed(ctxt: &SignalContext<'_>, operation: &str) -> zbus::Result<()>;
+}
+
+/// Unmount a Device and broadcast it using the DeviceUnmounted signal
+///
+/// Devices, that have not been mounted by caterpillar, are left mounted and are not signaled.
+async fn unmount_device(
+    connection: &Connection,
+    bus_connection: &Connection,
+    device: &mut Device,
+    kill_blocking_processes: bool,
+) -> Result<(), Error> {
+    let unmountable = device.is_unmountable();
+    let mountpoint = device
+        .mountpoint()
+        .map(|mountpoint| mountpoint.display().to_string())
+        .unwrap_or_default();
+    device
+        .unmount_filesystem(connection, kill_blocking_processes)
+        .await?;
+    if unmountable {
+        Caterpillar::device_unmounted(
+            &SignalContext::from_parts(
+                bus_connection.to_owned(),
+                ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+            ),
+            &device.device_path(),
+            &mountpoint,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Export each Device as DeviceObject below `/de/sleepmap/Caterpillar/devices/`
+///
+/// All previously exported DeviceObjects are removed first.
+async fn export_devices(connection: &Connection, devices: &[Device]) -> Result<(), Error> {
+    let object_server = connection.object_server();
+
+    let mut index = 0;
+    while object_server
+        .remove::<DeviceObject, _>(format!("/de/sleepmap/Caterpillar/devices/{}", index))
+        .await
+        .is_ok()
+    {
+        index += 1;
+    }
+
+    for (index, device) in devices.iter().enumerate() {
+        object_server
+            .at(
+                format!("/de/sleepmap/Caterpillar/devices/{}", index),
+                DeviceObject::from(device),
+            )
+            .await?;
+    }
+    devices_found_changed(connection, devices).await
+}
+
+/// Return the information on Devices as provided by the DevicesFound property
+fn devices_found(devices: &[Device]) -> Vec<(OwnedObjectPath, String, String, u32)> {
+    devices
+        .iter()
+        .enumerate()
+        .map(|(index, device)| {
+            (
+                ObjectPath::from_string_unchecked(format!(
+                    "/de/sleepmap/Caterpillar/devices/{}",
+                    index
+                ))
+                .into(),
+                device.device_path(),
+                device
+                    .mountpoint()
+                    .map(|mountpoint| mountpoint.display().to_string())
+                    .unwrap_or_default(),
+                u32::try_from(
+                    device.bundles().unwrap_or_default().len()
+                        + device.override_bundles().unwrap_or_default().len(),
+                )
+                .unwrap_or(u32::MAX),
+            )
+        })
+        .collect()
+}
+
+/// Emit the PropertiesChanged signal for the DevicesFound property
+async fn devices_found_changed(connection: &Connection, devices: &[Device]) -> Result<(), Error> {
+    let mut array = Array::new(<(OwnedObjectPath, String, String, u32)>::signature());
+    for device in devices_found(devices) {
+        array
+            .append(Value::Structure(device.into()))
+            .map_err(zbus::Error::Variant)?;
+    }
+    let devices_found = Value::Array(array);
+    Properties::properties_changed(
+        &SignalContext::from_parts(
+            connection.to_owned(),
+            ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+        ),
+        InterfaceName::from_static_str_unchecked(INTERFACE),
+        &HashMap::from([("DevicesFound", &devices_found)]),
+        &[],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Export each UpdateBundle as UpdateObject below `/de/sleepmap/Caterpillar/updates/`
+///
+/// All previously exported UpdateObjects are removed first.
+async fn export_updates(connection: &Connection, updates: &[UpdateBundle]) -> Result<(), Error> {
+    let object_server = connection.object_server();
+
+    let mut index = 0;
+    while object_server
+        .remove::<UpdateObject, _>(format!("/de/sleepmap/Caterpillar/updates/{}", index))
+        .await
+        .is_ok()
+    {
+        index += 1;
+    }
+
+    for (index, bundle) in updates.iter().enumerate() {
+        object_server
+            .at(
+                format!("/de/sleepmap/Caterpillar/updates/{}", index),
+                UpdateObject::new(index, bundle),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Emit the PropertiesChanged signal for all properties, that changed between two States
+///
+/// The ID of the current `transaction` is included along with changes of the Progress property.
+async fn emit_properties_changed(
+    connection: &Connection,
+    transaction: u64,
+    previous: &State,
+    current: &State,
+) -> Result<(), Error> {
+    let state = Value::from(current.to_string());
+    let updated = Value::from(current.get_updated());
+    let marked_for_reboot = Value::from(current.get_marked_for_reboot());
+    let progress = Value::Structure(current.get_progress().into());
+    let transaction = Value::from(transaction);
+
+    let mut changed: HashMap<&str, &Value<'_>> = HashMap::new();
+    if previous.to_string() != current.to_string() {
+        changed.insert("State", &state);
+    }
+    if previous.get_updated() != current.get_updated() {
+        changed.insert("Updated", &updated);
+    }
+    if previous.get_marked_for_reboot() != current.get_marked_for_reboot() {
+        changed.insert("MarkedForReboot", &marked_for_reboot);
+    }
+    if previous.get_progress() != current.get_progress() {
+        changed.insert("Progress", &progress);
+        changed.insert("Transaction", &transaction);
+    }
+
+    if !changed.is_empty() {
+        Properties::properties_changed(
+            &SignalContext::from_parts(
+                connection.to_owned(),
+                ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+            ),
+            InterfaceName::from_static_str_unchecked(INTERFACE),
+            &changed,
+            &[],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Confirm the boot of the slot installed by the most recent installation
+///
+/// The slot booted from must have been installed with the version of the update bundle, as otherwise the bootloader
+/// fell back to the previous slot.
+/// The slot is marked as good in RAUC, the confirmation is persisted in the state directory and the BootConfirmed signal
+/// is emitted on the bus of `connection`.
+/// Nothing is done, if the most recent installation failed or has already been confirmed (e.g. on a regular boot).
+pub async fn confirm_boot(
+    connection: &Connection,
+    config: &CaterpillarConfig,
+) -> Result<(), Error> {
+    let path = config.state_dir().join(LAST_UPDATE_RESULT_FILE);
+    let mut update_result = match UpdateResult::read(&path) {
+        Some(update_result) if update_result.success() && !update_result.confirmed() => {
+            update_result
+        }
+        _ => {
+            println!("No installed update awaits the confirmation of its boot");
+            return Ok(());
+        }
+    };
+
+    let system_connection = system_connection().await?;
+    let rauc_info = RaucInfo::new(&system_connection).await?;
+    let slot = rauc_info
+        .booted_slot(update_result.version())
+        .ok_or_else(|| {
+            Error::BootNotConfirmed(format!(
+                "the system did not boot from the slot installed with version {} (boot slot: {})",
+                update_result.version(),
+                rauc_info.boot_slot()
+            ))
+        })?;
+    let message = mark_booted_good(&system_connection).await?;
+    println!("Marked slot {} as good: {}", slot.name(), message);
+
+    update_result.confirm();
+    update_result.write(&path)?;
+    Caterpillar::boot_confirmed(
+        &SignalContext::from_parts(
+            connection.to_owned(),
+            ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+        ),
+        slot.name(),
+        update_result.version(),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Persist the outcome of an attempt to install the update bundle with `version` and emit the PropertiesChanged signal
+/// for the LastUpdateResult property
+///
+/// Errors are only printed, as they must not change the outcome of the installation.
+/// Returns the UpdateResult.
+async fn record_update_result(
+    connection: &Connection,
+    last_update_result: &RwLock<Option<UpdateResult>>,
+    path: &Path,
+    version: &str,
+    result: &Result<(), String>,
+) -> UpdateResult {
+    let update_result = UpdateResult::new(version, result);
+    if let Err(error) = update_result.write(path) {
+        eprintln!(
+            "Unable to persist the result of the installation: {}",
+            error
+        );
+    }
+    let value = Value::Structure(update_result.to_tuple().into());
+    *last_update_result.write().await = Some(update_result.clone());
+    if let Err(error) = Properties::properties_changed(
+        &SignalContext::from_parts(
+            connection.to_owned(),
+            ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+        ),
+        InterfaceName::from_static_str_unchecked(INTERFACE),
+        &HashMap::from([("LastUpdateResult", &value)]),
+        &[],
+    )
+    .await
+    {
+        eprintln!("{}", error);
+    }
+    update_result
+}
+
+/// Append a result report to the block device, from which an update `bundle` has been installed
+///
+/// No report is written, if the update bundle has not been found on a block device or if its filesystem is read-only.
+/// Errors are only printed, as they must not change the outcome of the installation.
+fn report_result_on_device(
+    devices: &[Device],
+    bundle: &str,
+    old_version: Option<&str>,
+    update_result: &UpdateResult,
+) {
+    match devices
+        .iter()
+        .find(|device| device.is_block_device() && device.contains(Path::new(bundle)))
+        .and_then(Device::mountpoint)
+    {
+        Some(mountpoint) if is_read_only(mountpoint) => println!(
+            "Not writing a result report to {}, as it is located on a read-only filesystem.",
+            mountpoint.display()
+        ),
+        Some(mountpoint) => {
+            match append_result_report(mountpoint, &Machine::read(), old_version, update_result) {
+                Ok(path) => println!("Wrote result report {}", path.display()),
+                Err(error) => eprintln!("Unable to write a result report: {}", error),
+            }
+        }
+        None => {}
+    }
+}
+
+/// Set the Progress property to a value and emit the PropertiesChanged signal for it
+async fn set_progress(
+    connection: &Connection,
+    progress: &RwLock<(i32, String)>,
+    transaction: u64,
+    value: (i32, String),
+) -> Result<(), Error> {
+    *progress.write().await = value.clone();
+    let value = Value::Structure(value.into());
+    let transaction = Value::from(transaction);
+    Properties::properties_changed(
+        &SignalContext::from_parts(
+            connection.to_owned(),
+            ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+        ),
+        InterfaceName::from_static_str_unchecked(INTERFACE),
+        &HashMap::from([("Progress", &value), ("Transaction", &transaction)]),
+        &[],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Track the installation progress reported by RAUC in the Progress property until aborted
+async fn watch_install_progress(
+    connection: Connection,
+    bus_connection: Connection,
+    progress: Arc<RwLock<(i32, String)>>,
+    transaction: u64,
+) -> Result<(), Error> {
+    let installer_proxy = InstallerProxy::new(&connection).await?;
+    let mut changes = installer_proxy.receive_progress_changed().await;
+    while let Some(change) = changes.next().await {
+        if let Ok((percentage, message, _)) = change.get().await {
+            set_progress(
+                &bus_connection,
+                &progress,
+                transaction,
+                install_progress(percentage, &message),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Track the progress of a transfer (download or staging of an update bundle) in the Progress property
+///
+/// The progress is received from a `receiver` (see [`transfer_reporter`]) until all of its senders are dropped.
+async fn watch_transfer_progress(
+    connection: Connection,
+    progress: Arc<RwLock<(i32, String)>>,
+    transaction: u64,
+    mut receiver: Receiver<(i32, String)>,
+) -> Result<(), Error> {
+    while let Some(value) = receiver.recv().await {
+        set_progress(&connection, &progress, transaction, value).await?;
+    }
+    Ok(())
+}
+
+/// Return a callback for blocking transfers, that sends their progress (mapped using `map`) to a `sender`
+///
+/// The progress is only sent, if the percentage of the transfer changes and it is dropped, if the channel is full, so
+/// that the transfer is never blocked by D-Bus.
+fn transfer_reporter(
+    sender: Sender<(i32, String)>,
+    map: fn(u64, u64) -> (i32, String),
+) -> impl FnMut(u64, u64) {
+    let mut reported = None;
+    move |bytes, total| {
+        let percentage = if total == 0 { 100 } else { bytes * 100 / total };
+        if reported != Some(percentage) {
+            reported = Some(percentage);
+            _ = sender.try_send(map(bytes, total));
+        }
+    }
+}
+
+/// Search for updates whenever update bundles are written to the inbox directories watched by an InboxWatcher
+///
+/// The `connection` is the one on which the Caterpillar interface is served.
+/// No searches are started, while automatic searches and installations are inhibited.
+pub async fn watch_inbox_dirs(
+    connection: Connection,
+    watcher: Arc<InboxWatcher>,
+) -> Result<(), Error> {
+    loop {
+        let bundles = watcher.next_bundles().await?;
+        println!("Update bundles written to inbox directory: {:?}", bundles);
+        let caterpillar = connection
+            .object_server()
+            .interface::<_, Caterpillar>("/de/sleepmap/Caterpillar")
+            .await?;
+        let caterpillar = caterpillar.get().await;
+        if let Some(reason) = caterpillar.inhibited.read().await.as_ref() {
+            println!(
+                "Not searching for updates, as automatic updates are inhibited: {}",
+                reason
+            );
+            continue;
+        }
+        // searches are denied during an installation, which must not stop the watcher
+        if let Err(error) = caterpillar.search(&connection, None).await {
+            eprintln!("Not searching for updates: {}", error);
+        }
+    }
+}
+
+/// Create the optional HawkbitClient for the hawkBit server configured in a CaterpillarConfig
+///
+/// If no controller ID is configured, the machine ID is used.
+/// If no hawkBit server is configured or no controller ID can be determined, None is returned.
+#[cfg(feature = "hawkbit")]
+fn hawkbit_client(config: &CaterpillarConfig) -> Option<HawkbitClient> {
+    let url = config.hawkbit_url()?;
+    let controller_id = match config.hawkbit_controller_id() {
+        Some(controller_id) => controller_id.to_string(),
+        None => match Machine::read().machine_id() {
+            Some(machine_id) => machine_id.to_string(),
+            None => {
+                eprintln!(
+                    "Not using hawkBit server {}, as no controller ID is available",
+                    url
+                );
+                return None;
+            }
+        },
+    };
+
+    Some(HawkbitClient::new(
+        url,
+        config.hawkbit_tenant(),
+        &controller_id,
+        config.hawkbit_token(),
+        config.hawkbit_cache_dir(),
+    ))
+}
+
+/// Search for updates whenever a hawkBit server assigns a new deployment to the device
+///
+/// The `connection` is the one on which the Caterpillar interface is served.
+/// The server is polled in the interval it requests.
+/// No searches are started, while automatic searches and installations are inhibited.
+#[cfg(feature = "hawkbit")]
+pub async fn watch_hawkbit(connection: Connection, client: HawkbitClient) -> Result<(), Error> {
+    let mut previous = None;
+    loop {
+        let poll_client = client.clone();
+        let interval = match spawn_blocking(move || poll_client.poll())
+            .await
+            .map_err(|x| Error::Hawkbit(x.to_string()))?
+        {
+            Ok(poll) => {
+                if poll.deployment.is_some() && poll.deployment != previous {
+                    println!("hawkBit server assigned deployment {:?}", poll.deployment);
+                    let caterpillar = connection
+                        .object_server()
+                        .interface::<_, Caterpillar>("/de/sleepmap/Caterpillar")
+                        .await?;
+                    let caterpillar = caterpillar.get().await;
+                    if let Some(reason) = caterpillar.inhibited.read().await.as_ref() {
+                        println!(
+                            "Not searching for updates, as automatic updates are inhibited: {}",
+                            reason
+                        );
+                    } else {
+                        // the deployment is searched for again with the next poll, if the search is denied (e.g.
+                        // during an installation)
+                        match caterpillar.search(&connection, None).await {
+                            Ok(_) => previous = poll.deployment,
+                            Err(error) => eprintln!("Not searching for updates: {}", error),
                         }
-                        Ok(())
-                    });
-                } else {
-                    return Err(zbus::fdo::Error::Failed(format!(
-                        "{}",
-                        Error::NoUpdateBundle
-                    )));
+                    }
                 }
+                poll.sleep
             }
-            State::NoUpdateFound(updated, iteration) | State::UpdateFound(updated, iteration)
-                if !update =>
-            {
-                let state_sender = self
-                    .state_handle
-                    .sender_clone()
-                    .await
-                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
-                state_sender
-                    .send(State::Skip(updated, iteration))
-                    .await
-                    .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+            Err(error) => {
+                eprintln!("{}", error);
+                DEFAULT_POLLING_INTERVAL
             }
-            _ => {
-                if state.get_updated() {
-                    return Err(zbus::fdo::Error::Failed(format!(
-                        "{}",
-                        Error::WrongState(
-                            "System is updated already, waiting for reboot".to_string()
-                        )
-                    )));
-                } else {
-                    return Err(zbus::fdo::Error::Failed(format!(
-                        "{}",
-                        Error::WrongState(format!("{}", state))
-                    )));
+        };
+        sleep(interval).await;
+    }
+}
+
+/// Forward changes of the operation of RAUC using the RaucOperationChanged signal
+///
+/// If the RAUC daemon restarts (e.g. after a crash or an upgrade), the proxy is rebuilt and the cached RaucInfo is
+/// refreshed, so that no stale information is used.
+pub async fn watch_rauc_operation(connection: Connection) -> Result<(), Error> {
+    let rauc_connection = system_connection().await?;
+    let mut previous = None;
+    loop {
+        let installer_proxy = InstallerProxy::new(&rauc_connection).await?;
+        let mut owner_changed = receive_rauc_owner_changed(&installer_proxy).await?;
+        let mut changes = installer_proxy.receive_operation_changed().await;
+        let operation = installer_proxy.operation().await?;
+        // the operation may have changed while reconnecting
+        if previous
+            .as_ref()
+            .is_some_and(|previous| previous != &operation)
+        {
+            signal_rauc_operation(&connection, &operation).await?;
+        }
+        previous = Some(operation);
+
+        loop {
+            tokio::select! {
+                Some(change) = changes.next() => {
+                    if let Ok(operation) = change.get().await {
+                        // the current value is also yielded when subscribing
+                        if previous.as_ref() == Some(&operation) {
+                            continue;
+                        }
+                        signal_rauc_operation(&connection, &operation).await?;
+                        previous = Some(operation);
+                    }
                 }
+                Some(signal) = owner_changed.next() => {
+                    if signal.args().is_ok_and(|args| args.new_owner().is_some()) {
+                        println!("RAUC daemon restarted, reconnecting");
+                        break;
+                    }
+                    eprintln!("RAUC daemon stopped");
+                }
+                else => return Ok(()),
             }
         }
-        Ok(())
+
+        let caterpillar = connection
+            .object_server()
+            .interface::<_, Caterpillar>("/de/sleepmap/Caterpillar")
+            .await?;
+        if let Err(error) =
+            refresh_rauc_info(&rauc_connection, &caterpillar.get().await.rauc_info).await
+        {
+            eprintln!("{}", error);
+        }
     }
+}
 
-    /// The internal state of Caterpillar
-    ///
-    /// One of
-    /// - "done"
-    /// - "idle"
-    /// - "init"
-    /// - "mounted"
-    /// - "mounting"
-    /// - "noupdatefound"
-    /// - "searching"
-    /// - "skip"
-    /// - "unmounted"
-    /// - "unmounting"
-    /// - "updated"
-    /// - "updatefound"
-    /// - "updating"
-    #[dbus_interface(property)]
-    async fn state(&self) -> String {
-        format!("{}", self.state_handle.read_state().await)
+/// Broadcast a change of the operation of RAUC using the RaucOperationChanged signal
+async fn signal_rauc_operation(connection: &Connection, operation: &str) -> Result<(), Error> {
+    println!("RAUC operation changed: {}", operation);
+    Caterpillar::rauc_operation_changed(
+        &SignalContext::from_parts(
+            connection.to_owned(),
+            ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+        ),
+        operation,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Broadcast the selected update (if any) using the UpdateFound signal
+async fn signal_update_found(
+    connection: &Connection,
+    transaction: u64,
+    updates: &[UpdateBundle],
+    devices: &[Device],
+    rauc_info: &RaucInfo,
+) -> Result<(), Error> {
+    let bundle = match updates.first() {
+        Some(bundle) => bundle,
+        None => return Ok(()),
+    };
+    let device = devices
+        .iter()
+        .find(|device| device.contains(Path::new(&bundle.path())));
+
+    // signal that we have found an update
+    println!("Signal over D-Bus, that an update is found");
+    Caterpillar::update_found(
+        &SignalContext::from_parts(
+            connection.to_owned(),
+            ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+        ),
+        transaction,
+        vec![Update::from_bundle(
+            bundle,
+            rauc_info.version().unwrap_or(&Version::new(0, 0, 0)),
+            device,
+        )],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Request the non-interactive installation of the selected update (and optionally a reboot) from the application
+async fn request_install(connection: &Connection, reboot: bool) -> Result<(), Error> {
+    connection
+        .call_method(
+            Some(
+                BusName::try_from("de.sleepmap.Caterpillar")
+                    .map_err(|x| Error::Default(x.to_string()))?,
+            ),
+            ObjectPath::try_from("/de/sleepmap/Caterpillar")
+                .map_err(|x| Error::Default(x.to_string()))?,
+            Some(InterfaceName::try_from(INTERFACE).map_err(|x| Error::Default(x.to_string()))?),
+            "InstallUpdate",
+            &(true, reboot),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Leave the Updating State, if an installation is aborted or RAUC fails to install an update bundle
+///
+/// The devices are unmounted and the application returns to idle state, so that the installation can be retried
+/// with the next search for updates.
+async fn abort_install(state_sender: &Sender<State>, updated: bool, iteration: usize) {
+    if let Err(error) = state_sender
+        .send(State::Unmounting(updated, iteration, false))
+        .await
+    {
+        eprintln!("{}", error);
     }
+}
 
-    /// Whether the system has been successfully updated
-    #[dbus_interface(property)]
-    async fn updated(&self) -> bool {
-        self.state_handle.read_state().await.get_updated()
+/// Await a background task of a transaction and report its error (if any)
+async fn record_error<F>(
+    connection: Connection,
+    health: Arc<RwLock<Health>>,
+    transaction: u64,
+    task: F,
+) -> zbus::fdo::Result<()>
+where
+    F: Future<Output = zbus::fdo::Result<()>>,
+{
+    let result = task.await;
+    if let Err(error) = &result {
+        report_error(&connection, &health, transaction, &error.to_string()).await
     }
+    result
+}
 
-    /// Whether the system has been marked for reboot when requesting the installation of an update
-    #[dbus_interface(property)]
-    async fn marked_for_reboot(&self) -> bool {
-        self.state_handle.read_state().await.get_marked_for_reboot()
+/// Record an error in the Health of the application and broadcast it using the ErrorOccurred signal
+///
+/// Failing to broadcast the error is only logged, as the error is reported on a best effort basis.
+async fn report_error(
+    connection: &Connection,
+    health: &RwLock<Health>,
+    transaction: u64,
+    error: &str,
+) {
+    health.write().await.last_error = Some(error.to_string());
+    let signal_context = SignalContext::from_parts(
+        connection.to_owned(),
+        ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+    );
+    let last_error = Value::from(error);
+    if let Err(error) = Properties::properties_changed(
+        &signal_context,
+        InterfaceName::from_static_str_unchecked(INTERFACE),
+        &HashMap::from([("LastError", &last_error)]),
+        &[],
+    )
+    .await
+    {
+        eprintln!("{}", error);
+    }
+    if let Err(error) = Caterpillar::error_occurred(&signal_context, transaction, error).await {
+        eprintln!("{}", error);
     }
+}
 
-    /// A signal, broadcasting information on found updates
-    ///
-    /// The update is returned in an array of length one.
-    /// The update information consists of the absolute filename (s),
-    /// the current version of the system (s),
-    /// the new version (s)
-    /// and whether the update is an override (b)
-    #[dbus_interface(signal)]
-    async fn update_found(ctxt: &SignalContext<'_>, update: Vec<Update>) -> zbus::Result<()>;
+/// Connect to the system bus
+#[cfg(not(feature = "mock-backends"))]
+pub async fn system_connection() -> zbus::Result<Connection> {
+    Connection::system().await
 }
 
-/// Test connections to UdisksInfo, RaucInfo and ManagerProxy instances in a Result
-async fn test_connections(connection: &Connection) -> Result<(), Error> {
-    println!("Connecting to logind over dbus...");
-    if let Err(error) = ManagerProxy::new(connection).await {
-        return Err(Error::Dbus(error));
-    };
+/// Connect to the fake services of the mock backends instead of the system bus
+#[cfg(feature = "mock-backends")]
+pub async fn system_connection() -> zbus::Result<Connection> {
+    crate::mock::connection()
+}
+
+/// Test connections to UdisksInfo, RaucInfo and the RebootBackend and return the RaucInfo in a Result
+async fn test_connections(
+    connection: &Connection,
+    reboot_backend: &(dyn RebootBackend + Send + Sync),
+) -> Result<RaucInfo, Error> {
+    reboot_backend.check(connection).await?;
 
     println!("Connecting to Udisks2 over dbus...");
     match UdisksInfo::new(connection).await {
@@ -642,52 +3320,367 @@ async fn test_connections(connection: &Connection) -> Result<(), Error> {
             for slot in proxy.slots() {
                 println!("{}", slot);
             }
+            for repository in proxy.artifact_repositories() {
+                println!("{}", repository);
+            }
+            Ok(proxy)
         }
-        Err(error) => return Err(error),
+        Err(error) => Err(error),
     }
+}
 
+/// Re-query RAUC and replace the cached RaucInfo
+async fn refresh_rauc_info(
+    connection: &Connection,
+    rauc_info: &RwLock<RaucInfo>,
+) -> Result<(), Error> {
+    let current = RaucInfo::new(connection).await?;
+    println!("Refreshed RAUC slot info:");
+    for slot in current.slots() {
+        println!("{}", slot);
+    }
+    *rauc_info.write().await = current;
     Ok(())
 }
 
+/// The options for finding, mounting and searching devices for update bundles
+///
+/// A DeviceSearch is created from the configuration (see [`Caterpillar::device_search`]) once per search.
+#[derive(Clone, Debug)]
+pub struct DeviceSearch {
+    /// the regular expression, that the paths of block devices must match
+    device_regex: String,
+    /// the filter for the drives of block devices
+    drive_filter: DriveFilter,
+    /// the partition labels, of which block devices must have one (if any)
+    partition_labels: Vec<String>,
+    /// the partition types, of which block devices must have one (if any)
+    partition_types: Vec<String>,
+    /// the options used for mounting block devices
+    mount_options: String,
+    /// whether block devices already mounted by others are skipped
+    private_mounts: bool,
+    /// the options for searching update bundles on a mounted device
+    bundle_search: BundleSearch,
+}
+
 /// Return a list of matching and mounted Device instances that have been searched for UpdateBundles in a Result
+///
+/// Block devices are found, mounted and searched using the options of a DeviceSearch.
+/// Additionally, `additional_devices` (e.g. inbox directories, loop images or network shares) are mounted (if required) and searched.
+/// If a `device` is provided, it is the only block device, that is considered.
 async fn mount_and_search_devices(
-    connection: &Connection,
-    device_regex: &str,
-    bundle_extension: &str,
-    override_dir: &str,
+    context: SearchContext<'_>,
+    search: &DeviceSearch,
+    additional_devices: Vec<Device>,
+    device: Option<&str>,
 ) -> Result<Vec<Device>, Error> {
+    let SearchContext {
+        connection,
+        bus_connection,
+        health,
+        transaction,
+        span,
+    } = context;
+    let DeviceSearch {
+        device_regex,
+        drive_filter,
+        partition_labels,
+        partition_types,
+        mount_options,
+        private_mounts,
+        bundle_search,
+    } = search;
     println!("Searching for compatible block devices...");
     let mut devices = UdisksInfo::get_block_devices(connection, device_regex).await?;
+    // only consider a single block device, if requested
+    if let Some(device) = device {
+        devices.retain(|x| x.device_path() == device);
+        if devices.is_empty() {
+            println!("Block device {} does not match the device_regex", device);
+        }
+    }
+    let devices = UdisksInfo::filter_block_devices(connection, devices, drive_filter).await?;
+    let devices =
+        UdisksInfo::filter_partition_labels(connection, devices, partition_labels).await?;
+    // never search the disks of the running system
+    let mut devices = UdisksInfo::exclude_system_devices(connection, devices).await?;
+    devices.extend(additional_devices);
+
+    for device in devices.iter_mut() {
+        let mount_span = span.child("mount");
+        mount_span.set_attribute("caterpillar.device", device.device_path());
+        mount_span.set_attribute(
+            "caterpillar.device.source",
+            device.source_kind(connection).await,
+        );
+        // inbox directories are always mounted
+        let mounted = if device.is_mounted() {
+            println!("Searching in directory {}...", device.device_path());
+            Ok(())
+        } else {
+            match device
+                .mount_filesystem(connection, partition_types, mount_options, *private_mounts)
+                .await
+            {
+                // devices mounted by others are not signaled
+                Ok(mountpoint) if device.is_unmountable() => {
+                    if let Err(error) = Caterpillar::device_mounted(
+                        &SignalContext::from_parts(
+                            bus_connection.to_owned(),
+                            ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+                        ),
+                        &device.device_path(),
+                        &mountpoint,
+                    )
+                    .await
+                    {
+                        eprintln!("{}", error);
+                    }
+                    Ok(())
+                }
+                Ok(_) => Ok(()),
+                Err(error) => Err(error),
+            }
+        };
 
-    for device in &mut devices[..] {
-        match device.mount_filesystem(connection).await {
-            Ok(_path) => {
+        match mounted {
+            Ok(()) => {
                 // gather PathBufs of update bundles
-                if let Err(error) = device.find_bundles(bundle_extension).await {
+                if let Err(error) = device.find_bundles(bundle_search).await {
                     eprintln!("{}", error)
                 }
 
                 // gather PathBufs of override update bundles
-                if let Err(error) = device
-                    .find_override_bundles(bundle_extension, Path::new(&override_dir))
-                    .await
-                {
+                if let Err(error) = device.find_override_bundles(bundle_search).await {
                     eprintln!("{}", error)
                 }
+
+                // broadcast all potential update bundles (including those rejected later on)
+                let paths = |paths: Option<Vec<PathBuf>>| -> Vec<String> {
+                    paths
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect()
+                };
+                if let Err(error) = Caterpillar::device_scanned(
+                    &SignalContext::from_parts(
+                        bus_connection.to_owned(),
+                        ObjectPath::from_str_unchecked("/de/sleepmap/Caterpillar"),
+                    ),
+                    transaction,
+                    &device.device_path(),
+                    paths(device.bundles()),
+                    paths(device.override_bundles()),
+                )
+                .await
+                {
+                    eprintln!("{}", error);
+                }
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                mount_span.set_error(&error);
+                report_error(bus_connection, health, transaction, &error.to_string()).await;
             }
-            Err(error) => eprintln!("{}", error),
         }
     }
     Ok(devices)
 }
 
-/// Get an optional UpdateBundle to update to in a Result
-async fn get_update_bundle(
+/// Verify an UpdateBundle (if `verify` is true) and return whether it may be offered
+///
+/// A failed verification is reported using the ErrorOccurred signal.
+async fn verify_bundle(
+    connection: &Connection,
+    bus_connection: &Connection,
+    bundle: &UpdateBundle,
+    verify: bool,
+    health: &RwLock<Health>,
+    transaction: u64,
+) -> bool {
+    if !verify {
+        return true;
+    }
+    match bundle.verify(connection).await {
+        Ok(()) => true,
+        Err(error) => {
+            eprintln!("{}", error);
+            report_error(bus_connection, health, transaction, &error.to_string()).await;
+            false
+        }
+    }
+}
+
+/// Create an UpdateBundle in a child Span of `span`
+async fn bundle_info(
+    path: &Path,
+    is_override: bool,
+    connection: &Connection,
+    span: &Span,
+) -> Result<UpdateBundle, Error> {
+    let span = span.child("bundle_info");
+    span.set_attribute("caterpillar.bundle.path", path.display());
+    let bundle = UpdateBundle::new(path, is_override, connection).await;
+    match &bundle {
+        Ok(bundle) => {
+            span.set_attribute("caterpillar.bundle.version", bundle.version());
+            span.set_attribute("caterpillar.bundle.compatible", bundle.compatible());
+        }
+        Err(error) => span.set_error(error),
+    }
+    bundle
+}
+
+/// Return a child Span of the trace of the current update cycle or the root Span of a new trace, if there is none
+async fn trace_span(trace: &RwLock<Option<Span>>, name: &'static str) -> Span {
+    match trace.read().await.as_ref() {
+        Some(trace) => trace.child(name),
+        None => Span::root(name),
+    }
+}
+
+/// Collapse identical UpdateBundles (same file name, version and size) found on several Devices into one
+///
+/// Of identical UpdateBundles, the one on the fastest source (see [`SourceKind`]) is kept.
+async fn deduplicate_bundles(
+    connection: &Connection,
+    devices: &[Device],
+    bundles: Vec<UpdateBundle>,
+) -> Vec<UpdateBundle> {
+    let mut unique: Vec<(UpdateBundle, SourceKind)> = vec![];
+    for bundle in bundles {
+        let source_kind = match devices
+            .iter()
+            .find(|device| device.contains(Path::new(&bundle.path())))
+        {
+            Some(device) => device.source_kind(connection).await,
+            None => SourceKind::Directory,
+        };
+        match unique
+            .iter_mut()
+            .find(|(other, _)| other.is_identical(&bundle))
+        {
+            Some((other, other_source_kind)) => {
+                if source_kind < *other_source_kind {
+                    println!(
+                        "Preferring update bundle {} ({}) over identical {} ({})",
+                        bundle.path(),
+                        source_kind,
+                        other.path(),
+                        other_source_kind
+                    );
+                    *other = bundle;
+                    *other_source_kind = source_kind;
+                } else {
+                    println!(
+                        "Skipping update bundle {}, as it is identical to {}",
+                        bundle.path(),
+                        other.path()
+                    );
+                }
+            }
+            None => unique.push((bundle, source_kind)),
+        }
+    }
+    unique.into_iter().map(|(bundle, _)| bundle).collect()
+}
+
+/// Log, that an UpdateBundle is accepted, because its compatible is an alias of the compatible of the system
+fn log_compatible_alias(bundle: &UpdateBundle, rauc_info: &RaucInfo) {
+    if bundle.compatible() != rauc_info.compatible() {
+        println!(
+            "Update bundle {} is accepted, as its compatible ({}) is an alias of the system compatible ({})",
+            bundle.path(),
+            bundle.compatible(),
+            rauc_info.compatible()
+        );
+    }
+}
+
+/// Return whether an UpdateBundle is refused by an optional RollbackFloor
+async fn is_rollback(
     connection: &Connection,
+    bundle: &UpdateBundle,
+    rollback_floor: Option<&RollbackFloor>,
+) -> bool {
+    match rollback_floor {
+        Some(rollback_floor) => !rollback_floor.permits(bundle.build_timestamp(connection).await),
+        None => false,
+    }
+}
+
+/// Return whether the version of an UpdateBundle is below an optional minimum version
+///
+/// The version of UpdateBundles, that only update artifact repositories, is unrelated to the system version and is
+/// therefore never considered to be below the minimum version.
+fn is_below_min_version(
+    bundle: &UpdateBundle,
+    rauc_info: &RaucInfo,
+    min_version: Option<&Version>,
+) -> bool {
+    min_version.is_some_and(|x| bundle.version() < x) && !bundle.is_artifact_only(rauc_info)
+}
+
+/// The connections, health and trace of the transaction of a search for updates
+#[derive(Clone, Copy)]
+struct SearchContext<'a> {
+    /// the connection to the system bus
+    connection: &'a Connection,
+    /// the connection on which the application is served and signals are emitted
+    bus_connection: &'a Connection,
+    health: &'a RwLock<Health>,
+    transaction: u64,
+    span: &'a Span,
+}
+
+/// The criteria by which update bundles are selected as candidates for an update
+///
+/// A BundleFilter is created from the configuration and the persisted state (see [`Caterpillar::bundle_filter`]) once
+/// per search.
+#[derive(Clone, Debug)]
+pub struct BundleFilter {
+    /// the versions, that are never selected
+    blocklist: Blocklist,
+    /// the update bundles, that repeatedly failed to install and are never selected
+    quarantine: Quarantine,
+    /// the optional floor of build timestamps, below which update bundles are never selected
+    rollback_floor: Option<RollbackFloor>,
+    /// the optional version, below which update bundles are never selected
+    min_version: Option<Version>,
+    /// the aliases of the system compatible, that are accepted
+    compatible_aliases: HashMap<String, Vec<String>>,
+    /// the optional slot class of the recovery slot, that only override bundles may target
+    recovery_slot_class: Option<String>,
+    /// whether update bundles are verified before they are selected
+    verify_bundles: bool,
+    /// whether update bundles with the current system version are selected
+    reinstall_current_version: bool,
+    /// the order in which update bundles are offered
+    bundle_sort: BundleSort,
+}
+
+/// Get the list of UpdateBundles to update to in a Result
+///
+/// UpdateBundles are selected using the criteria of a BundleFilter.
+/// An override bundle is returned on its own, while all other compatible UpdateBundles are sorted by its `bundle_sort`
+/// (best first).
+async fn get_update_bundles(
+    context: SearchContext<'_>,
     rauc_info: &RaucInfo,
     devices: &[Device],
-) -> Result<Option<UpdateBundle>, Error> {
+    filter: &BundleFilter,
+) -> Result<Vec<UpdateBundle>, Error> {
+    let SearchContext {
+        connection,
+        bus_connection,
+        health,
+        transaction,
+        span,
+    } = context;
     println!("Search for compatible RAUC update bundle...");
+    let machine = Machine::read();
     // get paths to all override bundles
     let override_bundle_paths: Vec<PathBuf> = devices
         .iter()
@@ -698,18 +3691,63 @@ async fn get_update_bundle(
     match override_bundle_paths.len() {
         0 => {}
         // install override bundle
-        1 => match UpdateBundle::new(&override_bundle_paths[0], true, connection).await {
-            Ok(bundle) => {
-                if bundle.compatible() == rauc_info.compatible() {
-                    return Ok(Some(bundle));
-                } else {
+        1 => match bundle_info(&override_bundle_paths[0], true, connection, span).await {
+            Ok(mut bundle) => {
+                if filter.blocklist.contains(bundle.version()) {
                     eprintln!(
-                        "Update bundle {} is not compatible with this system!",
+                        "Update bundle {} is skipped, as its version ({}) is blocked!",
+                        bundle.path(),
+                        bundle.version()
+                    )
+                } else if filter.quarantine.contains(&bundle.path(), bundle.version()) {
+                    eprintln!(
+                        "Update bundle {} is skipped, as it is quarantined!",
+                        bundle.path()
+                    )
+                } else if is_rollback(connection, &bundle, filter.rollback_floor.as_ref()).await {
+                    eprintln!(
+                        "Update bundle {} is skipped, as its build timestamp is older than the installed one!",
+                        bundle.path()
+                    )
+                } else if is_below_min_version(&bundle, rauc_info, filter.min_version.as_ref()) {
+                    eprintln!(
+                        "Update bundle {} is skipped, as its version ({}) is below the minimum version!",
+                        bundle.path(),
+                        bundle.version()
+                    )
+                } else if !bundle.targets_machine(connection, &machine).await {
+                    eprintln!(
+                        "Update bundle {} is skipped, as it targets other machines!",
                         bundle.path()
                     )
+                } else if rauc_info.is_compatible(bundle.compatible(), &filter.compatible_aliases) {
+                    log_compatible_alias(&bundle, rauc_info);
+                    if verify_bundle(
+                        connection,
+                        bus_connection,
+                        &bundle,
+                        filter.verify_bundles,
+                        health,
+                        transaction,
+                    )
+                    .await
+                    {
+                        bundle.read_signer().await;
+                        return Ok(vec![bundle]);
+                    }
+                } else {
+                    let error = format!(
+                        "Update bundle {} is not compatible with this system!",
+                        bundle.path()
+                    );
+                    eprintln!("{}", error);
+                    report_error(bus_connection, health, transaction, &error).await;
                 }
             }
-            Err(error) => eprintln!("{}", error),
+            Err(error) => {
+                eprintln!("{}", error);
+                report_error(bus_connection, health, transaction, &error.to_string()).await;
+            }
         },
         // error if there is more than one override bundle
         _ => return Err(Error::TooManyOverrides(override_bundle_paths)),
@@ -725,40 +3763,115 @@ async fn get_update_bundle(
     if !bundle_paths.is_empty() {
         let mut bundles = vec![];
         for path in bundle_paths {
-            match UpdateBundle::new(&path, false, connection).await {
+            match bundle_info(&path, false, connection, span).await {
                 Ok(bundle) => {
                     println!("Found update bundle: {}", bundle.path());
-                    // add bundle only if it is compatible and if its version is higher than the current
-                    if bundle.compatible() == rauc_info.compatible() {
-                        if rauc_info.version().is_none()
-                            || rauc_info.version().is_some_and(|x| bundle.version().gt(x))
+                    // add bundle only if it is not blocked, compatible and if its version is higher than the current
+                    if filter.blocklist.contains(bundle.version()) {
+                        eprintln!(
+                            "Update bundle {} is skipped, as its version ({}) is blocked!",
+                            bundle.path(),
+                            bundle.version()
+                        );
+                    } else if filter.quarantine.contains(&bundle.path(), bundle.version()) {
+                        eprintln!(
+                            "Update bundle {} is skipped, as it is quarantined!",
+                            bundle.path()
+                        );
+                    } else if is_rollback(connection, &bundle, filter.rollback_floor.as_ref()).await
+                    {
+                        eprintln!(
+                            "Update bundle {} is skipped, as its build timestamp is older than the installed one!",
+                            bundle.path()
+                        );
+                    } else if is_below_min_version(&bundle, rauc_info, filter.min_version.as_ref())
+                    {
+                        eprintln!(
+                            "Update bundle {} is skipped, as its version ({}) is below the minimum version!",
+                            bundle.path(),
+                            bundle.version()
+                        );
+                    } else if !bundle.targets_machine(connection, &machine).await {
+                        eprintln!(
+                            "Update bundle {} is skipped, as it targets other machines!",
+                            bundle.path()
+                        );
+                    } else if filter
+                        .recovery_slot_class
+                        .as_deref()
+                        .is_some_and(|x| bundle.targets_slot_class(x))
+                    {
+                        eprintln!(
+                            "Update bundle {} is skipped, as it targets the recovery slot!",
+                            bundle.path()
+                        );
+                    } else if rauc_info
+                        .is_compatible(bundle.compatible(), &filter.compatible_aliases)
+                    {
+                        log_compatible_alias(&bundle, rauc_info);
+                        // the version of update bundles, that only update artifacts, is unrelated to the system version
+                        if bundle.is_artifact_only(rauc_info) {
+                            println!(
+                                "Adding update bundle {} for artifact repositories to list of compatible bundles...",
+                                bundle.path()
+                            );
+                            if verify_bundle(
+                                connection,
+                                bus_connection,
+                                &bundle,
+                                filter.verify_bundles,
+                                health,
+                                transaction,
+                            )
+                            .await
+                            {
+                                bundles.push(bundle);
+                            }
+                        } else if rauc_info
+                            .is_update(bundle.version(), filter.reinstall_current_version)
                         {
                             println!(
                                 "Adding update bundle {} to list of compatible bundles...",
                                 bundle.path()
                             );
-                            bundles.push(bundle);
+                            if verify_bundle(
+                                connection,
+                                bus_connection,
+                                &bundle,
+                                filter.verify_bundles,
+                                health,
+                                transaction,
+                            )
+                            .await
+                            {
+                                bundles.push(bundle);
+                            }
                         } else {
                             eprintln!("Update bundle {} is compatible, but its version ({}) is lower or equal to the current ({})", bundle.path(), bundle.version(), rauc_info.version_string());
                         }
                     } else {
-                        eprintln!("Update bundle {} is not compatible!", bundle.path());
+                        let error = format!("Update bundle {} is not compatible!", bundle.path());
+                        eprintln!("{}", error);
+                        report_error(bus_connection, health, transaction, &error).await;
                     }
                 }
-                Err(error) => eprintln!("{}", error),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    report_error(bus_connection, health, transaction, &error.to_string()).await;
+                }
             }
         }
 
-        if bundles.is_empty() {
-            Ok(None)
-        } else {
-            // sort by version
-            bundles.sort();
-            bundles.reverse();
+        let mut bundles = deduplicate_bundles(connection, devices, bundles).await;
+        if !bundles.is_empty() {
+            filter.bundle_sort.sort(&mut bundles);
             println!("Selecting update bundle {}...", bundles[0].path());
-            Ok(Some(bundles[0].clone()))
         }
+        for bundle in bundles.iter_mut() {
+            bundle.read_signer().await;
+        }
+        Ok(bundles)
     } else {
-        Ok(None)
+        Ok(vec![])
     }
 }
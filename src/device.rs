@@ -1,22 +1,42 @@
 // SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::fs::read_dir;
+use std::fs::read_link;
+use std::fs::read_to_string;
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::MetadataExt;
 use std::path::Path;
 use std::str::FromStr;
 
+use glob::Pattern;
+use nix::sys::signal::kill;
+use nix::sys::signal::Signal;
+use nix::sys::statvfs::statvfs;
+use nix::sys::statvfs::FsFlags;
+use nix::unistd::Pid;
 use once_cell::sync::OnceCell;
+use regex::Regex;
 use std::path::PathBuf;
 use strum::Display;
+use strum::EnumIter;
 use strum::EnumString;
+use strum::IntoEnumIterator;
+use tokio::time::sleep;
+use tokio::time::Duration;
 use zbus::Connection;
-use zvariant::{ObjectPath, Str, Value};
+use zvariant::{Fd, ObjectPath, Str, Value};
 
+use crate::config::CaterpillarConfig;
 use crate::error::Error;
 use crate::macros::regex_once;
 use crate::proxy::udisks::ManagerProxy;
-use crate::proxy::udisks::{BlockProxy, FilesystemProxy, PartitionProxy};
+use crate::proxy::udisks::{BlockProxy, DriveProxy, FilesystemProxy, LoopProxy, PartitionProxy};
+use crate::share::NetworkShare;
 
 /// An enum of compatible filesystems
 ///
@@ -24,7 +44,10 @@ use crate::proxy::udisks::{BlockProxy, FilesystemProxy, PartitionProxy};
 /// https://en.wikipedia.org/wiki/GUID_Partition_Table
 /// MBR based partition type identifiers are found in:
 /// https://en.wikipedia.org/wiki/Partition_type
-#[derive(Debug, Display, EnumString, PartialEq)]
+///
+/// NOTE: exFAT and NTFS use the Microsoft basic data partition type on GPT.
+/// NOTE: Optical media (e.g. DVDs) are not partitioned and are identified by their filesystem type (ISO9660 or UDF).
+#[derive(Debug, Display, EnumIter, EnumString, PartialEq)]
 #[non_exhaustive]
 enum Filesystem {
     #[strum(
@@ -45,10 +68,170 @@ enum Filesystem {
     MbrFat32,
     #[strum(ascii_case_insensitive, to_string = "0X0C")]
     MbrFat32Lba,
+    #[strum(ascii_case_insensitive, to_string = "0X07")]
+    MbrExfatNtfs,
     #[strum(ascii_case_insensitive, to_string = "0X17")]
     MbrNtfs,
     #[strum(ascii_case_insensitive, to_string = "0X83")]
     MbrLinuxFilesystem,
+    #[strum(ascii_case_insensitive, to_string = "iso9660")]
+    Iso9660,
+    #[strum(ascii_case_insensitive, to_string = "udf")]
+    Udf,
+}
+
+/// The mountpoints of the running system (root filesystem and boot partitions), whose drives are never searched
+pub const SYSTEM_MOUNTPOINTS: &[&str] = &["/", "/boot", "/boot/efi", "/efi"];
+
+/// Return the sources (e.g. "/dev/sda2") of the SYSTEM_MOUNTPOINTS found in the contents of a mountinfo file
+///
+/// Only sources that are device paths are returned (see proc(5) for the format of mountinfo).
+fn system_mount_sources(mountinfo: &str) -> Vec<String> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let (mount, filesystem) = line.split_once(" - ")?;
+            let mountpoint = mount.split_whitespace().nth(4)?;
+            let source = filesystem.split_whitespace().nth(1)?;
+            (SYSTEM_MOUNTPOINTS.contains(&mountpoint) && source.starts_with("/dev/"))
+                .then(|| source.to_string())
+        })
+        .collect()
+}
+
+/// The mountpoints (including the paths below them) of the running system, at which filesystems are never considered as
+/// source of update bundles
+pub const PROTECTED_MOUNTPOINTS: &[&str] = &[
+    "/boot", "/efi", "/etc", "/home", "/opt", "/root", "/srv", "/usr", "/var",
+];
+
+/// Return whether a mountpoint is the root directory or located at or below one of the PROTECTED_MOUNTPOINTS
+fn is_protected_mountpoint(mountpoint: &Path) -> bool {
+    mountpoint == Path::new("/")
+        || PROTECTED_MOUNTPOINTS
+            .iter()
+            .any(|protected| mountpoint.starts_with(protected))
+}
+
+/// Return whether the filesystem a path is located on is mounted read-only
+pub fn is_read_only(path: &Path) -> bool {
+    statvfs(path).is_ok_and(|stat| stat.flags().contains(FsFlags::ST_RDONLY))
+}
+
+/// Return the processes (PID and name), that use a location at or below a mountpoint
+///
+/// The working directory, root directory, executable and open files of each process below `proc` (e.g. "/proc") are
+/// considered. The own process is never returned.
+fn processes_using(proc: &Path, mountpoint: &Path) -> Vec<(i32, String)> {
+    let mut processes: Vec<(i32, String)> = read_dir(proc)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let pid: i32 = entry.file_name().to_str()?.parse().ok()?;
+            if pid == std::process::id() as i32 {
+                return None;
+            }
+            let path = entry.path();
+            let used = ["cwd", "root", "exe"]
+                .iter()
+                .map(|name| path.join(name))
+                .chain(
+                    read_dir(path.join("fd"))
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|fd| fd.ok())
+                        .map(|fd| fd.path()),
+                )
+                .filter_map(|link| read_link(link).ok())
+                .any(|target| target.starts_with(mountpoint));
+            used.then(|| {
+                (
+                    pid,
+                    read_to_string(path.join("comm"))
+                        .map(|comm| comm.trim().to_string())
+                        .unwrap_or_default(),
+                )
+            })
+        })
+        .collect();
+    processes.sort();
+    processes
+}
+
+/// Terminate processes (PID and name) using SIGTERM
+fn terminate_processes(processes: &[(i32, String)]) {
+    for (pid, name) in processes {
+        println!("Terminating process {} ({})...", name, pid);
+        if let Err(error) = kill(Pid::from_raw(*pid), Signal::SIGTERM) {
+            eprintln!("Failed terminating process {} ({}): {}", name, pid, error);
+        }
+    }
+}
+
+/// Return mount options with "rw" replaced by "ro"
+///
+/// Optical media are always mounted read-only.
+fn read_only_mount_options(mount_options: &str) -> String {
+    let mut options: Vec<&str> = mount_options
+        .split(',')
+        .filter(|option| !option.is_empty() && *option != "rw" && *option != "ro")
+        .collect();
+    options.insert(0, "ro");
+    options.join(",")
+}
+
+/// Return the mountpoints of a source (e.g. "/dev/sda1") found in the contents of a mountinfo file
+fn mount_targets(mountinfo: &str, source: &str) -> Vec<String> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let (mount, filesystem) = line.split_once(" - ")?;
+            let mountpoint = mount.split_whitespace().nth(4)?;
+            (filesystem.split_whitespace().nth(1)? == source).then(|| mountpoint.to_string())
+        })
+        .collect()
+}
+
+/// Return the identifiers of all known compatible partition types
+pub fn default_partition_types() -> Vec<String> {
+    Filesystem::iter().map(|x| x.to_string()).collect()
+}
+
+/// The action performed on the drive of a Device, after an update bundle from it has been installed
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq)]
+#[non_exhaustive]
+pub enum ReleaseAction {
+    /// Do not release the drive
+    #[strum(ascii_case_insensitive, to_string = "none")]
+    None,
+    /// Eject the drive
+    #[strum(ascii_case_insensitive, to_string = "eject")]
+    Eject,
+    /// Power off the drive
+    #[strum(ascii_case_insensitive, to_string = "poweroff")]
+    PowerOff,
+}
+
+/// The kind of source a Device provides update bundles from, ordered from the fastest to the slowest
+#[derive(Clone, Copy, Debug, Display, Eq, Ord, PartialEq, PartialOrd)]
+#[non_exhaustive]
+pub enum SourceKind {
+    /// A local directory (e.g. an inbox directory or a download cache)
+    #[strum(to_string = "directory")]
+    Directory,
+    /// An image file set up as loop device
+    #[strum(to_string = "image")]
+    Image,
+    /// A block device on an internal (non-removable) drive
+    #[strum(to_string = "internal")]
+    Internal,
+    /// A block device on a removable drive (e.g. connected via USB)
+    #[strum(to_string = "removable")]
+    Removable,
+    /// A network share
+    #[strum(to_string = "share")]
+    Share,
 }
 
 pub struct UdisksInfo {
@@ -88,6 +271,163 @@ impl UdisksInfo {
             })
             .collect())
     }
+
+    /// Filter block devices by the properties of the drive they belong to
+    ///
+    /// If the DriveFilter is empty, all devices are returned.
+    /// Devices without a drive or whose drive can not be queried are removed otherwise.
+    pub async fn filter_block_devices(
+        connection: &Connection,
+        devices: Vec<Device>,
+        filter: &DriveFilter,
+    ) -> Result<Vec<Device>, Error> {
+        if filter.is_empty() {
+            return Ok(devices);
+        }
+
+        let mut filtered = vec![];
+        for device in devices {
+            match device.drive_info(connection).await {
+                Ok(Some((vendor, model, serial))) if filter.matches(&vendor, &model, &serial) => {
+                    filtered.push(device)
+                }
+                Ok(Some((vendor, model, serial))) => println!(
+                    "Skipping block device {}, as its drive (vendor: '{}', model: '{}', serial: '{}') does not match.",
+                    device.device_path(),
+                    vendor,
+                    model,
+                    serial
+                ),
+                Ok(None) => println!(
+                    "Skipping block device {}, as it does not belong to a drive.",
+                    device.device_path()
+                ),
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+        Ok(filtered)
+    }
+
+    /// Filter a list of block devices by the labels of their GPT partitions
+    ///
+    /// If `labels` is empty, all block devices are returned.
+    pub async fn filter_partition_labels(
+        connection: &Connection,
+        devices: Vec<Device>,
+        labels: &[String],
+    ) -> Result<Vec<Device>, Error> {
+        if labels.is_empty() {
+            return Ok(devices);
+        }
+
+        let mut filtered = vec![];
+        for device in devices {
+            match device.partition_label(connection).await {
+                Ok(label) if labels.contains(&label) => filtered.push(device),
+                Ok(label) => println!(
+                    "Skipping block device {}, as its partition label '{}' does not match.",
+                    device.device_path(),
+                    label
+                ),
+                // block devices without a partition (e.g. whole disks) can not match
+                Err(_) => println!(
+                    "Skipping block device {}, as it is not a partition.",
+                    device.device_path()
+                ),
+            }
+        }
+        Ok(filtered)
+    }
+
+    /// Remove the block devices of the running system from a list of devices
+    ///
+    /// The block devices backing the system mountpoints (see [`SYSTEM_MOUNTPOINTS`]) are derived from
+    /// `/proc/self/mountinfo`. All block devices on the same drives are removed as well, so that e.g. data partitions of
+    /// the running system are never mounted and searched for update bundles.
+    pub async fn exclude_system_devices(
+        connection: &Connection,
+        devices: Vec<Device>,
+    ) -> Result<Vec<Device>, Error> {
+        let mountinfo = match read_to_string("/proc/self/mountinfo") {
+            Ok(mountinfo) => mountinfo,
+            Err(error) => {
+                eprintln!(
+                    "Unable to determine the devices of the running system: {}",
+                    error
+                );
+                return Ok(devices);
+            }
+        };
+
+        let manager_proxy = ManagerProxy::new(connection).await?;
+        let mut system_devices = HashSet::new();
+        let mut system_drives = HashSet::new();
+        for source in system_mount_sources(&mountinfo) {
+            let devspec = HashMap::from([("path", Value::Str(Str::from(source.as_str())))]);
+            let options = HashMap::from([("auth.no_user_interaction", Value::Bool(false))]);
+            let objectpaths = match manager_proxy.resolve_device(devspec, options).await {
+                Ok(objectpaths) => objectpaths,
+                Err(error) => {
+                    eprintln!("Unable to resolve device {}: {}", source, error);
+                    continue;
+                }
+            };
+            for objectpath in objectpaths {
+                let device = Device::new(objectpath.to_string())?;
+                if let Some(drive) = device.drive(connection).await? {
+                    system_drives.insert(drive);
+                }
+                system_devices.insert(objectpath.to_string());
+            }
+        }
+
+        let mut filtered = vec![];
+        for device in devices {
+            let is_system_device = system_devices.contains(device.objectpath())
+                || match device.drive(connection).await {
+                    Ok(Some(drive)) => system_drives.contains(&drive),
+                    Ok(None) => false,
+                    Err(error) => {
+                        eprintln!("{}", error);
+                        false
+                    }
+                };
+            if is_system_device {
+                println!(
+                    "Skipping block device {}, as it belongs to the running system.",
+                    device.device_path()
+                );
+            } else {
+                filtered.push(device);
+            }
+        }
+        Ok(filtered)
+    }
+
+    /// Get the size (in bytes) of a block device identified by its device path (e.g. "/dev/sda1")
+    pub async fn get_block_device_size(
+        connection: &Connection,
+        device_path: &str,
+    ) -> Result<u64, Error> {
+        let manager_proxy = ManagerProxy::new(connection).await?;
+        let devspec = HashMap::from([("path", Value::Str(Str::from(device_path)))]);
+        let options = HashMap::from([("auth.no_user_interaction", Value::Bool(false))]);
+        match manager_proxy
+            .resolve_device(devspec, options)
+            .await?
+            .first()
+        {
+            Some(objectpath) => {
+                let block_proxy = BlockProxy::builder(connection)
+                    .cache_properties(zbus::CacheProperties::No)
+                    .path(objectpath.as_str())?
+                    .build()
+                    .await?;
+                Ok(block_proxy.size().await?)
+            }
+            None => Err(Error::InvalidDevicePath(device_path.to_string())),
+        }
+    }
 }
 
 impl Display for UdisksInfo {
@@ -96,11 +436,17 @@ impl Display for UdisksInfo {
     }
 }
 
-/// A block device
+/// A block device (or local directory, image file or network share), that is searched for update bundles
 #[derive(Clone, Debug)]
 pub struct Device {
     objectpath: String,
     mountpoint: OnceCell<PathBuf>,
+    /// a network share and the location it is mounted to
+    share: Option<(NetworkShare, PathBuf)>,
+    /// an image file, that is set up as loop device
+    image: Option<PathBuf>,
+    /// the objectpath of the loop device set up for the image file
+    loop_device: OnceCell<String>,
     /// indication whether the mountpoint should be unmounted
     unmountable: OnceCell<bool>,
     /// locations of potential UpdateBundles found at the top-level of a mountpoint
@@ -120,6 +466,9 @@ impl Device {
             Ok(Device {
                 objectpath,
                 mountpoint: OnceCell::new(),
+                share: None,
+                image: None,
+                loop_device: OnceCell::new(),
                 unmountable: OnceCell::new(),
                 bundles: vec![],
                 override_bundles: vec![],
@@ -127,11 +476,60 @@ impl Device {
         }
     }
 
+    /// Create a new Device for a local directory
+    ///
+    /// The directory is used as mountpoint of the Device, which is never unmounted.
+    pub fn from_directory(path: &Path) -> Self {
+        Device {
+            objectpath: path.display().to_string(),
+            mountpoint: OnceCell::from(path.to_path_buf()),
+            share: None,
+            image: None,
+            loop_device: OnceCell::new(),
+            unmountable: OnceCell::from(false),
+            bundles: vec![],
+            override_bundles: vec![],
+        }
+    }
+
+    /// Create a new Device for a network share, that is mounted to `mountpoint`
+    pub fn from_share(share: NetworkShare, mountpoint: &Path) -> Self {
+        Device {
+            objectpath: share.url().to_string(),
+            mountpoint: OnceCell::new(),
+            share: Some((share, mountpoint.to_path_buf())),
+            image: None,
+            loop_device: OnceCell::new(),
+            unmountable: OnceCell::new(),
+            bundles: vec![],
+            override_bundles: vec![],
+        }
+    }
+
+    /// Create a new Device for an image file, that is set up as loop device when mounting it
+    pub fn from_image(path: &Path) -> Self {
+        Device {
+            objectpath: path.display().to_string(),
+            mountpoint: OnceCell::new(),
+            share: None,
+            image: Some(path.to_path_buf()),
+            loop_device: OnceCell::new(),
+            unmountable: OnceCell::new(),
+            bundles: vec![],
+            override_bundles: vec![],
+        }
+    }
+
     /// Return whether the Device is mounted
     pub fn is_mounted(&self) -> bool {
         self.mountpoint.get().is_some()
     }
 
+    /// Return whether the Device has been mounted by caterpillar (and is unmounted by it again)
+    pub fn is_unmountable(&self) -> bool {
+        self.unmountable.get().is_some_and(|x| *x)
+    }
+
     /// Return a reference to the objectpath
     pub fn objectpath(&self) -> &str {
         &self.objectpath
@@ -143,6 +541,27 @@ impl Device {
             .replace("/org/freedesktop/UDisks2/block_devices", "/dev")
     }
 
+    /// Return whether the Device is an optical drive (e.g. "/dev/sr0")
+    pub fn is_optical(&self) -> bool {
+        self.is_block_device()
+            && self
+                .objectpath
+                .rsplit('/')
+                .next()
+                .is_some_and(|name| regex_once!("^sr[0-9]+$").is_match(name))
+    }
+
+    /// Return the optional mountpoint of the Device
+    pub fn mountpoint(&self) -> Option<&Path> {
+        self.mountpoint.get().map(|mountpoint| mountpoint.as_path())
+    }
+
+    /// Return whether a path is located below the mountpoint of the Device
+    pub fn contains(&self, path: &Path) -> bool {
+        self.mountpoint()
+            .is_some_and(|mountpoint| path.starts_with(mountpoint))
+    }
+
     /// Return vec of PathBufs of potential bundle locations in an Option
     pub fn bundles(&self) -> Option<Vec<PathBuf>> {
         if !self.bundles.is_empty() {
@@ -161,8 +580,174 @@ impl Device {
         }
     }
 
+    /// Return the object path of the drive the Device belongs to in an Option
+    ///
+    /// If the Device does not belong to a drive, None is returned.
+    pub async fn drive(&self, connection: &Connection) -> Result<Option<String>, Error> {
+        let block_proxy = BlockProxy::builder(connection)
+            .cache_properties(zbus::CacheProperties::No)
+            .path(self.objectpath.as_str())?
+            .build()
+            .await?;
+        let drive = block_proxy.drive().await?;
+        if drive.as_str() == "/" {
+            Ok(None)
+        } else {
+            Ok(Some(drive.to_string()))
+        }
+    }
+
+    /// Return the label of the GPT partition of the Device
+    ///
+    /// If the partition does not have a label (e.g. on MBR partition tables), an empty string is returned.
+    pub async fn partition_label(&self, connection: &Connection) -> Result<String, Error> {
+        let partition_proxy = PartitionProxy::builder(connection)
+            .cache_properties(zbus::CacheProperties::No)
+            .path(self.objectpath.as_str())?
+            .build()
+            .await?;
+        Ok(partition_proxy.name().await?)
+    }
+
+    /// Return the vendor, model and serial of the drive the Device belongs to in an Option
+    ///
+    /// If the Device does not belong to a drive, None is returned.
+    pub async fn drive_info(
+        &self,
+        connection: &Connection,
+    ) -> Result<Option<(String, String, String)>, Error> {
+        let drive = match self.drive(connection).await? {
+            Some(drive) => drive,
+            None => return Ok(None),
+        };
+
+        let drive_proxy = DriveProxy::builder(connection)
+            .cache_properties(zbus::CacheProperties::No)
+            .path(drive.as_str())?
+            .build()
+            .await?;
+        Ok(Some((
+            drive_proxy.vendor().await?,
+            drive_proxy.model().await?,
+            drive_proxy.serial().await?,
+        )))
+    }
+
+    /// Return the SourceKind of the Device
+    ///
+    /// Block devices, whose drive can not be inspected, are considered removable.
+    pub async fn source_kind(&self, connection: &Connection) -> SourceKind {
+        if self.share.is_some() {
+            return SourceKind::Share;
+        }
+        if self.image.is_some() {
+            return SourceKind::Image;
+        }
+        if !self.is_block_device() {
+            return SourceKind::Directory;
+        }
+
+        let internal = async {
+            let drive = match self.drive(connection).await? {
+                Some(drive) => drive,
+                None => return Ok::<bool, Error>(false),
+            };
+            let drive_proxy = DriveProxy::builder(connection)
+                .cache_properties(zbus::CacheProperties::No)
+                .path(drive.as_str())?
+                .build()
+                .await?;
+            Ok(!drive_proxy.removable().await? && drive_proxy.connection_bus().await? != "usb")
+        };
+        match internal.await {
+            Ok(true) => SourceKind::Internal,
+            _ => SourceKind::Removable,
+        }
+    }
+
+    /// Return whether the Device is a block device provided by udisks
+    pub fn is_block_device(&self) -> bool {
+        self.share.is_none()
+            && self.image.is_none()
+            && ObjectPath::try_from(self.objectpath.as_str()).is_ok()
+    }
+
+    /// Return whether the Device is a local directory (e.g. an inbox directory)
+    pub fn is_directory(&self) -> bool {
+        self.share.is_none() && self.image.is_none() && !self.is_block_device()
+    }
+
+    /// Release the drive of an unmounted block device using a ReleaseAction
+    ///
+    /// Returns whether the drive has been released in a Result.
+    pub async fn release(
+        &self,
+        connection: &Connection,
+        action: ReleaseAction,
+    ) -> Result<bool, Error> {
+        if action == ReleaseAction::None || !self.is_block_device() || self.is_mounted() {
+            return Ok(false);
+        }
+
+        let block_proxy = BlockProxy::builder(connection)
+            .cache_properties(zbus::CacheProperties::No)
+            .path(self.objectpath.as_str())?
+            .build()
+            .await?;
+        let drive = block_proxy.drive().await?;
+        if drive.as_str() == "/" {
+            return Ok(false);
+        }
+
+        let drive_proxy = DriveProxy::builder(connection)
+            .cache_properties(zbus::CacheProperties::No)
+            .path(drive.as_str())?
+            .build()
+            .await?;
+        let options = HashMap::from([("auth.no_user_interaction", Value::Bool(true))]);
+        match action {
+            ReleaseAction::Eject => drive_proxy.eject(options).await?,
+            ReleaseAction::PowerOff => drive_proxy.power_off(options).await?,
+            ReleaseAction::None => {}
+        }
+        println!(
+            "Released drive of {} (action: {})",
+            self.device_path(),
+            action
+        );
+        Ok(true)
+    }
+
     /// Mount a filesystem identified by the ObjectPath of the Device
-    pub async fn mount_filesystem(&self, connection: &Connection) -> Result<String, Error> {
+    ///
+    /// Only filesystems on partitions of one of `partition_types` (GPT or MBR partition type identifiers) are mounted,
+    /// using `mount_options` (e.g. "rw,nosuid,nodev,noexec").
+    /// Network shares are mounted using mount(8) instead.
+    /// If `private` is true, filesystems already mounted by others (e.g. by a desktop file manager in a user-visible
+    /// location) are not used, as their content may be modified by users while it is verified.
+    pub async fn mount_filesystem(
+        &self,
+        connection: &Connection,
+        partition_types: &[String],
+        mount_options: &str,
+        private: bool,
+    ) -> Result<String, Error> {
+        if let Some((share, mountpoint)) = &self.share {
+            println!("Checking network share {}...", share.url());
+            share.mount(mountpoint)?;
+            self.unmountable.set(true).unwrap();
+            if self.mountpoint.set(mountpoint.to_path_buf()).is_err() {
+                return Err(Error::AlreadyMounted(
+                    self.device_path(),
+                    mountpoint.to_string_lossy().into(),
+                ));
+            }
+            return Ok(mountpoint.to_string_lossy().into());
+        }
+        if let Some(image) = &self.image {
+            return self.mount_image(connection, image, mount_options).await;
+        }
+
         println!("Checking block device {}...", &self.device_path());
         let objectpath = ObjectPath::try_from(self.objectpath.as_str()).unwrap();
         let block_proxy = BlockProxy::builder(connection)
@@ -176,20 +761,31 @@ impl Device {
             return Err(Error::IncompatibleBlockDevice(self.device_path()));
         }
 
-        let partition_proxy = PartitionProxy::builder(connection)
-            .cache_properties(zbus::CacheProperties::No)
-            .path(&objectpath)?
-            .build()
-            .await?;
-        let partition_number = partition_proxy.number().await?;
+        // optical media are not partitioned, so their filesystem type is used instead of a partition type
+        let partition_type = if self.is_optical() {
+            block_proxy.id_type().await?
+        } else {
+            let partition_proxy = PartitionProxy::builder(connection)
+                .cache_properties(zbus::CacheProperties::No)
+                .path(&objectpath)?
+                .build()
+                .await?;
+            let partition_number = partition_proxy.number().await?;
 
-        if partition_number == 0 {
-            return Err(Error::IsBaseDevice(self.device_path()));
-        }
+            if partition_number == 0 {
+                return Err(Error::IsBaseDevice(self.device_path()));
+            }
 
-        let partition_type = partition_proxy.type_().await?;
-        if let Ok(partition_type_ok) = Filesystem::from_str(&partition_type) {
-            println!("Compatible partition type {:?} found!", &partition_type_ok);
+            partition_proxy.type_().await?
+        };
+        if partition_types
+            .iter()
+            .any(|x| x.eq_ignore_ascii_case(&partition_type))
+        {
+            match Filesystem::from_str(&partition_type) {
+                Ok(filesystem) => println!("Compatible partition type {:?} found!", &filesystem),
+                Err(_) => println!("Compatible partition type {} found!", &partition_type),
+            }
 
             let filesystem_proxy = FilesystemProxy::builder(connection)
                 .cache_properties(zbus::CacheProperties::No)
@@ -197,9 +793,34 @@ impl Device {
                 .build()
                 .await?;
             let mountpoints = filesystem_proxy.mount_points().await?;
+
+            // never treat a filesystem mounted at a path of the running system as source of update bundles
+            let mut system_mountpoints: Vec<String> = mountpoints
+                .iter()
+                .filter_map(|x| x.split(|byte| *byte == 0).next())
+                .filter_map(|x| String::from_utf8(x.to_vec()).ok())
+                .collect();
+            if let Ok(mountinfo) = read_to_string("/proc/self/mountinfo") {
+                system_mountpoints.extend(mount_targets(&mountinfo, &self.device_path()));
+            }
+            if let Some(mountpoint) = system_mountpoints
+                .iter()
+                .find(|x| is_protected_mountpoint(Path::new(x)))
+            {
+                return Err(Error::ProtectedMountpoint(
+                    self.device_path(),
+                    mountpoint.to_string(),
+                ));
+            }
+
             let mountpoint = if mountpoints.is_empty() {
-                // NOTE: mount read-writable by default
-                let mount_options = HashMap::from([("options", Value::Str(Str::from("rw")))]);
+                let mount_options = if self.is_optical() {
+                    read_only_mount_options(mount_options)
+                } else {
+                    mount_options.to_string()
+                };
+                let mount_options =
+                    HashMap::from([("options", Value::Str(Str::from(mount_options)))]);
                 let mountpoint = filesystem_proxy.mount(mount_options).await?;
                 println!("Mounted {} to {}.", &self.device_path(), &mountpoint);
                 self.unmountable.set(true).unwrap();
@@ -208,6 +829,9 @@ impl Device {
                 // NOTE: removing NUL byte from response
                 let mountpoint =
                     String::from_utf8(mountpoints[0][0..mountpoints[0].len() - 1].to_owned())?;
+                if private {
+                    return Err(Error::PublicMount(self.device_path(), mountpoint));
+                }
                 println!(
                     "Found {} already mounted to {}",
                     &self.device_path(),
@@ -230,8 +854,92 @@ impl Device {
         }
     }
 
+    /// Set up a read-only loop device for an image file and mount its filesystem
+    ///
+    /// The filesystem is mounted read-only and the loop device is deleted again, if mounting fails.
+    async fn mount_image(
+        &self,
+        connection: &Connection,
+        image: &Path,
+        mount_options: &str,
+    ) -> Result<String, Error> {
+        println!("Setting up loop device for image {}...", image.display());
+        let file = File::open(image)?;
+        let manager_proxy = ManagerProxy::new(connection).await?;
+        let loop_device = manager_proxy
+            .loop_setup(
+                Fd::from(file.as_raw_fd()),
+                HashMap::from([("read-only", Value::Bool(true))]),
+            )
+            .await?;
+        drop(file);
+        self.loop_device.set(loop_device.to_string()).unwrap();
+
+        let mount = async {
+            let filesystem_proxy = FilesystemProxy::builder(connection)
+                .cache_properties(zbus::CacheProperties::No)
+                .path(loop_device.as_str())?
+                .build()
+                .await?;
+            let mount_options = HashMap::from([(
+                "options",
+                Value::Str(Str::from(read_only_mount_options(mount_options))),
+            )]);
+            Ok::<String, Error>(filesystem_proxy.mount(mount_options).await?)
+        };
+        match mount.await {
+            Ok(mountpoint) => {
+                println!(
+                    "Mounted {} (loop device {}) to {}.",
+                    image.display(),
+                    loop_device.as_str(),
+                    &mountpoint
+                );
+                self.unmountable.set(true).unwrap();
+                if self.mountpoint.set(PathBuf::from(&mountpoint)).is_err() {
+                    return Err(Error::AlreadyMounted(self.device_path(), mountpoint));
+                }
+                Ok(mountpoint)
+            }
+            Err(error) => {
+                self.delete_loop_device(connection).await;
+                Err(error)
+            }
+        }
+    }
+
+    /// Delete the loop device set up for an image file
+    ///
+    /// Errors are only printed, as the loop device does not prevent the image file from being used again.
+    async fn delete_loop_device(&self, connection: &Connection) {
+        let loop_device = match self.loop_device.get() {
+            Some(loop_device) => loop_device,
+            None => return,
+        };
+        let deleted = async {
+            LoopProxy::builder(connection)
+                .cache_properties(zbus::CacheProperties::No)
+                .path(loop_device.as_str())?
+                .build()
+                .await?
+                .delete(HashMap::new())
+                .await
+        };
+        if let Err(error) = deleted.await {
+            eprintln!("Failed deleting loop device {}: {}", loop_device, error);
+        }
+    }
+
     /// Unmount a filesystem identified by an ObjectPath.
-    pub async fn unmount_filesystem(&mut self, connection: &Connection) -> Result<(), Error> {
+    ///
+    /// If the filesystem is busy, the processes using it are logged and (if `kill_blocking_processes` is true)
+    /// terminated, before unmounting it again. As a last resort the filesystem is unmounted lazily.
+    /// The loop device of an image file is deleted after unmounting its filesystem.
+    pub async fn unmount_filesystem(
+        &mut self,
+        connection: &Connection,
+        kill_blocking_processes: bool,
+    ) -> Result<(), Error> {
         if self.unmountable.get().is_some_and(|x| x == &false) {
             println!(
                 "Skipping unmount of {} as it was not mounted via udisks.",
@@ -239,60 +947,127 @@ impl Device {
             );
             return Ok(());
         }
-        let objectpath = ObjectPath::try_from(self.objectpath.as_str()).unwrap();
+
+        if let Err(error) = self.try_unmount(connection, false).await {
+            eprintln!("Failed unmounting {}: {}", &self, error);
+            let processes = self
+                .mountpoint()
+                .map(|mountpoint| processes_using(Path::new("/proc"), mountpoint))
+                .unwrap_or_default();
+            if !processes.is_empty() {
+                eprintln!(
+                    "{} is used by {}",
+                    self.device_path(),
+                    processes
+                        .iter()
+                        .map(|(pid, name)| format!("{} ({})", name, pid))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                );
+            }
+
+            let mut unmounted = false;
+            if kill_blocking_processes && !processes.is_empty() {
+                terminate_processes(&processes);
+                sleep(Duration::from_secs(1)).await;
+                unmounted = self.try_unmount(connection, false).await.is_ok();
+            }
+            if !unmounted {
+                println!("Unmounting {} lazily...", self.device_path());
+                if let Err(error) = self.try_unmount(connection, true).await {
+                    eprintln!("Failed unmounting {}: {}", &self, error);
+                    let mountpoint: String = if let Some(mountpoint) = self.mountpoint.get() {
+                        mountpoint
+                            .clone()
+                            .into_os_string()
+                            .to_string_lossy()
+                            .into_owned()
+                    } else {
+                        "unknown".to_owned()
+                    };
+                    return Err(Error::UnmountFailed(mountpoint));
+                }
+            }
+        }
+
+        println!("Successfully unmounted {}!", &self.device_path());
+        self.mountpoint.take();
+        self.delete_loop_device(connection).await;
+        self.loop_device.take();
+        Ok(())
+    }
+
+    /// Unmount the filesystem of a block device (using udisks) or a network share (using umount(8)) once
+    ///
+    /// If `lazy` is true, the filesystem is detached right away and cleaned up once it is no longer busy.
+    async fn try_unmount(&self, connection: &Connection, lazy: bool) -> Result<(), Error> {
+        if let Some((share, mountpoint)) = &self.share {
+            return share.unmount(mountpoint, lazy);
+        }
+        let objectpath = ObjectPath::try_from(
+            self.loop_device
+                .get()
+                .map(String::as_str)
+                .unwrap_or(self.objectpath.as_str()),
+        )
+        .unwrap();
         let filesystem_proxy = FilesystemProxy::builder(connection)
             .cache_properties(zbus::CacheProperties::No)
             .path(objectpath)?
             .build()
             .await?;
-        if filesystem_proxy
-            .unmount(HashMap::from([("force", zvariant::Value::Bool(true))]))
-            .await
-            .is_ok()
-        {
-            println!("Successfully unmounted {}!", &self.device_path());
-            self.mountpoint.take();
-            Ok(())
+        // udisks unmounts lazily, if the "force" option is set
+        let options = if lazy {
+            HashMap::from([("force", zvariant::Value::Bool(true))])
         } else {
-            eprintln!("Failed unmounting {}!", &self);
-            let mountpoint: String = if let Some(mountpoint) = self.mountpoint.get() {
-                mountpoint
-                    .clone()
-                    .into_os_string()
-                    .to_string_lossy()
-                    .into_owned()
-            } else {
-                "unknown".to_owned()
-            };
-            Err(Error::UnmountFailed(mountpoint))
+            HashMap::new()
+        };
+        filesystem_proxy.unmount(options).await?;
+        Ok(())
+    }
+
+    /// Return the directory below the mountpoint, that is searched for update bundles
+    ///
+    /// The channel configured in `search` only applies to media (e.g. block devices, loop images or network shares), while
+    /// local directories are always searched directly.
+    fn search_root(&self, mountpoint: &Path, search: &BundleSearch) -> PathBuf {
+        if self.is_directory() {
+            mountpoint.to_path_buf()
+        } else {
+            search.root(mountpoint)
         }
     }
 
     /// Find RAUC update bundles below the mountpoint
-    pub async fn find_bundles(&mut self, bundle_extension: &str) -> Result<(), Error> {
+    ///
+    /// Directories are searched recursively up to the depth configured in `search`, excluding the override directory.
+    /// If a channel is configured in `search`, only its directory below the mountpoint is searched.
+    /// If a search directory is configured in `search`, only it is searched (below the mountpoint or channel directory).
+    pub async fn find_bundles(&mut self, search: &BundleSearch) -> Result<(), Error> {
         if let Some(mountpoint) = self.mountpoint.get() {
+            let root = self.search_root(mountpoint, search);
+            let dir = if self.is_directory() {
+                root.clone()
+            } else {
+                search.bundle_dir(&root)
+            };
+            if !dir.is_dir() {
+                eprintln!(
+                    "Skipping search in {:?} as it does not exist.",
+                    dir.as_os_str()
+                );
+                return Ok(());
+            }
             println!(
-                "Searching for RAUC update bundles with file extension '{}' in {:?}...",
-                bundle_extension,
-                mountpoint.as_os_str()
+                "Searching for RAUC update bundles with file extension '{}' in {:?} (depth: {})...",
+                search.extension(),
+                dir.as_os_str(),
+                search.depth(),
             );
-            for entry in (mountpoint.read_dir()?).flatten() {
-                let path = entry.path();
-                let bundle = match path.extension() {
-                    Some(extension) => match extension.to_str() {
-                        Some(extension) => extension == bundle_extension,
-                        None => false,
-                    },
-                    None => false,
-                };
-
-                if bundle {
-                    println!("Detected potential update bundle: {:?}", path);
-                }
-
-                if path.exists() && path.is_file() && bundle {
-                    self.bundles.push(path)
-                }
+            let override_dir = root.join(search.override_dir());
+            for path in search.find_files(&dir, search.depth(), Some(&override_dir))? {
+                println!("Detected potential update bundle: {:?}", path);
+                self.bundles.push(path)
             }
             Ok(())
         } else {
@@ -301,13 +1076,14 @@ impl Device {
     }
 
     /// Find RAUC update bundles below the override directory of the mountpoint
-    pub async fn find_override_bundles(
-        &mut self,
-        bundle_extension: &str,
-        override_dir: &Path,
-    ) -> Result<(), Error> {
+    ///
+    /// Only the top-level of the override directory is searched.
+    /// If a channel is configured in `search`, the override directory is located in the channel directory.
+    pub async fn find_override_bundles(&mut self, search: &BundleSearch) -> Result<(), Error> {
         if let Some(mountpoint) = self.mountpoint.get() {
-            let path = mountpoint.join(override_dir);
+            let path = self
+                .search_root(mountpoint, search)
+                .join(search.override_dir());
             if !path.exists() {
                 eprintln!(
                     "Skipping search in override location {:?} as it does not exist.",
@@ -328,17 +1104,8 @@ impl Device {
                 path.as_os_str()
             );
 
-            for entry in (path.read_dir()?).flatten() {
-                let path = entry.path();
-                let bundle = match path.extension() {
-                    Some(extension) => extension == bundle_extension,
-                    None => false,
-                };
-
-                if path.exists() && path.is_file() && bundle {
-                    self.override_bundles.push(path)
-                }
-            }
+            self.override_bundles
+                .append(&mut search.find_files(&path, 1, None)?);
             Ok(())
         } else {
             Err(Error::DeviceNotMounted(self.objectpath.to_string()))
@@ -346,6 +1113,241 @@ impl Device {
     }
 }
 
+/// Filters for the drive (e.g. USB stick) a block device belongs to
+///
+/// Each non-empty list restricts matching drives to those with a vendor or model in the list, or a serial starting with one of
+/// the prefixes in the list.
+#[derive(Clone, Debug, Default)]
+pub struct DriveFilter {
+    vendors: Vec<String>,
+    models: Vec<String>,
+    serial_prefixes: Vec<String>,
+}
+
+impl DriveFilter {
+    /// Create a new DriveFilter
+    pub fn new(vendors: Vec<String>, models: Vec<String>, serial_prefixes: Vec<String>) -> Self {
+        DriveFilter {
+            vendors,
+            models,
+            serial_prefixes,
+        }
+    }
+
+    /// Create a DriveFilter from the options of a CaterpillarConfig
+    pub fn from_config(config: &CaterpillarConfig) -> Self {
+        DriveFilter::new(
+            config.drive_vendors().to_vec(),
+            config.drive_models().to_vec(),
+            config.drive_serial_prefixes().to_vec(),
+        )
+    }
+
+    /// Return whether the DriveFilter does not restrict drives
+    pub fn is_empty(&self) -> bool {
+        self.vendors.is_empty() && self.models.is_empty() && self.serial_prefixes.is_empty()
+    }
+
+    /// Return whether a drive with vendor, model and serial matches the DriveFilter
+    pub fn matches(&self, vendor: &str, model: &str, serial: &str) -> bool {
+        (self.vendors.is_empty() || self.vendors.iter().any(|x| x.trim() == vendor.trim()))
+            && (self.models.is_empty() || self.models.iter().any(|x| x.trim() == model.trim()))
+            && (self.serial_prefixes.is_empty()
+                || self
+                    .serial_prefixes
+                    .iter()
+                    .any(|x| serial.trim().starts_with(x.trim())))
+    }
+}
+
+/// Options for the search of RAUC update bundles on a mounted Device
+#[derive(Clone, Debug)]
+pub struct BundleSearch {
+    /// the file extension of update bundles
+    extension: String,
+    /// the optional regular expression, that file names of update bundles must match
+    filename_regex: Option<Regex>,
+    /// the directory (relative to a mountpoint) in which override update bundles are searched for
+    override_dir: PathBuf,
+    /// the optional channel, whose directory (relative to a mountpoint) is searched instead of the mountpoint
+    channel: Option<String>,
+    /// the optional directory (relative to the mountpoint or channel directory) in which update bundles are searched for
+    search_dir: Option<PathBuf>,
+    /// glob patterns for file names, that are ignored
+    ignore_patterns: Vec<Pattern>,
+    /// the depth up to which directories are searched (1 only searches the top-level directory)
+    depth: usize,
+    /// the maximum number of directory entries, that are considered per search
+    max_files: usize,
+}
+
+/// The defaults match those of the configuration options
+impl Default for BundleSearch {
+    fn default() -> Self {
+        BundleSearch {
+            extension: "raucb".to_string(),
+            filename_regex: None,
+            override_dir: PathBuf::from("override"),
+            channel: None,
+            search_dir: None,
+            ignore_patterns: vec![],
+            depth: 1,
+            max_files: 10000,
+        }
+    }
+}
+
+impl BundleSearch {
+    /// Create a BundleSearch from the options of a CaterpillarConfig
+    pub fn from_config(config: &CaterpillarConfig) -> Self {
+        BundleSearch {
+            extension: config.bundle_extension().to_string(),
+            filename_regex: config.bundle_filename_regex().cloned(),
+            override_dir: config.override_dir().into(),
+            channel: config.channel().map(String::from),
+            search_dir: config.search_dir().map(Path::to_path_buf),
+            ignore_patterns: config.ignore_patterns().to_vec(),
+            depth: config.search_depth(),
+            max_files: config.search_max_files(),
+        }
+    }
+
+    /// Return the file extension of update bundles
+    pub fn extension(&self) -> &str {
+        &self.extension
+    }
+
+    /// Return the override directory
+    pub fn override_dir(&self) -> &Path {
+        &self.override_dir
+    }
+
+    /// Return the directory below a mountpoint, that is searched for update bundles
+    ///
+    /// If a channel is configured, its directory below the mountpoint is returned, otherwise the mountpoint itself.
+    pub fn root(&self, mountpoint: &Path) -> PathBuf {
+        match &self.channel {
+            Some(channel) => mountpoint.join(channel),
+            None => mountpoint.to_path_buf(),
+        }
+    }
+
+    /// Return the directory below a search root (see [`BundleSearch::root`]), that is searched for update bundles
+    ///
+    /// If a search directory is configured, it is returned relative to `root`, otherwise `root` itself.
+    /// The override directory is not affected by the search directory.
+    pub fn bundle_dir(&self, root: &Path) -> PathBuf {
+        match &self.search_dir {
+            Some(search_dir) => root.join(search_dir),
+            None => root.to_path_buf(),
+        }
+    }
+
+    /// Return the search depth
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Return whether a path is a potential update bundle
+    ///
+    /// A potential update bundle is a file with the configured extension (and a file name matching the optional
+    /// regular expression), that does not match any of the ignore patterns.
+    pub fn is_bundle(&self, path: &Path) -> bool {
+        let bundle = match path.extension() {
+            Some(extension) => match extension.to_str() {
+                Some(extension) => extension == self.extension,
+                None => false,
+            },
+            None => false,
+        } && self.matches_filename(path);
+
+        if bundle && is_ignored(path, &self.ignore_patterns) {
+            println!("Ignoring potential update bundle: {:?}", path);
+            return false;
+        }
+
+        bundle && path.exists() && path.is_file()
+    }
+
+    /// Return whether the file name of a path matches the optional regular expression
+    fn matches_filename(&self, path: &Path) -> bool {
+        match &self.filename_regex {
+            Some(regex) => path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| regex.is_match(name)),
+            None => true,
+        }
+    }
+
+    /// Find potential update bundles in a directory, up to a depth
+    ///
+    /// Directories are only visited once (protecting against symlink loops) and an optional `exclude` directory is
+    /// never visited.
+    /// The search stops after considering the configured maximum number of directory entries.
+    fn find_files(
+        &self,
+        dir: &Path,
+        depth: usize,
+        exclude: Option<&Path>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let mut files = vec![];
+        let mut visited = HashSet::new();
+        let mut dirs = vec![(dir.to_path_buf(), 1)];
+        let mut count = 0;
+
+        'search: while let Some((dir, level)) = dirs.pop() {
+            let metadata = dir.metadata()?;
+            if !visited.insert((metadata.dev(), metadata.ino())) {
+                eprintln!("Skipping {:?} as it has been visited already.", dir);
+                continue;
+            }
+
+            for entry in (dir.read_dir()?).flatten() {
+                count += 1;
+                if count > self.max_files {
+                    eprintln!(
+                        "Stopping search in {:?} after considering {} files.",
+                        dir, self.max_files
+                    );
+                    break 'search;
+                }
+
+                let path = entry.path();
+                if path.is_dir() {
+                    if level < depth && exclude.map_or(true, |exclude| path != exclude) {
+                        dirs.push((path, level + 1));
+                    }
+                } else if self.is_bundle(&path) {
+                    files.push(path);
+                }
+            }
+        }
+
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// Compile a list of glob patterns
+pub fn compile_patterns(patterns: &[String]) -> Result<Vec<Pattern>, Error> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern)
+                .map_err(|error| Error::InvalidPattern(pattern.to_string(), error.to_string()))
+        })
+        .collect()
+}
+
+/// Return whether the file name of a path matches any of a list of glob patterns
+fn is_ignored(path: &Path, ignore_patterns: &[Pattern]) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => ignore_patterns.iter().any(|pattern| pattern.matches(name)),
+        None => false,
+    }
+}
+
 impl Display for Device {
     fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
         write!(
@@ -368,6 +1370,12 @@ mod test {
     use super::*;
     use dbus_launch::{BusType, Daemon, Launcher};
     use rstest::{fixture, rstest};
+    use std::fs::{create_dir_all, File};
+    use std::os::unix::fs::symlink;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use temp_dir::TempDir;
+    use testdir::testdir;
     use testresult::TestResult;
     use zbus::{dbus_interface, ConnectionBuilder};
 
@@ -394,6 +1402,127 @@ mod test {
         }
     }
 
+    /// A fake `org.freedesktop.UDisks2.Block`
+    struct FakeBlock {
+        id_usage: String,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.UDisks2.Block")]
+    impl FakeBlock {
+        #[dbus_interface(property, name = "IdUsage")]
+        fn id_usage(&self) -> String {
+            self.id_usage.clone()
+        }
+    }
+
+    /// A fake `org.freedesktop.UDisks2.Partition`
+    struct FakePartition {
+        number: u32,
+        partition_type: String,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.UDisks2.Partition")]
+    impl FakePartition {
+        #[dbus_interface(property, name = "Number")]
+        fn number(&self) -> u32 {
+            self.number
+        }
+
+        #[dbus_interface(property, name = "Type")]
+        fn type_(&self) -> String {
+            self.partition_type.clone()
+        }
+    }
+
+    /// A fake `org.freedesktop.UDisks2.Filesystem`, that is "mounted" to a directory without touching it
+    struct FakeFilesystem {
+        mountpoint: PathBuf,
+        mounted: Arc<AtomicBool>,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.UDisks2.Filesystem")]
+    impl FakeFilesystem {
+        #[dbus_interface(name = "Mount")]
+        fn mount(
+            &self,
+            _options: std::collections::HashMap<&str, zbus::zvariant::Value<'_>>,
+        ) -> zbus::fdo::Result<String> {
+            if self.mounted.swap(true, Ordering::SeqCst) {
+                return Err(zbus::fdo::Error::Failed("already mounted".to_string()));
+            }
+            Ok(self.mountpoint.to_string_lossy().to_string())
+        }
+
+        #[dbus_interface(name = "Unmount")]
+        fn unmount(
+            &self,
+            _options: std::collections::HashMap<&str, zbus::zvariant::Value<'_>>,
+        ) -> zbus::fdo::Result<()> {
+            if !self.mounted.swap(false, Ordering::SeqCst) {
+                return Err(zbus::fdo::Error::Failed("not mounted".to_string()));
+            }
+            Ok(())
+        }
+
+        #[dbus_interface(property, name = "MountPoints")]
+        fn mount_points(&self) -> Vec<Vec<u8>> {
+            if self.mounted.load(Ordering::SeqCst) {
+                let mut mountpoint = self.mountpoint.to_string_lossy().as_bytes().to_vec();
+                mountpoint.push(0);
+                vec![mountpoint]
+            } else {
+                vec![]
+            }
+        }
+    }
+
+    /// The objectpath of the fake block device
+    const FAKE_BLOCK_DEVICE: &str = "/org/freedesktop/UDisks2/block_devices/sdz9";
+
+    /// Serve a fake partition with a filesystem on a bus and return the connection serving it
+    ///
+    /// The returned AtomicBool tracks whether the filesystem is mounted (to `mountpoint`).
+    async fn serve_block_device(
+        daemon: &Daemon,
+        id_usage: &str,
+        number: u32,
+        mountpoint: &Path,
+        mounted: bool,
+    ) -> (Connection, Arc<AtomicBool>) {
+        let mounted = Arc::new(AtomicBool::new(mounted));
+        let connection = ConnectionBuilder::address(daemon.address())
+            .unwrap()
+            .name("org.freedesktop.UDisks2")
+            .unwrap()
+            .serve_at(
+                FAKE_BLOCK_DEVICE,
+                FakeBlock {
+                    id_usage: id_usage.to_string(),
+                },
+            )
+            .unwrap()
+            .serve_at(
+                FAKE_BLOCK_DEVICE,
+                FakePartition {
+                    number,
+                    partition_type: "0x0c".to_string(),
+                },
+            )
+            .unwrap()
+            .serve_at(
+                FAKE_BLOCK_DEVICE,
+                FakeFilesystem {
+                    mountpoint: mountpoint.to_path_buf(),
+                    mounted: mounted.clone(),
+                },
+            )
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        (connection, mounted)
+    }
+
     /// Create a dbus system bus and return it in a Result
     #[fixture]
     fn dbus_daemon() -> Daemon {
@@ -418,6 +1547,412 @@ mod test {
         (connection, dbus_daemon)
     }
 
+    #[rstest]
+    #[case("/run/media/root/foo/update.raucb", &["*-debug.raucb", "*.tmp.raucb"], false)]
+    #[case("/run/media/root/foo/update-debug.raucb", &["*-debug.raucb", "*.tmp.raucb"], true)]
+    #[case("/run/media/root/foo/update.tmp.raucb", &["*-debug.raucb", "*.tmp.raucb"], true)]
+    #[case("/run/media/root/foo/update.raucb", &[], false)]
+    fn test_is_ignored(#[case] path: &str, #[case] patterns: &[&str], #[case] result: bool) {
+        let patterns = compile_patterns(
+            &patterns
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect::<Vec<String>>(),
+        )
+        .unwrap();
+        assert_eq!(is_ignored(Path::new(path), &patterns), result);
+    }
+
+    #[rstest]
+    #[case(1, vec!["update.raucb"])]
+    #[case(2, vec!["update.raucb", "updates/update.raucb"])]
+    #[case(3, vec!["update.raucb", "updates/2024/update.raucb", "updates/update.raucb"])]
+    fn test_bundlesearch_find_files(#[case] depth: usize, #[case] files: Vec<&str>) -> TestResult {
+        let dir = testdir!();
+        for path in [
+            "update.raucb",
+            "update.txt",
+            "override/override.raucb",
+            "updates/update.raucb",
+            "updates/2024/update.raucb",
+        ] {
+            create_dir_all(dir.join(path).parent().unwrap())?;
+            File::create(dir.join(path))?;
+        }
+        // create a symlink loop
+        symlink(&dir, dir.join("updates").join("loop"))?;
+
+        let search = BundleSearch {
+            depth,
+            ..Default::default()
+        };
+        assert_eq!(
+            search.find_files(&dir, depth, Some(&dir.join("override")))?,
+            files
+                .iter()
+                .map(|file| dir.join(file))
+                .collect::<Vec<PathBuf>>()
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(DriveFilter::default(), true)]
+    #[case(DriveFilter::new(vec!["Company".to_string()], vec![], vec![]), true)]
+    #[case(DriveFilter::new(vec!["Other".to_string()], vec![], vec![]), false)]
+    #[case(DriveFilter::new(vec![], vec!["Other".to_string(), "Update Stick".to_string()], vec![]), true)]
+    #[case(DriveFilter::new(vec!["Company".to_string()], vec![], vec!["ABC".to_string()]), true)]
+    #[case(DriveFilter::new(vec!["Company".to_string()], vec![], vec!["XYZ".to_string()]), false)]
+    fn test_drive_filter_matches(#[case] filter: DriveFilter, #[case] matches: bool) {
+        assert_eq!(
+            filter.matches("Company ", "Update Stick", "ABC123"),
+            matches
+        );
+    }
+
+    #[rstest]
+    fn test_default_partition_types() {
+        let partition_types = default_partition_types();
+        assert!(partition_types.contains(&"0X07".to_string()));
+        assert!(partition_types.contains(&"EBD0A0A2-B9E5-4433-87C0-68B6B72699C7".to_string()));
+        assert!(partition_types.contains(&"iso9660".to_string()));
+        assert!(partition_types.contains(&"udf".to_string()));
+    }
+
+    #[rstest]
+    #[case("/org/freedesktop/UDisks2/block_devices/sr0", true)]
+    #[case("/org/freedesktop/UDisks2/block_devices/sr12", true)]
+    #[case("/org/freedesktop/UDisks2/block_devices/sda1", false)]
+    #[case("/org/freedesktop/UDisks2/block_devices/srx", false)]
+    fn test_device_is_optical(#[case] objectpath: &str, #[case] result: bool) -> TestResult {
+        assert_eq!(Device::new(objectpath.to_string())?.is_optical(), result);
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_processes_using() -> TestResult {
+        let proc = testdir!();
+        for (pid, name, cwd, fd) in [
+            ("100", "shell", "/run/media/root/disk/updates", "/dev/null"),
+            ("200", "editor", "/root", "/run/media/root/disk/notes.txt"),
+            ("300", "daemon", "/", "/var/log/daemon.log"),
+        ] {
+            create_dir_all(proc.join(pid).join("fd"))?;
+            std::os::unix::fs::symlink(cwd, proc.join(pid).join("cwd"))?;
+            std::os::unix::fs::symlink(fd, proc.join(pid).join("fd").join("3"))?;
+            std::fs::write(proc.join(pid).join("comm"), format!("{}\n", name))?;
+        }
+        create_dir_all(proc.join("self"))?;
+
+        assert_eq!(
+            processes_using(&proc, Path::new("/run/media/root/disk")),
+            vec![(100, "shell".to_string()), (200, "editor".to_string())]
+        );
+        assert!(processes_using(&proc, Path::new("/mnt")).is_empty());
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_device_from_image() {
+        let device = Device::from_image(Path::new("/var/lib/caterpillar/update-pack.squashfs"));
+        assert!(!device.is_block_device());
+        assert!(!device.is_optical());
+        assert!(!device.is_mounted());
+        assert_eq!(
+            device.device_path(),
+            "/var/lib/caterpillar/update-pack.squashfs"
+        );
+    }
+
+    #[rstest]
+    #[case("rw,nosuid,nodev,noexec", "ro,nosuid,nodev,noexec")]
+    #[case("nosuid,rw", "ro,nosuid")]
+    #[case("", "ro")]
+    fn test_read_only_mount_options(#[case] mount_options: &str, #[case] result: &str) {
+        assert_eq!(read_only_mount_options(mount_options), result);
+    }
+
+    #[rstest]
+    #[case("/", true)]
+    #[case("/var", true)]
+    #[case("/var/lib/data", true)]
+    #[case("/boot/efi", true)]
+    #[case("/run/media/root/disk", false)]
+    #[case("/variable", false)]
+    #[case("/mnt", false)]
+    fn test_is_protected_mountpoint(#[case] mountpoint: &str, #[case] result: bool) {
+        assert_eq!(is_protected_mountpoint(Path::new(mountpoint)), result);
+    }
+
+    #[rstest]
+    fn test_mount_targets() {
+        let mountinfo = "\
+22 1 179:2 / / ro,relatime shared:1 - ext4 /dev/mmcblk0p2 ro
+25 22 8:1 / /var rw,relatime shared:3 - ext4 /dev/sda1 rw
+26 22 8:1 / /run/media/root/disk rw,relatime shared:4 - ext4 /dev/sda1 rw
+";
+        assert_eq!(
+            mount_targets(mountinfo, "/dev/sda1"),
+            vec!["/var".to_string(), "/run/media/root/disk".to_string()]
+        );
+        assert!(mount_targets(mountinfo, "/dev/sdb1").is_empty());
+    }
+
+    #[rstest]
+    fn test_system_mount_sources() {
+        let mountinfo = "\
+22 1 179:2 / / ro,relatime shared:1 - ext4 /dev/mmcblk0p2 ro
+23 22 0:21 / /proc rw,nosuid shared:12 - proc proc rw
+24 22 179:1 / /boot/efi rw,relatime shared:2 - vfat /dev/mmcblk0p1 rw
+25 22 179:4 / /data rw,relatime shared:3 - ext4 /dev/mmcblk0p4 rw
+26 22 8:1 / /run/media/root/disk rw,relatime shared:4 - vfat /dev/sda1 rw
+";
+        assert_eq!(
+            system_mount_sources(mountinfo),
+            vec!["/dev/mmcblk0p2".to_string(), "/dev/mmcblk0p1".to_string()]
+        );
+    }
+
+    #[rstest]
+    async fn test_device_from_directory() -> TestResult {
+        let dir = testdir!();
+        create_dir_all(dir.join("override"))?;
+        File::create(dir.join("update.raucb"))?;
+        File::create(dir.join("override").join("override.raucb"))?;
+
+        let mut device = Device::from_directory(&dir);
+        let search = BundleSearch::default();
+        device.find_bundles(&search).await?;
+        device.find_override_bundles(&search).await?;
+        assert!(device.is_mounted());
+        assert_eq!(device.bundles(), Some(vec![dir.join("update.raucb")]));
+        assert_eq!(
+            device.override_bundles(),
+            Some(vec![dir.join("override").join("override.raucb")])
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(None, vec!["update.raucb"])]
+    #[case(Some("caterpillar"), vec!["caterpillar/update.raucb"])]
+    #[case(Some("missing"), vec![])]
+    async fn test_device_find_bundles_search_dir(
+        #[case] search_dir: Option<&str>,
+        #[case] files: Vec<&str>,
+    ) -> TestResult {
+        let dir = testdir!();
+        for path in [
+            "update.raucb",
+            "caterpillar/update.raucb",
+            "documents/notes.txt",
+        ] {
+            create_dir_all(dir.join(path).parent().unwrap())?;
+            File::create(dir.join(path))?;
+        }
+        let search = BundleSearch {
+            search_dir: search_dir.map(PathBuf::from),
+            ..Default::default()
+        };
+
+        let mut device = Device::from_image(&dir.join("update-pack.squashfs"));
+        device.mountpoint.set(dir.clone()).unwrap();
+        device.find_bundles(&search).await?;
+        assert_eq!(
+            device.bundles().unwrap_or_default(),
+            files
+                .iter()
+                .map(|file| dir.join(file))
+                .collect::<Vec<PathBuf>>()
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    async fn test_device_find_bundles_channel() -> TestResult {
+        let dir = testdir!();
+        for path in [
+            "update.raucb",
+            "stable/stable.raucb",
+            "stable/override/override.raucb",
+            "testing/testing.raucb",
+        ] {
+            create_dir_all(dir.join(path).parent().unwrap())?;
+            File::create(dir.join(path))?;
+        }
+        let search = BundleSearch {
+            channel: Some("stable".to_string()),
+            ..Default::default()
+        };
+
+        // the channel applies to media
+        let mut device = Device::from_image(&dir.join("update-pack.squashfs"));
+        device.mountpoint.set(dir.clone()).unwrap();
+        device.find_bundles(&search).await?;
+        device.find_override_bundles(&search).await?;
+        assert_eq!(
+            device.bundles(),
+            Some(vec![dir.join("stable").join("stable.raucb")])
+        );
+        assert_eq!(
+            device.override_bundles(),
+            Some(vec![dir
+                .join("stable")
+                .join("override")
+                .join("override.raucb")])
+        );
+
+        // local directories are searched directly
+        let mut device = Device::from_directory(&dir);
+        device.find_bundles(&search).await?;
+        assert_eq!(device.bundles(), Some(vec![dir.join("update.raucb")]));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(None, vec!["myproduct-1.0.0.raucb", "otherproduct-1.0.0.raucb"])]
+    #[case(Some(r"^myproduct-.*\.raucb$"), vec!["myproduct-1.0.0.raucb"])]
+    fn test_bundlesearch_filename_regex(
+        #[case] regex: Option<&str>,
+        #[case] files: Vec<&str>,
+    ) -> TestResult {
+        let dir = testdir!();
+        for path in ["myproduct-1.0.0.raucb", "otherproduct-1.0.0.raucb"] {
+            File::create(dir.join(path))?;
+        }
+
+        let search = BundleSearch {
+            filename_regex: regex.map(|regex| Regex::new(regex).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            search.find_files(&dir, 1, None)?,
+            files
+                .iter()
+                .map(|file| dir.join(file))
+                .collect::<Vec<PathBuf>>()
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_bundlesearch_find_files_max_files() -> TestResult {
+        let dir = testdir!();
+        for path in ["a.raucb", "b.raucb", "c.raucb"] {
+            File::create(dir.join(path))?;
+        }
+
+        let search = BundleSearch {
+            max_files: 2,
+            ..Default::default()
+        };
+        assert_eq!(search.find_files(&dir, 1, None)?.len(), 2);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("/run/media/root/foo", "/run/media/root/foo/update.raucb", true)]
+    #[case(
+        "/run/media/root/foo",
+        "/run/media/root/foo/override/update.raucb",
+        true
+    )]
+    #[case("/run/media/root/foo", "/run/media/root/bar/update.raucb", false)]
+    fn test_device_contains(#[case] mountpoint: &str, #[case] path: &str, #[case] result: bool) {
+        let device =
+            Device::new("/org/freedesktop/UDisks2/block_devices/sda1".to_string()).unwrap();
+        device.mountpoint.set(PathBuf::from(mountpoint)).unwrap();
+        assert_eq!(device.contains(Path::new(path)), result);
+    }
+
+    #[rstest]
+    async fn test_device_mount_filesystem(dbus_daemon: Daemon) -> TestResult {
+        // filesystems below the testdir (e.g. in /root or /home) would be at a protected mountpoint
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_path_buf();
+        File::create(dir.join("update.raucb"))?;
+        let (connection, mounted) =
+            serve_block_device(&dbus_daemon, "filesystem", 1, &dir, false).await;
+
+        let mut device = Device::new(FAKE_BLOCK_DEVICE.to_string())?;
+        let mountpoint = device
+            .mount_filesystem(&connection, &["0X0C".to_string()], "rw", true)
+            .await?;
+        assert_eq!(Path::new(&mountpoint), dir);
+        assert!(mounted.load(Ordering::SeqCst));
+        assert!(device.is_unmountable());
+
+        let search = BundleSearch::default();
+        device.find_bundles(&search).await?;
+        assert_eq!(device.bundles(), Some(vec![dir.join("update.raucb")]));
+
+        device.unmount_filesystem(&connection, false).await?;
+        assert!(!mounted.load(Ordering::SeqCst));
+        assert!(!device.is_mounted());
+        drop(dbus_daemon);
+        Ok(())
+    }
+
+    #[rstest]
+    async fn test_device_mount_filesystem_premounted(dbus_daemon: Daemon) -> TestResult {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_path_buf();
+        let (connection, mounted) =
+            serve_block_device(&dbus_daemon, "filesystem", 1, &dir, true).await;
+
+        // filesystems mounted by others are refused for private mounts
+        let device = Device::new(FAKE_BLOCK_DEVICE.to_string())?;
+        assert!(matches!(
+            device
+                .mount_filesystem(&connection, &["0x0c".to_string()], "rw", true)
+                .await,
+            Err(Error::PublicMount(_, _))
+        ));
+
+        // filesystems mounted by others are used, but not unmounted
+        let mut device = Device::new(FAKE_BLOCK_DEVICE.to_string())?;
+        let mountpoint = device
+            .mount_filesystem(&connection, &["0x0c".to_string()], "rw", false)
+            .await?;
+        assert_eq!(Path::new(&mountpoint), dir);
+        assert!(!device.is_unmountable());
+        device.unmount_filesystem(&connection, false).await?;
+        assert!(mounted.load(Ordering::SeqCst));
+        assert!(device.is_mounted());
+        drop(dbus_daemon);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("other", 1, "0x0c")]
+    #[case("filesystem", 0, "0x0c")]
+    #[case("filesystem", 1, "0x83")]
+    async fn test_device_mount_filesystem_incompatible(
+        dbus_daemon: Daemon,
+        #[case] id_usage: &str,
+        #[case] number: u32,
+        #[case] partition_type: &str,
+    ) -> TestResult {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_path_buf();
+        let (connection, mounted) =
+            serve_block_device(&dbus_daemon, id_usage, number, &dir, false).await;
+
+        let device = Device::new(FAKE_BLOCK_DEVICE.to_string())?;
+        let result = device
+            .mount_filesystem(&connection, &[partition_type.to_string()], "rw", false)
+            .await;
+        match (id_usage, number) {
+            ("other", _) => assert!(matches!(result, Err(Error::IncompatibleBlockDevice(_)))),
+            (_, 0) => assert!(matches!(result, Err(Error::IsBaseDevice(_)))),
+            _ => assert!(matches!(result, Err(Error::IncompatibleFilesystem(_)))),
+        }
+        assert!(!mounted.load(Ordering::SeqCst));
+        assert!(!device.is_mounted());
+        drop(dbus_daemon);
+        Ok(())
+    }
+
     #[rstest]
     async fn test_udisksinfo_new(#[future] connection_daemon: (Connection, Daemon)) -> TestResult {
         let (connection, daemon) = connection_daemon.await;
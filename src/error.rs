@@ -7,7 +7,7 @@ use std::string::FromUtf8Error;
 
 use config::ConfigError;
 
-use crate::dbus::State;
+use crate::statemachine::State;
 
 /// An error that could occur when caterpillar runs
 #[derive(Debug, thiserror::Error)]
@@ -28,12 +28,21 @@ pub enum Error {
     /// A filesystem is not compatible (not one of CompatibleFilesystem)
     #[error("Device {0} does not have a compatible filesystem")]
     IncompatibleFilesystem(String),
+    /// A glob pattern is invalid
+    #[error("Pattern {0} is not valid: {1}")]
+    InvalidPattern(String, String),
     /// A device path is invalid
     #[error("Device path {0} is not valid")]
     InvalidDevicePath(String),
     /// A problem with dbus
     #[error("A problem occurred while communicating over dbus: {0}")]
     Dbus(zbus::Error),
+    /// Another instance already owns the D-Bus name
+    #[error("Another instance of caterpillar is already running as {0}")]
+    AlreadyRunning(String),
+    /// The D-Bus name has been lost
+    #[error("Lost the D-Bus name {0}")]
+    NameLost(String),
     /// A problem with internal dbus
     #[error("A problem occurred while communicating over dbus internally: {0}")]
     DbusInternal(zbus::fdo::Error),
@@ -43,12 +52,30 @@ pub enum Error {
     /// A file issue
     #[error("An error occurred reading or writing a file: {0}")]
     File(io::Error),
+    /// The boot of a newly installed slot can not be confirmed
+    #[error("Unable to confirm the boot: {0}")]
+    BootNotConfirmed(String),
     /// Failed retrieving information on a RAUC update bundle
     #[error("Unable to get information on a RAUC update bundle {0}")]
     BundleInfo(String, String),
     /// A bundle path is invalid
     #[error("RAUC update bundle path {0} is invalid")]
     BundlePath(PathBuf),
+    /// A bundle fails verification
+    #[error("RAUC update bundle {0} failed verification: {1}")]
+    BundleVerification(String, String),
+    /// Staging a RAUC update bundle in local storage failed
+    #[error("Staging RAUC update bundle {0} failed: {1}")]
+    StagingFailed(String, String),
+    /// Communicating with a hawkBit server failed
+    #[error("hawkBit error: {0}")]
+    Hawkbit(String),
+    /// Sandboxing the daemon failed
+    #[error("Sandboxing failed: {0}")]
+    Sandbox(String),
+    /// Failed retrieving the signer of a RAUC update bundle
+    #[error("Unable to get the signer of RAUC update bundle {0}: {1}")]
+    BundleSigner(String, String),
     /// A bundle version is invalid
     #[error("Version ({0}) of RAUC update bundle {1} is invalid: {2}")]
     BundleVersion(String, String, String),
@@ -57,6 +84,12 @@ pub enum Error {
     SlotVersion(String, String, String),
     #[error("An error occurred reading configuration: {0}")]
     Config(ConfigError),
+    /// An installation window is invalid
+    #[error("The installation window {0} is invalid (expected e.g. \"02:00-05:00\")")]
+    InvalidInstallWindow(String),
+    /// A configuration option is invalid
+    #[error("The configuration option {0} is invalid: {1}")]
+    InvalidConfig(String, String),
     /// No compatible update bundle is found
     #[error("No compatible RAUC update bundle found")]
     NoUpdateBundle,
@@ -66,9 +99,53 @@ pub enum Error {
     /// There is more than one override bundle
     #[error("There is more than one override update bundle")]
     TooManyOverrides(Vec<PathBuf>),
+    /// Mounting a network share failed
+    #[error("Mounting {0} failed: {1}")]
+    MountFailed(String, String),
+    /// A problem with SFTP
+    #[error("A problem occurred while communicating over SFTP: {0}")]
+    Sftp(ssh2::Error),
+    /// The host key of an SSH server can not be verified
+    #[error("SHA-256 digest of {0} does not match (expected {1}, got {2})")]
+    DigestMismatch(String, String, String),
+
+    #[error("Host key of {0} could not be verified")]
+    HostKey(String),
     /// Unmounting a filesystem failed
+    #[error("Device {0} is mounted at {1}, which belongs to the running system")]
+    ProtectedMountpoint(String, String),
+    /// A device is mounted by others, while only private mounts are used
+    #[error(
+        "Device {0} is mounted by others at {1} and is not used, as only private mounts are used"
+    )]
+    PublicMount(String, String),
+
     #[error("Unmounting mountpoint {0} failed")]
     UnmountFailed(String),
+    /// The system clock is not plausible
+    #[error("System time ({0}) is earlier than the plausible minimum ({1})")]
+    ImplausibleClock(i64, i64),
+    /// There is not enough space to install an update bundle
+    #[error("Insufficient space on {0}: {1} bytes required, but only {2} bytes available")]
+    InsufficientSpace(String, u64, u64),
+    /// The system can not be rebooted
+    #[error("Unable to reboot the system: {0}")]
+    Reboot(String),
+    /// The system runs on a battery, that is charged below a threshold
+    #[error("Running on battery at {0}%, which is below the threshold of {1}%")]
+    LowBattery(f64, f64),
+    /// Exporting traces failed
+    #[error("An error occurred setting up the export of traces: {0}")]
+    Telemetry(String),
+    /// Running the rauc command failed
+    #[error("Running \"rauc {0}\" failed: {1}")]
+    RaucCommand(String, String),
+    /// Reporting a lifecycle event failed
+    #[error("The {0} reporter failed: {1}")]
+    Report(String, String),
+    /// The RAUC daemon stopped or restarted during an installation
+    #[error("The RAUC daemon stopped or restarted during the installation")]
+    RaucRestarted,
     /// Installing an update bundle failed
     #[error("Update failed: {0}")]
     UpdateFailed(String),
@@ -110,6 +187,12 @@ impl From<zbus::Error> for Error {
     }
 }
 
+impl From<ssh2::Error> for Error {
+    fn from(err: ssh2::Error) -> Error {
+        Error::Sftp(err)
+    }
+}
+
 impl From<ConfigError> for Error {
     fn from(err: ConfigError) -> Error {
         Error::Config(err)
@@ -0,0 +1,452 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! A client for the Direct Device Integration (DDI) API of Eclipse hawkBit
+//!
+//! The hawkBit server is polled for deployments assigned to this device (the "controller").
+//! Update bundles of an assigned deployment are downloaded to a local cache directory, which is then searched like any
+//! other local directory, so that they pass the same checks as update bundles found on media.
+//! The outcome of the installation is reported back to the server as feedback of the deployment.
+use std::fs::create_dir_all;
+use std::fs::read_dir;
+use std::fs::read_to_string;
+use std::fs::remove_file;
+use std::fs::rename;
+use std::fs::write;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::json;
+use serde_json::Value;
+use tokio::task::spawn_blocking;
+
+use crate::error::Error;
+use crate::outcome::UpdateResult;
+use crate::rauc::UpdateBundle;
+use crate::report::Reporter;
+use crate::sftp::sha256_digest;
+
+/// The file in the cache directory, that records the deployment the cached update bundle belongs to
+const ACTION_FILE: &str = "action";
+/// The interval in which the server is polled, if it does not provide one
+pub const DEFAULT_POLLING_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The result of polling the hawkBit server
+#[derive(Clone, Debug, PartialEq)]
+pub struct Poll {
+    /// the interval after which the server should be polled again
+    pub sleep: Duration,
+    /// the optional URL of a deployment assigned to the device
+    pub deployment: Option<String>,
+}
+
+/// An artifact of a deployment
+#[derive(Clone, Debug, PartialEq)]
+struct Artifact {
+    filename: String,
+    sha256: String,
+    size: u64,
+    url: String,
+}
+
+/// A client for the DDI API of a hawkBit server
+#[derive(Clone, Debug)]
+pub struct HawkbitClient {
+    url: String,
+    tenant: String,
+    controller_id: String,
+    token: Option<String>,
+    cache_dir: PathBuf,
+}
+
+impl HawkbitClient {
+    /// Create a new HawkbitClient
+    ///
+    /// The device authenticates using the (optional) target security `token` of the controller.
+    pub fn new(
+        url: &str,
+        tenant: &str,
+        controller_id: &str,
+        token: Option<&str>,
+        cache_dir: &Path,
+    ) -> Self {
+        HawkbitClient {
+            url: url.trim_end_matches('/').to_string(),
+            tenant: tenant.to_string(),
+            controller_id: controller_id.to_string(),
+            token: token.map(String::from),
+            cache_dir: cache_dir.into(),
+        }
+    }
+
+    /// Return the local cache directory, to which update bundles are downloaded
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// Return the URL of the controller resource of the device
+    fn base(&self) -> String {
+        format!(
+            "{}/{}/controller/v1/{}",
+            self.url, self.tenant, self.controller_id
+        )
+    }
+
+    /// Create a request to the server, authenticated using the target security token (if any)
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let request = ureq::request(method, url).set("Accept", "application/hal+json");
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("TargetToken {}", token)),
+            None => request,
+        }
+    }
+
+    /// Request a JSON document from the server
+    fn get_json(&self, url: &str) -> Result<Value, Error> {
+        let body = self
+            .request("GET", url)
+            .call()
+            .map_err(|x| Error::Hawkbit(x.to_string()))?
+            .into_string()?;
+        serde_json::from_str(&body).map_err(|x| Error::Hawkbit(x.to_string()))
+    }
+
+    /// Poll the server for a deployment assigned to the device
+    pub fn poll(&self) -> Result<Poll, Error> {
+        Ok(parse_poll(&self.get_json(&self.base())?))
+    }
+
+    /// Fetch the update bundle of the deployment assigned to the device
+    ///
+    /// Only the first artifact with `extension` is considered.
+    /// It is downloaded to the cache directory (if it is not there yet) and verified using its SHA-256 digest, while all
+    /// other update bundles are removed from the cache directory, so that only the assigned update bundle is offered.
+    /// The deployment is recorded in the cache directory and the server is informed, that the deployment proceeds.
+    /// The `progress` of the download is reported as bytes downloaded and total bytes.
+    /// The path to the downloaded update bundle is returned in an Option.
+    pub fn fetch(
+        &self,
+        extension: &str,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Result<Option<PathBuf>, Error> {
+        let deployment_url = match self.poll()?.deployment {
+            Some(deployment_url) => deployment_url,
+            None => {
+                println!("No deployment assigned by hawkBit server {}", self.url);
+                return Ok(None);
+            }
+        };
+        let deployment = self.get_json(&deployment_url)?;
+        let action = deployment["id"]
+            .as_str()
+            .map(String::from)
+            .or_else(|| deployment["id"].as_u64().map(|id| id.to_string()))
+            .ok_or(Error::Hawkbit("the deployment has no id".to_string()))?;
+        let artifact = match parse_artifacts(&deployment, extension).into_iter().next() {
+            Some(artifact) => artifact,
+            None => {
+                let details = format!(
+                    "The deployment contains no artifact with extension {}",
+                    extension
+                );
+                self.feedback(&action, "closed", "failure", &[details.clone()])?;
+                return Err(Error::Hawkbit(details));
+            }
+        };
+        println!(
+            "Found update bundle {} in deployment {} of hawkBit server {}",
+            artifact.filename, action, self.url
+        );
+
+        create_dir_all(&self.cache_dir)?;
+        let path = self.cache_dir.join(&artifact.filename);
+        self.evict(extension, &path)?;
+        if path.exists() && sha256_digest(&path)?.eq_ignore_ascii_case(&artifact.sha256) {
+            println!("Update bundle {:?} is already cached", path);
+        } else {
+            // download to a temporary file first, so that no partial update bundles are considered
+            let partial_path = self.cache_dir.join(format!("{}.part", artifact.filename));
+            println!("Downloading {} to {:?}...", artifact.filename, path);
+            let mut reader = self
+                .request("GET", &artifact.url)
+                .call()
+                .map_err(|x| Error::Hawkbit(x.to_string()))?
+                .into_reader();
+            let mut partial_file = File::create(&partial_path)?;
+            let mut downloaded = 0;
+            let mut buffer = [0u8; 65536];
+            loop {
+                let length = reader.read(&mut buffer)?;
+                if length == 0 {
+                    break;
+                }
+                partial_file.write_all(&buffer[..length])?;
+                downloaded += length as u64;
+                progress(downloaded, artifact.size);
+            }
+
+            let actual = sha256_digest(&partial_path)?;
+            if !artifact.sha256.eq_ignore_ascii_case(&actual) {
+                remove_file(&partial_path)?;
+                return Err(Error::DigestMismatch(
+                    artifact.filename,
+                    artifact.sha256,
+                    actual,
+                ));
+            }
+            println!("Verified SHA-256 digest of {}", artifact.filename);
+            rename(&partial_path, &path)?;
+        }
+
+        write(self.cache_dir.join(ACTION_FILE), &action)?;
+        self.feedback(
+            &action,
+            "proceeding",
+            "none",
+            &[format!("Downloaded {}", artifact.filename)],
+        )?;
+        Ok(Some(path))
+    }
+
+    /// Remove all update bundles with `extension` (and partial downloads) except `keep` from the cache directory
+    fn evict(&self, extension: &str, keep: &Path) -> Result<(), Error> {
+        for entry in read_dir(&self.cache_dir)? {
+            let path = entry?.path();
+            let name = path.to_string_lossy();
+            if path != keep
+                && (name.ends_with(&format!(".{}", extension))
+                    || name.ends_with(&format!(".{}.part", extension)))
+            {
+                println!("Evicting cached file {:?}", path);
+                remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the deployment, that the update bundles in the cache directory belong to
+    fn action(&self) -> Option<String> {
+        read_to_string(self.cache_dir.join(ACTION_FILE))
+            .ok()
+            .map(|action| action.trim().to_string())
+            .filter(|action| !action.is_empty())
+    }
+
+    /// Send feedback on a deployment to the server
+    ///
+    /// The `execution` is one of e.g. "proceeding" or "closed" and the `finished` result one of "none", "success" or
+    /// "failure".
+    fn feedback(
+        &self,
+        action: &str,
+        execution: &str,
+        finished: &str,
+        details: &[String],
+    ) -> Result<(), Error> {
+        let body = json!({
+            "id": action,
+            "status": {
+                "execution": execution,
+                "result": {"finished": finished},
+                "details": details,
+            },
+        });
+        self.request(
+            "POST",
+            &format!("{}/deploymentBase/{}/feedback", self.base(), action),
+        )
+        .set("Content-Type", "application/json")
+        .send_string(&body.to_string())
+        .map_err(|x| Error::Hawkbit(x.to_string()))?;
+        println!(
+            "Sent feedback ({}, {}) on deployment {} to hawkBit server {}",
+            execution, finished, action, self.url
+        );
+        Ok(())
+    }
+}
+
+/// A Reporter, that sends the outcome of installing the update bundle of a deployment to the hawkBit server
+///
+/// Only update bundles located in the cache directory of the HawkbitClient are considered.
+pub struct HawkbitReporter {
+    client: HawkbitClient,
+    /// the transaction, in which the update bundle of a deployment has been found, and the deployment
+    pending: Mutex<Option<(u64, String)>>,
+}
+
+impl HawkbitReporter {
+    /// Create a new HawkbitReporter for a HawkbitClient
+    pub fn new(client: HawkbitClient) -> Self {
+        HawkbitReporter {
+            client,
+            pending: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Reporter for HawkbitReporter {
+    async fn update_found(&self, transaction: u64, bundle: &UpdateBundle) -> Result<(), Error> {
+        if !Path::new(&bundle.path()).starts_with(self.client.cache_dir()) {
+            return Ok(());
+        }
+        if let Some(action) = self.client.action() {
+            *self.pending.lock().unwrap() = Some((transaction, action));
+        }
+        Ok(())
+    }
+
+    async fn install_finished(&self, transaction: u64, result: &UpdateResult) -> Result<(), Error> {
+        let action = match self.pending.lock().unwrap().take() {
+            Some((pending, action)) if pending == transaction => action,
+            _ => return Ok(()),
+        };
+        let client = self.client.clone();
+        let (finished, details) = if result.success() {
+            ("success", format!("Installed version {}", result.version()))
+        } else {
+            ("failure", result.error().to_string())
+        };
+        spawn_blocking(move || {
+            client.feedback(&action, "closed", finished, &[details])?;
+            remove_file(client.cache_dir().join(ACTION_FILE))?;
+            Ok::<(), Error>(())
+        })
+        .await
+        .map_err(|x| Error::Hawkbit(x.to_string()))??;
+        Ok(())
+    }
+}
+
+/// Parse a polling interval in the format "HH:MM:SS"
+fn parse_sleep(sleep: &str) -> Option<Duration> {
+    let mut seconds = 0;
+    let mut parts = 0;
+    for part in sleep.split(':') {
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+        parts += 1;
+    }
+    (parts == 3).then_some(Duration::from_secs(seconds))
+}
+
+/// Parse the controller resource of the device
+fn parse_poll(base: &Value) -> Poll {
+    Poll {
+        sleep: base["config"]["polling"]["sleep"]
+            .as_str()
+            .and_then(parse_sleep)
+            .unwrap_or(DEFAULT_POLLING_INTERVAL),
+        deployment: base["_links"]["deploymentBase"]["href"]
+            .as_str()
+            .map(String::from),
+    }
+}
+
+/// Parse the artifacts with `extension` of all chunks (software modules) of a deployment
+///
+/// The HTTPS download link of an artifact is preferred over the HTTP one.
+fn parse_artifacts(deployment: &Value, extension: &str) -> Vec<Artifact> {
+    deployment["deployment"]["chunks"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|chunk| chunk["artifacts"].as_array())
+        .flatten()
+        .filter_map(|artifact| {
+            let filename = artifact["filename"].as_str()?;
+            if !filename.ends_with(&format!(".{}", extension)) || filename.contains('/') {
+                return None;
+            }
+            Some(Artifact {
+                filename: filename.to_string(),
+                sha256: artifact["hashes"]["sha256"].as_str()?.to_string(),
+                size: artifact["size"].as_u64().unwrap_or_default(),
+                url: artifact["_links"]["download"]["href"]
+                    .as_str()
+                    .or_else(|| artifact["_links"]["download-http"]["href"].as_str())?
+                    .to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("00:05:00", Some(Duration::from_secs(300)))]
+    #[case("01:00:30", Some(Duration::from_secs(3630)))]
+    #[case("5 minutes", None)]
+    #[case("05:00", None)]
+    fn test_parse_sleep(#[case] sleep: &str, #[case] duration: Option<Duration>) {
+        assert_eq!(parse_sleep(sleep), duration);
+    }
+
+    #[rstest]
+    fn test_parse_poll() {
+        let base = json!({
+            "config": {"polling": {"sleep": "00:01:00"}},
+            "_links": {"deploymentBase": {"href": "https://hawkbit/DEFAULT/controller/v1/device/deploymentBase/3"}},
+        });
+        assert_eq!(
+            parse_poll(&base),
+            Poll {
+                sleep: Duration::from_secs(60),
+                deployment: Some(
+                    "https://hawkbit/DEFAULT/controller/v1/device/deploymentBase/3".to_string()
+                ),
+            }
+        );
+        assert_eq!(
+            parse_poll(&json!({})),
+            Poll {
+                sleep: DEFAULT_POLLING_INTERVAL,
+                deployment: None,
+            }
+        );
+    }
+
+    #[rstest]
+    fn test_parse_artifacts() {
+        let deployment = json!({
+            "id": "3",
+            "deployment": {"chunks": [{
+                "part": "os",
+                "artifacts": [
+                    {
+                        "filename": "update-1.2.3.raucb",
+                        "hashes": {"sha256": "abc"},
+                        "size": 1024,
+                        "_links": {
+                            "download": {"href": "https://hawkbit/download"},
+                            "download-http": {"href": "http://hawkbit/download"},
+                        },
+                    },
+                    {
+                        "filename": "release-notes.txt",
+                        "hashes": {"sha256": "def"},
+                        "size": 16,
+                        "_links": {"download-http": {"href": "http://hawkbit/notes"}},
+                    },
+                ],
+            }]},
+        });
+        assert_eq!(
+            parse_artifacts(&deployment, "raucb"),
+            vec![Artifact {
+                filename: "update-1.2.3.raucb".to_string(),
+                sha256: "abc".to_string(),
+                size: 1024,
+                url: "https://hawkbit/download".to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use nix::sys::inotify::AddWatchFlags;
+use nix::sys::inotify::InitFlags;
+use nix::sys::inotify::Inotify;
+use nix::sys::inotify::WatchDescriptor;
+use tokio::io::unix::AsyncFd;
+
+use crate::device::BundleSearch;
+use crate::error::Error;
+
+/// A watcher of inbox directories, that reports update bundles as soon as they have been written
+///
+/// Update bundles are reported once they are closed after writing or moved into a watched directory (e.g. by `rsync`),
+/// so that partially written files are not considered.
+/// Next to each inbox directory, its override directory (if it exists) is watched as well.
+pub struct InboxWatcher {
+    inotify: AsyncFd<Inotify>,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    bundle_search: BundleSearch,
+}
+
+impl InboxWatcher {
+    /// Create a new InboxWatcher for a list of inbox directories
+    ///
+    /// Directories, that do not exist are skipped.
+    pub fn new(inbox_dirs: &[PathBuf], bundle_search: BundleSearch) -> Result<Self, Error> {
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+            .map_err(io::Error::from)?;
+        let mut dirs = HashMap::new();
+
+        for dir in inbox_dirs
+            .iter()
+            .flat_map(|dir| [dir.to_path_buf(), dir.join(bundle_search.override_dir())])
+            .filter(|dir| dir.is_dir())
+        {
+            let watch = inotify
+                .add_watch(
+                    &dir,
+                    AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO,
+                )
+                .map_err(io::Error::from)?;
+            println!("Watching inbox directory {:?} for update bundles", dir);
+            dirs.insert(watch, dir);
+        }
+
+        Ok(InboxWatcher {
+            inotify: AsyncFd::new(inotify)?,
+            dirs,
+            bundle_search,
+        })
+    }
+
+    /// Return whether any directory is watched
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    /// Wait for update bundles to be written to any of the watched directories and return their paths
+    ///
+    /// Files, that are not potential update bundles (see [`BundleSearch`]) are ignored.
+    pub async fn next_bundles(&self) -> Result<Vec<PathBuf>, Error> {
+        loop {
+            let mut guard = self.inotify.readable().await?;
+            let events = match guard
+                .try_io(|inotify| inotify.get_ref().read_events().map_err(io::Error::from))
+            {
+                Ok(events) => events?,
+                Err(_would_block) => continue,
+            };
+
+            let bundles: Vec<PathBuf> = events
+                .into_iter()
+                .filter_map(|event| match (self.dirs.get(&event.wd), event.name) {
+                    (Some(dir), Some(name)) => Some(dir.join(name)),
+                    _ => None,
+                })
+                .filter(|path| self.is_bundle(path))
+                .collect();
+            if !bundles.is_empty() {
+                return Ok(bundles);
+            }
+        }
+    }
+
+    /// Return whether a path is a potential update bundle
+    fn is_bundle(&self, path: &Path) -> bool {
+        self.bundle_search.is_bundle(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::fs::create_dir;
+    use std::fs::rename;
+    use std::fs::write;
+    use testdir::testdir;
+    use testresult::TestResult;
+    use tokio::time::timeout;
+    use tokio::time::Duration;
+
+    #[tokio::test]
+    #[rstest]
+    async fn test_inbox_watcher() -> TestResult {
+        let inbox = testdir!();
+        create_dir(inbox.join("override"))?;
+        let search = BundleSearch::default();
+        let watcher = InboxWatcher::new(&[inbox.clone(), inbox.join("missing")], search)?;
+        assert!(!watcher.is_empty());
+
+        write(inbox.join("notes.txt"), "foo")?;
+        write(inbox.join("update.raucb"), "foo")?;
+        assert_eq!(
+            timeout(Duration::from_secs(5), watcher.next_bundles()).await??,
+            vec![inbox.join("update.raucb")]
+        );
+
+        write(inbox.join("override.tmp"), "foo")?;
+        rename(
+            inbox.join("override.tmp"),
+            inbox.join("override/override.raucb"),
+        )?;
+        assert_eq!(
+            timeout(Duration::from_secs(5), watcher.next_bundles()).await??,
+            vec![inbox.join("override/override.raucb")]
+        );
+        Ok(())
+    }
+}
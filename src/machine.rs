@@ -0,0 +1,114 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// The location of the machine ID
+const MACHINE_ID: &str = "/etc/machine-id";
+/// The locations of the serial number of the machine (DMI on x86, device tree on embedded devices)
+const SERIALS: &[&str] = &[
+    "/sys/class/dmi/id/product_serial",
+    "/sys/firmware/devicetree/base/serial-number",
+];
+
+/// The identity of the local machine
+///
+/// The identity consists of the optional machine ID (see machine-id(5)) and the optional serial number of the machine.
+/// It is used for matching update bundles, that are targeted at specific machines.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Machine {
+    machine_id: Option<String>,
+    serial: Option<String>,
+}
+
+impl Machine {
+    /// Read the identity of the local machine
+    pub fn read() -> Self {
+        Machine {
+            machine_id: read_first(&[Path::new(MACHINE_ID)]),
+            serial: read_first(&SERIALS.iter().map(Path::new).collect::<Vec<&Path>>()),
+        }
+    }
+
+    /// Return the optional machine ID
+    pub fn machine_id(&self) -> Option<&str> {
+        self.machine_id.as_deref()
+    }
+
+    /// Return the optional serial number
+    pub fn serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
+}
+
+/// Return the trimmed contents of the first file of a list, that is readable and not empty
+///
+/// Trailing NUL bytes (e.g. of device tree properties) are removed.
+fn read_first(paths: &[&Path]) -> Option<String> {
+    paths.iter().find_map(|path| {
+        read_to_string(path)
+            .ok()
+            .map(|contents| {
+                contents
+                    .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+                    .to_string()
+            })
+            .filter(|contents| !contents.is_empty())
+    })
+}
+
+/// Return whether a comma-separated list of `targets` contains a value
+///
+/// If there are no targets, any value (including none) is targeted.
+pub fn is_targeted(targets: Option<&str>, value: Option<&str>) -> bool {
+    match targets {
+        Some(targets) => value.is_some_and(|value| {
+            targets
+                .split(',')
+                .map(str::trim)
+                .any(|target| target == value)
+        }),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::fs::write;
+    use testdir::testdir;
+    use testresult::TestResult;
+
+    #[rstest]
+    fn test_read_first() -> TestResult {
+        let dir = testdir!();
+        write(dir.join("empty"), "\n")?;
+        write(dir.join("serial-number"), "ABC123\0")?;
+        assert_eq!(
+            read_first(&[
+                &dir.join("missing"),
+                &dir.join("empty"),
+                &dir.join("serial-number")
+            ]),
+            Some("ABC123".to_string())
+        );
+        assert_eq!(read_first(&[&dir.join("missing")]), None);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(None, None, true)]
+    #[case(None, Some("abc"), true)]
+    #[case(Some("abc"), Some("abc"), true)]
+    #[case(Some("def, abc"), Some("abc"), true)]
+    #[case(Some("def"), Some("abc"), false)]
+    #[case(Some("abc"), None, false)]
+    fn test_is_targeted(
+        #[case] targets: Option<&str>,
+        #[case] value: Option<&str>,
+        #[case] result: bool,
+    ) {
+        assert_eq!(is_targeted(targets, value), result);
+    }
+}
@@ -1,56 +1,399 @@
 // SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
 // SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
 use event_listener::Event;
+use futures::StreamExt;
+use nix::unistd::dup2;
+use tokio::runtime::Builder;
+use tokio::runtime::Runtime;
+use tokio::signal::unix::SignalKind;
+use tokio::task::JoinHandle;
+use zbus::fdo::DBusProxy;
+use zbus::fdo::ObjectManager;
+use zbus::fdo::PropertiesProxy;
+use zbus::fdo::RequestNameFlags;
+use zbus::fdo::RequestNameReply;
 use zbus::names::BusName;
 use zbus::names::InterfaceName;
-use zbus::ConnectionBuilder;
+use zbus::Connection;
+use zbus::MessageStream;
 use zvariant::ObjectPath;
 
+mod blocklist;
+mod cli;
+mod clock;
 mod config;
 mod dbus;
 mod device;
 mod error;
+#[cfg(feature = "hawkbit")]
+mod hawkbit;
+mod inbox;
+mod machine;
 mod macros;
+#[cfg(feature = "mock-backends")]
+mod mock;
+mod monitor;
+mod outcome;
+mod peer;
+mod power;
 mod proxy;
+mod quarantine;
 mod rauc;
+mod reboot;
+mod report;
+mod rollback;
+mod sandbox;
+mod sftp;
+mod share;
+mod stage;
+mod statemachine;
+mod telemetry;
+#[cfg(test)]
+mod testing;
+mod window;
 
+use crate::config::CaterpillarConfig;
+use cli::Bus;
+use cli::Cli;
+use cli::Command;
+use cli::LogLevel;
+use dbus::confirm_boot;
+#[cfg(feature = "hawkbit")]
+use dbus::watch_hawkbit;
+use dbus::watch_inbox_dirs;
+use dbus::watch_rauc_operation;
 use dbus::Caterpillar;
+use dbus::INTERFACE;
+use device::BundleSearch;
 use error::Error;
+#[cfg(feature = "hawkbit")]
+use hawkbit::HawkbitClient;
+use inbox::InboxWatcher;
+
+/// The interval in which reconnecting to the bus is retried
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Discard messages written to stdout, so that only errors and warnings (written to stderr) are logged
+fn discard_stdout() -> Result<(), Error> {
+    let null = File::options().write(true).open("/dev/null")?;
+    dup2(null.as_raw_fd(), std::io::stdout().as_raw_fd())
+        .map_err(|x| Error::Default(x.to_string()))?;
+    Ok(())
+}
+
+/// Build the multi-threaded runtime
+fn runtime() -> Result<Runtime, Error> {
+    Ok(Builder::new_multi_thread().enable_all().build()?)
+}
+
+pub fn main() -> Result<(), Error> {
+    let cli = Cli::parse();
+    // monitor a running instance instead of starting one
+    if cli.command == Some(Command::Monitor) {
+        return runtime()?.block_on(monitor::run(&cli.bus.unwrap_or_default()));
+    }
+    if cli.log_level == LogLevel::Error {
+        discard_stdout()?;
+    }
 
-#[tokio::main]
-pub async fn main() -> Result<(), Error> {
     println!(
         "Starting {} {}.",
         env!("CARGO_BIN_NAME"),
         env!("CARGO_PKG_VERSION")
     );
 
-    let caterpillar = Caterpillar::new(Event::new()).await?;
+    // the configuration is read before starting the runtime, so that the sandbox applies to all of its threads
+    let config = Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(CaterpillarConfig::from_cli(&cli))?;
+    if config.sandbox() {
+        sandbox::apply(&config)?;
+    }
+
+    runtime()?.block_on(run(cli, config))
+}
+
+/// Run caterpillar with a configuration
+async fn run(cli: Cli, config: CaterpillarConfig) -> Result<(), Error> {
+    #[cfg(feature = "mock-backends")]
+    mock::start().await?;
+
+    // confirm the boot of a newly installed slot without providing the D-Bus interface
+    if cli.command == Some(Command::ConfirmBoot) {
+        let connection = connect(&cli.bus.unwrap_or_default()).await?;
+        return confirm_boot(&connection, &config).await;
+    }
+
+    telemetry::init(config.otlp_endpoint())?;
+
+    // search for and install an update without providing the D-Bus interface
+    if let Some(Command::RunOnce { no_reboot }) = cli.command {
+        println!("Running once without providing the D-Bus interface");
+        let caterpillar = Caterpillar::new(Event::new(), config).await?;
+        let result = caterpillar.run_once(!no_reboot).await;
+        telemetry::shutdown();
+        return result.map(|_| ());
+    }
+
+    // ensure that only a single instance is running, before devices are searched and RAUC is queried
+    let bus = cli.bus.unwrap_or_default();
+    let mut connection = connect(&bus).await?;
+    match connection
+        .request_name_with_flags(
+            "de.sleepmap.Caterpillar",
+            RequestNameFlags::DoNotQueue.into(),
+        )
+        .await
+    {
+        Ok(RequestNameReply::PrimaryOwner) | Ok(RequestNameReply::AlreadyOwner) => {}
+        Ok(RequestNameReply::InQueue)
+        | Ok(RequestNameReply::Exists)
+        | Err(zbus::Error::NameTaken) => {
+            let error = Error::AlreadyRunning("de.sleepmap.Caterpillar".to_string());
+            eprintln!("{}", error);
+            if config.forward_to_instance() && config.auto_search() {
+                // respect an inhibition of automatic updates by the running instance
+                let inhibited = PropertiesProxy::builder(&connection)
+                    .destination("de.sleepmap.Caterpillar")?
+                    .path("/de/sleepmap/Caterpillar")?
+                    .build()
+                    .await?
+                    .get(InterfaceName::try_from(INTERFACE).unwrap(), "Inhibited")
+                    .await
+                    .ok()
+                    .and_then(|value| bool::try_from(value).ok())
+                    .unwrap_or_default();
+                if inhibited {
+                    println!("Not forwarding the search for updates, as the running instance is inhibited");
+                    return Ok(());
+                }
+                println!("Forwarding the search for updates to the running instance");
+                connection
+                    .call_method(
+                        Some(BusName::try_from("de.sleepmap.Caterpillar").unwrap()),
+                        ObjectPath::try_from("/de/sleepmap/Caterpillar").unwrap(),
+                        Some(InterfaceName::try_from(INTERFACE).unwrap()),
+                        "SearchForUpdate",
+                        &(),
+                    )
+                    .await?;
+                return Ok(());
+            }
+            return Err(error);
+        }
+        Err(error) => return Err(error.into()),
+    }
+
+    let caterpillar = Caterpillar::new(Event::new(), config).await?;
+
     let mut listener = caterpillar.done().listen();
-    let autorun = caterpillar.config().get_bool("autorun")?;
+    // do not search for updates, while monitoring an ongoing installation
+    let auto_search = caterpillar.config().auto_search() && !caterpillar.resume_install();
+    let inbox_watcher = if caterpillar.config().watch_inbox_dirs() {
+        Some(InboxWatcher::new(
+            caterpillar.config().inbox_dirs(),
+            BundleSearch::from_config(caterpillar.config()),
+        )?)
+    } else {
+        None
+    };
+
+    #[cfg(feature = "hawkbit")]
+    let hawkbit_client = caterpillar.hawkbit_client();
+    #[cfg(not(feature = "hawkbit"))]
+    if let Some(url) = caterpillar.config().hawkbit_url() {
+        eprintln!(
+            "Warning: Not polling hawkBit server {}, as caterpillar is built without the hawkbit feature",
+            url
+        );
+    }
+
+    let watchers = Watchers {
+        inbox_watcher: inbox_watcher
+            .filter(|watcher| !watcher.is_empty())
+            .map(Arc::new),
+        #[cfg(feature = "hawkbit")]
+        hawkbit_client,
+    };
 
     println!("Making Caterpillar available on D-Bus");
-    let connection = ConnectionBuilder::system()?
-        .name("de.sleepmap.Caterpillar")?
-        .serve_at("/de/sleepmap/Caterpillar", caterpillar)?
-        .build()
-        .await?;
+    let shared = caterpillar.share();
+    serve(&connection, caterpillar).await?;
+    // signals and calls to the instance itself are sent on the bus it is served on
+    shared.serve_on(&connection).await;
 
-    // autorun caterpillar
-    if autorun {
+    // exit, if the name is lost (e.g. because it is replaced)
+    let dbus_proxy = DBusProxy::new(&connection).await?;
+    let mut name_lost = dbus_proxy.receive_name_lost().await?;
+
+    // search for updates non-interactively
+    if auto_search {
         println!("Non-interactive mode on first run");
         connection
             .call_method(
                 Some(BusName::try_from("de.sleepmap.Caterpillar").unwrap()),
                 ObjectPath::try_from("/de/sleepmap/Caterpillar").unwrap(),
-                Some(InterfaceName::try_from("de.sleepmap.Caterpillar").unwrap()),
+                Some(InterfaceName::try_from(INTERFACE).unwrap()),
                 "SearchForUpdate",
                 &(),
             )
             .await?;
     }
 
-    listener.as_mut().wait();
+    let mut tasks = watchers.spawn(&connection);
+    // detect the loss of the connection (e.g. if the bus is restarted)
+    let mut messages = MessageStream::from(&connection);
+
+    // unmount devices before exiting, when the service is stopped
+    let mut sigterm = tokio::signal::unix::signal(SignalKind::terminate())?;
+    let mut sigint = tokio::signal::unix::signal(SignalKind::interrupt())?;
+
+    let shutdown = loop {
+        tokio::select! {
+            _ = listener.as_mut() => break false,
+            _ = sigterm.recv() => {
+                println!("Received SIGTERM. Shutting down...");
+                break true;
+            }
+            _ = sigint.recv() => {
+                println!("Received SIGINT. Shutting down...");
+                break true;
+            }
+            Some(signal) = name_lost.next() => {
+                if signal.args().is_ok_and(|args| args.name() == "de.sleepmap.Caterpillar") {
+                    let error = Error::NameLost("de.sleepmap.Caterpillar".to_string());
+                    eprintln!("{}", error);
+                    return Err(error);
+                }
+            }
+            message = messages.next() => {
+                if !matches!(message, Some(Ok(_))) {
+                    eprintln!("Lost the connection to the bus. Reconnecting...");
+                    for task in tasks.drain(..) {
+                        task.abort();
+                    }
+                    connection = reconnect(&bus, &shared).await?;
+                    name_lost = DBusProxy::new(&connection).await?.receive_name_lost().await?;
+                    messages = MessageStream::from(&connection);
+                    tasks = watchers.spawn(&connection);
+                }
+            }
+        }
+    };
+
+    if shutdown {
+        connection
+            .object_server()
+            .interface::<_, Caterpillar>("/de/sleepmap/Caterpillar")
+            .await?
+            .get()
+            .await
+            .shutdown(&connection)
+            .await?;
+    }
+
+    telemetry::shutdown();
+    Ok(())
+}
+
+/// The sources of events outside of the D-Bus interface, that are watched for a connection
+struct Watchers {
+    inbox_watcher: Option<Arc<InboxWatcher>>,
+    #[cfg(feature = "hawkbit")]
+    hawkbit_client: Option<HawkbitClient>,
+}
+
+impl Watchers {
+    /// Spawn the tasks watching the sources of events for a connection
+    fn spawn(&self, connection: &Connection) -> Vec<JoinHandle<()>> {
+        let mut tasks = vec![];
+
+        // search for updates as soon as update bundles are written to the inbox directories
+        if let Some(watcher) = self.inbox_watcher.clone() {
+            let connection = connection.clone();
+            tasks.push(tokio::spawn(async move {
+                if let Err(error) = watch_inbox_dirs(connection, watcher).await {
+                    eprintln!("Stopped watching inbox directories: {}", error);
+                }
+            }));
+        }
+
+        // search for updates as soon as a hawkBit server assigns a deployment
+        #[cfg(feature = "hawkbit")]
+        if let Some(client) = self.hawkbit_client.clone() {
+            let connection = connection.clone();
+            tasks.push(tokio::spawn(async move {
+                if let Err(error) = watch_hawkbit(connection, client).await {
+                    eprintln!("Stopped polling the hawkBit server: {}", error);
+                }
+            }));
+        }
+
+        // forward changes of RAUC's operation (e.g. if another agent installs an update bundle)
+        let connection = connection.clone();
+        tasks.push(tokio::spawn(async move {
+            if let Err(error) = watch_rauc_operation(connection).await {
+                eprintln!("Stopped forwarding changes of RAUC's operation: {}", error);
+            }
+        }));
+
+        tasks
+    }
+}
+
+/// Create a new connection to a bus
+async fn connect(bus: &Bus) -> Result<Connection, Error> {
+    Ok(bus.connection_builder()?.build().await?)
+}
 
+/// Serve a Caterpillar instance on a connection to a bus
+async fn serve(connection: &Connection, caterpillar: Caterpillar) -> Result<(), Error> {
+    let object_server = connection.object_server();
+    object_server
+        .at("/de/sleepmap/Caterpillar", caterpillar)
+        .await?;
+    object_server
+        .at("/de/sleepmap/Caterpillar", ObjectManager)
+        .await?;
     Ok(())
 }
+
+/// Reclaim the name on a new connection, once the bus is available again, and serve a Caterpillar instance on it
+///
+/// Connecting is retried until the bus is available, while another instance owning the name in the meantime is an
+/// error.
+async fn reconnect(bus: &Bus, caterpillar: &Caterpillar) -> Result<Connection, Error> {
+    let connection = loop {
+        match connect(bus).await {
+            Ok(connection) => break connection,
+            Err(error) => {
+                eprintln!("Unable to connect to the bus: {}", error);
+                tokio::time::sleep(RECONNECT_INTERVAL).await;
+            }
+        }
+    };
+    match connection
+        .request_name_with_flags(
+            "de.sleepmap.Caterpillar",
+            RequestNameFlags::DoNotQueue.into(),
+        )
+        .await
+    {
+        Ok(RequestNameReply::PrimaryOwner) | Ok(RequestNameReply::AlreadyOwner) => {}
+        Ok(_) | Err(zbus::Error::NameTaken) => {
+            return Err(Error::AlreadyRunning("de.sleepmap.Caterpillar".to_string()))
+        }
+        Err(error) => return Err(error.into()),
+    }
+    serve(&connection, caterpillar.share()).await?;
+    caterpillar.reconnect(&connection).await?;
+    println!("Reconnected to the bus");
+    Ok(connection)
+}
@@ -0,0 +1,554 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! In-process fakes for the RAUC, UDisks2, logind and systemd D-Bus services
+//!
+//! This module is only available with the `mock-backends` feature.
+//! The fakes are served on the session bus and are configured using a TOML scenario file (see [`Scenario`]), which
+//! allows exercising the full state machine of the application without a RAUC-enabled system.
+use std::collections::HashMap;
+use std::env::var;
+use std::path::Path;
+use std::path::PathBuf;
+
+use config::Config;
+use config::File;
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use tokio::time::sleep;
+use tokio::time::Duration;
+use zbus::dbus_interface;
+use zbus::Connection;
+use zbus::ConnectionBuilder;
+use zbus::SignalContext;
+use zvariant::OwnedObjectPath;
+use zvariant::OwnedValue;
+use zvariant::Str;
+use zvariant::Value;
+
+use crate::error::Error;
+
+/// The environment variable used for providing the path to a scenario file
+pub const SCENARIO_ENV: &str = "CATERPILLAR_MOCK_SCENARIO";
+
+/// The connection on which the fake services are served
+static CONNECTION: OnceCell<Connection> = OnceCell::new();
+
+/// A fake RAUC slot
+#[derive(Clone, Debug, Deserialize)]
+pub struct MockSlot {
+    name: String,
+    class: String,
+    #[serde(default)]
+    device: String,
+    #[serde(default)]
+    state: String,
+    #[serde(default)]
+    version: Option<String>,
+}
+
+/// A fake block device
+///
+/// The `path` is a local directory, that is returned as mountpoint when the block device is mounted.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MockDevice {
+    name: String,
+    path: PathBuf,
+    #[serde(default = "default_partition_type")]
+    partition_type: String,
+    #[serde(default = "default_number")]
+    number: u32,
+    #[serde(default = "default_id_usage")]
+    id_usage: String,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    vendor: String,
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    serial: String,
+}
+
+/// A fake update bundle
+///
+/// The `path` must point to an existing file.
+/// If `fail` is true, the installation of the update bundle fails.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MockBundle {
+    path: PathBuf,
+    compatible: String,
+    version: String,
+    #[serde(default)]
+    fail: bool,
+}
+
+/// A scenario for the fake services
+#[derive(Clone, Debug, Deserialize)]
+pub struct Scenario {
+    compatible: String,
+    #[serde(default)]
+    variant: String,
+    boot_slot: String,
+    primary: String,
+    #[serde(default)]
+    slots: Vec<MockSlot>,
+    #[serde(default)]
+    devices: Vec<MockDevice>,
+    #[serde(default)]
+    bundles: Vec<MockBundle>,
+}
+
+fn default_partition_type() -> String {
+    "0x0c".to_string()
+}
+
+fn default_number() -> u32 {
+    1
+}
+
+fn default_id_usage() -> String {
+    "filesystem".to_string()
+}
+
+impl Scenario {
+    /// Read a Scenario from a TOML file
+    pub fn read(path: &str) -> Result<Self, Error> {
+        Ok(Config::builder()
+            .add_source(File::from(Path::new(path)))
+            .build()?
+            .try_deserialize()?)
+    }
+}
+
+/// Return the object path of a fake block device
+fn block_path(name: &str) -> String {
+    format!("/org/freedesktop/UDisks2/block_devices/{}", name)
+}
+
+/// Return the object path of the drive of a fake block device
+fn drive_path(name: &str) -> String {
+    format!("/org/freedesktop/UDisks2/drives/{}", name)
+}
+
+/// A fake `de.pengutronix.rauc.Installer`
+struct MockInstaller {
+    scenario: Scenario,
+    last_error: String,
+}
+
+#[dbus_interface(name = "de.pengutronix.rauc.Installer")]
+impl MockInstaller {
+    #[dbus_interface(name = "GetPrimary")]
+    fn get_primary(&self) -> String {
+        self.scenario.primary.clone()
+    }
+
+    #[dbus_interface(name = "GetSlotStatus")]
+    fn get_slot_status(&self) -> Vec<(String, HashMap<String, OwnedValue>)> {
+        self.scenario
+            .slots
+            .iter()
+            .map(|slot| {
+                let mut status = HashMap::from([
+                    ("class".to_string(), Str::from(slot.class.clone()).into()),
+                    ("device".to_string(), Str::from(slot.device.clone()).into()),
+                    ("state".to_string(), Str::from(slot.state.clone()).into()),
+                ]);
+                if let Some(version) = &slot.version {
+                    status.insert(
+                        "bundle.version".to_string(),
+                        Str::from(version.clone()).into(),
+                    );
+                }
+                (slot.name.clone(), status)
+            })
+            .collect()
+    }
+
+    #[dbus_interface(name = "Info")]
+    fn info(&self, bundle: &str) -> zbus::fdo::Result<(String, String)> {
+        match self
+            .scenario
+            .bundles
+            .iter()
+            .find(|x| x.path.to_string_lossy() == bundle)
+        {
+            Some(bundle) => Ok((bundle.compatible.clone(), bundle.version.clone())),
+            None => Err(zbus::fdo::Error::Failed(format!(
+                "{} is not a bundle of the scenario",
+                bundle
+            ))),
+        }
+    }
+
+    #[dbus_interface(name = "InspectBundle")]
+    fn inspect_bundle(
+        &self,
+        _source: &str,
+        _args: HashMap<&str, Value<'_>>,
+    ) -> zbus::fdo::Result<HashMap<String, OwnedValue>> {
+        Err(zbus::fdo::Error::NotSupported(
+            "Inspecting bundles is not supported by the mock backend".to_string(),
+        ))
+    }
+
+    #[dbus_interface(name = "InstallBundle")]
+    async fn install_bundle(
+        &mut self,
+        source: &str,
+        _args: HashMap<&str, Value<'_>>,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> zbus::fdo::Result<()> {
+        println!("Mock: installing update bundle {}", source);
+        let fail = self
+            .scenario
+            .bundles
+            .iter()
+            .find(|x| x.path.to_string_lossy() == source)
+            .map_or(true, |x| x.fail);
+        self.last_error = if fail {
+            format!("Mock: installation of {} failed", source)
+        } else {
+            String::new()
+        };
+        let ctxt = ctxt.to_owned();
+        // emit the Completed signal after the method call returned
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(500)).await;
+            MockInstaller::completed(&ctxt, i32::from(fail)).await
+        });
+        Ok(())
+    }
+
+    #[dbus_interface(signal)]
+    async fn completed(ctxt: &SignalContext<'_>, result: i32) -> zbus::Result<()>;
+
+    #[dbus_interface(property, name = "BootSlot")]
+    fn boot_slot(&self) -> String {
+        self.scenario.boot_slot.clone()
+    }
+
+    #[dbus_interface(property, name = "Compatible")]
+    fn compatible(&self) -> String {
+        self.scenario.compatible.clone()
+    }
+
+    #[dbus_interface(property, name = "LastError")]
+    fn last_error(&self) -> String {
+        self.last_error.clone()
+    }
+
+    #[dbus_interface(property, name = "Operation")]
+    fn operation(&self) -> String {
+        "idle".to_string()
+    }
+
+    #[dbus_interface(property, name = "Variant")]
+    fn variant(&self) -> String {
+        self.scenario.variant.clone()
+    }
+}
+
+/// A fake `org.freedesktop.UDisks2.Manager`
+struct MockManager {
+    devices: Vec<MockDevice>,
+}
+
+#[dbus_interface(name = "org.freedesktop.UDisks2.Manager")]
+impl MockManager {
+    #[dbus_interface(name = "GetBlockDevices")]
+    fn get_block_devices(&self, _options: HashMap<&str, Value<'_>>) -> Vec<OwnedObjectPath> {
+        self.devices
+            .iter()
+            .filter_map(|device| OwnedObjectPath::try_from(block_path(&device.name)).ok())
+            .collect()
+    }
+
+    #[dbus_interface(name = "ResolveDevice")]
+    fn resolve_device(
+        &self,
+        devspec: HashMap<&str, Value<'_>>,
+        _options: HashMap<&str, Value<'_>>,
+    ) -> Vec<OwnedObjectPath> {
+        let path = match devspec.get("path") {
+            Some(Value::Str(path)) => path.to_string(),
+            _ => return vec![],
+        };
+        self.devices
+            .iter()
+            .filter(|device| format!("/dev/{}", device.name) == path)
+            .filter_map(|device| OwnedObjectPath::try_from(block_path(&device.name)).ok())
+            .collect()
+    }
+
+    #[dbus_interface(property, name = "Version")]
+    fn version(&self) -> String {
+        "mock".to_string()
+    }
+}
+
+/// A fake `org.freedesktop.UDisks2.Block`
+struct MockBlock {
+    device: MockDevice,
+}
+
+#[dbus_interface(name = "org.freedesktop.UDisks2.Block")]
+impl MockBlock {
+    #[dbus_interface(property, name = "Drive")]
+    fn drive(&self) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(drive_path(&self.device.name)).unwrap()
+    }
+
+    #[dbus_interface(property, name = "IdUsage")]
+    fn id_usage(&self) -> String {
+        self.device.id_usage.clone()
+    }
+
+    #[dbus_interface(property, name = "Size")]
+    fn size(&self) -> u64 {
+        self.device.size
+    }
+}
+
+/// A fake `org.freedesktop.UDisks2.Partition`
+struct MockPartition {
+    device: MockDevice,
+}
+
+#[dbus_interface(name = "org.freedesktop.UDisks2.Partition")]
+impl MockPartition {
+    #[dbus_interface(property, name = "Number")]
+    fn number(&self) -> u32 {
+        self.device.number
+    }
+
+    #[dbus_interface(property, name = "Type")]
+    fn type_(&self) -> String {
+        self.device.partition_type.clone()
+    }
+}
+
+/// A fake `org.freedesktop.UDisks2.Filesystem`
+///
+/// Mounting returns the path of the MockDevice without touching the filesystem.
+struct MockFilesystem {
+    device: MockDevice,
+    mounted: bool,
+}
+
+#[dbus_interface(name = "org.freedesktop.UDisks2.Filesystem")]
+impl MockFilesystem {
+    #[dbus_interface(name = "Mount")]
+    fn mount(&mut self, _options: HashMap<&str, Value<'_>>) -> zbus::fdo::Result<String> {
+        if self.mounted {
+            return Err(zbus::fdo::Error::Failed(format!(
+                "{} is already mounted",
+                self.device.name
+            )));
+        }
+        println!("Mock: mounting {}", self.device.name);
+        self.mounted = true;
+        Ok(self.device.path.to_string_lossy().to_string())
+    }
+
+    #[dbus_interface(name = "Unmount")]
+    fn unmount(&mut self, _options: HashMap<&str, Value<'_>>) -> zbus::fdo::Result<()> {
+        println!("Mock: unmounting {}", self.device.name);
+        self.mounted = false;
+        Ok(())
+    }
+
+    #[dbus_interface(property, name = "MountPoints")]
+    fn mount_points(&self) -> Vec<Vec<u8>> {
+        if self.mounted {
+            let mut mountpoint = self.device.path.to_string_lossy().as_bytes().to_vec();
+            mountpoint.push(0);
+            vec![mountpoint]
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// A fake `org.freedesktop.UDisks2.Drive`
+struct MockDrive {
+    device: MockDevice,
+}
+
+#[dbus_interface(name = "org.freedesktop.UDisks2.Drive")]
+impl MockDrive {
+    #[dbus_interface(name = "Eject")]
+    fn eject(&self, _options: HashMap<&str, Value<'_>>) {
+        println!("Mock: ejecting drive of {}", self.device.name);
+    }
+
+    #[dbus_interface(name = "PowerOff")]
+    fn power_off(&self, _options: HashMap<&str, Value<'_>>) {
+        println!("Mock: powering off drive of {}", self.device.name);
+    }
+
+    #[dbus_interface(property, name = "Model")]
+    fn model(&self) -> String {
+        self.device.model.clone()
+    }
+
+    #[dbus_interface(property, name = "Serial")]
+    fn serial(&self) -> String {
+        self.device.serial.clone()
+    }
+
+    #[dbus_interface(property, name = "Vendor")]
+    fn vendor(&self) -> String {
+        self.device.vendor.clone()
+    }
+}
+
+/// A fake `org.freedesktop.login1.Manager`
+struct MockLogin;
+
+#[dbus_interface(name = "org.freedesktop.login1.Manager")]
+impl MockLogin {
+    #[dbus_interface(name = "Reboot")]
+    fn reboot(&self, _interactive: bool) {
+        println!("Mock: reboot requested");
+    }
+}
+
+/// A fake `org.freedesktop.systemd1.Manager`
+struct MockSystemd;
+
+#[dbus_interface(name = "org.freedesktop.systemd1.Manager")]
+impl MockSystemd {
+    #[dbus_interface(name = "Reboot")]
+    fn reboot(&self) {
+        println!("Mock: reboot requested");
+    }
+
+    #[dbus_interface(name = "StartUnit")]
+    fn start_unit(&self, name: &str, _mode: &str) -> OwnedObjectPath {
+        println!("Mock: starting unit {}", name);
+        OwnedObjectPath::try_from("/org/freedesktop/systemd1/job/1").unwrap()
+    }
+
+    #[dbus_interface(property, name = "Version")]
+    fn version(&self) -> String {
+        "mock".to_string()
+    }
+}
+
+/// Start the fake services on the session bus
+///
+/// The scenario is read from the file provided by the [`SCENARIO_ENV`] environment variable.
+pub async fn start() -> Result<(), Error> {
+    let path = var(SCENARIO_ENV).map_err(|_| {
+        Error::Default(format!(
+            "The {} environment variable must point at a scenario file",
+            SCENARIO_ENV
+        ))
+    })?;
+    println!("Starting mock backends using scenario {}", path);
+    let scenario = Scenario::read(&path)?;
+
+    let mut builder = ConnectionBuilder::session()?
+        .name("de.pengutronix.rauc")?
+        .name("org.freedesktop.UDisks2")?
+        .name("org.freedesktop.login1")?
+        .name("org.freedesktop.systemd1")?
+        .serve_at(
+            "/",
+            MockInstaller {
+                scenario: scenario.clone(),
+                last_error: String::new(),
+            },
+        )?
+        .serve_at(
+            "/org/freedesktop/UDisks2/Manager",
+            MockManager {
+                devices: scenario.devices.clone(),
+            },
+        )?
+        .serve_at("/org/freedesktop/login1", MockLogin)?
+        .serve_at("/org/freedesktop/systemd1", MockSystemd)?;
+    for device in scenario.devices.iter() {
+        builder = builder
+            .serve_at(
+                block_path(&device.name),
+                MockBlock {
+                    device: device.clone(),
+                },
+            )?
+            .serve_at(
+                block_path(&device.name),
+                MockPartition {
+                    device: device.clone(),
+                },
+            )?
+            .serve_at(
+                block_path(&device.name),
+                MockFilesystem {
+                    device: device.clone(),
+                    mounted: false,
+                },
+            )?
+            .serve_at(
+                drive_path(&device.name),
+                MockDrive {
+                    device: device.clone(),
+                },
+            )?;
+    }
+
+    CONNECTION
+        .set(builder.build().await?)
+        .map_err(|_| Error::Default("The mock backends are already started".to_string()))
+}
+
+/// Return a connection to the fake services
+pub fn connection() -> zbus::Result<Connection> {
+    CONNECTION.get().cloned().ok_or(zbus::Error::Failure(
+        "The mock backends are not started".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+    use testdir::testdir;
+    use testresult::TestResult;
+
+    #[test]
+    fn test_read_scenario() -> TestResult {
+        let path = testdir!().join("scenario.toml");
+        write(
+            &path,
+            r#"
+compatible = "mock"
+boot_slot = "A"
+primary = "rootfs.0"
+
+[[slots]]
+name = "rootfs.0"
+class = "rootfs"
+state = "booted"
+version = "1.0.0"
+
+[[devices]]
+name = "sdb1"
+path = "/tmp/sdb1"
+
+[[bundles]]
+path = "/tmp/sdb1/update.raucb"
+compatible = "mock"
+version = "2.0.0"
+"#,
+        )?;
+        let scenario = Scenario::read(&path.to_string_lossy())?;
+        assert_eq!(scenario.compatible, "mock");
+        assert_eq!(scenario.slots[0].version, Some("1.0.0".to_string()));
+        assert_eq!(scenario.devices[0].partition_type, "0x0c");
+        assert_eq!(scenario.devices[0].number, 1);
+        assert!(!scenario.bundles[0].fail);
+        Ok(())
+    }
+}
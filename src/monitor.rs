@@ -0,0 +1,462 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! A terminal UI for monitoring a running instance
+//!
+//! The monitor subscribes to the signals and property changes of the D-Bus interface of a running instance and renders
+//! the current pipeline (devices, update bundles, installation progress and pending reboots) on each change.
+use std::collections::VecDeque;
+use std::io::Write;
+
+use chrono::Local;
+use futures::StreamExt;
+use tokio::signal::unix::SignalKind;
+use zbus::fdo::PropertiesProxy;
+use zbus::names::InterfaceName;
+use zbus::Message;
+use zbus::Proxy;
+use zvariant::OwnedObjectPath;
+use zvariant::Value;
+
+use crate::cli::Bus;
+use crate::dbus::INTERFACE;
+use crate::error::Error;
+
+/// The number of events shown in the monitor
+const EVENTS: usize = 8;
+/// The width of the progress bar
+const PROGRESS_WIDTH: usize = 40;
+
+/// An update bundle as provided by the UpdateFound signal
+type UpdateInfo = (
+    String,
+    String,
+    String,
+    bool,
+    String,
+    u64,
+    String,
+    String,
+    String,
+);
+
+/// A device, that is searched for update bundles
+#[derive(Clone, Debug, PartialEq)]
+struct MonitoredDevice {
+    device: String,
+    mountpoint: String,
+    bundles: Option<usize>,
+}
+
+/// An update bundle, that has been found
+#[derive(Clone, Debug, PartialEq)]
+struct Candidate {
+    path: String,
+    version: String,
+    is_override: bool,
+    device: String,
+}
+
+/// The state of a running instance, as seen by the monitor
+#[derive(Debug, Default)]
+struct Monitor {
+    daemon_version: String,
+    state: String,
+    transaction: u64,
+    progress: (i32, String),
+    inhibited: bool,
+    marked_for_reboot: bool,
+    updated: bool,
+    last_error: String,
+    rauc_operation: String,
+    reboot: Option<u64>,
+    devices: Vec<MonitoredDevice>,
+    candidates: Vec<Candidate>,
+    events: VecDeque<String>,
+}
+
+impl Monitor {
+    /// Add an event, only keeping the latest EVENTS
+    fn event(&mut self, message: String) {
+        self.events
+            .push_back(format!("{} {}", Local::now().format("%H:%M:%S"), message));
+        while self.events.len() > EVENTS {
+            self.events.pop_front();
+        }
+    }
+
+    /// Apply the change of a property of the interface
+    fn apply_property(&mut self, name: &str, value: &Value<'_>) {
+        let value = value.clone();
+        match name {
+            "DaemonVersion" => self.daemon_version = value.try_into().unwrap_or_default(),
+            "State" => self.state = value.try_into().unwrap_or_default(),
+            "Transaction" => {
+                let transaction = value.try_into().unwrap_or_default();
+                // a new transaction starts a new search
+                if transaction != self.transaction {
+                    self.candidates.clear();
+                }
+                self.transaction = transaction;
+            }
+            "Progress" => self.progress = value.try_into().unwrap_or_default(),
+            "Inhibited" => self.inhibited = value.try_into().unwrap_or_default(),
+            "MarkedForReboot" => self.marked_for_reboot = value.try_into().unwrap_or_default(),
+            "Updated" => self.updated = value.try_into().unwrap_or_default(),
+            "LastError" => self.last_error = value.try_into().unwrap_or_default(),
+            // the list of devices is empty while devices are mounted, which is tracked using signals instead
+            "DevicesFound" => {
+                let devices: Vec<(OwnedObjectPath, String, String, u32)> =
+                    value.try_into().unwrap_or_default();
+                if !devices.is_empty() {
+                    self.devices = devices
+                        .into_iter()
+                        .map(|(_, device, mountpoint, bundles)| MonitoredDevice {
+                            device,
+                            mountpoint,
+                            bundles: Some(bundles as usize),
+                        })
+                        .collect();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply a signal of the interface
+    fn apply_signal(&mut self, message: &Message) -> Result<(), Error> {
+        let member = match message.member() {
+            Some(member) => member.to_string(),
+            None => return Ok(()),
+        };
+        match member.as_str() {
+            "DeviceMounted" => {
+                let (device, mountpoint): (String, String) = message.body()?;
+                self.event(format!("Mounted {} to {}", device, mountpoint));
+                self.devices.retain(|x| x.device != device);
+                self.devices.push(MonitoredDevice {
+                    device,
+                    mountpoint,
+                    bundles: None,
+                });
+            }
+            "DeviceScanned" => {
+                let (_, device, bundles, override_bundles): (
+                    u64,
+                    String,
+                    Vec<String>,
+                    Vec<String>,
+                ) = message.body()?;
+                let found = bundles.len() + override_bundles.len();
+                self.event(format!("Found {} update bundle(s) on {}", found, device));
+                match self.devices.iter_mut().find(|x| x.device == device) {
+                    Some(monitored) => monitored.bundles = Some(found),
+                    None => self.devices.push(MonitoredDevice {
+                        device,
+                        mountpoint: String::new(),
+                        bundles: Some(found),
+                    }),
+                }
+            }
+            "DeviceUnmounted" => {
+                let (device, mountpoint): (String, String) = message.body()?;
+                self.event(format!("Unmounted {} from {}", device, mountpoint));
+                self.devices.retain(|x| x.device != device);
+            }
+            "DeviceReleased" => {
+                let (device, action): (String, String) = message.body()?;
+                self.event(format!("Released {} ({})", device, action));
+            }
+            "UpdateFound" => {
+                let (_, updates): (u64, Vec<UpdateInfo>) = message.body()?;
+                self.candidates = updates
+                    .into_iter()
+                    .map(|update| Candidate {
+                        path: update.0,
+                        version: update.2,
+                        is_override: update.3,
+                        device: update.6,
+                    })
+                    .collect();
+                for candidate in self.candidates.clone() {
+                    self.event(format!(
+                        "Found update {} ({})",
+                        candidate.path, candidate.version
+                    ));
+                }
+            }
+            "UpdateInstalled" => {
+                let (_, bundle, version, _): (u64, String, String, bool) = message.body()?;
+                self.event(format!("Installed {} ({})", bundle, version));
+            }
+            "ErrorOccurred" => {
+                let (_, error): (u64, String) = message.body()?;
+                self.event(format!("Error: {}", error));
+            }
+            "BundleQuarantined" => {
+                let (bundle, version, failures): (String, String, u32) = message.body()?;
+                self.event(format!(
+                    "Quarantined {} ({}) after {} failure(s)",
+                    bundle, version, failures
+                ));
+            }
+            "ClockImplausible" => {
+                let (time, floor): (i64, i64) = message.body()?;
+                self.event(format!(
+                    "System time {} is implausible (earlier than {})",
+                    time, floor
+                ));
+            }
+            "InstallDeferred" => {
+                let delay: u64 = message.body()?;
+                self.event(format!("Installation deferred by {}s", delay));
+            }
+            "OperationTimedOut" => {
+                let (state, duration): (String, u64) = message.body()?;
+                self.event(format!("State {} timed out after {}s", state, duration));
+            }
+            "RaucOperationChanged" => {
+                self.rauc_operation = message.body()?;
+            }
+            "RebootPending" => {
+                let delay: u64 = message.body()?;
+                self.event(format!("Reboot pending in {}s", delay));
+                self.reboot = Some(delay);
+            }
+            "RebootCountdown" => {
+                self.reboot = Some(message.body()?);
+            }
+            "RebootCancelled" => {
+                self.event("Reboot cancelled".to_string());
+                self.reboot = None;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Render the state as text
+    fn render(&self) -> String {
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+        let mut lines = vec![
+            format!(
+                "{} monitor ({} {})",
+                env!("CARGO_BIN_NAME"),
+                INTERFACE,
+                if self.daemon_version.is_empty() {
+                    "unavailable"
+                } else {
+                    self.daemon_version.as_str()
+                }
+            ),
+            String::new(),
+            format!(
+                "State:       {} (transaction {})",
+                self.state, self.transaction
+            ),
+            format!(
+                "Progress:    {} {}",
+                progress_bar(self.progress.0, PROGRESS_WIDTH),
+                self.progress.1
+            ),
+            format!(
+                "RAUC:        {}",
+                if self.rauc_operation.is_empty() {
+                    "unknown"
+                } else {
+                    self.rauc_operation.as_str()
+                }
+            ),
+            format!("Inhibited:   {}", yes_no(self.inhibited)),
+            format!("Updated:     {}", yes_no(self.updated)),
+            format!(
+                "Reboot:      {}",
+                match self.reboot {
+                    Some(remaining) => format!("pending in {}s", remaining),
+                    None if self.marked_for_reboot => "marked".to_string(),
+                    None => "none".to_string(),
+                }
+            ),
+            format!("Last error:  {}", self.last_error),
+            String::new(),
+            "Devices:".to_string(),
+        ];
+        if self.devices.is_empty() {
+            lines.push("  none".to_string());
+        }
+        for device in self.devices.iter() {
+            lines.push(format!(
+                "  {} {}{}",
+                device.device,
+                if device.mountpoint.is_empty() {
+                    String::new()
+                } else {
+                    format!("at {}", device.mountpoint)
+                },
+                match device.bundles {
+                    Some(bundles) => format!(" ({} update bundle(s))", bundles),
+                    None => " (searching)".to_string(),
+                }
+            ));
+        }
+
+        lines.push(String::new());
+        lines.push("Updates:".to_string());
+        if self.candidates.is_empty() {
+            lines.push("  none".to_string());
+        }
+        for candidate in self.candidates.iter() {
+            lines.push(format!(
+                "  {} {}{} on {}",
+                candidate.version,
+                candidate.path,
+                if candidate.is_override {
+                    " (override)"
+                } else {
+                    ""
+                },
+                candidate.device
+            ));
+        }
+
+        lines.push(String::new());
+        lines.push("Events:".to_string());
+        lines.extend(self.events.iter().map(|event| format!("  {}", event)));
+        lines.push(String::new());
+        lines.push("Press Ctrl+C to quit.".to_string());
+        lines.join("\n")
+    }
+}
+
+/// Return a progress bar of a width for a percentage
+fn progress_bar(percentage: i32, width: usize) -> String {
+    let filled = (percentage.clamp(0, 100) as usize * width) / 100;
+    format!(
+        "[{}{}] {:>3}%",
+        "#".repeat(filled),
+        "-".repeat(width - filled),
+        percentage.clamp(0, 100)
+    )
+}
+
+/// Draw the state of the monitor on the (alternate) screen of the terminal
+fn draw(monitor: &Monitor) -> Result<(), Error> {
+    let mut stdout = std::io::stdout().lock();
+    // move to the top left corner and clear the screen
+    write!(
+        stdout,
+        "\x1b[H\x1b[2J{}",
+        monitor.render().replace('\n', "\r\n")
+    )?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Monitor a running instance on a bus until interrupted
+pub async fn run(bus: &Bus) -> Result<(), Error> {
+    let connection = bus.connection_builder()?.build().await?;
+    let interface = InterfaceName::try_from(INTERFACE).unwrap();
+    let properties_proxy = PropertiesProxy::builder(&connection)
+        .destination("de.sleepmap.Caterpillar")?
+        .path("/de/sleepmap/Caterpillar")?
+        .build()
+        .await?;
+    let proxy = Proxy::new(
+        &connection,
+        "de.sleepmap.Caterpillar",
+        "/de/sleepmap/Caterpillar",
+        INTERFACE,
+    )
+    .await?;
+    let mut properties_changed = properties_proxy.receive_properties_changed().await?;
+    let mut signals = proxy.receive_all_signals().await?;
+    let mut sigterm = tokio::signal::unix::signal(SignalKind::terminate())?;
+    let mut sigint = tokio::signal::unix::signal(SignalKind::interrupt())?;
+
+    let mut monitor = Monitor::default();
+    // the instance may not be running yet, in which case its properties are received once it starts
+    match properties_proxy.get_all(interface.clone()).await {
+        Ok(properties) => {
+            for (name, value) in properties.iter() {
+                monitor.apply_property(name, value);
+            }
+        }
+        Err(error) => monitor.event(format!("Instance unavailable: {}", error)),
+    }
+
+    // use the alternate screen and hide the cursor
+    print!("\x1b[?1049h\x1b[?25l");
+    let result = loop {
+        if let Err(error) = draw(&monitor) {
+            break Err(error);
+        }
+        tokio::select! {
+            Some(change) = properties_changed.next() => {
+                if let Ok(args) = change.args() {
+                    if args.interface_name() == &interface {
+                        for (name, value) in args.changed_properties().iter() {
+                            monitor.apply_property(name, value);
+                        }
+                    }
+                }
+            }
+            Some(message) = signals.next() => {
+                if let Err(error) = monitor.apply_signal(&message) {
+                    monitor.event(format!("Invalid signal: {}", error));
+                }
+            }
+            _ = sigterm.recv() => break Ok(()),
+            _ = sigint.recv() => break Ok(()),
+            else => break Ok(()),
+        }
+    };
+    // restore the screen and the cursor
+    print!("\x1b[?25h\x1b[?1049l");
+    std::io::stdout().flush()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(0, 10, "[----------]   0%")]
+    #[case(45, 10, "[####------]  45%")]
+    #[case(100, 10, "[##########] 100%")]
+    #[case(-1, 10, "[----------]   0%")]
+    #[case(120, 4, "[####] 100%")]
+    fn test_progress_bar(#[case] percentage: i32, #[case] width: usize, #[case] bar: &str) {
+        assert_eq!(progress_bar(percentage, width), bar);
+    }
+
+    #[rstest]
+    fn test_monitor_render() {
+        let mut monitor = Monitor::default();
+        monitor.apply_property("State", &Value::from("mounted"));
+        monitor.apply_property("Transaction", &Value::from(3u64));
+        monitor.apply_property(
+            "Progress",
+            &Value::from((50i32, "Searching for update bundles")),
+        );
+        monitor.apply_property("MarkedForReboot", &Value::from(true));
+        monitor.devices.push(MonitoredDevice {
+            device: "/dev/sda1".to_string(),
+            mountpoint: "/run/media/root/disk".to_string(),
+            bundles: Some(2),
+        });
+        for _ in 0..(EVENTS + 2) {
+            monitor.event("Found update".to_string());
+        }
+        assert_eq!(monitor.events.len(), EVENTS);
+
+        let screen = monitor.render();
+        assert!(screen.contains("State:       mounted (transaction 3)"));
+        assert!(screen.contains("50% Searching for update bundles"));
+        assert!(screen.contains("Reboot:      marked"));
+        assert!(screen.contains("/dev/sda1 at /run/media/root/disk (2 update bundle(s))"));
+
+        monitor.reboot = Some(30);
+        assert!(monitor.render().contains("Reboot:      pending in 30s"));
+    }
+}
@@ -0,0 +1,211 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::fs::create_dir_all;
+use std::fs::read_to_string;
+use std::fs::write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde_json::json;
+use serde_json::Value;
+
+use crate::clock::now;
+use crate::error::Error;
+use crate::machine::Machine;
+
+/// The name of the result report, that is written onto update media
+pub const RESULT_REPORT_FILE: &str = "caterpillar-result.json";
+
+/// The outcome of the most recent attempt to install an update bundle
+///
+/// The outcome consists of the version of the update bundle, whether it has been installed successfully, the time of
+/// the attempt (in seconds since the epoch), the error, that occurred (empty on success) and whether the boot of the
+/// updated system has been confirmed.
+/// It is persisted as JSON object in a file, so that it is available after a restart (e.g. after rebooting into the
+/// updated system).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UpdateResult {
+    version: String,
+    success: bool,
+    timestamp: i64,
+    error: String,
+    confirmed: bool,
+}
+
+impl UpdateResult {
+    /// Create the UpdateResult of an attempt to install the update bundle with `version`, that happened just now
+    pub fn new(version: &str, result: &Result<(), String>) -> Self {
+        UpdateResult {
+            version: version.to_string(),
+            success: result.is_ok(),
+            timestamp: now(),
+            error: result.as_ref().err().cloned().unwrap_or_default(),
+            confirmed: false,
+        }
+    }
+
+    /// Read an UpdateResult from a file
+    ///
+    /// If the file does not exist or can not be parsed, None is returned.
+    pub fn read(path: &Path) -> Option<Self> {
+        let value: Value = serde_json::from_str(&read_to_string(path).ok()?).ok()?;
+        Some(UpdateResult {
+            version: value.get("version")?.as_str()?.to_string(),
+            success: value.get("success")?.as_bool()?,
+            timestamp: value.get("timestamp")?.as_i64()?,
+            error: value
+                .get("error")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            confirmed: value
+                .get("confirmed")
+                .and_then(Value::as_bool)
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Persist the UpdateResult in a file
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        write(
+            path,
+            json!({
+                "version": self.version,
+                "success": self.success,
+                "timestamp": self.timestamp,
+                "error": self.error,
+                "confirmed": self.confirmed,
+            })
+            .to_string(),
+        )?;
+        Ok(())
+    }
+
+    /// Return the version of the update bundle
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Return whether the update bundle has been installed successfully
+    pub fn success(&self) -> bool {
+        self.success
+    }
+
+    /// Return the error, that occurred (empty on success)
+    pub fn error(&self) -> &str {
+        &self.error
+    }
+
+    /// Return whether the boot of the updated system has been confirmed
+    pub fn confirmed(&self) -> bool {
+        self.confirmed
+    }
+
+    /// Mark the boot of the updated system as confirmed
+    pub fn confirm(&mut self) {
+        self.confirmed = true;
+    }
+
+    /// Return the UpdateResult as tuple of version, success, timestamp and error
+    pub fn to_tuple(&self) -> (String, bool, i64, String) {
+        (
+            self.version.clone(),
+            self.success,
+            self.timestamp,
+            self.error.clone(),
+        )
+    }
+}
+
+/// Append the report of an attempt to install an update bundle to the result report in a directory
+///
+/// The result report (see [`RESULT_REPORT_FILE`]) is a JSON array with one object per attempt, consisting of the
+/// machine ID and serial number of the machine, the version of the system before the attempt (empty, if unknown), the
+/// version of the update bundle, whether it has been installed successfully, the time of the attempt and the error, that
+/// occurred.
+/// Reports are appended, so that field technicians collecting update media can see which machines have been updated
+/// from them. An existing file, that is not a JSON array, is replaced.
+pub fn append_result_report(
+    dir: &Path,
+    machine: &Machine,
+    old_version: Option<&str>,
+    update_result: &UpdateResult,
+) -> Result<PathBuf, Error> {
+    let path = dir.join(RESULT_REPORT_FILE);
+    let mut reports = read_to_string(&path)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<Value>(&contents).ok())
+        .and_then(|value| match value {
+            Value::Array(reports) => Some(reports),
+            _ => None,
+        })
+        .unwrap_or_default();
+    reports.push(json!({
+        "machine_id": machine.machine_id().unwrap_or_default(),
+        "serial": machine.serial().unwrap_or_default(),
+        "old_version": old_version.unwrap_or_default(),
+        "new_version": update_result.version,
+        "success": update_result.success,
+        "timestamp": update_result.timestamp,
+        "error": update_result.error,
+    }));
+    write(
+        &path,
+        serde_json::to_string_pretty(&reports).map_err(|x| Error::Default(x.to_string()))?,
+    )?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use testdir::testdir;
+    use testresult::TestResult;
+
+    #[rstest]
+    #[case(Ok(()))]
+    #[case(Err("Installation error: Failed to check bundle".to_string()))]
+    fn test_update_result(#[case] result: Result<(), String>) -> TestResult {
+        let path = testdir!().join("state").join("last_update_result");
+        assert_eq!(UpdateResult::read(&path), None);
+
+        let update_result = UpdateResult::new("1.2.3", &result);
+        update_result.write(&path)?;
+        let read = UpdateResult::read(&path).unwrap();
+        assert_eq!(read, update_result);
+        assert_eq!(read.to_tuple().1, result.is_ok());
+        assert_eq!(read.to_tuple().3, result.err().unwrap_or_default());
+        assert!(!read.confirmed());
+
+        let mut update_result = read;
+        update_result.confirm();
+        update_result.write(&path)?;
+        assert!(UpdateResult::read(&path).unwrap().confirmed());
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_append_result_report() -> TestResult {
+        let dir = testdir!();
+        let update_result = UpdateResult::new("1.2.3", &Ok(()));
+
+        let path = append_result_report(&dir, &Machine::default(), Some("1.0.0"), &update_result)?;
+        append_result_report(&dir, &Machine::default(), Some("1.0.0"), &update_result)?;
+        let reports: Value = serde_json::from_str(&read_to_string(path)?)?;
+        let reports = reports.as_array().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0]["old_version"], "1.0.0");
+        assert_eq!(reports[0]["new_version"], "1.2.3");
+        assert_eq!(reports[0]["success"], true);
+
+        write(dir.join(RESULT_REPORT_FILE), "garbage")?;
+        append_result_report(&dir, &Machine::default(), None, &update_result)?;
+        let reports: Value = serde_json::from_str(&read_to_string(dir.join(RESULT_REPORT_FILE))?)?;
+        assert_eq!(reports.as_array().unwrap().len(), 1);
+        Ok(())
+    }
+}
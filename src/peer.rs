@@ -0,0 +1,106 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! Authorization of D-Bus callers using their peer credentials
+//!
+//! As a lighter-weight alternative to polkit (e.g. on embedded images without polkitd), the D-Bus methods changing the
+//! state of caterpillar can be restricted to root, the user running caterpillar and the members of a group (see the
+//! `authorized_group` configuration option).
+//! The user running caterpillar is always authorized, as caterpillar calls its own methods (e.g. to install an update
+//! automatically).
+//! The UID of a caller is requested from the bus, so that it can not be forged by the caller.
+use nix::unistd::Group;
+use nix::unistd::Uid;
+use nix::unistd::User;
+use zbus::fdo::DBusProxy;
+use zbus::names::BusName;
+use zbus::Connection;
+use zbus::MessageHeader;
+
+/// Return whether the user with `uid` is root, the user with `daemon_uid` or a member of a group
+///
+/// The user is a member of the group, if it is its primary group or if the user is listed in its members.
+fn is_authorized(uid: u32, daemon_uid: u32, user: Option<&User>, group: &Group) -> bool {
+    uid == 0
+        || uid == daemon_uid
+        || user.is_some_and(|user| user.gid == group.gid || group.mem.contains(&user.name))
+}
+
+/// Authorize the sender of a method call, if it is root, the user running caterpillar or a member of the group with the
+/// name `group`
+pub async fn authorize(
+    connection: &Connection,
+    header: &MessageHeader<'_>,
+    group: &str,
+) -> zbus::fdo::Result<()> {
+    let sender = header.sender()?.ok_or_else(|| {
+        zbus::fdo::Error::AccessDenied("The sender of the method call is unknown".to_string())
+    })?;
+    let uid = DBusProxy::new(connection)
+        .await?
+        .get_connection_unix_user(BusName::from(sender.to_owned()))
+        .await?;
+    let group = Group::from_name(group)
+        .map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?
+        .ok_or_else(|| zbus::fdo::Error::Failed(format!("The group {} does not exist", group)))?;
+    let user =
+        User::from_uid(Uid::from_raw(uid)).map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+
+    if is_authorized(uid, Uid::current().as_raw(), user.as_ref(), &group) {
+        Ok(())
+    } else {
+        eprintln!(
+            "Denying method call of {} (UID {}), which is neither root nor a member of group {}",
+            sender, uid, group.name
+        );
+        Err(zbus::fdo::Error::AccessDenied(format!(
+            "UID {} is neither root nor a member of group {}",
+            uid, group.name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::unistd::Gid;
+    use rstest::rstest;
+    use std::ffi::CString;
+    use std::path::PathBuf;
+
+    fn user(name: &str, uid: u32, gid: u32) -> User {
+        User {
+            name: name.to_string(),
+            passwd: CString::new("x").unwrap(),
+            uid: Uid::from_raw(uid),
+            gid: Gid::from_raw(gid),
+            gecos: CString::new("").unwrap(),
+            dir: PathBuf::from("/"),
+            shell: PathBuf::from("/bin/sh"),
+        }
+    }
+
+    #[rstest]
+    #[case(0, 999, None, true)]
+    #[case(1000, 999, None, false)]
+    #[case(999, 999, Some(user("caterpillar", 999, 999)), true)]
+    #[case(1000, 999, Some(user("operator", 1000, 900)), true)]
+    #[case(1001, 999, Some(user("maintainer", 1001, 1001)), true)]
+    #[case(1002, 999, Some(user("guest", 1002, 1002)), false)]
+    fn test_is_authorized(
+        #[case] uid: u32,
+        #[case] daemon_uid: u32,
+        #[case] user: Option<User>,
+        #[case] result: bool,
+    ) {
+        let group = Group {
+            name: "caterpillar-admin".to_string(),
+            passwd: CString::new("x").unwrap(),
+            gid: Gid::from_raw(900),
+            mem: vec!["maintainer".to_string()],
+        };
+        assert_eq!(
+            is_authorized(uid, daemon_uid, user.as_ref(), &group),
+            result
+        );
+    }
+}
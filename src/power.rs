@@ -0,0 +1,87 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use strum::Display;
+use strum::EnumString;
+use zbus::Connection;
+
+use crate::error::Error;
+use crate::proxy::upower::{DeviceProxy, UPowerProxy};
+
+/// The behavior of the power state check before installing an update
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq)]
+#[non_exhaustive]
+pub enum PowerCheck {
+    /// Do not check the power state
+    #[strum(ascii_case_insensitive, to_string = "disabled")]
+    Disabled,
+    /// Only warn about a low battery
+    #[strum(ascii_case_insensitive, to_string = "warn")]
+    Warn,
+    /// Refuse installation on low battery
+    #[strum(ascii_case_insensitive, to_string = "refuse")]
+    Refuse,
+}
+
+/// Get the charge (in percent) of the battery the system is running on
+///
+/// The state is retrieved from UPower's display device.
+/// If the system is not running on battery, None is returned.
+async fn get_battery_percentage(connection: &Connection) -> Result<Option<f64>, Error> {
+    println!("Connecting to UPower over dbus...");
+    let upower_proxy = UPowerProxy::new(connection).await?;
+    if !upower_proxy.on_battery().await? {
+        return Ok(None);
+    }
+    let device_proxy = DeviceProxy::new(connection).await?;
+    Ok(Some(device_proxy.percentage().await?))
+}
+
+/// Check whether the system runs on a battery charged below a threshold (in percent)
+///
+/// Depending on `power_check` a low battery (or an unavailable UPower) either leads to a warning or an error.
+pub async fn check_power(
+    connection: &Connection,
+    power_check: PowerCheck,
+    threshold: f64,
+) -> Result<(), Error> {
+    let result = match power_check {
+        PowerCheck::Disabled => return Ok(()),
+        _ => match get_battery_percentage(connection).await {
+            Ok(Some(percentage)) if percentage < threshold => {
+                Err(Error::LowBattery(percentage, threshold))
+            }
+            Ok(Some(percentage)) => {
+                println!("System is running on battery at {}%.", percentage);
+                Ok(())
+            }
+            Ok(None) => {
+                println!("System is not running on battery.");
+                Ok(())
+            }
+            Err(error) => Err(error),
+        },
+    };
+
+    match (power_check, result) {
+        (PowerCheck::Warn, Err(error)) => {
+            eprintln!("Warning: {}", error);
+            Ok(())
+        }
+        (_, result) => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::str::FromStr;
+
+    #[rstest]
+    #[case("disabled", PowerCheck::Disabled)]
+    #[case("Warn", PowerCheck::Warn)]
+    #[case("REFUSE", PowerCheck::Refuse)]
+    fn test_power_check_from_str(#[case] input: &str, #[case] power_check: PowerCheck) {
+        assert_eq!(PowerCheck::from_str(input).unwrap(), power_check);
+    }
+}
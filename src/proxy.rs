@@ -2,4 +2,6 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 pub mod login1;
 pub mod rauc;
+pub mod systemd1;
 pub mod udisks;
+pub mod upower;
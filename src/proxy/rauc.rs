@@ -23,6 +23,11 @@ use zbus::dbus_proxy;
     assume_defaults = true
 )]
 trait Installer {
+    /// GetArtifactRepositories method (RAUC >= 1.13)
+    fn get_artifact_repositories(
+        &self,
+    ) -> zbus::Result<Vec<std::collections::HashMap<String, zbus::zvariant::OwnedValue>>>;
+
     /// GetPrimary method
     fn get_primary(&self) -> zbus::Result<String>;
 
@@ -39,6 +44,13 @@ trait Installer {
     /// Info method
     fn info(&self, bundle: &str) -> zbus::Result<(String, String)>;
 
+    /// InspectBundle method
+    fn inspect_bundle(
+        &self,
+        source: &str,
+        args: std::collections::HashMap<&str, zbus::zvariant::Value<'_>>,
+    ) -> zbus::Result<std::collections::HashMap<String, zbus::zvariant::OwnedValue>>;
+
     /// Install method
     fn install(&self, source: &str) -> zbus::Result<()>;
 
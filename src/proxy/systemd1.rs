@@ -0,0 +1,32 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! # DBus interface proxy for: `org.freedesktop.systemd1.Manager`
+//!
+//! Only the subset of the interface used by caterpillar is covered.
+//! More extensive documentation can be found at https://www.freedesktop.org/software/systemd/man/org.freedesktop.systemd1.html
+//!
+//! More information can be found in the
+//! [Writing a client proxy](https://dbus.pages.freedesktop.org/zbus/client.html)
+//! section of the zbus documentation.
+
+// NOTE: this code is auto-generated, we do not care about clippy warnings
+#![allow(clippy::all)]
+use zbus::dbus_proxy;
+
+#[dbus_proxy(
+    assume_defaults = true,
+    default_path = "/org/freedesktop/systemd1",
+    default_service = "org.freedesktop.systemd1",
+    interface = "org.freedesktop.systemd1.Manager"
+)]
+trait Manager {
+    /// Reboot method
+    fn reboot(&self) -> zbus::Result<()>;
+
+    /// StartUnit method
+    fn start_unit(&self, name: &str, mode: &str) -> zbus::Result<zbus::zvariant::OwnedObjectPath>;
+
+    /// Version property
+    #[dbus_proxy(property)]
+    fn version(&self) -> zbus::Result<String>;
+}
@@ -0,0 +1,202 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! # DBus interface proxies for UPower components.
+//!
+//! Upstream documentation on the dbus interface can be found at https://upower.freedesktop.org/docs/
+//!
+//! The following proxies are covered:
+//! * `org.freedesktop.UPower`
+//! * `org.freedesktop.UPower.Device`
+//!
+//! This code was generated by `zbus-xmlgen` `3.1.0` from DBus introspection data.
+//! Source: `org.freedesktop.UPower.xml`.
+//!
+//! More information can be found in the
+//! [Writing a client proxy](https://dbus.pages.freedesktop.org/zbus/client.html)
+//! section of the zbus documentation.
+//!
+
+// NOTE: this code is auto-generated, we do not care about clippy warnings
+#![allow(clippy::all)]
+use zbus::dbus_proxy;
+
+#[dbus_proxy(
+    interface = "org.freedesktop.UPower",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower"
+)]
+trait UPower {
+    /// EnumerateDevices method
+    fn enumerate_devices(&self) -> zbus::Result<Vec<zbus::zvariant::OwnedObjectPath>>;
+
+    /// GetCriticalAction method
+    fn get_critical_action(&self) -> zbus::Result<String>;
+
+    /// GetDisplayDevice method
+    fn get_display_device(&self) -> zbus::Result<zbus::zvariant::OwnedObjectPath>;
+
+    /// DeviceAdded signal
+    #[dbus_proxy(signal)]
+    fn device_added(&self, device: zbus::zvariant::ObjectPath<'_>) -> zbus::Result<()>;
+
+    /// DeviceRemoved signal
+    #[dbus_proxy(signal)]
+    fn device_removed(&self, device: zbus::zvariant::ObjectPath<'_>) -> zbus::Result<()>;
+
+    /// DaemonVersion property
+    #[dbus_proxy(property)]
+    fn daemon_version(&self) -> zbus::Result<String>;
+
+    /// LidIsClosed property
+    #[dbus_proxy(property)]
+    fn lid_is_closed(&self) -> zbus::Result<bool>;
+
+    /// LidIsPresent property
+    #[dbus_proxy(property)]
+    fn lid_is_present(&self) -> zbus::Result<bool>;
+
+    /// OnBattery property
+    #[dbus_proxy(property)]
+    fn on_battery(&self) -> zbus::Result<bool>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.UPower.Device",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower/devices/DisplayDevice"
+)]
+trait Device {
+    /// GetHistory method
+    fn get_history(
+        &self,
+        type_: &str,
+        timespan: u32,
+        resolution: u32,
+    ) -> zbus::Result<Vec<(u32, f64, u32)>>;
+
+    /// GetStatistics method
+    fn get_statistics(&self, type_: &str) -> zbus::Result<Vec<(f64, f64)>>;
+
+    /// Refresh method
+    fn refresh(&self) -> zbus::Result<()>;
+
+    /// BatteryLevel property
+    #[dbus_proxy(property)]
+    fn battery_level(&self) -> zbus::Result<u32>;
+
+    /// Capacity property
+    #[dbus_proxy(property)]
+    fn capacity(&self) -> zbus::Result<f64>;
+
+    /// ChargeCycles property
+    #[dbus_proxy(property)]
+    fn charge_cycles(&self) -> zbus::Result<i32>;
+
+    /// Energy property
+    #[dbus_proxy(property)]
+    fn energy(&self) -> zbus::Result<f64>;
+
+    /// EnergyEmpty property
+    #[dbus_proxy(property)]
+    fn energy_empty(&self) -> zbus::Result<f64>;
+
+    /// EnergyFull property
+    #[dbus_proxy(property)]
+    fn energy_full(&self) -> zbus::Result<f64>;
+
+    /// EnergyFullDesign property
+    #[dbus_proxy(property)]
+    fn energy_full_design(&self) -> zbus::Result<f64>;
+
+    /// EnergyRate property
+    #[dbus_proxy(property)]
+    fn energy_rate(&self) -> zbus::Result<f64>;
+
+    /// HasHistory property
+    #[dbus_proxy(property)]
+    fn has_history(&self) -> zbus::Result<bool>;
+
+    /// HasStatistics property
+    #[dbus_proxy(property)]
+    fn has_statistics(&self) -> zbus::Result<bool>;
+
+    /// IconName property
+    #[dbus_proxy(property)]
+    fn icon_name(&self) -> zbus::Result<String>;
+
+    /// IsPresent property
+    #[dbus_proxy(property)]
+    fn is_present(&self) -> zbus::Result<bool>;
+
+    /// IsRechargeable property
+    #[dbus_proxy(property)]
+    fn is_rechargeable(&self) -> zbus::Result<bool>;
+
+    /// Luminosity property
+    #[dbus_proxy(property)]
+    fn luminosity(&self) -> zbus::Result<f64>;
+
+    /// Model property
+    #[dbus_proxy(property)]
+    fn model(&self) -> zbus::Result<String>;
+
+    /// NativePath property
+    #[dbus_proxy(property)]
+    fn native_path(&self) -> zbus::Result<String>;
+
+    /// Online property
+    #[dbus_proxy(property)]
+    fn online(&self) -> zbus::Result<bool>;
+
+    /// Percentage property
+    #[dbus_proxy(property)]
+    fn percentage(&self) -> zbus::Result<f64>;
+
+    /// PowerSupply property
+    #[dbus_proxy(property)]
+    fn power_supply(&self) -> zbus::Result<bool>;
+
+    /// Serial property
+    #[dbus_proxy(property)]
+    fn serial(&self) -> zbus::Result<String>;
+
+    /// State property
+    #[dbus_proxy(property)]
+    fn state(&self) -> zbus::Result<u32>;
+
+    /// Technology property
+    #[dbus_proxy(property)]
+    fn technology(&self) -> zbus::Result<u32>;
+
+    /// Temperature property
+    #[dbus_proxy(property)]
+    fn temperature(&self) -> zbus::Result<f64>;
+
+    /// TimeToEmpty property
+    #[dbus_proxy(property)]
+    fn time_to_empty(&self) -> zbus::Result<i64>;
+
+    /// TimeToFull property
+    #[dbus_proxy(property)]
+    fn time_to_full(&self) -> zbus::Result<i64>;
+
+    /// Type property
+    #[dbus_proxy(property)]
+    fn type_(&self) -> zbus::Result<u32>;
+
+    /// UpdateTime property
+    #[dbus_proxy(property)]
+    fn update_time(&self) -> zbus::Result<u64>;
+
+    /// Vendor property
+    #[dbus_proxy(property)]
+    fn vendor(&self) -> zbus::Result<String>;
+
+    /// Voltage property
+    #[dbus_proxy(property)]
+    fn voltage(&self) -> zbus::Result<f64>;
+
+    /// WarningLevel property
+    #[dbus_proxy(property)]
+    fn warning_level(&self) -> zbus::Result<u32>;
+}
@@ -0,0 +1,170 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::fs::create_dir_all;
+use std::fs::read_to_string;
+use std::fs::write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use semver::Version;
+
+use crate::error::Error;
+
+/// A persistent record of consecutive installation failures of update bundles
+///
+/// Update bundles are identified by their path and version.
+/// The record is stored in a file with one update bundle per line, consisting of the number of failures, the version and
+/// the path of the update bundle (separated by spaces).
+/// An update bundle is quarantined, once it has failed to install `threshold` consecutive times.
+#[derive(Clone, Debug)]
+pub struct Quarantine {
+    path: PathBuf,
+    threshold: u32,
+    failures: Vec<(u32, Version, String)>,
+}
+
+impl Quarantine {
+    /// Read a Quarantine from a file
+    ///
+    /// If the file does not exist, an empty Quarantine is returned.
+    /// Lines that can not be parsed are ignored.
+    /// A `threshold` of 0 disables quarantining of update bundles.
+    pub fn read(path: &Path, threshold: u32) -> Result<Self, Error> {
+        let failures = if path.exists() {
+            read_to_string(path)?
+                .lines()
+                .filter_map(|line| {
+                    let mut fields = line.trim().splitn(3, ' ');
+                    match (fields.next(), fields.next(), fields.next()) {
+                        (Some(failures), Some(version), Some(bundle)) => Some((
+                            failures.parse::<u32>().ok()?,
+                            Version::parse(version).ok()?,
+                            bundle.to_string(),
+                        )),
+                        _ => None,
+                    }
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        Ok(Quarantine {
+            path: path.into(),
+            threshold,
+            failures,
+        })
+    }
+
+    /// Return the number of consecutive installation failures of an update bundle
+    pub fn failures(&self, bundle: &str, version: &Version) -> u32 {
+        self.failures
+            .iter()
+            .find(|(_, failed_version, failed_bundle)| {
+                failed_bundle == bundle && failed_version == version
+            })
+            .map(|(failures, _, _)| *failures)
+            .unwrap_or_default()
+    }
+
+    /// Return whether an update bundle is quarantined
+    pub fn contains(&self, bundle: &str, version: &Version) -> bool {
+        self.threshold > 0 && self.failures(bundle, version) >= self.threshold
+    }
+
+    /// Record an installation failure of an update bundle and persist the Quarantine
+    ///
+    /// Returns whether the update bundle is quarantined as a result.
+    pub fn add_failure(&mut self, bundle: &str, version: &Version) -> Result<bool, Error> {
+        match self
+            .failures
+            .iter_mut()
+            .find(|(_, failed_version, failed_bundle)| {
+                failed_bundle == bundle && failed_version == version
+            }) {
+            Some((failures, _, _)) => *failures += 1,
+            None => self.failures.push((1, version.clone(), bundle.to_string())),
+        }
+        self.write()?;
+
+        let quarantined = self.contains(bundle, version);
+        if quarantined {
+            println!(
+                "Quarantined update bundle {} (version {}) after {} failed installations",
+                bundle,
+                version,
+                self.failures(bundle, version)
+            );
+        }
+        Ok(quarantined)
+    }
+
+    /// Reset the installation failures of an update bundle and persist the Quarantine
+    pub fn reset(&mut self, bundle: &str, version: &Version) -> Result<(), Error> {
+        let len = self.failures.len();
+        self.failures.retain(|(_, failed_version, failed_bundle)| {
+            failed_bundle != bundle || failed_version != version
+        });
+        if len != self.failures.len() {
+            self.write()?;
+        }
+        Ok(())
+    }
+
+    /// Persist the Quarantine
+    fn write(&self) -> Result<(), Error> {
+        if let Some(parent) = self.path.parent() {
+            create_dir_all(parent)?;
+        }
+        write(
+            &self.path,
+            self.failures
+                .iter()
+                .map(|(failures, version, bundle)| format!("{} {} {}\n", failures, version, bundle))
+                .collect::<String>(),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use testdir::testdir;
+    use testresult::TestResult;
+
+    #[rstest]
+    fn test_quarantine() -> TestResult {
+        let path = testdir!().join("state").join("quarantine");
+        let bundle = "/run/media/root/disk/update with spaces.raucb";
+        let version = Version::new(1, 0, 0);
+
+        let mut quarantine = Quarantine::read(&path, 2)?;
+        assert!(!quarantine.add_failure(bundle, &version)?);
+        assert!(!quarantine.contains(bundle, &version));
+        assert!(quarantine.add_failure(bundle, &version)?);
+
+        let mut quarantine = Quarantine::read(&path, 2)?;
+        assert!(quarantine.contains(bundle, &version));
+        assert!(!quarantine.contains(bundle, &Version::new(2, 0, 0)));
+        assert_eq!(quarantine.failures(bundle, &version), 2);
+        assert_eq!(read_to_string(&path)?, format!("2 1.0.0 {}\n", bundle));
+
+        quarantine.reset(bundle, &version)?;
+        assert_eq!(Quarantine::read(&path, 2)?.failures(bundle, &version), 0);
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_quarantine_disabled() -> TestResult {
+        let path = testdir!().join("quarantine");
+        let version = Version::new(1, 0, 0);
+
+        let mut quarantine = Quarantine::read(&path, 0)?;
+        assert!(!quarantine.add_failure("update.raucb", &version)?);
+        assert!(!quarantine.add_failure("update.raucb", &version)?);
+        assert!(!quarantine.contains("update.raucb", &version));
+        Ok(())
+    }
+}
@@ -1,30 +1,240 @@
 // SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 use std::cmp::Ordering;
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::fs::{remove_file, OpenOptions};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use async_std::stream::StreamExt;
 use futures::try_join;
+use nix::sys::statvfs::statvfs;
 use once_cell::sync::OnceCell;
 use semver::Version;
+use strum::EnumString;
+use tokio::time::timeout;
+use tokio::time::Duration;
+use zbus::fdo::DBusProxy;
+use zbus::fdo::NameOwnerChangedStream;
 use zbus::Connection;
-use zvariant::OwnedValue;
+use zvariant::{OwnedValue, Value};
 
+use crate::device::UdisksInfo;
 use crate::error::Error;
+use crate::machine::is_targeted;
+use crate::machine::Machine;
+use crate::proxy::rauc::CompletedStream;
 use crate::proxy::rauc::InstallerProxy;
+use manifest::Manifest;
+use signer::Signer;
+
+mod command;
+mod manifest;
+mod signer;
+
+/// The time after which RAUC's information on an update bundle is replaced by parsing its manifest offline
+const BUNDLE_INFO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An image contained in a RAUC update bundle
+///
+/// Images are exposed by the `slot_class` they target, their `size` (in bytes) and their `filename` in the bundle.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BundleImage {
+    slot_class: String,
+    size: u64,
+    filename: String,
+}
+
+impl BundleImage {
+    /// Create a new BundleImage
+    pub fn new(slot_class: &str, size: u64, filename: &str) -> Self {
+        BundleImage {
+            slot_class: slot_class.to_string(),
+            size,
+            filename: filename.to_string(),
+        }
+    }
+
+    /// Return whether the image is a casync index, whose chunks RAUC fetches (seeded from the active slot)
+    pub fn is_casync(&self) -> bool {
+        self.filename.ends_with(".caibx") || self.filename.ends_with(".caidx")
+    }
+
+    /// Get the slot class the image targets
+    pub fn slot_class(&self) -> &str {
+        &self.slot_class
+    }
+
+    /// Get the size of the image in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// An artifact repository of a RAUC instance (e.g. for applications or containers)
+///
+/// Artifact repositories are supported by RAUC >= 1.13 and are updated next to the image slots.
+/// Installing artifacts does not require a reboot.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ArtifactRepository {
+    name: String,
+    repository_type: String,
+    path: String,
+    artifacts: Vec<String>,
+}
+
+impl ArtifactRepository {
+    /// Create a new ArtifactRepository
+    pub fn new(name: &str, repository_type: &str, path: &str, artifacts: Vec<String>) -> Self {
+        ArtifactRepository {
+            name: name.to_string(),
+            repository_type: repository_type.to_string(),
+            path: path.to_string(),
+            artifacts,
+        }
+    }
+
+    /// Return the name of the repository (used as slot class by the images of update bundles)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the type of the repository (e.g. "files" or "trees")
+    pub fn repository_type(&self) -> &str {
+        &self.repository_type
+    }
+
+    /// Return the path of the repository
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Return the names of the artifacts installed in the repository
+    pub fn artifacts(&self) -> &[String] {
+        &self.artifacts
+    }
+}
+
+impl Display for ArtifactRepository {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "Artifact repository \"{}\" (type: {}; path: {}) artifacts: {:?}",
+            self.name, self.repository_type, self.path, self.artifacts,
+        )
+    }
+}
+
+/// TLS options passed to RAUC when installing update bundles streamed from HTTPS servers
+///
+/// A client certificate (`cert`) and its private key (`key`) allow for mutual authentication with the server, while a custom
+/// certificate authority (`ca`) is used for verifying the server.
+/// The options are ignored by RAUC for local update bundles.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TlsOptions {
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+    ca: Option<PathBuf>,
+}
+
+impl TlsOptions {
+    /// Create new TlsOptions
+    pub fn new(cert: Option<&Path>, key: Option<&Path>, ca: Option<&Path>) -> Self {
+        TlsOptions {
+            cert: cert.map(Path::to_path_buf),
+            key: key.map(Path::to_path_buf),
+            ca: ca.map(Path::to_path_buf),
+        }
+    }
+
+    /// Return the arguments for RAUC's InstallBundle method
+    fn args(&self) -> HashMap<&'static str, Value<'_>> {
+        [
+            ("tls-cert", &self.cert),
+            ("tls-key", &self.key),
+            ("tls-ca", &self.ca),
+        ]
+        .into_iter()
+        .filter_map(|(name, path)| {
+            path.as_ref()
+                .map(|path| (name, Value::from(path.display().to_string())))
+        })
+        .collect()
+    }
+
+    /// Return the arguments for `rauc install`
+    fn cli_args(&self) -> Vec<String> {
+        self.args()
+            .into_iter()
+            .filter_map(|(name, value)| match value {
+                Value::Str(path) => Some(format!("--{}={}", name, path)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The order in which compatible update bundles are offered, of which the first is selected for installation
+#[derive(Clone, Copy, Debug, EnumString, PartialEq)]
+#[non_exhaustive]
+pub enum BundleSort {
+    /// The update bundle with the highest version first
+    #[strum(ascii_case_insensitive, to_string = "version")]
+    Version,
+    /// The most recently modified update bundle first
+    #[strum(ascii_case_insensitive, to_string = "mtime")]
+    Mtime,
+    /// The update bundle with the lexicographically highest file name first
+    #[strum(ascii_case_insensitive, to_string = "filename")]
+    Filename,
+}
+
+impl BundleSort {
+    /// Sort a list of UpdateBundles, so that the best one is first
+    ///
+    /// Ties are broken by the version and then by the path of the UpdateBundles (highest first), so that the order does
+    /// not depend on the order in which the UpdateBundles have been found.
+    /// UpdateBundles without a modification time are sorted last by [`BundleSort::Mtime`].
+    pub fn sort(&self, bundles: &mut [UpdateBundle]) {
+        match self {
+            BundleSort::Version => {
+                bundles.sort_by(|a, b| b.cmp(a).then_with(|| b.path.cmp(&a.path)))
+            }
+            BundleSort::Mtime => bundles.sort_by_cached_key(|bundle| {
+                Reverse((
+                    bundle.path.metadata().and_then(|x| x.modified()).ok(),
+                    bundle.version.clone(),
+                    bundle.path.clone(),
+                ))
+            }),
+            BundleSort::Filename => bundles.sort_by_cached_key(|bundle| {
+                Reverse((
+                    bundle.path.file_name().map(ToOwned::to_owned),
+                    bundle.version.clone(),
+                    bundle.path.clone(),
+                ))
+            }),
+        }
+    }
+}
 
 /// RAUC update bundle
 ///
-/// RAUC update bundles are exposed by their `path`, the `variant` they are compatible with and their `version`.
-/// The information apart from the location is obtained from an `InstallerProxy`.
+/// RAUC update bundles are exposed by their `path`, the `variant` they are compatible with, their `version`, their
+/// `size` (in bytes), the `slot_classes` their images target and (once read) their `signer`.
+/// The information apart from the location and size is obtained from an `InstallerProxy` (or the `rauc` command line
+/// interface, if the RAUC D-Bus service is not available).
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct UpdateBundle {
     path: PathBuf,
     compatible: String,
     version: Version,
+    size: u64,
     is_override: bool,
+    slot_classes: Vec<String>,
+    signer: Option<Signer>,
 }
 
 impl UpdateBundle {
@@ -39,15 +249,86 @@ impl UpdateBundle {
         } else {
             return Err(Error::BundlePath(path.into()));
         };
-        let installer_proxy = InstallerProxy::new(connection).await?;
+        let size = path.metadata()?.len();
+        let installer_proxy = if let Some(installer_proxy) = installer_proxy(connection).await {
+            installer_proxy
+        } else {
+            let info = match command::info(path_str).await {
+                Ok(info) => info,
+                Err(error) => {
+                    return UpdateBundle::from_manifest(
+                        path,
+                        size,
+                        is_override,
+                        Error::BundleInfo(path_str.to_string(), error.to_string()),
+                    )
+                    .await
+                }
+            };
+            return match command::bundle_compatible_and_version(&info) {
+                Some((compatible, version)) => match Version::parse(&version) {
+                    Ok(version) => Ok(UpdateBundle {
+                        path: path.into(),
+                        compatible,
+                        version,
+                        size,
+                        is_override,
+                        slot_classes: unique_slot_classes(&command::bundle_images(&info)),
+                        signer: None,
+                    }),
+                    Err(error) => Err(Error::BundleVersion(
+                        path_str.to_string(),
+                        version,
+                        error.to_string(),
+                    )),
+                },
+                None => Err(Error::BundleInfo(
+                    path_str.to_string(),
+                    "no compatible or version".to_string(),
+                )),
+            };
+        };
 
-        match &installer_proxy.info(path_str).await {
+        // the slot classes are informational, so a failing inspection of the bundle is not fatal
+        let slot_classes = match installer_proxy
+            .inspect_bundle(path_str, HashMap::new())
+            .await
+        {
+            Ok(info) => unwrap_bundle_slot_classes(&info),
+            Err(error) => {
+                eprintln!(
+                    "Unable to get the slot classes of update bundle {}: {}",
+                    path_str, error
+                );
+                vec![]
+            }
+        };
+
+        let bundle_info = match timeout(BUNDLE_INFO_TIMEOUT, installer_proxy.info(path_str)).await {
+            Ok(bundle_info) => bundle_info,
+            Err(_) => {
+                return UpdateBundle::from_manifest(
+                    path,
+                    size,
+                    is_override,
+                    Error::BundleInfo(
+                        path_str.to_string(),
+                        format!("RAUC did not respond within {:?}", BUNDLE_INFO_TIMEOUT),
+                    ),
+                )
+                .await
+            }
+        };
+        match &bundle_info {
             Ok(bundle_info) => match Version::parse(bundle_info.1.as_str()) {
                 Ok(version) => Ok(UpdateBundle {
                     path: path.into(),
                     compatible: bundle_info.0.to_owned(),
                     version,
+                    size,
                     is_override,
+                    slot_classes,
+                    signer: None,
                 }),
                 Err(error) => Err(Error::BundleVersion(
                     path_str.to_string(),
@@ -55,7 +336,52 @@ impl UpdateBundle {
                     error.to_string(),
                 )),
             },
-            Err(error) => Err(Error::BundleInfo(path_str.to_string(), error.to_string())),
+            Err(error) => {
+                UpdateBundle::from_manifest(
+                    path,
+                    size,
+                    is_override,
+                    Error::BundleInfo(path_str.to_string(), error.to_string()),
+                )
+                .await
+            }
+        }
+    }
+
+    /// Create a new UpdateBundle from its manifest parsed offline
+    ///
+    /// This is used, if RAUC is not able to provide information on the bundle (in time), which is described by `error`.
+    /// As the manifest is not verified, the verification of the bundle is deferred to its installation.
+    /// If the manifest can not be read either, the `error` is returned.
+    async fn from_manifest(
+        path: &Path,
+        size: u64,
+        is_override: bool,
+        error: Error,
+    ) -> Result<UpdateBundle, Error> {
+        let manifest = match Manifest::read(path).await {
+            Ok(manifest) => manifest,
+            Err(manifest_error) => {
+                eprintln!("{}", manifest_error);
+                return Err(error);
+            }
+        };
+        eprintln!("Warning: {}, using its unverified manifest instead", error);
+        match Version::parse(manifest.version()) {
+            Ok(version) => Ok(UpdateBundle {
+                path: path.into(),
+                compatible: manifest.compatible().to_string(),
+                version,
+                size,
+                is_override,
+                slot_classes: unique_slot_classes(manifest.images()),
+                signer: None,
+            }),
+            Err(version_error) => Err(Error::BundleVersion(
+                path.display().to_string(),
+                manifest.version().to_string(),
+                version_error.to_string(),
+            )),
         }
     }
 
@@ -70,31 +396,233 @@ impl UpdateBundle {
     }
 
     /// Install the update bundle
-    pub async fn install(&self, connection: &Connection) -> Result<(), Error> {
+    ///
+    /// If `ignore_compatible` is true, RAUC does not check the compatible of the bundle (e.g. when it is an alias of the
+    /// system's compatible).
+    /// The `tls` options are passed to RAUC for update bundles streamed from HTTPS servers.
+    pub async fn install(
+        &self,
+        connection: &Connection,
+        ignore_compatible: bool,
+        tls: &TlsOptions,
+    ) -> Result<(), Error> {
         println!("Installing update bundle {}", self.path());
-        let installer_proxy = InstallerProxy::new(connection).await?;
+        if ignore_compatible {
+            println!(
+                "Ignoring compatible ({}) of update bundle {}",
+                self.compatible(),
+                self.path()
+            );
+        }
+        let installer_proxy = if let Some(installer_proxy) = installer_proxy(connection).await {
+            installer_proxy
+        } else {
+            let mut args = tls.cli_args();
+            if ignore_compatible {
+                args.push("--ignore-compatible".to_string());
+            }
+            return command::install(&self.path(), &args).await;
+        };
         let mut completed = installer_proxy.receive_completed().await?;
-        let mut failed = false;
+        let mut args = tls.args();
+        if ignore_compatible {
+            args.insert("ignore-compatible", Value::Bool(true));
+        }
         installer_proxy
-            .install_bundle(self.path.to_str().unwrap(), HashMap::new())
+            .install_bundle(self.path.to_str().unwrap(), args)
             .await?;
 
-        while let Some(signal) = completed.next().await {
-            if let Ok(args) = signal.args() {
-                if args.result().is_positive() {
-                    failed = true;
+        wait_completed(&installer_proxy, &mut completed).await
+    }
+
+    /// Inspect the update bundle and return the raw information RAUC provides about it
+    ///
+    /// This requires the RAUC D-Bus service.
+    pub async fn inspect(
+        &self,
+        connection: &Connection,
+    ) -> Result<HashMap<String, OwnedValue>, Error> {
+        let installer_proxy = InstallerProxy::new(connection).await?;
+        installer_proxy
+            .inspect_bundle(&self.path(), HashMap::new())
+            .await
+            .map_err(|error| Error::BundleInfo(self.path(), error.to_string()))
+    }
+
+    /// Return the list of images contained in the update bundle
+    pub async fn images(&self, connection: &Connection) -> Result<Vec<BundleImage>, Error> {
+        if installer_proxy(connection).await.is_some() {
+            Ok(unwrap_bundle_images(&self.inspect(connection).await?))
+        } else {
+            Ok(command::bundle_images(&self.info().await?))
+        }
+    }
+
+    /// Return the optional value of a key in a section of the meta data in the manifest of the update bundle
+    ///
+    /// Meta data is provided in `[meta.<section>]` sections of the manifest of an update bundle.
+    pub async fn meta(
+        &self,
+        connection: &Connection,
+        section: &str,
+        key: &str,
+    ) -> Result<Option<String>, Error> {
+        if installer_proxy(connection).await.is_some() {
+            Ok(unwrap_bundle_meta(
+                &self.inspect(connection).await?,
+                section,
+                key,
+            ))
+        } else {
+            Ok(command::bundle_meta(&self.info().await?, section, key))
+        }
+    }
+
+    /// Verify the update bundle
+    ///
+    /// RAUC checks the signature of the bundle, which covers its manifest (including the hashes of its images).
+    /// If RAUC is not able to inspect the bundle (e.g. because it is too old), the verification is skipped.
+    pub async fn verify(&self, connection: &Connection) -> Result<(), Error> {
+        println!("Verifying update bundle {}", self.path());
+        let installer_proxy = if let Some(installer_proxy) = installer_proxy(connection).await {
+            installer_proxy
+        } else {
+            // rauc info verifies the signature of the bundle
+            return command::info(&self.path())
+                .await
+                .map(|_| ())
+                .map_err(|error| Error::BundleVerification(self.path(), error.to_string()));
+        };
+        match installer_proxy
+            .inspect_bundle(&self.path(), HashMap::new())
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(zbus::Error::MethodError(name, _, _))
+                if name.as_str() == "org.freedesktop.DBus.Error.UnknownMethod"
+                    || name.as_str() == "org.freedesktop.DBus.Error.NotSupported" =>
+            {
+                eprintln!(
+                    "Skipping verification of update bundle {}, as RAUC is not able to inspect it",
+                    self.path()
+                );
+                Ok(())
+            }
+            Err(error) => Err(Error::BundleVerification(self.path(), error.to_string())),
+        }
+    }
+
+    /// Return whether the update bundle is targeted at a machine
+    ///
+    /// Update bundles may be targeted at specific machines using comma-separated lists of machine IDs and serial numbers
+    /// in the "target-machine-id" and "target-serial" keys of the `[meta.caterpillar]` section of their manifest.
+    /// Update bundles without these keys target all machines.
+    /// If RAUC is not able to inspect the bundle, it is considered to target all machines.
+    pub async fn targets_machine(&self, connection: &Connection, machine: &Machine) -> bool {
+        let targets = match self
+            .meta(connection, "caterpillar", "target-machine-id")
+            .await
+        {
+            Ok(machine_ids) => match self.meta(connection, "caterpillar", "target-serial").await {
+                Ok(serials) => (machine_ids, serials),
+                Err(error) => {
+                    eprintln!("Skipping machine target check: {}", error);
+                    return true;
                 }
-                break;
+            },
+            Err(error) => {
+                eprintln!("Skipping machine target check: {}", error);
+                return true;
+            }
+        };
+        is_targeted(targets.0.as_deref(), machine.machine_id())
+            && is_targeted(targets.1.as_deref(), machine.serial())
+    }
+
+    /// Return the optional build timestamp of the update bundle
+    ///
+    /// The build timestamp (in seconds since the epoch) is provided in the "build-timestamp" key of the
+    /// `[meta.caterpillar]` section of the manifest of an update bundle.
+    /// If RAUC is not able to inspect the bundle or the value can not be parsed, no build timestamp is returned.
+    pub async fn build_timestamp(&self, connection: &Connection) -> Option<i64> {
+        match self
+            .meta(connection, "caterpillar", "build-timestamp")
+            .await
+        {
+            Ok(value) => value.and_then(|value| value.trim().parse().ok()),
+            Err(error) => {
+                eprintln!("Unable to read the build timestamp: {}", error);
+                None
             }
         }
+    }
 
-        if failed {
-            let error_message = installer_proxy.last_error().await?;
-            eprintln!("RAUC error: {}", &error_message);
-            Err(Error::UpdateFailed(error_message))
-        } else {
-            Ok(())
+    /// Return the information the `rauc` command line interface provides about the update bundle
+    async fn info(&self) -> Result<serde_json::Value, Error> {
+        command::info(&self.path())
+            .await
+            .map_err(|error| Error::BundleInfo(self.path(), error.to_string()))
+    }
+
+    /// Ensure that there is enough space to install the update bundle
+    ///
+    /// The size of each image in the bundle is compared against the size of the (non-booted) slot it targets.
+    /// Additionally the largest image is compared against the space available in `tmp_dir`.
+    /// If RAUC is not able to inspect the bundle (e.g. because it is too old), the check is skipped.
+    pub async fn check_space(
+        &self,
+        connection: &Connection,
+        rauc_info: &RaucInfo,
+        tmp_dir: &Path,
+    ) -> Result<(), Error> {
+        println!("Checking available space for update bundle {}", self.path());
+        let images = match self.images(connection).await {
+            Ok(images) => images,
+            Err(error) => {
+                eprintln!("Skipping space check: {}", error);
+                return Ok(());
+            }
+        };
+
+        for image in images.iter() {
+            match rauc_info.get_target_slot(image.slot_class()) {
+                Some(slot) => match slot.device() {
+                    Some(device) => {
+                        let available =
+                            UdisksInfo::get_block_device_size(connection, device).await?;
+                        if image.size() > available {
+                            return Err(Error::InsufficientSpace(
+                                format!("slot {} ({})", slot.name(), device),
+                                image.size(),
+                                available,
+                            ));
+                        }
+                    }
+                    None => eprintln!(
+                        "Skipping space check for slot {} as it has no device.",
+                        slot.name()
+                    ),
+                },
+                None => eprintln!(
+                    "Skipping space check for image of slot class {} as no target slot is found.",
+                    image.slot_class()
+                ),
+            }
         }
+
+        if let Some(required) = images.iter().map(|image| image.size()).max() {
+            let stat = statvfs(tmp_dir).map_err(std::io::Error::from)?;
+            let available = stat.blocks_available() as u64 * stat.fragment_size() as u64;
+            if required > available {
+                return Err(Error::InsufficientSpace(
+                    tmp_dir.display().to_string(),
+                    required,
+                    available,
+                ));
+            }
+        }
+
+        Ok(())
     }
 
     /// Return a reference to the UpdateBundle's Version
@@ -102,10 +630,65 @@ impl UpdateBundle {
         &self.version
     }
 
+    /// Return the size of the UpdateBundle in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
     /// Return a bool specifying whether the UpdateBundle is an override
     pub fn is_override(&self) -> bool {
         self.is_override
     }
+
+    /// Return whether another UpdateBundle is identical (same file name, version and size), but possibly in another location
+    pub fn is_identical(&self, other: &UpdateBundle) -> bool {
+        self.path.file_name() == other.path.file_name()
+            && self.version == other.version
+            && self.size == other.size
+    }
+
+    /// Return the slot classes targeted by the images of the UpdateBundle
+    pub fn slot_classes(&self) -> &[String] {
+        &self.slot_classes
+    }
+
+    /// Return a copy of the UpdateBundle, that is located at another path (e.g. in a staging directory)
+    pub fn relocated(&self, path: &Path) -> UpdateBundle {
+        UpdateBundle {
+            path: path.to_path_buf(),
+            ..self.clone()
+        }
+    }
+
+    /// Return the optional signer of the UpdateBundle (see [`UpdateBundle::read_signer`])
+    pub fn signer(&self) -> Option<&Signer> {
+        self.signer.as_ref()
+    }
+
+    /// Read the signer of the UpdateBundle from its signing certificate
+    ///
+    /// The signer is informational, so failing to read it is not fatal.
+    pub async fn read_signer(&mut self) {
+        let path = self.path();
+        match command::dump_cert(&path).await.and_then(|output| {
+            Signer::from_pem(&output).map_err(|error| Error::BundleSigner(path.clone(), error))
+        }) {
+            Ok(signer) => self.signer = Some(signer),
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+
+    /// Return whether an image of the UpdateBundle targets a slot class
+    pub fn targets_slot_class(&self, slot_class: &str) -> bool {
+        self.slot_classes.iter().any(|x| x == slot_class)
+    }
+
+    /// Return whether the UpdateBundle only updates artifact repositories of a RAUC instance
+    ///
+    /// Such update bundles do not update the system and therefore do not require a reboot.
+    pub fn is_artifact_only(&self, rauc_info: &RaucInfo) -> bool {
+        is_artifact_only(&self.slot_classes, rauc_info.artifact_repositories())
+    }
 }
 
 impl Display for UpdateBundle {
@@ -169,6 +752,17 @@ impl Slot {
         }
     }
 
+    /// Return whether a version is an update for the system
+    ///
+    /// A version is an update, if it is higher than the system version (or if the system version is unknown).
+    /// If `reinstall_current_version` is true, a version equal to the system version is considered an update as well.
+    pub fn is_update(&self, version: &Version, reinstall_current_version: bool) -> bool {
+        match self.version.as_ref() {
+            Some(current) => version > current || (reinstall_current_version && version == current),
+            None => true,
+        }
+    }
+
     /// Return the optional Version as String
     pub fn version_string(&self) -> String {
         match self.version.as_ref() {
@@ -181,6 +775,45 @@ impl Slot {
     pub fn status(&self) -> Option<&HashMap<String, String>> {
         self.status.as_ref()
     }
+
+    /// Return the name of the slot
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return whether the slot was booted from
+    pub fn is_booted(&self) -> bool {
+        self.booted
+    }
+
+    /// Return whether the slot is the primary
+    pub fn is_primary(&self) -> bool {
+        self.primary
+    }
+
+    /// Return the optional class of the slot
+    pub fn class(&self) -> Option<&str> {
+        self.status
+            .as_ref()
+            .and_then(|status| status.get("class"))
+            .map(|class| class.as_str())
+    }
+
+    /// Return the optional device of the slot
+    pub fn device(&self) -> Option<&str> {
+        self.status
+            .as_ref()
+            .and_then(|status| status.get("device"))
+            .map(|device| device.as_str())
+    }
+
+    /// Return the optional bootname of the slot
+    pub fn bootname(&self) -> Option<&str> {
+        self.status
+            .as_ref()
+            .and_then(|status| status.get("bootname"))
+            .map(|bootname| bootname.as_str())
+    }
 }
 
 impl Display for Slot {
@@ -198,6 +831,7 @@ impl Display for Slot {
 }
 
 /// Information about a RAUC instance
+#[derive(Default)]
 pub struct RaucInfo {
     /// operational state of RAUC
     operation: OnceCell<String>,
@@ -211,12 +845,39 @@ pub struct RaucInfo {
     version: Option<Version>,
     /// The slots the RAUC instance tracks
     slots: Vec<Slot>,
+    /// The artifact repositories the RAUC instance tracks
+    artifact_repositories: Vec<ArtifactRepository>,
 }
 
 impl RaucInfo {
     /// Create a new RaucInfo and return it in a Result
+    ///
+    /// If the RAUC D-Bus service is not available, the information is retrieved using `rauc status`.
+    /// In this case the operation of the RAUC instance and its artifact repositories are unknown.
     pub async fn new(connection: &Connection) -> Result<Self, Error> {
-        let installer_proxy = InstallerProxy::new(connection).await?;
+        let installer_proxy = if let Some(installer_proxy) = installer_proxy(connection).await {
+            installer_proxy
+        } else {
+            let status = command::unwrap_status(&command::status().await?);
+            return RaucInfo::from_slot_status(
+                OnceCell::new(),
+                status.compatible,
+                status.variant,
+                status.boot_slot,
+                &status.primary,
+                status
+                    .slots
+                    .into_iter()
+                    .map(|(name, status)| (name, Some(status)))
+                    .collect(),
+                vec![],
+            );
+        };
+        // artifact repositories are only supported by RAUC >= 1.13
+        let artifact_repositories = match installer_proxy.get_artifact_repositories().await {
+            Ok(repositories) => unwrap_artifact_repositories(&repositories),
+            Err(_) => vec![],
+        };
         match try_join!(
             installer_proxy.operation(),
             installer_proxy.compatible(),
@@ -226,59 +887,21 @@ impl RaucInfo {
             installer_proxy.get_slot_status(),
         ) {
             Ok((operation, compatible, variant, boot_slot, primary, slot_status)) => {
-                let mut slots = vec![];
-                let mut system_version = None;
-
-                for slot_name in get_slot_names(&slot_status) {
-                    let raw_slot_status = unwrap_slot_status(&slot_name, &slot_status);
-                    let slot_booted = raw_slot_status.as_ref().is_some_and(|x| {
-                        x.get("state")
-                            .is_some_and(|x| x == "booted" || x == "active")
-                    });
-                    let slot_version = match raw_slot_status.as_ref() {
-                        Some(map) => match map.get("bundle.version") {
-                            Some(map_version) => match Version::parse(map_version) {
-                                Ok(version) => Some(version),
-                                Err(error) => {
-                                    return Err(Error::SlotVersion(
-                                        map_version.to_owned(),
-                                        slot_name,
-                                        error.to_string(),
-                                    ))
-                                }
-                            },
-                            None => None,
-                        },
-                        None => None,
-                    };
-                    let slot_primary = slot_name == primary;
-
-                    // if this slot is the primary and has a version, expose it as the system version
-                    system_version = if (slot_primary && slot_version.is_some())
-                        || (slot_name == boot_slot && slot_version.is_some())
-                    {
-                        slot_version.clone()
-                    } else {
-                        None
-                    };
-
-                    slots.push(Slot::new(
-                        slot_primary,
-                        slot_booted.to_owned(),
-                        slot_name.as_str(),
-                        slot_version,
-                        raw_slot_status,
-                    ));
-                }
-
-                Ok(RaucInfo {
-                    operation: OnceCell::from(operation),
+                RaucInfo::from_slot_status(
+                    OnceCell::from(operation),
                     compatible,
                     variant,
                     boot_slot,
-                    version: system_version,
-                    slots,
-                })
+                    &primary,
+                    get_slot_names(&slot_status)
+                        .into_iter()
+                        .map(|slot_name| {
+                            let raw_slot_status = unwrap_slot_status(&slot_name, &slot_status);
+                            (slot_name, raw_slot_status)
+                        })
+                        .collect(),
+                    artifact_repositories,
+                )
             }
             Err(error) => {
                 eprintln!(
@@ -290,6 +913,71 @@ impl RaucInfo {
         }
     }
 
+    /// Create a new RaucInfo from the status of each slot and return it in a Result
+    fn from_slot_status(
+        operation: OnceCell<String>,
+        compatible: String,
+        variant: String,
+        boot_slot: String,
+        primary: &str,
+        slot_status: Vec<(String, Option<HashMap<String, String>>)>,
+        artifact_repositories: Vec<ArtifactRepository>,
+    ) -> Result<Self, Error> {
+        let mut slots = vec![];
+        let mut system_version = None;
+
+        for (slot_name, raw_slot_status) in slot_status {
+            let slot_booted = raw_slot_status.as_ref().is_some_and(|x| {
+                x.get("state")
+                    .is_some_and(|x| x == "booted" || x == "active")
+            });
+            let slot_version = match raw_slot_status.as_ref() {
+                Some(map) => match map.get("bundle.version") {
+                    Some(map_version) => match Version::parse(map_version) {
+                        Ok(version) => Some(version),
+                        Err(error) => {
+                            return Err(Error::SlotVersion(
+                                map_version.to_owned(),
+                                slot_name,
+                                error.to_string(),
+                            ))
+                        }
+                    },
+                    None => None,
+                },
+                None => None,
+            };
+            let slot_primary = slot_name == primary;
+
+            // if this slot is the primary and has a version, expose it as the system version
+            system_version = if (slot_primary && slot_version.is_some())
+                || (slot_name == boot_slot && slot_version.is_some())
+            {
+                slot_version.clone()
+            } else {
+                None
+            };
+
+            slots.push(Slot::new(
+                slot_primary,
+                slot_booted.to_owned(),
+                slot_name.as_str(),
+                slot_version,
+                raw_slot_status,
+            ));
+        }
+
+        Ok(RaucInfo {
+            operation,
+            compatible,
+            variant,
+            boot_slot,
+            version: system_version,
+            slots,
+            artifact_repositories,
+        })
+    }
+
     /// Get the operation status of the RAUC instance
     pub fn operation(&self) -> Option<&str> {
         if let Some(operation) = self.operation.get() {
@@ -304,6 +992,13 @@ impl RaucInfo {
         &self.compatible
     }
 
+    /// Return whether a bundle compatible is compatible with the system
+    ///
+    /// A bundle compatible is compatible, if it equals the system's compatible or if it is one of its `aliases`.
+    pub fn is_compatible(&self, compatible: &str, aliases: &HashMap<String, Vec<String>>) -> bool {
+        is_compatible(&self.compatible, compatible, aliases)
+    }
+
     /// Get the variant of the RAUC instance
     pub fn variant(&self) -> &str {
         &self.variant
@@ -331,6 +1026,29 @@ impl RaucInfo {
     pub fn slots(&self) -> &Vec<Slot> {
         self.slots.as_ref()
     }
+
+    /// Return the booted slot, that has been installed with `version`
+    ///
+    /// Returns None, if no booted slot has the version (e.g. if the bootloader fell back to the previous slot).
+    pub fn booted_slot(&self, version: &str) -> Option<&Slot> {
+        self.slots
+            .iter()
+            .find(|slot| slot.is_booted() && slot.version_string() == version)
+    }
+
+    /// Get the artifact repositories of the RAUC instance
+    pub fn artifact_repositories(&self) -> &[ArtifactRepository] {
+        &self.artifact_repositories
+    }
+
+    /// Get the optional slot of a slot class, that an update would be installed to
+    ///
+    /// The target slot is the first slot of the class, that has not been booted from.
+    pub fn get_target_slot(&self, slot_class: &str) -> Option<&Slot> {
+        self.slots
+            .iter()
+            .find(|slot| slot.class() == Some(slot_class) && !slot.is_booted())
+    }
 }
 
 impl Display for RaucInfo {
@@ -354,6 +1072,126 @@ impl Display for RaucInfo {
 /// Get the unwrapped status of a specific slot
 ///
 /// Unpacks the zbus variants to native owned types and returns them as a HashMap of Strings.
+/// Return an InstallerProxy, if the RAUC D-Bus service is available
+///
+/// If RAUC neither owns its name on the bus nor is D-Bus activatable (e.g. on minimal images, that do not run
+/// `rauc.service`), `None` is returned and the `rauc` command line interface should be used instead.
+async fn installer_proxy(connection: &Connection) -> Option<InstallerProxy<'_>> {
+    let installer_proxy = match InstallerProxy::new(connection).await {
+        Ok(installer_proxy) => installer_proxy,
+        Err(error) => {
+            eprintln!(
+                "RAUC D-Bus service is not available, falling back to the rauc command: {}",
+                error
+            );
+            return None;
+        }
+    };
+    let dbus_proxy = DBusProxy::new(connection).await.ok()?;
+    let destination = installer_proxy.inner().destination().to_owned();
+    if dbus_proxy
+        .name_has_owner(destination.clone())
+        .await
+        .unwrap_or(true)
+        || dbus_proxy
+            .list_activatable_names()
+            .await
+            .map_or(true, |names| {
+                names
+                    .iter()
+                    .any(|name| name.as_str() == destination.as_str())
+            })
+    {
+        Some(installer_proxy)
+    } else {
+        eprintln!("RAUC D-Bus service is not available, falling back to the rauc command");
+        None
+    }
+}
+
+/// Return a stream of changes of the owner of RAUC's name on the bus of an InstallerProxy
+///
+/// The owner changes, if the RAUC daemon is stopped or (re)started (e.g. after a crash or an upgrade).
+pub async fn receive_rauc_owner_changed(
+    installer_proxy: &InstallerProxy<'_>,
+) -> Result<NameOwnerChangedStream<'static>, Error> {
+    let dbus_proxy = DBusProxy::new(installer_proxy.inner().connection()).await?;
+    Ok(dbus_proxy
+        .receive_name_owner_changed_with_args(&[(
+            0,
+            installer_proxy.inner().destination().as_str(),
+        )])
+        .await?)
+}
+
+/// Wait for the Completed signal of RAUC and return the result of the installation
+///
+/// If the RAUC daemon stops or restarts before the installation completed, an error is returned instead of waiting for
+/// a signal, that never arrives.
+async fn wait_completed(
+    installer_proxy: &InstallerProxy<'_>,
+    completed: &mut CompletedStream<'_>,
+) -> Result<(), Error> {
+    let mut owner_changed = receive_rauc_owner_changed(installer_proxy).await?;
+    let mut failed = false;
+    loop {
+        tokio::select! {
+            signal = completed.next() => match signal {
+                Some(signal) => {
+                    if let Ok(args) = signal.args() {
+                        failed = args.result().is_positive();
+                        break;
+                    }
+                }
+                None => break,
+            },
+            Some(_) = owner_changed.next() => {
+                let error = Error::RaucRestarted;
+                eprintln!("{}", error);
+                return Err(error);
+            }
+        }
+    }
+
+    if failed {
+        let error_message = installer_proxy.last_error().await?;
+        eprintln!("RAUC error: {}", &error_message);
+        Err(Error::UpdateFailed(error_message))
+    } else {
+        Ok(())
+    }
+}
+
+/// Wait for an ongoing installation of RAUC (e.g. one started before a restart of the application) to complete
+///
+/// If the installation completed before its Completed signal could be received, the result is derived from RAUC's last
+/// error.
+pub async fn await_installation(connection: &Connection) -> Result<(), Error> {
+    let installer_proxy = InstallerProxy::new(connection).await?;
+    let mut completed = installer_proxy.receive_completed().await?;
+    if installer_proxy.operation().await? == "installing" {
+        wait_completed(&installer_proxy, &mut completed).await
+    } else {
+        let error_message = installer_proxy.last_error().await?;
+        if error_message.is_empty() {
+            Ok(())
+        } else {
+            eprintln!("RAUC error: {}", &error_message);
+            Err(Error::UpdateFailed(error_message))
+        }
+    }
+}
+
+/// Return the operation RAUC is carrying out (e.g. "idle" or "installing")
+///
+/// If the RAUC D-Bus service is not available, RAUC is considered to be idle.
+pub async fn rauc_operation(connection: &Connection) -> Result<String, Error> {
+    match installer_proxy(connection).await {
+        Some(installer_proxy) => Ok(installer_proxy.operation().await?),
+        None => Ok("idle".to_string()),
+    }
+}
+
 fn unwrap_slot_status(
     key: &str,
     status: &[(String, HashMap<String, OwnedValue>)],
@@ -379,21 +1217,200 @@ fn unwrap_slot_status(
         .last()
 }
 
+/// Unwrap a Value from any variant it may be wrapped in
+fn unwrap_variant<'a, 'b>(value: &'b Value<'a>) -> &'b Value<'a> {
+    match value {
+        Value::Value(inner) => unwrap_variant(inner),
+        _ => value,
+    }
+}
+
+/// Get the images of an update bundle from the raw information returned by RAUC's InspectBundle
+fn unwrap_bundle_images(info: &HashMap<String, OwnedValue>) -> Vec<BundleImage> {
+    match info.get("images").map(|images| unwrap_variant(images)) {
+        Some(Value::Array(images)) => images
+            .get()
+            .iter()
+            .filter_map(|image| match unwrap_variant(image) {
+                Value::Dict(image) => {
+                    let image: HashMap<String, Value> = image.clone().try_into().ok()?;
+                    let filename = match image.get("filename").map(unwrap_variant) {
+                        Some(Value::Str(filename)) => filename.as_str(),
+                        _ => "",
+                    };
+                    match (
+                        image.get("slot-class").map(unwrap_variant),
+                        image.get("size").map(unwrap_variant),
+                    ) {
+                        (Some(Value::Str(slot_class)), Some(Value::U64(size))) => {
+                            Some(BundleImage::new(slot_class.as_str(), *size, filename))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Get the unique slot classes targeted by the images of an update bundle from the raw information returned by RAUC's
+/// InspectBundle
+fn unwrap_bundle_slot_classes(info: &HashMap<String, OwnedValue>) -> Vec<String> {
+    unique_slot_classes(&unwrap_bundle_images(info))
+}
+
+/// Get the unique slot classes targeted by a list of images of an update bundle
+fn unique_slot_classes(images: &[BundleImage]) -> Vec<String> {
+    let mut slot_classes: Vec<String> = vec![];
+    for image in images {
+        if !slot_classes.iter().any(|x| x == image.slot_class()) {
+            slot_classes.push(image.slot_class().to_string());
+        }
+    }
+    slot_classes
+}
+
+/// Get the value of a key in a section of the meta data of an update bundle from the raw information returned by RAUC's
+/// InspectBundle
+fn unwrap_bundle_meta(
+    info: &HashMap<String, OwnedValue>,
+    section: &str,
+    key: &str,
+) -> Option<String> {
+    let meta: HashMap<String, Value> = match info.get("meta").map(|meta| unwrap_variant(meta)) {
+        Some(Value::Dict(meta)) => meta.clone().try_into().ok()?,
+        _ => return None,
+    };
+    let section: HashMap<String, Value> = match meta.get(section).map(unwrap_variant) {
+        Some(Value::Dict(section)) => section.clone().try_into().ok()?,
+        _ => return None,
+    };
+    match section.get(key).map(unwrap_variant) {
+        Some(Value::Str(value)) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Get the artifact repositories from the raw information returned by RAUC's GetArtifactRepositories
+///
+/// Repositories without a name are skipped.
+fn unwrap_artifact_repositories(
+    repositories: &[HashMap<String, OwnedValue>],
+) -> Vec<ArtifactRepository> {
+    repositories
+        .iter()
+        .filter_map(|repository| {
+            let string = |key: &str| match repository.get(key).map(|value| unwrap_variant(value)) {
+                Some(Value::Str(value)) => Some(value.to_string()),
+                _ => None,
+            };
+            let artifacts = match repository
+                .get("artifacts")
+                .map(|artifacts| unwrap_variant(artifacts))
+            {
+                Some(Value::Array(artifacts)) => artifacts
+                    .get()
+                    .iter()
+                    .filter_map(|artifact| match unwrap_variant(artifact) {
+                        Value::Dict(artifact) => {
+                            let artifact: HashMap<String, Value> =
+                                artifact.clone().try_into().ok()?;
+                            match artifact.get("name").map(unwrap_variant) {
+                                Some(Value::Str(name)) => Some(name.to_string()),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    })
+                    .collect(),
+                _ => vec![],
+            };
+            Some(ArtifactRepository::new(
+                &string("name")?,
+                &string("type").unwrap_or_default(),
+                &string("path").unwrap_or_default(),
+                artifacts,
+            ))
+        })
+        .collect()
+}
+
+/// Return whether a list of slot classes (targeted by the images of an update bundle) only contains artifact
+/// repositories
+///
+/// Images of artifacts may target a repository as "<repository>/<artifact>".
+fn is_artifact_only(slot_classes: &[String], repositories: &[ArtifactRepository]) -> bool {
+    !slot_classes.is_empty()
+        && slot_classes.iter().all(|slot_class| {
+            let name = slot_class.split('/').next().unwrap_or(slot_class);
+            repositories
+                .iter()
+                .any(|repository| repository.name() == name)
+        })
+}
+
 /// Get the names of all slots from the slot status
 fn get_slot_names(status: &[(String, HashMap<String, OwnedValue>)]) -> Vec<String> {
     status.iter().map(|x| x.0.clone()).collect()
 }
 
+/// Mark the booted slot as good and return the message of RAUC
+///
+/// If the RAUC D-Bus service is not available, `rauc status mark-good booted` is used instead.
+pub async fn mark_booted_good(connection: &Connection) -> Result<String, Error> {
+    match installer_proxy(connection).await {
+        Some(installer_proxy) => Ok(installer_proxy.mark("good", "booted").await?.1),
+        None => command::mark("good", "booted").await,
+    }
+}
+
+/// Return whether a bundle compatible is compatible with a system compatible
+///
+/// The `aliases` map system compatibles to lists of additionally accepted bundle compatibles.
+pub fn is_compatible(
+    system_compatible: &str,
+    compatible: &str,
+    aliases: &HashMap<String, Vec<String>>,
+) -> bool {
+    compatible == system_compatible
+        || aliases
+            .get(system_compatible)
+            .is_some_and(|aliases| aliases.iter().any(|alias| alias == compatible))
+}
+
+/// Overwrite a file with zeros and remove it afterwards
+///
+/// This prevents the recovery of e.g. a consumed override update bundle from the filesystem.
+/// On flash based storage (e.g. USB sticks) overwriting is not guaranteed to erase the previous data, due to wear leveling.
+pub fn shred(path: &Path) -> Result<(), Error> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let buffer = [0u8; 65536];
+    let mut remaining = file.metadata()?.len();
+    while remaining > 0 {
+        let length = remaining.min(buffer.len() as u64);
+        file.write_all(&buffer[..length as usize])?;
+        remaining -= length;
+    }
+    file.sync_all()?;
+    drop(file);
+    remove_file(path)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use std::fs::OpenOptions;
-
     use super::*;
     use dbus_launch::BusType;
     use dbus_launch::Daemon;
     use dbus_launch::Launcher;
+    use nix::sys::stat::utimes;
+    use nix::sys::time::TimeVal;
+    use nix::sys::time::TimeValLike;
     use rstest::fixture;
     use rstest::rstest;
+    use std::str::FromStr;
     use testdir::testdir;
     use testresult::TestResult;
     use zbus::dbus_interface;
@@ -485,6 +1502,14 @@ mod tests {
             Ok("error".to_string())
         }
 
+        #[dbus_interface(name = "Mark")]
+        fn mark(&self, state: &str, slot_identifier: &str) -> zbus::fdo::Result<(String, String)> {
+            Ok((
+                "A".to_string(),
+                format!("marked slot {} as {}", slot_identifier, state),
+            ))
+        }
+
         /// Completed signal
         #[dbus_interface(signal)]
         async fn completed(ctxt: &SignalContext<'_>, result: i32) -> zbus::Result<()>;
@@ -563,6 +1588,118 @@ mod tests {
         assert_eq!(slots, get_slot_names(status));
     }
 
+    #[rstest]
+    #[case("vendor-product", true)]
+    #[case("vendor-product-rev2", true)]
+    #[case("vendor-product-rev3", false)]
+    #[case("other-product", false)]
+    fn test_is_compatible(#[case] compatible: &str, #[case] result: bool) {
+        let aliases = HashMap::from([(
+            "vendor-product-rev2".to_string(),
+            vec!["vendor-product".to_string()],
+        )]);
+        assert_eq!(
+            is_compatible("vendor-product-rev2", compatible, &aliases),
+            result
+        );
+    }
+
+    #[rstest]
+    fn test_unwrap_bundle_images() {
+        let images = vec![
+            HashMap::from([
+                ("slot-class", Value::from("rootfs")),
+                ("size", Value::from(1024_u64)),
+                ("filename", Value::from("rootfs.img.caibx")),
+            ]),
+            HashMap::from([
+                ("slot-class", Value::from("appfs")),
+                ("size", Value::from(512_u64)),
+            ]),
+            HashMap::from([("slot-class", Value::from("efi"))]),
+        ];
+        let info = HashMap::from([(
+            String::from("images"),
+            OwnedValue::from(Value::from(images)),
+        )]);
+        let images = unwrap_bundle_images(&info);
+        assert_eq!(
+            images,
+            vec![
+                BundleImage::new("rootfs", 1024, "rootfs.img.caibx"),
+                BundleImage::new("appfs", 512, ""),
+            ]
+        );
+        assert!(images[0].is_casync());
+        assert!(!images[1].is_casync());
+        assert_eq!(
+            unwrap_bundle_slot_classes(&info),
+            vec!["rootfs".to_string(), "appfs".to_string()]
+        );
+    }
+
+    #[rstest]
+    #[case("caterpillar", "soft-reboot", Some("true".to_string()))]
+    #[case("caterpillar", "foo", None)]
+    #[case("foo", "soft-reboot", None)]
+    fn test_unwrap_bundle_meta(
+        #[case] section: &str,
+        #[case] key: &str,
+        #[case] value: Option<String>,
+    ) {
+        let meta = HashMap::from([(
+            "caterpillar",
+            HashMap::from([("soft-reboot", Value::from("true"))]),
+        )]);
+        let info = HashMap::from([(String::from("meta"), OwnedValue::from(Value::from(meta)))]);
+        assert_eq!(unwrap_bundle_meta(&info, section, key), value);
+    }
+
+    #[rstest]
+    fn test_unwrap_artifact_repositories() {
+        let repositories = vec![
+            HashMap::from([
+                (String::from("name"), OwnedValue::from(Value::from("apps"))),
+                (String::from("type"), OwnedValue::from(Value::from("trees"))),
+                (
+                    String::from("path"),
+                    OwnedValue::from(Value::from("/srv/apps")),
+                ),
+                (
+                    String::from("artifacts"),
+                    OwnedValue::from(Value::from(vec![HashMap::from([(
+                        "name",
+                        Value::from("app1"),
+                    )])])),
+                ),
+            ]),
+            HashMap::from([(String::from("type"), OwnedValue::from(Value::from("files")))]),
+        ];
+        assert_eq!(
+            unwrap_artifact_repositories(&repositories),
+            vec![ArtifactRepository::new(
+                "apps",
+                "trees",
+                "/srv/apps",
+                vec!["app1".to_string()]
+            )]
+        );
+    }
+
+    #[rstest]
+    #[case(&["apps"], true)]
+    #[case(&["apps/app1", "containers"], true)]
+    #[case(&["apps", "rootfs"], false)]
+    #[case(&[], false)]
+    fn test_is_artifact_only(#[case] slot_classes: &[&str], #[case] result: bool) {
+        let repositories = vec![
+            ArtifactRepository::new("apps", "trees", "/srv/apps", vec![]),
+            ArtifactRepository::new("containers", "files", "/srv/containers", vec![]),
+        ];
+        let slot_classes: Vec<String> = slot_classes.iter().map(|x| x.to_string()).collect();
+        assert_eq!(is_artifact_only(&slot_classes, &repositories), result);
+    }
+
     #[rstest]
     #[case(
         "A",
@@ -595,7 +1732,9 @@ mod tests {
     ) -> TestResult {
         let (connection, daemon) = connection_daemon.await;
         let bundle = UpdateBundle::new(&bundle_path, false, &connection).await?;
-        bundle.install(&connection).await?;
+        bundle
+            .install(&connection, false, &TlsOptions::default())
+            .await?;
         drop(daemon);
         Ok(())
     }
@@ -607,13 +1746,145 @@ mod tests {
     ) -> TestResult {
         let (connection, daemon) = connection_daemon_installer_fail.await;
         let bundle = UpdateBundle::new(&bundle_path, false, &connection).await?;
-        let update_result = bundle.install(&connection).await;
+        let update_result = bundle
+            .install(&connection, false, &TlsOptions::default())
+            .await;
         assert!(update_result
             .is_err_and(|x| format!("{:?}", x) == "UpdateFailed(\"error\")".to_string()));
         drop(daemon);
         Ok(())
     }
 
+    #[rstest]
+    fn test_tls_options_args() {
+        assert!(TlsOptions::default().args().is_empty());
+        let tls = TlsOptions::new(
+            Some(Path::new("/etc/caterpillar/client.crt")),
+            Some(Path::new("/etc/caterpillar/client.key")),
+            None,
+        );
+        let args = tls.args();
+        assert_eq!(args.len(), 2);
+        assert_eq!(
+            args.get("tls-cert"),
+            Some(&Value::from("/etc/caterpillar/client.crt".to_string()))
+        );
+        assert_eq!(
+            args.get("tls-key"),
+            Some(&Value::from("/etc/caterpillar/client.key".to_string()))
+        );
+    }
+
+    #[rstest]
+    #[case(Some("1.0.0"), "1.1.0", false, true)]
+    #[case(Some("1.0.0"), "1.0.0", false, false)]
+    #[case(Some("1.0.0"), "1.0.0", true, true)]
+    #[case(Some("1.0.0"), "0.9.0", true, false)]
+    #[case(None, "0.9.0", false, true)]
+    fn test_raucinfo_is_update(
+        #[case] current: Option<&str>,
+        #[case] version: &str,
+        #[case] reinstall_current_version: bool,
+        #[case] result: bool,
+    ) -> TestResult {
+        let raucinfo = RaucInfo {
+            version: current.map(Version::parse).transpose()?,
+            ..Default::default()
+        };
+        assert_eq!(
+            raucinfo.is_update(&Version::parse(version)?, reinstall_current_version),
+            result
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("1.1.0", true, Some("rootfs.1"))]
+    #[case("1.0.0", true, None)]
+    #[case("1.1.0", false, None)]
+    fn test_raucinfo_booted_slot(
+        #[case] version: &str,
+        #[case] booted: bool,
+        #[case] slot: Option<&str>,
+    ) -> TestResult {
+        let raucinfo = RaucInfo {
+            slots: vec![
+                Slot::new(
+                    false,
+                    !booted,
+                    "rootfs.0",
+                    Some(Version::parse("1.0.0")?),
+                    None,
+                ),
+                Slot::new(
+                    true,
+                    booted,
+                    "rootfs.1",
+                    Some(Version::parse("1.1.0")?),
+                    None,
+                ),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(raucinfo.booted_slot(version).map(Slot::name), slot);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(BundleSort::Version, &["b-1.0.0.raucb", "a-2.0.0.raucb", "c-2.0.0.raucb"], &["c-2.0.0.raucb", "a-2.0.0.raucb", "b-1.0.0.raucb"])]
+    #[case(BundleSort::Mtime, &["b-1.0.0.raucb", "a-2.0.0.raucb", "c-2.0.0.raucb"], &["b-1.0.0.raucb", "c-2.0.0.raucb", "a-2.0.0.raucb"])]
+    #[case(BundleSort::Filename, &["b-1.0.0.raucb", "a-2.0.0.raucb", "c-2.0.0.raucb"], &["c-2.0.0.raucb", "b-1.0.0.raucb", "a-2.0.0.raucb"])]
+    fn test_bundle_sort(
+        #[case] sort: BundleSort,
+        #[case] names: &[&str],
+        #[case] sorted: &[&str],
+    ) -> TestResult {
+        let dir = testdir!();
+        let mut bundles = vec![];
+        for (index, name) in names.iter().enumerate() {
+            let path = dir.join(name);
+            OpenOptions::new().create(true).write(true).open(&path)?;
+            // the first file is the most recently modified, while the others share a modification time
+            let modified = TimeVal::seconds(if index == 0 { 2000 } else { 1000 });
+            utimes(&path, &modified, &modified)?;
+            bundles.push(UpdateBundle {
+                path,
+                compatible: "foo".to_string(),
+                version: Version::parse(
+                    name.split_once('-').unwrap().1.trim_end_matches(".raucb"),
+                )?,
+                size: 0,
+                is_override: false,
+                slot_classes: vec![],
+                signer: None,
+            });
+        }
+        sort.sort(&mut bundles);
+        assert_eq!(
+            bundles
+                .iter()
+                .map(|bundle| bundle.path.file_name().unwrap().to_str().unwrap())
+                .collect::<Vec<_>>(),
+            sorted
+        );
+        assert_eq!(BundleSort::from_str("MTIME")?, BundleSort::Mtime);
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_shred() -> TestResult {
+        let dir = testdir!();
+        let path = dir.join("update.raucb");
+        let link = dir.join("update.raucb.link");
+        std::fs::write(&path, vec![1u8; 100000])?;
+        // the hard link still references the overwritten data
+        std::fs::hard_link(&path, &link)?;
+        shred(&path)?;
+        assert!(!path.exists());
+        assert_eq!(std::fs::read(&link)?, vec![0u8; 100000]);
+        Ok(())
+    }
+
     #[rstest]
     async fn test_raucinfo_new(#[future] connection_daemon: (Connection, Daemon)) -> TestResult {
         let (connection, daemon) = connection_daemon.await;
@@ -623,4 +1894,17 @@ mod tests {
         drop(daemon);
         Ok(())
     }
+
+    #[rstest]
+    async fn test_mark_booted_good(
+        #[future] connection_daemon: (Connection, Daemon),
+    ) -> TestResult {
+        let (connection, daemon) = connection_daemon.await;
+        assert_eq!(
+            mark_booted_good(&connection).await?,
+            "marked slot booted as good"
+        );
+        drop(daemon);
+        Ok(())
+    }
 }
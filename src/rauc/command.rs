@@ -0,0 +1,292 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! Fallback to the `rauc` command line interface
+//!
+//! On systems on which the RAUC D-Bus service is not available (e.g. on minimal images, that do not run
+//! `rauc.service`), information on update bundles and the system is retrieved from the JSON output of `rauc info` and
+//! `rauc status` and update bundles are installed using `rauc install`.
+//! As RAUC does not provide the signing certificate of update bundles over D-Bus, it is always retrieved using
+//! `rauc info --dump-cert`.
+use std::collections::HashMap;
+
+use serde_json::Value;
+use tokio::process::Command;
+
+use super::BundleImage;
+use crate::error::Error;
+
+/// Run `rauc` with a list of arguments and return its JSON output
+async fn run_json(args: &[&str]) -> Result<Value, Error> {
+    let output = Command::new("rauc").args(args).output().await?;
+    if output.status.success() {
+        serde_json::from_slice(&output.stdout)
+            .map_err(|error| Error::RaucCommand(args.join(" "), error.to_string()))
+    } else {
+        Err(Error::RaucCommand(
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// Return the information on an update bundle as provided by `rauc info`
+pub async fn info(bundle: &str) -> Result<Value, Error> {
+    run_json(&["info", "--output-format=json", bundle]).await
+}
+
+/// Return the output of `rauc info --dump-cert`, which contains the PEM encoded signing certificate of an update bundle
+pub async fn dump_cert(bundle: &str) -> Result<String, Error> {
+    let output = Command::new("rauc")
+        .args(["info", "--dump-cert", bundle])
+        .output()
+        .await?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(Error::RaucCommand(
+            format!("info --dump-cert {}", bundle),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// Return the information on the system as provided by `rauc status`
+pub async fn status() -> Result<Value, Error> {
+    run_json(&["status", "--detailed", "--output-format=json"]).await
+}
+
+/// Mark a slot using `rauc status mark-<state>` and return the message of RAUC
+pub async fn mark(state: &str, slot_identifier: &str) -> Result<String, Error> {
+    let args = ["status", &format!("mark-{}", state), slot_identifier];
+    let output = Command::new("rauc").args(args).output().await?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(Error::RaucCommand(
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// Install an update bundle using `rauc install`
+///
+/// The `args` are passed to `rauc install` before the path of the update bundle.
+pub async fn install(bundle: &str, args: &[String]) -> Result<(), Error> {
+    let output = Command::new("rauc")
+        .arg("install")
+        .args(args)
+        .arg(bundle)
+        .output()
+        .await?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let error_message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        eprintln!("RAUC error: {}", &error_message);
+        Err(Error::UpdateFailed(error_message))
+    }
+}
+
+/// Get the compatible and version of an update bundle from the output of `rauc info`
+pub fn bundle_compatible_and_version(info: &Value) -> Option<(String, String)> {
+    match (info.get("compatible"), info.get("version")) {
+        (Some(Value::String(compatible)), Some(Value::String(version))) => {
+            Some((compatible.to_owned(), version.to_owned()))
+        }
+        _ => None,
+    }
+}
+
+/// Get the images of an update bundle from the output of `rauc info`
+///
+/// Images are either provided with a "slot-class" field or (in older versions of RAUC) as an object with the slot class
+/// as single key.
+pub fn bundle_images(info: &Value) -> Vec<BundleImage> {
+    match info.get("images") {
+        Some(Value::Array(images)) => images
+            .iter()
+            .filter_map(|image| {
+                let (slot_class, image) = match image.get("slot-class") {
+                    Some(Value::String(slot_class)) => (slot_class.as_str(), image),
+                    _ => {
+                        let image = image.as_object()?;
+                        if image.len() != 1 {
+                            return None;
+                        }
+                        let (slot_class, image) = image.iter().next()?;
+                        (slot_class.as_str(), image)
+                    }
+                };
+                Some(BundleImage::new(
+                    slot_class,
+                    image.get("size")?.as_u64()?,
+                    image
+                        .get("filename")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default(),
+                ))
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Get the value of a key in a section of the meta data of an update bundle from the output of `rauc info`
+pub fn bundle_meta(info: &Value, section: &str, key: &str) -> Option<String> {
+    info.get("meta")?
+        .get(section)?
+        .get(key)?
+        .as_str()
+        .map(|value| value.to_string())
+}
+
+/// The information on the system as provided by `rauc status`
+#[derive(Debug, Default, PartialEq)]
+pub struct Status {
+    pub compatible: String,
+    pub variant: String,
+    pub boot_slot: String,
+    pub primary: String,
+    pub slots: Vec<(String, HashMap<String, String>)>,
+}
+
+/// Get the information on the system from the output of `rauc status`
+///
+/// The status of each slot is flattened to the keys used by RAUC's GetSlotStatus D-Bus method (e.g. "boot-status" or
+/// "bundle.version").
+pub fn unwrap_status(status: &Value) -> Status {
+    let string = |key: &str| {
+        status
+            .get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let slots = match status.get("slots") {
+        Some(Value::Array(slots)) => slots
+            .iter()
+            .filter_map(Value::as_object)
+            .flat_map(|slots| slots.iter())
+            .map(|(name, slot)| {
+                let mut map = HashMap::new();
+                flatten_slot_status(None, slot, &mut map);
+                (name.to_string(), map)
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    Status {
+        compatible: string("compatible"),
+        variant: string("variant"),
+        boot_slot: string("booted"),
+        primary: string("boot_primary"),
+        slots,
+    }
+}
+
+/// Flatten the (nested) status of a slot to a map of dotted keys
+///
+/// The detailed "slot_status" of a slot is merged into the top-level keys.
+fn flatten_slot_status(prefix: Option<&str>, value: &Value, map: &mut HashMap<String, String>) {
+    if let Some(object) = value.as_object() {
+        for (key, value) in object.iter() {
+            let key = key.replace('_', "-");
+            let key = match (prefix, key.as_str()) {
+                (None, "slot-status") => {
+                    flatten_slot_status(None, value, map);
+                    continue;
+                }
+                (None, _) => key,
+                (Some(prefix), _) => format!("{}.{}", prefix, key),
+            };
+            match value {
+                Value::Object(_) => flatten_slot_status(Some(&key), value, map),
+                Value::String(value) => {
+                    map.insert(key, value.to_string());
+                }
+                Value::Number(value) => {
+                    map.insert(key, value.to_string());
+                }
+                Value::Bool(value) => {
+                    map.insert(key, value.to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        r#"{"compatible": "foo", "version": "1.0.0", "images": [{"slot-class": "rootfs", "filename": "rootfs.ext4", "size": 4096}], "meta": {"caterpillar": {"soft-reboot": "true"}}}"#,
+        Some(("foo".to_string(), "1.0.0".to_string())),
+        vec![BundleImage::new("rootfs", 4096, "rootfs.ext4")],
+        Some("true".to_string()),
+    )]
+    #[case(
+        r#"{"compatible": "foo", "version": "1.0.0", "images": [{"rootfs": {"filename": "rootfs.ext4", "size": 4096}}]}"#,
+        Some(("foo".to_string(), "1.0.0".to_string())),
+        vec![BundleImage::new("rootfs", 4096, "rootfs.ext4")],
+        None,
+    )]
+    #[case(r#"{"compatible": "foo"}"#, None, vec![], None)]
+    fn test_bundle_info(
+        #[case] output: &str,
+        #[case] compatible_and_version: Option<(String, String)>,
+        #[case] images: Vec<BundleImage>,
+        #[case] soft_reboot: Option<String>,
+    ) {
+        let info: Value = serde_json::from_str(output).unwrap();
+        assert_eq!(bundle_compatible_and_version(&info), compatible_and_version);
+        assert_eq!(bundle_images(&info), images);
+        assert_eq!(
+            bundle_meta(&info, "caterpillar", "soft-reboot"),
+            soft_reboot
+        );
+    }
+
+    #[rstest]
+    fn test_unwrap_status() {
+        let status: Value = serde_json::from_str(
+            r#"{
+                "compatible": "foo",
+                "variant": "bar",
+                "booted": "A",
+                "boot_primary": "rootfs.0",
+                "slots": [
+                    {"rootfs.0": {"class": "rootfs", "device": "/dev/sda2", "bootname": "A", "state": "booted", "boot_status": "good", "parent": null, "slot_status": {"bundle": {"compatible": "foo", "version": "1.0.0"}, "status": "ok"}}},
+                    {"rootfs.1": {"class": "rootfs", "device": "/dev/sda3", "bootname": "B", "state": "inactive", "boot_status": "good", "parent": null}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let status = unwrap_status(&status);
+        assert_eq!(status.compatible, "foo");
+        assert_eq!(status.variant, "bar");
+        assert_eq!(status.boot_slot, "A");
+        assert_eq!(status.primary, "rootfs.0");
+        assert_eq!(status.slots.len(), 2);
+        assert_eq!(status.slots[0].0, "rootfs.0");
+        assert_eq!(
+            status.slots[0].1,
+            HashMap::from([
+                ("class".to_string(), "rootfs".to_string()),
+                ("device".to_string(), "/dev/sda2".to_string()),
+                ("bootname".to_string(), "A".to_string()),
+                ("state".to_string(), "booted".to_string()),
+                ("boot-status".to_string(), "good".to_string()),
+                ("bundle.compatible".to_string(), "foo".to_string()),
+                ("bundle.version".to_string(), "1.0.0".to_string()),
+                ("status".to_string(), "ok".to_string()),
+            ])
+        );
+        assert_eq!(status.slots[1].1.get("bundle.version"), None);
+    }
+}
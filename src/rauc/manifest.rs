@@ -0,0 +1,246 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! Offline parsing of the manifest of RAUC update bundles
+//!
+//! If RAUC is not able to provide information on an update bundle (in time), its compatible, version and images are read
+//! from the manifest directly, so that the update bundle can still be considered as update candidate.
+//! A RAUC update bundle is a squashfs image followed by a CMS signature and the size of the signature (as big-endian
+//! 64 bit integer).
+//! For bundles in the "verity" and "crypt" format, the manifest is encapsulated in the signature, while for bundles in
+//! the "plain" format, it is read from the squashfs image using `unsquashfs`.
+//!
+//! **NOTE**: The manifest is not verified when read offline. The verification of the update bundle is deferred to its
+//! installation (or the `verify_bundles` configuration option).
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use tokio::process::Command;
+
+use super::BundleImage;
+use crate::error::Error;
+
+/// The name of the manifest in the squashfs image of an update bundle
+const MANIFEST_FILE: &str = "manifest.raucm";
+/// The maximum size of a signature, that is read from the end of an update bundle
+const MAX_SIGNATURE_SIZE: u64 = 1024 * 1024;
+
+/// The manifest of a RAUC update bundle
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Manifest {
+    compatible: String,
+    version: String,
+    images: Vec<BundleImage>,
+}
+
+impl Manifest {
+    /// Read the Manifest of an update bundle
+    ///
+    /// The manifest encapsulated in the signature is preferred over the one in the squashfs image.
+    pub async fn read(path: &Path) -> Result<Self, Error> {
+        let path_str = path.display().to_string();
+        let signature = read_signature(path)
+            .map_err(|error| Error::BundleInfo(path_str.clone(), error.to_string()))?;
+        let manifest = match encapsulated_manifest(&signature) {
+            Some(manifest) => manifest.to_string(),
+            None => {
+                let output = Command::new("unsquashfs")
+                    .args(["-cat", &path_str, MANIFEST_FILE])
+                    .output()
+                    .await?;
+                if !output.status.success() {
+                    return Err(Error::BundleInfo(
+                        path_str,
+                        String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                    ));
+                }
+                String::from_utf8_lossy(&output.stdout).to_string()
+            }
+        };
+
+        Manifest::parse(&manifest).ok_or(Error::BundleInfo(
+            path_str,
+            "no compatible or version in manifest".to_string(),
+        ))
+    }
+
+    /// Parse a Manifest from its INI representation
+    ///
+    /// Returns None, if the manifest does not provide a compatible and version.
+    fn parse(manifest: &str) -> Option<Self> {
+        let mut parsed = Manifest::default();
+        let mut section = "";
+        let mut image: Option<(String, u64, String)> = None;
+        for line in manifest.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                if let Some((slot_class, size, filename)) = image.take() {
+                    parsed
+                        .images
+                        .push(BundleImage::new(&slot_class, size, &filename));
+                }
+                section = &line[1..line.len() - 1];
+                if let Some(slot_class) = section.strip_prefix("image.") {
+                    image = Some((slot_class.to_string(), 0, String::new()));
+                }
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+            match (section, key, image.as_mut()) {
+                ("update", "compatible", _) => parsed.compatible = value.to_string(),
+                ("update", "version", _) => parsed.version = value.to_string(),
+                (_, "filename", Some(image)) => image.2 = value.to_string(),
+                (_, "size", Some(image)) => image.1 = value.parse().unwrap_or_default(),
+                _ => {}
+            }
+        }
+        if let Some((slot_class, size, filename)) = image {
+            parsed
+                .images
+                .push(BundleImage::new(&slot_class, size, &filename));
+        }
+
+        if parsed.compatible.is_empty() || parsed.version.is_empty() {
+            None
+        } else {
+            Some(parsed)
+        }
+    }
+
+    /// Get the compatible of the update bundle
+    pub fn compatible(&self) -> &str {
+        &self.compatible
+    }
+
+    /// Get the (unparsed) version of the update bundle
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Get the images of the update bundle
+    pub fn images(&self) -> &[BundleImage] {
+        &self.images
+    }
+}
+
+/// Read the CMS signature from the end of an update bundle
+fn read_signature(path: &Path) -> Result<Vec<u8>, Error> {
+    let mut file = File::open(path)?;
+    let length = file.metadata()?.len();
+    if length < 8 {
+        return Err(Error::Default("the file is too small".to_string()));
+    }
+    let mut size = [0u8; 8];
+    file.seek(SeekFrom::Start(length - 8))?;
+    file.read_exact(&mut size)?;
+    let size = u64::from_be_bytes(size);
+    if size == 0 || size > MAX_SIGNATURE_SIZE || size > length - 8 {
+        return Err(Error::Default(format!("invalid signature size {}", size)));
+    }
+
+    let mut signature = vec![0u8; size as usize];
+    file.seek(SeekFrom::Start(length - 8 - size))?;
+    file.read_exact(&mut signature)?;
+    Ok(signature)
+}
+
+/// Return the manifest encapsulated in a (DER encoded) CMS signature
+///
+/// The manifest is the content of the first OCTET STRING, that contains an "[update]" section.
+/// Returns None, if the signature is detached (i.e. the bundle is in the "plain" format).
+fn encapsulated_manifest(signature: &[u8]) -> Option<&str> {
+    for offset in 0..signature.len() {
+        // the tag of an OCTET STRING
+        if signature[offset] != 0x04 {
+            continue;
+        }
+        let (start, length) = match signature.get(offset + 1) {
+            Some(&length) if length < 0x80 => (offset + 2, length as usize),
+            // the long form of the length, in which the lower bits encode the number of length bytes
+            Some(&length) if (0x81..=0x84).contains(&length) => {
+                let bytes = (length & 0x7f) as usize;
+                let encoded = signature.get(offset + 2..offset + 2 + bytes)?;
+                (
+                    offset + 2 + bytes,
+                    encoded
+                        .iter()
+                        .fold(0usize, |length, byte| (length << 8) | *byte as usize),
+                )
+            }
+            _ => continue,
+        };
+        let content = match signature.get(start..start.saturating_add(length)) {
+            Some(content) if content.starts_with(b"[") => content,
+            _ => continue,
+        };
+        if let Ok(manifest) = std::str::from_utf8(content) {
+            if manifest.contains("[update]") {
+                return Some(manifest);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::fs::write;
+    use testdir::testdir;
+    use testresult::TestResult;
+
+    const MANIFEST: &str = "[update]\ncompatible=caterpillar\nversion=1.2.3\nbuild=20231001\n\n[bundle]\nformat=verity\n\n[image.rootfs]\nfilename=rootfs.img\nsize=4096\nsha256=abc\n";
+
+    /// Return a DER encoded OCTET STRING with a (long form) length
+    fn octet_string(content: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![0x04, 0x82];
+        encoded.extend((content.len() as u16).to_be_bytes());
+        encoded.extend(content);
+        encoded
+    }
+
+    #[rstest]
+    fn test_manifest_parse() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        assert_eq!(manifest.compatible(), "caterpillar");
+        assert_eq!(manifest.version(), "1.2.3");
+        assert_eq!(
+            manifest.images(),
+            &[BundleImage::new("rootfs", 4096, "rootfs.img")]
+        );
+        assert_eq!(Manifest::parse("[update]\ncompatible=caterpillar\n"), None);
+    }
+
+    #[rstest]
+    fn test_encapsulated_manifest() {
+        let mut signature = vec![0x30, 0x80, 0x06, 0x01, 0x2a, 0x04, 0x02, 0x00, 0x01];
+        signature.extend(octet_string(MANIFEST.as_bytes()));
+        signature.extend([0x04, 0x03, b'[', b'a', b']']);
+        assert_eq!(encapsulated_manifest(&signature), Some(MANIFEST));
+        assert_eq!(encapsulated_manifest(&[0x30, 0x03, 0x04, 0x01, 0x00]), None);
+    }
+
+    #[tokio::test]
+    #[rstest]
+    async fn test_manifest_read() -> TestResult {
+        let path = testdir!().join("update.raucb");
+        let signature = octet_string(MANIFEST.as_bytes());
+        let mut bundle = b"hsqs".repeat(16);
+        bundle.extend(&signature);
+        bundle.extend((signature.len() as u64).to_be_bytes());
+        write(&path, bundle)?;
+
+        let manifest = Manifest::read(&path).await?;
+        assert_eq!(manifest.compatible(), "caterpillar");
+        assert_eq!(manifest.version(), "1.2.3");
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use sha2::Digest;
+use sha2::Sha256;
+use x509_parser::pem::parse_x509_pem;
+
+/// The signer of an update bundle
+///
+/// The signer is derived from the signing certificate of an update bundle and consists of the common name of its
+/// subject, the hex encoded SHA-256 fingerprint of the certificate and the start and end of its validity (in seconds
+/// since the epoch).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Signer {
+    common_name: String,
+    fingerprint: String,
+    not_before: i64,
+    not_after: i64,
+}
+
+impl Signer {
+    /// Create a Signer from the first PEM encoded certificate found in a string
+    ///
+    /// Any text before the certificate (e.g. other output of `rauc info --dump-cert`) is ignored.
+    pub fn from_pem(input: &str) -> Result<Self, String> {
+        let start = input
+            .find("-----BEGIN CERTIFICATE-----")
+            .ok_or_else(|| "no certificate found".to_string())?;
+        let (_, pem) =
+            parse_x509_pem(input[start..].as_bytes()).map_err(|error| error.to_string())?;
+        let certificate = pem.parse_x509().map_err(|error| error.to_string())?;
+
+        Ok(Signer {
+            common_name: certificate
+                .subject()
+                .iter_common_name()
+                .next()
+                .and_then(|common_name| common_name.as_str().ok())
+                .unwrap_or_default()
+                .to_string(),
+            fingerprint: format!("{:x}", Sha256::digest(&pem.contents)),
+            not_before: certificate.validity().not_before.timestamp(),
+            not_after: certificate.validity().not_after.timestamp(),
+        })
+    }
+
+    /// Return the common name of the subject of the signing certificate
+    pub fn common_name(&self) -> &str {
+        &self.common_name
+    }
+
+    /// Return the hex encoded SHA-256 fingerprint of the signing certificate
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// Return the start of the validity of the signing certificate (in seconds since the epoch)
+    pub fn not_before(&self) -> i64 {
+        self.not_before
+    }
+
+    /// Return the end of the validity of the signing certificate (in seconds since the epoch)
+    pub fn not_after(&self) -> i64 {
+        self.not_after
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const CERTIFICATE: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIB0DCCAXWgAwIBAgIUWLsUiFCSLp6cuONuID8znCenilMwCgYIKoZIzj0EAwIw
+PTEZMBcGA1UECgwQQ2F0ZXJwaWxsYXIgVGVzdDEgMB4GA1UEAwwXQ2F0ZXJwaWxs
+YXIgVGVzdCBTaWduZXIwHhcNMjYxMDE2MDEyMzAzWhcNMzYxMDEzMDEyMzAzWjA9
+MRkwFwYDVQQKDBBDYXRlcnBpbGxhciBUZXN0MSAwHgYDVQQDDBdDYXRlcnBpbGxh
+ciBUZXN0IFNpZ25lcjBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABAFjDNdjZHoI
+5XYGrBOJquQ1fUJEJBz5CPzAPmd5SYS5B/o1Cu7tjMLIb0t60+5nTqlOHkKdc22C
+4v5Ri1lDtm+jUzBRMB0GA1UdDgQWBBSC6Xgi+vGTntv+Gb1+XGbAEhnQzDAfBgNV
+HSMEGDAWgBSC6Xgi+vGTntv+Gb1+XGbAEhnQzDAPBgNVHRMBAf8EBTADAQH/MAoG
+CCqGSM49BAMCA0kAMEYCIQD6YJk7+DB2EaZAsWzyN6tlJfqY4PFsFZSw9NZu58Xm
+zgIhAO9VpjOtQ86Zd0hE60LPrMlsS4k8rxJA2QluPCPz+DN6
+-----END CERTIFICATE-----
+";
+
+    #[rstest]
+    fn test_signer_from_pem() {
+        let signer = Signer::from_pem(&format!("Compatible: 'foo'\n\n{}", CERTIFICATE)).unwrap();
+        assert_eq!(signer.common_name(), "Caterpillar Test Signer");
+        assert_eq!(
+            signer.fingerprint(),
+            "387785d45d097cd280351464a34c7c92957d6bf428221cda0709e690031f7509"
+        );
+        assert_eq!(signer.not_before(), 1792113783);
+        assert_eq!(signer.not_after(), 2107473783);
+
+        assert!(Signer::from_pem("Compatible: 'foo'").is_err());
+    }
+}
@@ -0,0 +1,417 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::fs::create_dir_all;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use async_trait::async_trait;
+use nix::sys::reboot::reboot;
+use nix::sys::reboot::RebootMode;
+use nix::unistd::sync;
+use strum::Display;
+use strum::EnumString;
+use zbus::Connection;
+
+use crate::config::CaterpillarConfig;
+use crate::error::Error;
+use crate::proxy::login1::ManagerProxy as LoginManagerProxy;
+use crate::proxy::systemd1::ManagerProxy as SystemdManagerProxy;
+use crate::rauc::RaucInfo;
+use crate::rauc::Slot;
+
+/// The method used for rebooting the system
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq)]
+#[non_exhaustive]
+pub enum RebootMethod {
+    /// Reboot using logind
+    #[strum(ascii_case_insensitive, to_string = "logind")]
+    Logind,
+    /// Reboot using the systemd manager
+    #[strum(ascii_case_insensitive, to_string = "systemd")]
+    Systemd,
+    /// Reboot directly using the reboot(2) system call
+    #[strum(ascii_case_insensitive, to_string = "syscall")]
+    Syscall,
+    /// Load the kernel of the primary slot using kexec and boot into it using the systemd manager
+    #[strum(ascii_case_insensitive, to_string = "kexec")]
+    Kexec,
+}
+
+/// A backend, that is able to reboot the system
+#[async_trait]
+pub trait RebootBackend {
+    /// Ensure that the backend is available
+    async fn check(&self, connection: &Connection) -> Result<(), Error>;
+
+    /// Reboot the system
+    async fn reboot(&self, connection: &Connection) -> Result<(), Error>;
+}
+
+/// A RebootBackend using `org.freedesktop.login1.Manager`
+pub struct Logind;
+
+#[async_trait]
+impl RebootBackend for Logind {
+    async fn check(&self, connection: &Connection) -> Result<(), Error> {
+        println!("Connecting to logind over dbus...");
+        LoginManagerProxy::new(connection).await?;
+        Ok(())
+    }
+
+    async fn reboot(&self, connection: &Connection) -> Result<(), Error> {
+        println!("Rebooting using logind...");
+        LoginManagerProxy::new(connection)
+            .await?
+            .reboot(false)
+            .await?;
+        Ok(())
+    }
+}
+
+/// A RebootBackend using `org.freedesktop.systemd1.Manager`
+pub struct Systemd;
+
+#[async_trait]
+impl RebootBackend for Systemd {
+    async fn check(&self, connection: &Connection) -> Result<(), Error> {
+        println!("Connecting to systemd over dbus...");
+        let version = SystemdManagerProxy::new(connection)
+            .await?
+            .version()
+            .await?;
+        println!("Communicating with systemd {}", version);
+        Ok(())
+    }
+
+    async fn reboot(&self, connection: &Connection) -> Result<(), Error> {
+        println!("Rebooting using systemd...");
+        SystemdManagerProxy::new(connection).await?.reboot().await?;
+        Ok(())
+    }
+}
+
+/// A RebootBackend using the reboot(2) system call
+///
+/// This backend does not stop any services and is meant as a fallback for minimal images without logind or systemd.
+pub struct Syscall;
+
+#[async_trait]
+impl RebootBackend for Syscall {
+    async fn check(&self, _connection: &Connection) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn reboot(&self, _connection: &Connection) -> Result<(), Error> {
+        println!("Rebooting using the reboot system call...");
+        sync();
+        reboot(RebootMode::RB_AUTOBOOT).map_err(|error| Error::Reboot(error.to_string()))?;
+        Ok(())
+    }
+}
+
+/// A RebootBackend, that loads the kernel of the primary slot using kexec(8) and boots into it using the systemd manager
+///
+/// The device of the primary slot (i.e. the one an update bundle has been installed to) is mounted read-only, so that its
+/// kernel and initrd can be loaded.
+/// If loading the kernel is not supported or fails, the system is rebooted using the [`Systemd`] backend instead.
+pub struct Kexec {
+    kernel: PathBuf,
+    initrd: Option<PathBuf>,
+    cmdline: Option<String>,
+    mount_dir: PathBuf,
+}
+
+impl Kexec {
+    /// Create a new Kexec
+    ///
+    /// The `kernel` and `initrd` paths are relative to the root of the slot.
+    /// If no `cmdline` is provided, the current kernel command line is adapted to the slot.
+    pub fn new(
+        kernel: &Path,
+        initrd: Option<&Path>,
+        cmdline: Option<&str>,
+        mount_dir: &Path,
+    ) -> Self {
+        Kexec {
+            kernel: kernel.into(),
+            initrd: initrd.map(|initrd| initrd.into()),
+            cmdline: cmdline.map(|cmdline| cmdline.to_string()),
+            mount_dir: mount_dir.into(),
+        }
+    }
+
+    /// Return whether the running kernel supports kexec
+    fn is_supported() -> bool {
+        Path::new("/sys/kernel/kexec_loaded").exists()
+    }
+
+    /// Load the kernel of the primary slot
+    async fn load(&self, connection: &Connection) -> Result<(), Error> {
+        if !Kexec::is_supported() {
+            return Err(Error::Reboot(
+                "The running kernel does not support kexec".to_string(),
+            ));
+        }
+
+        let rauc_info = RaucInfo::new(connection).await?;
+        let slot = primary_slot(&rauc_info)?;
+        let device = slot.device().unwrap_or_default();
+        let cmdline = match self.cmdline.as_ref() {
+            Some(cmdline) => cmdline.to_string(),
+            None => slot_cmdline(
+                read_to_string("/proc/cmdline")?.trim(),
+                device,
+                slot.bootname(),
+            ),
+        };
+
+        create_dir_all(&self.mount_dir)?;
+        run(Command::new("mount")
+            .args(["-o", "ro"])
+            .arg(device)
+            .arg(&self.mount_dir))?;
+
+        let mut command = Command::new("kexec");
+        command
+            .arg("--load")
+            .arg(self.mount_dir.join(&self.kernel))
+            .arg(format!("--append={}", cmdline));
+        if let Some(initrd) = self.initrd.as_ref() {
+            command.arg(format!(
+                "--initrd={}",
+                self.mount_dir.join(initrd).display()
+            ));
+        }
+        println!("Loading kernel of slot {} using kexec...", slot.name());
+        let result = run(&mut command);
+        run(Command::new("umount").arg(&self.mount_dir))?;
+        result
+    }
+}
+
+#[async_trait]
+impl RebootBackend for Kexec {
+    async fn check(&self, connection: &Connection) -> Result<(), Error> {
+        if !Kexec::is_supported() {
+            eprintln!("The running kernel does not support kexec, falling back to a full reboot.");
+        }
+        Systemd.check(connection).await
+    }
+
+    async fn reboot(&self, connection: &Connection) -> Result<(), Error> {
+        match self.load(connection).await {
+            Ok(()) => {
+                println!("Rebooting into the loaded kernel using systemd...");
+                SystemdManagerProxy::new(connection)
+                    .await?
+                    .start_unit("kexec.target", "replace-irreversibly")
+                    .await?;
+                Ok(())
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                eprintln!("Falling back to a full reboot.");
+                Systemd.reboot(connection).await
+            }
+        }
+    }
+}
+
+/// A RebootBackend, that soft-reboots into the primary slot using the systemd manager
+///
+/// The device of the primary slot (i.e. the one an update bundle has been installed to) is mounted to `/run/nextroot`, so
+/// that systemd switches to it as new root filesystem, while the kernel keeps running.
+/// This is only suitable for updates, that do not change the kernel.
+pub struct SoftReboot;
+
+#[async_trait]
+impl RebootBackend for SoftReboot {
+    async fn check(&self, connection: &Connection) -> Result<(), Error> {
+        Systemd.check(connection).await
+    }
+
+    async fn reboot(&self, connection: &Connection) -> Result<(), Error> {
+        let rauc_info = RaucInfo::new(connection).await?;
+        let slot = primary_slot(&rauc_info)?;
+        let nextroot = Path::new("/run/nextroot");
+        create_dir_all(nextroot)?;
+        run(Command::new("mount")
+            .arg(slot.device().unwrap_or_default())
+            .arg(nextroot))?;
+
+        println!("Soft-rebooting into slot {} using systemd...", slot.name());
+        SystemdManagerProxy::new(connection)
+            .await?
+            .start_unit("soft-reboot.target", "replace-irreversibly")
+            .await?;
+        Ok(())
+    }
+}
+
+/// Return the primary slot (i.e. the one an update bundle has been installed to) of a RaucInfo, if it has a device
+fn primary_slot(rauc_info: &RaucInfo) -> Result<&Slot, Error> {
+    rauc_info
+        .slots()
+        .iter()
+        .find(|slot| slot.is_primary() && slot.device().is_some())
+        .ok_or(Error::Reboot(
+            "No primary slot with a device found".to_string(),
+        ))
+}
+
+/// Run a command and return an error containing its stderr, if it fails
+fn run(command: &mut Command) -> Result<(), Error> {
+    let output = command.output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::Reboot(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// Adapt a kernel command line to a slot
+///
+/// The `root` parameter is set to the device of the slot and the `rauc.slot` parameter (if any) to its bootname.
+fn slot_cmdline(cmdline: &str, device: &str, bootname: Option<&str>) -> String {
+    cmdline
+        .split_whitespace()
+        .map(|parameter| {
+            if parameter.starts_with("root=") {
+                format!("root={}", device)
+            } else if let (true, Some(bootname)) = (parameter.starts_with("rauc.slot="), bootname) {
+                format!("rauc.slot={}", bootname)
+            } else {
+                parameter.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Create the RebootBackend for the RebootMethod of a CaterpillarConfig
+pub fn reboot_backend(config: &CaterpillarConfig) -> Box<dyn RebootBackend + Send + Sync> {
+    match config.reboot_method() {
+        RebootMethod::Logind => Box::new(Logind),
+        RebootMethod::Systemd => Box::new(Systemd),
+        RebootMethod::Syscall => Box::new(Syscall),
+        RebootMethod::Kexec => Box::new(Kexec::new(
+            config.kexec_kernel(),
+            config.kexec_initrd(),
+            config.kexec_cmdline(),
+            config.kexec_mount_dir(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statemachine::next;
+    use crate::statemachine::Action;
+    use crate::statemachine::Context;
+    use crate::statemachine::State;
+    use crate::testing::private_bus;
+    use crate::testing::serve_fake_login;
+    use crate::testing::FakeLogin;
+    use crate::testing::LoginCall;
+    use rstest::rstest;
+    use std::str::FromStr;
+    use testresult::TestResult;
+
+    #[rstest]
+    #[case("logind", RebootMethod::Logind)]
+    #[case("systemd", RebootMethod::Systemd)]
+    #[case("SYSCALL", RebootMethod::Syscall)]
+    #[case("kexec", RebootMethod::Kexec)]
+    fn test_reboot_method_from_str(#[case] input: &str, #[case] method: RebootMethod) {
+        assert_eq!(RebootMethod::from_str(input).unwrap(), method);
+    }
+
+    #[rstest]
+    #[case(
+        "root=/dev/sda2 rauc.slot=A quiet",
+        Some("B"),
+        "root=/dev/sda3 rauc.slot=B quiet"
+    )]
+    #[case(
+        "root=/dev/sda2 rauc.slot=A quiet",
+        None,
+        "root=/dev/sda3 rauc.slot=A quiet"
+    )]
+    #[case("quiet  splash", Some("B"), "quiet splash")]
+    fn test_slot_cmdline(
+        #[case] cmdline: &str,
+        #[case] bootname: Option<&str>,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(slot_cmdline(cmdline, "/dev/sda3", bootname), expected);
+    }
+
+    #[rstest]
+    #[case(true, 1, false, true)]
+    #[case(false, 1, false, false)]
+    #[case(false, 2, true, true)]
+    async fn test_logind_reboot_after_unmounted(
+        #[case] auto_reboot: bool,
+        #[case] iteration: usize,
+        #[case] marked_for_reboot: bool,
+        #[case] reboot: bool,
+    ) -> TestResult {
+        let daemon = private_bus();
+        let login = FakeLogin::default();
+        let connection = serve_fake_login(&daemon, login.clone()).await;
+        Logind.check(&connection).await?;
+
+        let context = Context {
+            auto_reboot,
+            ..Default::default()
+        };
+        let (_, actions) = next(
+            &State::Unmounting(true, iteration, marked_for_reboot),
+            State::Unmounted(true, iteration, marked_for_reboot),
+            &context,
+        );
+        for action in actions {
+            if action == Action::Reboot {
+                Logind.reboot(&connection).await?;
+            }
+        }
+
+        assert_eq!(
+            login.calls(),
+            if reboot {
+                vec![LoginCall::Reboot(false)]
+            } else {
+                vec![]
+            }
+        );
+        drop(daemon);
+        Ok(())
+    }
+
+    #[rstest]
+    async fn test_fake_login_inhibit() -> TestResult {
+        let daemon = private_bus();
+        let login = FakeLogin::default();
+        let connection = serve_fake_login(&daemon, login.clone()).await;
+        LoginManagerProxy::new(&connection)
+            .await?
+            .inhibit("shutdown", "caterpillar", "Installing update", "block")
+            .await?;
+        assert_eq!(
+            login.calls(),
+            vec![LoginCall::Inhibit(
+                "shutdown".to_string(),
+                "caterpillar".to_string(),
+                "Installing update".to_string(),
+                "block".to_string()
+            )]
+        );
+        drop(daemon);
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! Reporting of the lifecycle of update cycles
+//!
+//! [`Reporter`]s are notified when a search for updates is started, an update is found, an installation has finished and
+//! a reboot is pending, so that notifications (e.g. to the terminals of logged-in users or to a remote service) are
+//! implemented behind a single interface.
+//! The reporters are registered using the `reporters` configuration option.
+use std::process::Command;
+
+use async_trait::async_trait;
+use strum::Display;
+use strum::EnumString;
+
+use crate::config::CaterpillarConfig;
+use crate::error::Error;
+use crate::outcome::UpdateResult;
+use crate::rauc::UpdateBundle;
+
+mod email;
+
+pub use email::Email;
+
+/// The kind of a Reporter, that can be registered using the configuration
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq)]
+#[non_exhaustive]
+pub enum ReporterKind {
+    /// Print all lifecycle events to the log
+    #[strum(ascii_case_insensitive, to_string = "log")]
+    Log,
+    /// Broadcast pending reboots to the terminals of logged-in users using wall(1)
+    #[strum(ascii_case_insensitive, to_string = "wall")]
+    Wall,
+    /// Send a summary mail after each installation using an SMTP relay
+    #[strum(ascii_case_insensitive, to_string = "email")]
+    Email,
+}
+
+/// A receiver of the lifecycle events of update cycles
+///
+/// All hooks default to doing nothing, so that a Reporter only implements the ones it is interested in.
+#[async_trait]
+pub trait Reporter {
+    /// A search for updates has been started in a transaction
+    async fn scan_started(&self, _transaction: u64) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// An update bundle has been found in a transaction
+    async fn update_found(&self, _transaction: u64, _bundle: &UpdateBundle) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The installation of an update bundle has finished (successfully or not) in a transaction
+    async fn install_finished(
+        &self,
+        _transaction: u64,
+        _result: &UpdateResult,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The system is rebooted after a delay (in seconds)
+    async fn reboot_pending(&self, _transaction: u64, _delay: u64) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A Reporter, that prints all lifecycle events to the log
+pub struct Log;
+
+#[async_trait]
+impl Reporter for Log {
+    async fn scan_started(&self, transaction: u64) -> Result<(), Error> {
+        println!("[transaction {}] Search for updates started", transaction);
+        Ok(())
+    }
+
+    async fn update_found(&self, transaction: u64, bundle: &UpdateBundle) -> Result<(), Error> {
+        println!(
+            "[transaction {}] Update found: {} (version {})",
+            transaction,
+            bundle.path(),
+            bundle.version()
+        );
+        Ok(())
+    }
+
+    async fn install_finished(&self, transaction: u64, result: &UpdateResult) -> Result<(), Error> {
+        if result.success() {
+            println!(
+                "[transaction {}] Installation of version {} succeeded",
+                transaction,
+                result.version()
+            );
+        } else {
+            println!(
+                "[transaction {}] Installation of version {} failed: {}",
+                transaction,
+                result.version(),
+                result.error()
+            );
+        }
+        Ok(())
+    }
+
+    async fn reboot_pending(&self, transaction: u64, delay: u64) -> Result<(), Error> {
+        println!("[transaction {}] Rebooting in {}s", transaction, delay);
+        Ok(())
+    }
+}
+
+/// A Reporter, that broadcasts pending reboots to the terminals of logged-in users using wall(1)
+pub struct Wall;
+
+#[async_trait]
+impl Reporter for Wall {
+    async fn reboot_pending(&self, _transaction: u64, delay: u64) -> Result<(), Error> {
+        let output = Command::new("wall")
+            .arg(format!("System restarting in {}s to finish update", delay))
+            .output()?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(Error::Report(
+                ReporterKind::Wall.to_string(),
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ))
+        }
+    }
+}
+
+/// The Reporters registered using the configuration
+///
+/// Errors of Reporters are only printed, as reporting must not change the outcome of an update cycle.
+#[derive(Default)]
+pub struct Reporters {
+    reporters: Vec<Box<dyn Reporter + Send + Sync>>,
+}
+
+impl Reporters {
+    /// Create the Reporters from the configuration
+    pub fn from_config(config: &CaterpillarConfig) -> Self {
+        let mut reporters = Reporters::default();
+        for kind in config.reporters() {
+            reporters.register(match kind {
+                ReporterKind::Log => Box::new(Log),
+                ReporterKind::Wall => Box::new(Wall),
+                ReporterKind::Email => Box::new(Email::new(
+                    config.email_relay(),
+                    config.email_from(),
+                    config.email_to(),
+                )),
+            });
+        }
+        reporters
+    }
+
+    /// Register an additional Reporter
+    pub fn register(&mut self, reporter: Box<dyn Reporter + Send + Sync>) {
+        self.reporters.push(reporter);
+    }
+
+    /// Notify all Reporters, that a search for updates has been started
+    pub async fn scan_started(&self, transaction: u64) {
+        for reporter in self.reporters.iter() {
+            if let Err(error) = reporter.scan_started(transaction).await {
+                eprintln!("{}", error);
+            }
+        }
+    }
+
+    /// Notify all Reporters, that an update bundle has been found
+    pub async fn update_found(&self, transaction: u64, bundle: &UpdateBundle) {
+        for reporter in self.reporters.iter() {
+            if let Err(error) = reporter.update_found(transaction, bundle).await {
+                eprintln!("{}", error);
+            }
+        }
+    }
+
+    /// Notify all Reporters, that the installation of an update bundle has finished
+    pub async fn install_finished(&self, transaction: u64, result: &UpdateResult) {
+        for reporter in self.reporters.iter() {
+            if let Err(error) = reporter.install_finished(transaction, result).await {
+                eprintln!("{}", error);
+            }
+        }
+    }
+
+    /// Notify all Reporters, that a reboot is pending
+    pub async fn reboot_pending(&self, transaction: u64, delay: u64) {
+        for reporter in self.reporters.iter() {
+            if let Err(error) = reporter.reboot_pending(transaction, delay).await {
+                eprintln!("{}", error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::str::FromStr;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    /// A Reporter, that records the transaction of the last started search
+    struct Recorder(Arc<AtomicU64>);
+
+    #[async_trait]
+    impl Reporter for Recorder {
+        async fn scan_started(&self, transaction: u64) -> Result<(), Error> {
+            self.0.store(transaction, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[rstest]
+    #[case("log", ReporterKind::Log)]
+    #[case("WALL", ReporterKind::Wall)]
+    #[case("email", ReporterKind::Email)]
+    fn test_reporter_kind_from_str(#[case] input: &str, #[case] kind: ReporterKind) {
+        assert_eq!(ReporterKind::from_str(input).unwrap(), kind);
+    }
+
+    #[tokio::test]
+    #[rstest]
+    async fn test_reporters() {
+        let transaction = Arc::new(AtomicU64::new(0));
+        let mut reporters = Reporters::default();
+        reporters.register(Box::new(Log));
+        reporters.register(Box::new(Recorder(transaction.clone())));
+        reporters.scan_started(3).await;
+        reporters.reboot_pending(3, 0).await;
+        assert_eq!(transaction.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,235 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! A Reporter sending summary mails using an SMTP relay
+//!
+//! The mails are sent using plain SMTP (without authentication or TLS), which is meant for a relay on the local machine
+//! or in a trusted network (e.g. a local MTA forwarding mails).
+use std::fs::read_to_string;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpStream;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::task::spawn_blocking;
+
+use super::Reporter;
+use crate::error::Error;
+use crate::machine::Machine;
+use crate::outcome::UpdateResult;
+
+/// The timeout for connecting to and communicating with the SMTP relay
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A Reporter, that sends a summary mail after each installation using an SMTP relay
+pub struct Email {
+    relay: String,
+    from: String,
+    to: Vec<String>,
+}
+
+impl Email {
+    /// Create a new Email Reporter for an SMTP `relay` (e.g. "localhost:25"), a sender and a list of recipients
+    pub fn new(relay: &str, from: &str, to: &[String]) -> Self {
+        Email {
+            relay: relay.to_string(),
+            from: from.to_string(),
+            to: to.to_vec(),
+        }
+    }
+}
+
+#[async_trait]
+impl Reporter for Email {
+    async fn install_finished(&self, transaction: u64, result: &UpdateResult) -> Result<(), Error> {
+        let hostname = read_to_string("/proc/sys/kernel/hostname")
+            .map(|hostname| hostname.trim().to_string())
+            .unwrap_or_default();
+        let message = summary(
+            &self.from,
+            &self.to,
+            &hostname,
+            &Machine::read(),
+            transaction,
+            result,
+        );
+        let (relay, from, to) = (self.relay.clone(), self.from.clone(), self.to.clone());
+        spawn_blocking(move || send(&relay, &from, &to, &message))
+            .await
+            .map_err(|x| Error::Report("email".to_string(), x.to_string()))??;
+        println!("Sent summary mail to {}", self.to.join(", "));
+        Ok(())
+    }
+}
+
+/// Return the summary mail (headers and body) of an installation on a machine
+fn summary(
+    from: &str,
+    to: &[String],
+    hostname: &str,
+    machine: &Machine,
+    transaction: u64,
+    result: &UpdateResult,
+) -> String {
+    let outcome = if result.success() {
+        "succeeded"
+    } else {
+        "failed"
+    };
+    let mut body = format!(
+        "The installation of version {} {} on {}.\r\n\r\nTransaction: {}\r\nMachine ID: {}\r\nSerial: {}\r\n",
+        result.version(),
+        outcome,
+        hostname,
+        transaction,
+        machine.machine_id().unwrap_or_default(),
+        machine.serial().unwrap_or_default(),
+    );
+    if !result.success() {
+        body.push_str(&format!("Error: {}\r\n", result.error()));
+    }
+    format!(
+        "From: {}\r\nTo: {}\r\nSubject: [caterpillar] Update to {} {} on {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}",
+        from,
+        to.join(", "),
+        result.version(),
+        outcome,
+        hostname,
+        body
+    )
+}
+
+/// Read the (possibly multiline) reply of an SMTP server and ensure, that its code is the `expected` one
+fn expect_reply(reader: &mut impl BufRead, expected: &str) -> Result<(), Error> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(Error::Report(
+                "email".to_string(),
+                "the SMTP relay closed the connection".to_string(),
+            ));
+        }
+        // lines of multiline replies have a "-" after the code
+        if line.len() > 3 && line.as_bytes()[3] == b'-' {
+            continue;
+        }
+        if line.starts_with(expected) {
+            return Ok(());
+        }
+        return Err(Error::Report(
+            "email".to_string(),
+            format!("unexpected reply of the SMTP relay: {}", line.trim()),
+        ));
+    }
+}
+
+/// Send a `message` from a sender to a list of recipients using an SMTP relay
+///
+/// Lines of the message starting with a dot are escaped (see RFC 5321, section 4.5.2).
+fn send(relay: &str, from: &str, to: &[String], message: &str) -> Result<(), Error> {
+    let mut stream = TcpStream::connect(relay)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    expect_reply(&mut reader, "220")?;
+    let mut command = |command: String, expected: &str| -> Result<(), Error> {
+        stream.write_all(command.as_bytes())?;
+        expect_reply(&mut reader, expected)
+    };
+    command("EHLO caterpillar\r\n".to_string(), "250")?;
+    command(format!("MAIL FROM:<{}>\r\n", from), "250")?;
+    for recipient in to {
+        command(format!("RCPT TO:<{}>\r\n", recipient), "250")?;
+    }
+    command("DATA\r\n".to_string(), "354")?;
+    let data = message
+        .split("\r\n")
+        .map(|line| {
+            if line.starts_with('.') {
+                format!(".{}", line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\r\n");
+    command(format!("{}\r\n.\r\n", data), "250")?;
+    command("QUIT\r\n".to_string(), "221")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::net::TcpListener;
+    use std::thread;
+    use testresult::TestResult;
+
+    #[rstest]
+    fn test_summary() {
+        let message = summary(
+            "caterpillar@localhost",
+            &["ops@example.org".to_string()],
+            "device",
+            &Machine::default(),
+            3,
+            &UpdateResult::new("1.2.3", &Err("Installation error".to_string())),
+        );
+        assert!(message.contains("Subject: [caterpillar] Update to 1.2.3 failed on device\r\n"));
+        assert!(message.contains("To: ops@example.org\r\n"));
+        assert!(message.contains("Error: Installation error\r\n"));
+    }
+
+    #[rstest]
+    fn test_send() -> TestResult {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let relay = listener.local_addr()?.to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut received = vec![];
+            stream.write_all(b"220 relay ESMTP\r\n").unwrap();
+            let mut data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                received.push(line.clone());
+                let reply: &[u8] = match line.trim_end() {
+                    "." if data => {
+                        data = false;
+                        b"250 queued\r\n"
+                    }
+                    _ if data => continue,
+                    "EHLO caterpillar" => b"250-relay\r\n250 8BITMIME\r\n",
+                    "DATA" => {
+                        data = true;
+                        b"354 go ahead\r\n"
+                    }
+                    "QUIT" => {
+                        stream.write_all(b"221 bye\r\n").unwrap();
+                        break;
+                    }
+                    _ => b"250 ok\r\n",
+                };
+                stream.write_all(reply).unwrap();
+            }
+            received
+        });
+
+        send(
+            &relay,
+            "caterpillar@localhost",
+            &["ops@example.org".to_string()],
+            "Subject: test\r\n\r\n.hidden",
+        )?;
+        let received = server.join().unwrap();
+        assert!(received.contains(&"RCPT TO:<ops@example.org>\r\n".to_string()));
+        assert!(received.contains(&"..hidden\r\n".to_string()));
+        Ok(())
+    }
+}
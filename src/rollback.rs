@@ -0,0 +1,104 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::fs::create_dir_all;
+use std::fs::read_to_string;
+use std::fs::write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::error::Error;
+
+/// A persistent build timestamp, below which update bundles are refused
+///
+/// The floor is the build timestamp (in seconds since the epoch) of the newest update bundle installed so far and is
+/// stored in a file on its own.
+/// As the build timestamp is part of the signed manifest of an update bundle, this protects against the replay of old
+/// (but validly signed) update bundles, regardless of their version.
+#[derive(Clone, Debug)]
+pub struct RollbackFloor {
+    path: PathBuf,
+    timestamp: Option<i64>,
+}
+
+impl RollbackFloor {
+    /// Read a RollbackFloor from a file
+    ///
+    /// If the file does not exist or can not be parsed, no floor is set.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let timestamp = if path.exists() {
+            read_to_string(path)?.trim().parse().ok()
+        } else {
+            None
+        };
+
+        Ok(RollbackFloor {
+            path: path.into(),
+            timestamp,
+        })
+    }
+
+    /// The optional build timestamp below which update bundles are refused
+    pub fn timestamp(&self) -> Option<i64> {
+        self.timestamp
+    }
+
+    /// Return whether an update bundle with an optional build timestamp is permitted
+    ///
+    /// Update bundles without a build timestamp are never permitted, while all update bundles with a build timestamp
+    /// are permitted, as long as no floor is set.
+    pub fn permits(&self, build_timestamp: Option<i64>) -> bool {
+        match (build_timestamp, self.timestamp) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(build_timestamp), Some(floor)) => build_timestamp >= floor,
+        }
+    }
+
+    /// Raise the floor to a build timestamp and persist it
+    ///
+    /// The floor is never lowered.
+    pub fn raise(&mut self, build_timestamp: i64) -> Result<(), Error> {
+        if self.timestamp.is_some_and(|floor| floor >= build_timestamp) {
+            return Ok(());
+        }
+        self.timestamp = Some(build_timestamp);
+
+        if let Some(parent) = self.path.parent() {
+            create_dir_all(parent)?;
+        }
+        write(&self.path, format!("{}\n", build_timestamp))?;
+        println!(
+            "Raised rollback floor {:?} to build timestamp {}",
+            self.path, build_timestamp
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use testdir::testdir;
+    use testresult::TestResult;
+
+    #[rstest]
+    fn test_rollback_floor() -> TestResult {
+        let path = testdir!().join("state").join("rollback_floor");
+
+        let mut floor = RollbackFloor::read(&path)?;
+        assert_eq!(floor.timestamp(), None);
+        assert!(floor.permits(Some(0)));
+        assert!(!floor.permits(None));
+        floor.raise(1700000000)?;
+        floor.raise(1600000000)?;
+
+        let floor = RollbackFloor::read(&path)?;
+        assert_eq!(floor.timestamp(), Some(1700000000));
+        assert!(floor.permits(Some(1700000000)));
+        assert!(floor.permits(Some(1800000000)));
+        assert!(!floor.permits(Some(1600000000)));
+        assert_eq!(read_to_string(&path)?, "1700000000\n");
+        Ok(())
+    }
+}
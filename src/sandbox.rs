@@ -0,0 +1,348 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! Opt-in sandboxing of the daemon using Landlock and seccomp
+//!
+//! The sandbox is applied to the calling thread (and is inherited by all threads and processes started by it), so it has
+//! to be applied before the runtime starts its threads.
+//! A Landlock ruleset restricts filesystem writes to the directories caterpillar writes to, while a seccomp filter denies
+//! system calls, that caterpillar never uses (e.g. loading kernel modules or tracing other processes).
+//! As sandboxed processes can not mount filesystems, media are mounted using udisks2.
+use std::fs::create_dir_all;
+use std::fs::File;
+use std::io;
+use std::mem::size_of;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+use nix::libc;
+
+use crate::config::CaterpillarConfig;
+use crate::error::Error;
+
+/// Flag for landlock_create_ruleset(2) to return the highest supported Landlock ABI version
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+/// The rule type of landlock_add_rule(2) for a file hierarchy
+const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+/// The write-related filesystem access rights of Landlock ABI version 1
+const LANDLOCK_ACCESS_FS_WRITE_V1: u64 = (1 << 1) // WRITE_FILE
+    | (1 << 4) // REMOVE_DIR
+    | (1 << 5) // REMOVE_FILE
+    | (1 << 6) // MAKE_CHAR
+    | (1 << 7) // MAKE_DIR
+    | (1 << 8) // MAKE_REG
+    | (1 << 9) // MAKE_SOCK
+    | (1 << 10) // MAKE_FIFO
+    | (1 << 11) // MAKE_BLOCK
+    | (1 << 12); // MAKE_SYM
+/// The access right to link or rename files across directories (Landlock ABI version 2)
+const LANDLOCK_ACCESS_FS_REFER: u64 = 1 << 13;
+/// The access right to truncate files (Landlock ABI version 3)
+const LANDLOCK_ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+/// The directories, that are writable regardless of the configuration
+///
+/// UDisks2 mounts filesystems below /run/media or /media, /dev provides e.g. /dev/null and terminals.
+const WRITABLE_DIRS: &[&str] = &["/dev", "/media", "/run"];
+
+/// The attributes of a Landlock ruleset (see landlock_create_ruleset(2))
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+/// The attributes of a Landlock rule for a file hierarchy (see landlock_add_rule(2))
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// The AUDIT_ARCH of the target architecture, that the seccomp filter is built for
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+#[cfg(target_arch = "riscv64")]
+const AUDIT_ARCH: Option<u32> = Some(0xC000_00F3);
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// The BPF instructions used by the seccomp filter (BPF_LD | BPF_W | BPF_ABS, BPF_JMP | BPF_JEQ | BPF_K, etc.)
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_JMP_JEQ_K: u16 = 0x15;
+const BPF_JMP_JGE_K: u16 = 0x35;
+const BPF_RET_K: u16 = 0x06;
+/// The offsets of the system call number and architecture in struct seccomp_data
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+/// The return values of the seccomp filter
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+/// The first system call number of the x32 ABI
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// The system calls denied by the seccomp filter
+///
+/// These are never used by caterpillar or the processes it starts, but widen the attack surface considerably.
+const DENIED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_acct,
+    libc::SYS_add_key,
+    libc::SYS_bpf,
+    libc::SYS_delete_module,
+    libc::SYS_finit_module,
+    libc::SYS_init_module,
+    libc::SYS_kexec_file_load,
+    libc::SYS_kexec_load,
+    libc::SYS_keyctl,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_perf_event_open,
+    libc::SYS_pivot_root,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_ptrace,
+    libc::SYS_request_key,
+    libc::SYS_setns,
+    libc::SYS_swapoff,
+    libc::SYS_swapon,
+    libc::SYS_userfaultfd,
+];
+
+/// Return the directories, that are writable in the sandbox
+///
+/// Directories, that do not exist, are omitted.
+pub fn writable_dirs(config: &CaterpillarConfig) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = WRITABLE_DIRS.iter().map(PathBuf::from).collect();
+    dirs.push(config.state_dir().to_path_buf());
+    dirs.push(config.sftp_cache_dir().to_path_buf());
+    dirs.push(config.hawkbit_cache_dir().to_path_buf());
+    dirs.push(config.tmp_dir().to_path_buf());
+    dirs.extend(config.stage_dir().map(|dir| dir.to_path_buf()));
+    // override update bundles in inbox directories are disabled after installation
+    dirs.extend(config.inbox_dirs().iter().cloned());
+    dirs.retain(|dir| dir.is_dir());
+    dirs
+}
+
+/// Return the seccomp filter, that denies the [`DENIED_SYSCALLS`] with EPERM
+///
+/// System calls of other architectures (and of the x32 ABI) kill the process, as their numbers differ.
+fn seccomp_filter(audit_arch: u32) -> Vec<libc::sock_filter> {
+    let statement = |code: u16, k: u32| libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jump = |code: u16, k: u32, jt: u8, jf: u8| libc::sock_filter { code, jt, jf, k };
+
+    let mut filter = vec![
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_ARCH),
+        jump(BPF_JMP_JEQ_K, audit_arch, 1, 0),
+        statement(BPF_RET_K, SECCOMP_RET_KILL_PROCESS),
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_NR),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    filter.extend([
+        jump(BPF_JMP_JGE_K, X32_SYSCALL_BIT, 0, 1),
+        statement(BPF_RET_K, SECCOMP_RET_KILL_PROCESS),
+    ]);
+    for syscall in DENIED_SYSCALLS {
+        filter.extend([
+            jump(BPF_JMP_JEQ_K, *syscall as u32, 0, 1),
+            statement(BPF_RET_K, SECCOMP_RET_ERRNO | libc::EPERM as u32),
+        ]);
+    }
+    filter.push(statement(BPF_RET_K, SECCOMP_RET_ALLOW));
+    filter
+}
+
+/// Restrict filesystem writes of the calling thread to a list of directories using Landlock
+///
+/// Returns whether the ruleset has been applied, which is not the case, if the kernel does not support Landlock.
+fn apply_landlock(dirs: &[PathBuf]) -> Result<bool, Error> {
+    // SAFETY: querying the ABI version does not access any memory
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 1 {
+        eprintln!("Unable to use Landlock: {}", io::Error::last_os_error());
+        return Ok(false);
+    }
+    let mut handled_access_fs = LANDLOCK_ACCESS_FS_WRITE_V1;
+    if abi >= 2 {
+        handled_access_fs |= LANDLOCK_ACCESS_FS_REFER;
+    }
+    if abi >= 3 {
+        handled_access_fs |= LANDLOCK_ACCESS_FS_TRUNCATE;
+    }
+
+    let attr = RulesetAttr { handled_access_fs };
+    // SAFETY: the attributes are valid for the duration of the call
+    let ruleset = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            size_of::<RulesetAttr>(),
+            0,
+        )
+    };
+    if ruleset < 0 {
+        return Err(Error::Sandbox(format!(
+            "creating the Landlock ruleset failed: {}",
+            io::Error::last_os_error()
+        )));
+    }
+    // SAFETY: the file descriptor has just been returned by the kernel and is owned by nobody else
+    let ruleset = unsafe { OwnedFd::from_raw_fd(ruleset as i32) };
+
+    for dir in dirs {
+        let parent = File::options()
+            .read(true)
+            .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+            .open(dir)?;
+        let rule = PathBeneathAttr {
+            allowed_access: handled_access_fs,
+            parent_fd: parent.as_raw_fd(),
+        };
+        // SAFETY: the rule is valid for the duration of the call
+        if unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                LANDLOCK_RULE_PATH_BENEATH,
+                &rule as *const PathBeneathAttr,
+                0,
+            )
+        } < 0
+        {
+            return Err(Error::Sandbox(format!(
+                "adding a Landlock rule for {} failed: {}",
+                dir.display(),
+                io::Error::last_os_error()
+            )));
+        }
+    }
+
+    // SAFETY: restricting the calling thread does not access any memory
+    if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) } < 0 {
+        return Err(Error::Sandbox(format!(
+            "applying the Landlock ruleset failed: {}",
+            io::Error::last_os_error()
+        )));
+    }
+    Ok(true)
+}
+
+/// Deny unused system calls of the calling thread using a seccomp filter
+///
+/// Returns whether the filter has been applied, which is not the case on architectures it is not built for.
+fn apply_seccomp() -> Result<bool, Error> {
+    let audit_arch = match AUDIT_ARCH {
+        Some(audit_arch) => audit_arch,
+        None => {
+            eprintln!("Unable to use seccomp: the architecture is not supported");
+            return Ok(false);
+        }
+    };
+    let mut filter = seccomp_filter(audit_arch);
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+    // SAFETY: the program is valid for the duration of the call and copied by the kernel
+    if unsafe {
+        libc::prctl(
+            libc::PR_SET_SECCOMP,
+            libc::SECCOMP_MODE_FILTER,
+            &program as *const libc::sock_fprog,
+        )
+    } < 0
+    {
+        return Err(Error::Sandbox(format!(
+            "applying the seccomp filter failed: {}",
+            io::Error::last_os_error()
+        )));
+    }
+    Ok(true)
+}
+
+/// Apply the sandbox to the calling thread
+///
+/// The state directory is created beforehand, as directories created later on would not be writable.
+/// The calling thread (and all processes it starts) can no longer gain privileges (e.g. using setuid binaries).
+pub fn apply(config: &CaterpillarConfig) -> Result<(), Error> {
+    create_dir_all(config.state_dir())?;
+    let dirs = writable_dirs(config);
+
+    // SAFETY: setting no_new_privs only affects the calling thread and its children
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } < 0 {
+        return Err(Error::Sandbox(format!(
+            "setting no_new_privs failed: {}",
+            io::Error::last_os_error()
+        )));
+    }
+    if apply_landlock(&dirs)? {
+        println!(
+            "Restricted filesystem writes to {} using Landlock",
+            dirs.iter()
+                .map(|dir| dir.display().to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+    if apply_seccomp()? {
+        println!(
+            "Denied {} unused system calls using seccomp",
+            DENIED_SYSCALLS.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_config;
+    use rstest::rstest;
+    use testresult::TestResult;
+
+    #[tokio::test]
+    #[rstest]
+    async fn test_writable_dirs() -> TestResult {
+        let config = CaterpillarConfig::from_config(read_config(None).await?)?;
+        let dirs = writable_dirs(&config);
+        assert!(dirs.contains(&PathBuf::from("/dev")));
+        assert!(dirs.iter().all(|dir| dir.is_dir()));
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_seccomp_filter() {
+        let filter = seccomp_filter(0xC000_003E);
+        assert_eq!(filter[1].k, 0xC000_003E);
+        assert_eq!(filter.last().unwrap().code, BPF_RET_K);
+        assert_eq!(filter.last().unwrap().k, SECCOMP_RET_ALLOW);
+        // every denied system call is matched and answered with EPERM
+        assert_eq!(
+            filter
+                .iter()
+                .filter(|x| x.k == SECCOMP_RET_ERRNO | libc::EPERM as u32)
+                .count(),
+            DENIED_SYSCALLS.len()
+        );
+    }
+}
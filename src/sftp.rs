@@ -0,0 +1,360 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::fs::create_dir_all;
+use std::fs::read_dir;
+use std::fs::remove_file;
+use std::fs::rename;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::copy;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::net::TcpStream;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use semver::Version;
+use sha2::Digest;
+use sha2::Sha256;
+use ssh2::CheckResult;
+use ssh2::KnownHostFileKind;
+use ssh2::Session;
+
+use crate::blocklist::Blocklist;
+use crate::config::CaterpillarConfig;
+use crate::error::Error;
+use crate::macros::regex_once;
+
+/// A remote directory on an SFTP server, that is searched for update bundles
+///
+/// Update bundles are downloaded to a local cache directory, which is then searched like any other local directory.
+#[derive(Clone, Debug)]
+pub struct SftpSource {
+    host: String,
+    port: u16,
+    user: String,
+    identity: PathBuf,
+    known_hosts: PathBuf,
+    dir: PathBuf,
+    cache_dir: PathBuf,
+    cache_max_size: u64,
+}
+
+impl SftpSource {
+    /// Create an SftpSource from the options of a CaterpillarConfig
+    pub fn from_config(config: &CaterpillarConfig) -> Self {
+        SftpSource {
+            host: config.sftp_host().to_string(),
+            port: config.sftp_port(),
+            user: config.sftp_user().to_string(),
+            identity: config.sftp_identity().into(),
+            known_hosts: config.sftp_known_hosts().into(),
+            dir: config.sftp_dir().into(),
+            cache_dir: config.sftp_cache_dir().into(),
+            cache_max_size: config.sftp_cache_max_size(),
+        }
+    }
+
+    /// Return the local cache directory, to which update bundles are downloaded
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// Connect to the SFTP server and authenticate using a private key
+    ///
+    /// The host key of the server is verified against the configured known hosts file.
+    fn connect(&self) -> Result<Session, Error> {
+        println!(
+            "Connecting to SFTP server {}@{}:{}...",
+            self.user, self.host, self.port
+        );
+        let mut session = Session::new()?;
+        session.set_tcp_stream(TcpStream::connect((self.host.as_str(), self.port))?);
+        session.handshake()?;
+
+        let mut known_hosts = session.known_hosts()?;
+        known_hosts.read_file(&self.known_hosts, KnownHostFileKind::OpenSSH)?;
+        let (key, _) = session
+            .host_key()
+            .ok_or(Error::HostKey(self.host.clone()))?;
+        match known_hosts.check_port(&self.host, self.port, key) {
+            CheckResult::Match => {}
+            _ => return Err(Error::HostKey(self.host.clone())),
+        }
+
+        session.userauth_pubkey_file(&self.user, None, &self.identity, None)?;
+        Ok(session)
+    }
+
+    /// Fetch the best update bundle candidate from the remote directory
+    ///
+    /// The versions of the remote update bundles are derived from their file names.
+    /// Only versions higher than `current` (if any) and not found in the `blocklist` are considered.
+    /// The candidate with the highest version is downloaded to the cache directory (if it is not there yet).
+    /// Interrupted downloads are resumed and the download is verified using a SHA-256 digest file next to the candidate
+    /// (e.g. "update-1.2.3.raucb.sha256"), if the server provides one.
+    /// All other files in the cache directory are evicted (oldest first), once they exceed the maximum cache size.
+    /// The `progress` of the download is reported as bytes downloaded and total bytes.
+    /// The path to the downloaded candidate is returned in an Option.
+    pub fn fetch(
+        &self,
+        extension: &str,
+        current: Option<&Version>,
+        blocklist: &Blocklist,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Result<Option<PathBuf>, Error> {
+        let session = self.connect()?;
+        let sftp = session.sftp()?;
+
+        let names: Vec<String> = sftp
+            .readdir(&self.dir)?
+            .iter()
+            .filter(|(_, stat)| stat.is_file())
+            .filter_map(|(path, _)| path.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .collect();
+
+        let candidate = match select_candidate(&names, extension, current, blocklist) {
+            Some((name, version)) => {
+                println!(
+                    "Found update bundle candidate {} (version {}) on SFTP server {}",
+                    name, version, self.host
+                );
+                name
+            }
+            None => {
+                println!(
+                    "No update bundle candidate found on SFTP server {}",
+                    self.host
+                );
+                return Ok(None);
+            }
+        };
+
+        create_dir_all(&self.cache_dir)?;
+        let path = self.cache_dir.join(&candidate);
+        // download to a temporary file first, so that no partial update bundles are considered
+        let partial_path = self.cache_dir.join(format!("{}.part", candidate));
+        self.evict(&[&path, &partial_path])?;
+
+        if path.exists() {
+            println!("Update bundle {:?} is already cached", path);
+        } else {
+            let remote_path = self.dir.join(&candidate);
+            let remote_size = sftp.stat(&remote_path)?.size.unwrap_or_default();
+            let offset = match partial_path.metadata() {
+                Ok(metadata) if metadata.len() <= remote_size => metadata.len(),
+                _ => 0,
+            };
+            let mut remote_file = sftp.open(&remote_path)?;
+            let mut partial_file = if offset > 0 {
+                println!(
+                    "Resuming download of {} to {:?} at {} of {} bytes...",
+                    candidate, path, offset, remote_size
+                );
+                remote_file.seek(SeekFrom::Start(offset))?;
+                OpenOptions::new().append(true).open(&partial_path)?
+            } else {
+                println!("Downloading {} to {:?}...", candidate, path);
+                File::create(&partial_path)?
+            };
+            let mut downloaded = offset;
+            let mut buffer = [0u8; 65536];
+            loop {
+                let length = remote_file.read(&mut buffer)?;
+                if length == 0 {
+                    break;
+                }
+                partial_file.write_all(&buffer[..length])?;
+                downloaded += length as u64;
+                progress(downloaded, remote_size);
+            }
+
+            // verify the download, if the server provides a digest
+            if let Ok(mut digest_file) = sftp.open(&self.dir.join(format!("{}.sha256", candidate)))
+            {
+                let mut digest = String::new();
+                digest_file.read_to_string(&mut digest)?;
+                let expected = digest.split_whitespace().next().unwrap_or_default();
+                let actual = sha256_digest(&partial_path)?;
+                if !expected.eq_ignore_ascii_case(&actual) {
+                    remove_file(&partial_path)?;
+                    return Err(Error::DigestMismatch(
+                        candidate,
+                        expected.to_string(),
+                        actual,
+                    ));
+                }
+                println!("Verified SHA-256 digest of {}", candidate);
+            }
+            rename(&partial_path, &path)?;
+        }
+
+        Ok(Some(path))
+    }
+
+    /// Evict files from the cache directory (oldest first), until they no longer exceed the maximum cache size
+    ///
+    /// The files in `keep` are never evicted and do not count towards the maximum cache size.
+    fn evict(&self, keep: &[&Path]) -> Result<(), Error> {
+        let mut files = vec![];
+        for entry in read_dir(&self.cache_dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() && !keep.contains(&entry.path().as_path()) {
+                files.push((
+                    entry.path(),
+                    metadata.len(),
+                    metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                ));
+            }
+        }
+
+        for path in select_evictions(files, self.cache_max_size) {
+            println!("Evicting cached file {:?}", path);
+            remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Return the hex encoded SHA-256 digest of a file
+pub fn sha256_digest(path: &Path) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Select the files to evict from a list of files with their size and modification time
+///
+/// The newest files are kept, as long as their accumulated size does not exceed `max_size`.
+fn select_evictions(mut files: Vec<(PathBuf, u64, SystemTime)>, max_size: u64) -> Vec<PathBuf> {
+    files.sort_by(|a, b| b.2.cmp(&a.2));
+    let mut size = 0;
+    files
+        .into_iter()
+        .filter_map(|(path, file_size, _)| {
+            size += file_size;
+            (size > max_size).then_some(path)
+        })
+        .collect()
+}
+
+/// Derive a Version from the file name of an update bundle
+///
+/// The first semver compatible version string in the file name (excluding the file extension) is used.
+fn version_from_name(name: &str, extension: &str) -> Option<Version> {
+    let stem = name.strip_suffix(&format!(".{}", extension))?;
+    regex_once!(r"\d+\.\d+\.\d+(?:-[0-9A-Za-z.-]+)?(?:\+[0-9A-Za-z.-]+)?")
+        .find_iter(stem)
+        .find_map(|x| Version::parse(x.as_str()).ok())
+}
+
+/// Select the file name and Version of the best update bundle candidate from a list of file names
+///
+/// Only file names with `extension`, a version higher than `current` (if any) and not found in the `blocklist` are
+/// considered. Of those, the one with the highest version is returned in an Option.
+fn select_candidate(
+    names: &[String],
+    extension: &str,
+    current: Option<&Version>,
+    blocklist: &Blocklist,
+) -> Option<(String, Version)> {
+    names
+        .iter()
+        .filter_map(|name| version_from_name(name, extension).map(|version| (name, version)))
+        .filter(|(_, version)| !blocklist.contains(version))
+        .filter(|(_, version)| current.is_none() || current.is_some_and(|x| version.gt(x)))
+        .max_by(|a, b| a.1.cmp(&b.1))
+        .map(|(name, version)| (name.to_string(), version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::time::Duration;
+    use testdir::testdir;
+    use testresult::TestResult;
+
+    #[rstest]
+    #[case("update-1.2.3.raucb", Some(Version::new(1, 2, 3)))]
+    #[case("update_1.2.3-rc1+build.2.raucb", Some(Version::parse("1.2.3-rc1+build.2").unwrap()))]
+    #[case("update.raucb", None)]
+    #[case("update-1.2.3.img", None)]
+    fn test_version_from_name(#[case] name: &str, #[case] version: Option<Version>) {
+        assert_eq!(version_from_name(name, "raucb"), version);
+    }
+
+    #[rstest]
+    #[case(0, vec!["new", "mid", "old"])]
+    #[case(3, vec!["mid", "old"])]
+    #[case(5, vec!["old"])]
+    #[case(6, vec!["old"])]
+    #[case(10, vec![])]
+    fn test_select_evictions(#[case] max_size: u64, #[case] evicted: Vec<&str>) {
+        let files = vec![
+            (
+                PathBuf::from("mid"),
+                2,
+                SystemTime::UNIX_EPOCH + Duration::from_secs(2),
+            ),
+            (
+                PathBuf::from("old"),
+                4,
+                SystemTime::UNIX_EPOCH + Duration::from_secs(1),
+            ),
+            (
+                PathBuf::from("new"),
+                3,
+                SystemTime::UNIX_EPOCH + Duration::from_secs(3),
+            ),
+        ];
+        assert_eq!(
+            select_evictions(files, max_size),
+            evicted.iter().map(PathBuf::from).collect::<Vec<PathBuf>>()
+        );
+    }
+
+    #[rstest]
+    fn test_sha256_digest() -> TestResult {
+        let path = testdir!().join("update.raucb");
+        std::fs::write(&path, "caterpillar")?;
+        assert_eq!(
+            sha256_digest(&path)?,
+            "baffdf37aa5d3961c77ba6315dcd836823a20ead97869d4232780df8e4369f13"
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(None, Some("update-2.1.0.raucb"))]
+    #[case(Some(Version::new(2, 0, 0)), Some("update-2.1.0.raucb"))]
+    #[case(Some(Version::new(2, 1, 0)), None)]
+    fn test_select_candidate(
+        #[case] current: Option<Version>,
+        #[case] candidate: Option<&str>,
+    ) -> TestResult {
+        let mut blocklist = Blocklist::read(&testdir!().join("blocklist"))?;
+        blocklist.add(&Version::new(3, 0, 0))?;
+        let names: Vec<String> = [
+            "update-1.0.0.raucb",
+            "update-2.1.0.raucb",
+            "update-3.0.0.raucb",
+            "update-4.0.0.img",
+            "update.raucb",
+        ]
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+
+        assert_eq!(
+            select_candidate(&names, "raucb", current.as_ref(), &blocklist).map(|x| x.0),
+            candidate.map(|x| x.to_string())
+        );
+        Ok(())
+    }
+}
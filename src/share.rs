@@ -0,0 +1,160 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::fs::create_dir_all;
+use std::fs::remove_dir;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use serde::Deserialize;
+use strum::Display;
+
+use crate::error::Error;
+
+/// The type of a network share
+#[derive(Clone, Copy, Debug, Deserialize, Display, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ShareType {
+    #[strum(to_string = "nfs")]
+    Nfs,
+    #[strum(to_string = "cifs")]
+    Cifs,
+}
+
+/// A network share (NFS or CIFS), that is searched for update bundles
+///
+/// Network shares are mounted and unmounted using mount(8) and umount(8).
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct NetworkShare {
+    url: String,
+    #[serde(rename = "type")]
+    share_type: ShareType,
+    #[serde(default)]
+    credentials: Option<PathBuf>,
+    #[serde(default)]
+    options: Option<String>,
+}
+
+impl NetworkShare {
+    /// Create a new NetworkShare
+    pub fn new(
+        url: &str,
+        share_type: ShareType,
+        credentials: Option<&Path>,
+        options: Option<&str>,
+    ) -> Self {
+        NetworkShare {
+            url: url.to_string(),
+            share_type,
+            credentials: credentials.map(|x| x.to_path_buf()),
+            options: options.map(|x| x.to_string()),
+        }
+    }
+
+    /// Return the URL of the NetworkShare (e.g. "server:/export" or "//server/share")
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Return the type of the NetworkShare
+    pub fn share_type(&self) -> ShareType {
+        self.share_type
+    }
+
+    /// Return the mount options of the NetworkShare
+    ///
+    /// The optional credentials file is added as `credentials` option for CIFS shares.
+    pub fn mount_options(&self) -> Option<String> {
+        let credentials = match (self.share_type, &self.credentials) {
+            (ShareType::Cifs, Some(credentials)) => {
+                Some(format!("credentials={}", credentials.display()))
+            }
+            _ => None,
+        };
+
+        let options: Vec<String> = [self.options.clone(), credentials]
+            .into_iter()
+            .flatten()
+            .filter(|x| !x.is_empty())
+            .collect();
+        if options.is_empty() {
+            None
+        } else {
+            Some(options.join(","))
+        }
+    }
+
+    /// Mount the NetworkShare to a mountpoint
+    ///
+    /// The mountpoint is created if it does not exist.
+    pub fn mount(&self, mountpoint: &Path) -> Result<(), Error> {
+        create_dir_all(mountpoint)?;
+
+        let mut command = Command::new("mount");
+        command.args(["-t", &self.share_type.to_string()]);
+        if let Some(options) = self.mount_options() {
+            command.args(["-o", &options]);
+        }
+        let output = command.arg(&self.url).arg(mountpoint).output()?;
+
+        if output.status.success() {
+            println!("Mounted {} to {}.", self.url, mountpoint.display());
+            Ok(())
+        } else {
+            // do not leave behind an empty mountpoint
+            _ = remove_dir(mountpoint);
+            Err(Error::MountFailed(
+                self.url.clone(),
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ))
+        }
+    }
+
+    /// Unmount the NetworkShare from a mountpoint and remove the mountpoint
+    ///
+    /// If `lazy` is true, the mountpoint is detached right away and cleaned up once it is no longer busy.
+    pub fn unmount(&self, mountpoint: &Path, lazy: bool) -> Result<(), Error> {
+        let mut command = Command::new("umount");
+        if lazy {
+            command.arg("--lazy");
+        }
+        let output = command.arg(mountpoint).output()?;
+
+        if output.status.success() {
+            remove_dir(mountpoint)?;
+            Ok(())
+        } else {
+            eprintln!(
+                "Failed unmounting {}: {}",
+                self.url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            Err(Error::UnmountFailed(mountpoint.display().to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(NetworkShare::new("server:/export", ShareType::Nfs, None, None), None)]
+    #[case(
+        NetworkShare::new("server:/export", ShareType::Nfs, Some(Path::new("/etc/creds")), Some("ro")),
+        Some("ro".to_string()),
+    )]
+    #[case(
+        NetworkShare::new("//server/share", ShareType::Cifs, Some(Path::new("/etc/creds")), None),
+        Some("credentials=/etc/creds".to_string()),
+    )]
+    #[case(
+        NetworkShare::new("//server/share", ShareType::Cifs, Some(Path::new("/etc/creds")), Some("ro,vers=3.0")),
+        Some("ro,vers=3.0,credentials=/etc/creds".to_string()),
+    )]
+    fn test_mount_options(#[case] share: NetworkShare, #[case] options: Option<String>) {
+        assert_eq!(share.mount_options(), options);
+    }
+}
@@ -0,0 +1,208 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::fs::create_dir_all;
+use std::fs::remove_file;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::Duration;
+use std::time::Instant;
+
+use nix::libc;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::error::Error;
+use crate::sftp::sha256_digest;
+
+/// The I/O priority class "idle" (see ioprio_set(2))
+const IOPRIO_CLASS_IDLE: i32 = 3;
+/// The shift of the I/O priority class in an I/O priority (see ioprio_set(2))
+const IOPRIO_CLASS_SHIFT: i32 = 13;
+/// The "which" argument of ioprio_get(2) and ioprio_set(2) for targeting a thread
+const IOPRIO_WHO_PROCESS: i32 = 1;
+
+/// Copy an update bundle to a staging directory and return the location of the copy
+///
+/// The SHA-256 digest of the data read from the update bundle is compared to the digest of the copy, so that an
+/// incomplete or corrupted copy (e.g. because the source medium has been removed) is never installed.
+/// The copy is removed again, if staging fails.
+/// The copy is limited to `bandwidth` bytes per second (unlimited, if 0) and uses the idle I/O priority class, if
+/// `idle_io` is true, so that it does not starve other applications on the system.
+/// The `progress` of the copy is reported as bytes copied and total bytes.
+pub fn stage_bundle(
+    bundle: &Path,
+    stage_dir: &Path,
+    bandwidth: u64,
+    idle_io: bool,
+    progress: impl FnMut(u64, u64),
+) -> Result<PathBuf, Error> {
+    let file_name = bundle
+        .file_name()
+        .ok_or_else(|| Error::BundlePath(bundle.into()))?;
+    create_dir_all(stage_dir)?;
+    let staged = stage_dir.join(file_name);
+    println!(
+        "Staging update bundle {} in {}...",
+        bundle.display(),
+        stage_dir.display()
+    );
+
+    // the I/O priority is set for the current thread, so it is restored after copying
+    let ioprio = if idle_io { set_idle_ioprio() } else { None };
+    let result = copy_with_digest(bundle, &staged, bandwidth, progress).and_then(|digest| {
+        let staged_digest = sha256_digest(&staged)?;
+        if staged_digest == digest {
+            Ok(())
+        } else {
+            Err(Error::StagingFailed(
+                bundle.display().to_string(),
+                format!(
+                    "the digest of the copy ({}) does not match the digest of the update bundle ({})",
+                    staged_digest, digest
+                ),
+            ))
+        }
+    });
+    if let Some(ioprio) = ioprio {
+        restore_ioprio(ioprio);
+    }
+    if let Err(error) = result {
+        _ = remove_file(&staged);
+        return Err(error);
+    }
+
+    println!("Staged update bundle {}", staged.display());
+    Ok(staged)
+}
+
+/// Set the I/O priority class of the current thread to idle and return the previous I/O priority
+///
+/// Errors are only printed, as the copy can be done with the previous I/O priority as well.
+fn set_idle_ioprio() -> Option<i32> {
+    // SAFETY: ioprio_get(2) and ioprio_set(2) only read and change the I/O priority of the current thread
+    let previous = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0) };
+    if previous < 0 {
+        eprintln!(
+            "Unable to get the I/O priority: {}",
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+    let idle = IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT;
+    // SAFETY: see above
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, idle) } < 0 {
+        eprintln!(
+            "Unable to set the idle I/O priority: {}",
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+    Some(previous as i32)
+}
+
+/// Restore the I/O priority of the current thread
+fn restore_ioprio(ioprio: i32) {
+    // SAFETY: ioprio_set(2) only changes the I/O priority of the current thread
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) } < 0 {
+        eprintln!(
+            "Unable to restore the I/O priority: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// Return the time to wait, so that the transfer of `bytes` within `elapsed` does not exceed `bandwidth` bytes per second
+///
+/// If `bandwidth` is 0, the transfer is not limited.
+fn throttle_delay(bytes: u64, bandwidth: u64, elapsed: Duration) -> Duration {
+    if bandwidth == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(bytes as f64 / bandwidth as f64).saturating_sub(elapsed)
+}
+
+/// Copy a file and return the hex encoded SHA-256 digest of the data read from it
+///
+/// The copy is limited to `bandwidth` bytes per second (unlimited, if 0) and synced to disk before returning.
+/// The `progress` is reported after each chunk as bytes copied and total bytes.
+fn copy_with_digest(
+    source: &Path,
+    target: &Path,
+    bandwidth: u64,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<String, Error> {
+    let mut reader = File::open(source)?;
+    let total = reader.metadata()?.len();
+    let mut writer = File::create(target)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 65536];
+    let started = Instant::now();
+    let mut copied = 0;
+    loop {
+        let length = reader.read(&mut buffer)?;
+        if length == 0 {
+            break;
+        }
+        hasher.update(&buffer[..length]);
+        writer.write_all(&buffer[..length])?;
+        copied += length as u64;
+        progress(copied, total);
+        sleep(throttle_delay(copied, bandwidth, started.elapsed()));
+    }
+    writer.sync_all()?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::fs::read;
+    use std::fs::write;
+    use testdir::testdir;
+    use testresult::TestResult;
+
+    #[rstest]
+    fn test_stage_bundle() -> TestResult {
+        let dir = testdir!();
+        let bundle = dir.join("update.raucb");
+        write(&bundle, vec![1u8; 100000])?;
+
+        let mut reported = (0, 0);
+        let staged = stage_bundle(&bundle, &dir.join("staging"), 0, true, |copied, total| {
+            reported = (copied, total)
+        })?;
+        assert_eq!(staged, dir.join("staging").join("update.raucb"));
+        assert_eq!(read(&staged)?, read(&bundle)?);
+        assert_eq!(reported, (100000, 100000));
+
+        assert!(stage_bundle(
+            &dir.join("missing.raucb"),
+            &dir.join("staging"),
+            0,
+            false,
+            |_, _| {}
+        )
+        .is_err());
+        assert!(!dir.join("staging").join("missing.raucb").exists());
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(1000, 0, Duration::ZERO, Duration::ZERO)]
+    #[case(1000, 1000, Duration::ZERO, Duration::from_secs(1))]
+    #[case(1000, 1000, Duration::from_millis(400), Duration::from_millis(600))]
+    #[case(1000, 1000, Duration::from_secs(2), Duration::ZERO)]
+    fn test_throttle_delay(
+        #[case] bytes: u64,
+        #[case] bandwidth: u64,
+        #[case] elapsed: Duration,
+        #[case] delay: Duration,
+    ) {
+        assert_eq!(throttle_delay(bytes, bandwidth, elapsed), delay);
+    }
+}
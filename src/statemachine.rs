@@ -0,0 +1,485 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! The state machine of the application
+//!
+//! Transitions are computed by the pure [`next`] function, which returns the new [`State`] and a list of [`Action`]s.
+//! The actions are carried out by the executor of the application (see [`crate::dbus::StateExecutor`]).
+/// State of the application
+#[derive(Clone, Debug, strum::Display, strum::EnumString, PartialEq)]
+#[non_exhaustive]
+pub enum State {
+    #[strum(to_string = "done")]
+    Done(bool, usize),
+    #[strum(to_string = "idle")]
+    Idle(bool, usize),
+    #[strum(to_string = "init")]
+    Init,
+    #[strum(to_string = "mounted")]
+    Mounted(bool, usize),
+    #[strum(to_string = "mounting")]
+    Mounting(bool, usize),
+    #[strum(to_string = "noupdatefound")]
+    NoUpdateFound(bool, usize),
+    #[strum(to_string = "searching")]
+    Searching(bool, usize),
+    #[strum(to_string = "skip")]
+    Skip(bool, usize),
+    #[strum(to_string = "unmounted")]
+    Unmounted(bool, usize, bool),
+    #[strum(to_string = "unmounting")]
+    Unmounting(bool, usize, bool),
+    #[strum(to_string = "updated")]
+    Updated(bool, usize, bool),
+    #[strum(to_string = "updatefound")]
+    UpdateFound(bool, usize),
+    #[strum(to_string = "updating")]
+    Updating(bool, usize),
+}
+
+impl State {
+    /// Return whether the system has been updated successfully
+    ///
+    /// Since users may choose not to reboot right after update, this indicator helps in distinguishing whether to allow another update attempt.
+    pub fn get_updated(&self) -> bool {
+        match self {
+            State::Init => false,
+            State::Done(updated, _)
+            | State::UpdateFound(updated, _)
+            | State::Idle(updated, _)
+            | State::Mounting(updated, _)
+            | State::Mounted(updated, _)
+            | State::NoUpdateFound(updated, _)
+            | State::Searching(updated, _)
+            | State::Skip(updated, _)
+            | State::Unmounting(updated, _, _)
+            | State::Unmounted(updated, _, _)
+            | State::Updating(updated, _)
+            | State::Updated(updated, _, _) => updated.to_owned(),
+        }
+    }
+
+    /// Return the iteration the program is currently in
+    ///
+    /// An iteration is defined by how often [`State::Unmounted`] has been reached
+    pub fn get_iteration(&self) -> usize {
+        match self {
+            State::Init => 0,
+            State::Done(_, iteration)
+            | State::UpdateFound(_, iteration)
+            | State::Idle(_, iteration)
+            | State::Mounting(_, iteration)
+            | State::Mounted(_, iteration)
+            | State::NoUpdateFound(_, iteration)
+            | State::Searching(_, iteration)
+            | State::Skip(_, iteration)
+            | State::Unmounting(_, iteration, _)
+            | State::Unmounted(_, iteration, _)
+            | State::Updating(_, iteration)
+            | State::Updated(_, iteration, _) => iteration.to_owned(),
+        }
+    }
+
+    /// Return whether the system is marked for reboot
+    ///
+    /// A system is marked for reboot, if reboot has been selected as action after successful update
+    pub fn get_marked_for_reboot(&self) -> bool {
+        match self {
+            State::Init
+            | State::Done(_, _)
+            | State::UpdateFound(_, _)
+            | State::Idle(_, _)
+            | State::Mounting(_, _)
+            | State::Mounted(_, _)
+            | State::NoUpdateFound(_, _)
+            | State::Searching(_, _)
+            | State::Updating(_, _)
+            | State::Skip(_, _) => false,
+            State::Unmounting(_, _, reboot)
+            | State::Unmounted(_, _, reboot)
+            | State::Updated(_, _, reboot) => reboot.to_owned(),
+        }
+    }
+
+    /// Return the progress of the whole pipeline as percentage and message
+    ///
+    /// The download of an update bundle covers the range from [`DOWNLOAD_PROGRESS_START`] to
+    /// [`DOWNLOAD_PROGRESS_END`] (see [`download_progress`]) and the staging of an update bundle the range from
+    /// [`STAGE_PROGRESS_START`] to [`INSTALL_PROGRESS_START`] (see [`stage_progress`]).
+    /// The installation of an update bundle covers the range from [`INSTALL_PROGRESS_START`] to
+    /// [`INSTALL_PROGRESS_END`], in which the progress reported by RAUC is mapped (see [`install_progress`]).
+    pub fn get_progress(&self) -> (i32, String) {
+        let (percentage, message) = match self {
+            State::Init => (0, "Initializing"),
+            State::Idle(true, _) => (100, "Updated"),
+            State::Idle(false, _) => (0, "Idle"),
+            State::Mounting(_, _) => (DOWNLOAD_PROGRESS_START, "Mounting devices"),
+            State::Mounted(_, _) => (DOWNLOAD_PROGRESS_END, "Mounted devices"),
+            State::Searching(_, _) => (30, "Searching and verifying updates"),
+            State::UpdateFound(_, _) => (40, "Found update"),
+            State::NoUpdateFound(_, _) => (90, "No update found"),
+            State::Skip(_, _) => (90, "Skipped update"),
+            State::Updating(_, _) => (STAGE_PROGRESS_START, "Installing update"),
+            State::Updated(_, _, _) => (INSTALL_PROGRESS_END, "Installed update"),
+            State::Unmounting(_, _, _) => (95, "Unmounting devices"),
+            State::Unmounted(_, _, _) => (99, "Unmounted devices"),
+            State::Done(_, _) => (100, "Done"),
+        };
+        (percentage, message.to_string())
+    }
+}
+
+/// The percentage of the pipeline's progress at which the download of an update bundle starts
+pub const DOWNLOAD_PROGRESS_START: i32 = 10;
+/// The percentage of the pipeline's progress at which the download of an update bundle ends
+pub const DOWNLOAD_PROGRESS_END: i32 = 20;
+/// The percentage of the pipeline's progress at which the staging of an update bundle starts
+pub const STAGE_PROGRESS_START: i32 = 50;
+/// The percentage of the pipeline's progress at which the installation of an update bundle starts
+pub const INSTALL_PROGRESS_START: i32 = 60;
+/// The percentage of the pipeline's progress at which the installation of an update bundle ends
+pub const INSTALL_PROGRESS_END: i32 = 90;
+
+/// Map the installation progress reported by RAUC to the progress of the whole pipeline
+pub fn install_progress(percentage: i32, message: &str) -> (i32, String) {
+    (
+        INSTALL_PROGRESS_START
+            + percentage.clamp(0, 100) * (INSTALL_PROGRESS_END - INSTALL_PROGRESS_START) / 100,
+        format!("Installing update: {}", message),
+    )
+}
+
+/// Map the bytes downloaded of an update bundle to the progress of the whole pipeline
+pub fn download_progress(bytes: u64, total: u64) -> (i32, String) {
+    transfer_progress(
+        DOWNLOAD_PROGRESS_START,
+        DOWNLOAD_PROGRESS_END,
+        "Downloading update",
+        bytes,
+        total,
+    )
+}
+
+/// Map the bytes copied of an update bundle to a staging directory to the progress of the whole pipeline
+pub fn stage_progress(bytes: u64, total: u64) -> (i32, String) {
+    transfer_progress(
+        STAGE_PROGRESS_START,
+        INSTALL_PROGRESS_START,
+        "Staging update",
+        bytes,
+        total,
+    )
+}
+
+/// Map the bytes transferred of a total to a range of the progress of the whole pipeline
+fn transfer_progress(start: i32, end: i32, message: &str, bytes: u64, total: u64) -> (i32, String) {
+    let fraction = if total == 0 {
+        1.0
+    } else {
+        (bytes as f64 / total as f64).clamp(0.0, 1.0)
+    };
+    (
+        start + (fraction * (end - start) as f64) as i32,
+        format!("{}: {} of {} bytes", message, bytes, total),
+    )
+}
+
+/// An action, that is carried out by the executor after a transition
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Action {
+    /// Broadcast changes of the properties derived from the previous and the new state
+    EmitPropertiesChanged(State),
+    /// Broadcast the found update
+    SignalUpdateFound,
+    /// Install the found update non-interactively and reboot
+    InstallUpdate,
+    /// Install the found update non-interactively and reboot, once the installation window opens
+    DeferInstall,
+    /// Unmount all devices and release the drive of the source device, if the system has been updated
+    Unmount(bool),
+    /// Reboot the system
+    Reboot,
+    /// Reset the lists of found devices and updates
+    Reset,
+    /// Request a transition to another State
+    Request(State),
+    /// Notify that the application is done
+    Done,
+}
+
+/// The context in which a transition takes place
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Context {
+    /// Whether to install updates non-interactively in the first iteration
+    pub auto_install: bool,
+    /// Whether to reboot non-interactively after an update in the first iteration
+    pub auto_reboot: bool,
+    /// Whether the system time is plausible (or its plausibility is irrelevant)
+    pub clock_plausible: bool,
+    /// Whether automatic installations are inhibited for maintenance
+    pub inhibited: bool,
+    /// Whether the current time is outside of the installation window
+    pub outside_install_window: bool,
+    /// Whether a pending reboot has been cancelled
+    pub reboot_cancelled: bool,
+}
+
+/// Compute the transition from a State when an event (the requested State) occurs
+///
+/// Returns the new State and the list of Actions, that are to be carried out in order.
+pub fn next(state: &State, event: State, context: &Context) -> (State, Vec<Action>) {
+    let mut actions = vec![];
+    let new_state = match event {
+        // increment our iteration
+        State::Idle(updated, iteration) => State::Idle(updated, iteration + 1),
+        _ => event,
+    };
+    actions.push(Action::EmitPropertiesChanged(state.clone()));
+
+    match new_state {
+        State::Init
+        | State::Idle(_, _)
+        | State::Mounting(_, _)
+        | State::Mounted(_, _)
+        | State::Searching(_, _)
+        | State::Updating(_, _) => {}
+        State::Done(_, _) => actions.push(Action::Done),
+        State::UpdateFound(_, iteration) => {
+            actions.push(Action::SignalUpdateFound);
+            // if this is the first iteration (i.e. boot) and configured to do so, install update and reboot
+            // if outside of the installation window, defer the installation until it opens
+            if iteration == 1
+                && context.auto_install
+                && context.clock_plausible
+                && !context.inhibited
+            {
+                if context.outside_install_window {
+                    actions.push(Action::DeferInstall);
+                } else {
+                    actions.push(Action::InstallUpdate);
+                }
+            }
+        }
+        State::NoUpdateFound(updated, iteration) | State::Skip(updated, iteration) => actions.push(
+            Action::Request(State::Unmounting(updated, iteration, false)),
+        ),
+        State::Unmounting(updated, iteration, reboot) => {
+            actions.push(Action::Unmount(updated));
+            actions.push(Action::Request(State::Unmounted(
+                updated, iteration, reboot,
+            )));
+        }
+        State::Unmounted(updated, iteration, reboot) => {
+            // if this is the first iteration, successfully updated and configured to do so, reboot (unless cancelled)
+            if updated
+                && !context.reboot_cancelled
+                && ((iteration == 1 && context.auto_reboot) || reboot)
+            {
+                actions.push(Action::Reboot);
+            // return to idle state if not updated or no reboot is wanted
+            } else {
+                actions.push(Action::Request(State::Idle(updated, iteration)));
+            }
+            actions.push(Action::Reset);
+        }
+        // mark ourselves as updated
+        State::Updated(_, iteration, reboot) => actions.push(Action::Request(State::Unmounting(
+            true,
+            iteration,
+            reboot && !context.reboot_cancelled,
+        ))),
+    }
+
+    (new_state, actions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(State::Init, State::Idle(false, 0), State::Idle(false, 1), vec![])]
+    #[case(State::Idle(false, 1), State::Mounting(false, 1), State::Mounting(false, 1), vec![])]
+    #[case(
+        State::Searching(false, 1),
+        State::NoUpdateFound(false, 1),
+        State::NoUpdateFound(false, 1),
+        vec![Action::Request(State::Unmounting(false, 1, false))],
+    )]
+    #[case(
+        State::UpdateFound(false, 1),
+        State::Skip(false, 1),
+        State::Skip(false, 1),
+        vec![Action::Request(State::Unmounting(false, 1, false))],
+    )]
+    #[case(
+        State::Updating(false, 1),
+        State::Updated(false, 1, true),
+        State::Updated(false, 1, true),
+        vec![Action::Request(State::Unmounting(true, 1, true))],
+    )]
+    #[case(
+        State::Updating(false, 1),
+        State::Unmounting(false, 1, false),
+        State::Unmounting(false, 1, false),
+        vec![Action::Unmount(false), Action::Request(State::Unmounted(false, 1, false))],
+    )]
+    #[case(
+        State::Updated(false, 2, false),
+        State::Unmounting(true, 2, false),
+        State::Unmounting(true, 2, false),
+        vec![Action::Unmount(true), Action::Request(State::Unmounted(true, 2, false))],
+    )]
+    #[case(
+        State::Unmounting(true, 2, false),
+        State::Unmounted(true, 2, false),
+        State::Unmounted(true, 2, false),
+        vec![Action::Request(State::Idle(true, 2)), Action::Reset],
+    )]
+    #[case(
+        State::Unmounting(true, 2, true),
+        State::Unmounted(true, 2, true),
+        State::Unmounted(true, 2, true),
+        vec![Action::Reboot, Action::Reset],
+    )]
+    #[case(
+        State::Unmounting(false, 1, false),
+        State::Unmounted(false, 1, false),
+        State::Unmounted(false, 1, false),
+        vec![Action::Request(State::Idle(false, 1)), Action::Reset],
+    )]
+    #[case(State::Idle(true, 2), State::Done(true, 2), State::Done(true, 2), vec![Action::Done])]
+    fn test_next(
+        #[case] state: State,
+        #[case] event: State,
+        #[case] new_state: State,
+        #[case] actions: Vec<Action>,
+    ) {
+        let context = Context::default();
+        let mut expected_actions = vec![Action::EmitPropertiesChanged(state.clone())];
+        expected_actions.extend(actions);
+        assert_eq!(next(&state, event, &context), (new_state, expected_actions));
+    }
+
+    #[rstest]
+    #[case(true, true, false, false, 1, true, false)]
+    #[case(true, false, false, false, 1, false, false)]
+    #[case(true, true, true, false, 1, false, false)]
+    #[case(true, true, false, true, 1, false, true)]
+    #[case(true, true, true, true, 1, false, false)]
+    #[case(false, true, false, false, 1, false, false)]
+    #[case(true, true, false, false, 2, false, false)]
+    fn test_next_update_found(
+        #[case] auto_install: bool,
+        #[case] clock_plausible: bool,
+        #[case] inhibited: bool,
+        #[case] outside_install_window: bool,
+        #[case] iteration: usize,
+        #[case] install: bool,
+        #[case] defer: bool,
+    ) {
+        let context = Context {
+            auto_install,
+            auto_reboot: false,
+            clock_plausible,
+            inhibited,
+            outside_install_window,
+            reboot_cancelled: false,
+        };
+        let (_, actions) = next(
+            &State::Searching(false, iteration),
+            State::UpdateFound(false, iteration),
+            &context,
+        );
+        assert!(actions.contains(&Action::SignalUpdateFound));
+        assert_eq!(actions.contains(&Action::InstallUpdate), install);
+        assert_eq!(actions.contains(&Action::DeferInstall), defer);
+    }
+
+    #[rstest]
+    #[case(0, 60)]
+    #[case(50, 75)]
+    #[case(100, 90)]
+    #[case(200, 90)]
+    fn test_install_progress(#[case] percentage: i32, #[case] expected: i32) {
+        assert_eq!(
+            install_progress(percentage, "Copying image"),
+            (expected, "Installing update: Copying image".to_string())
+        );
+    }
+
+    #[rstest]
+    #[case(0, 1000, 50)]
+    #[case(500, 1000, 55)]
+    #[case(1000, 1000, 60)]
+    #[case(2000, 1000, 60)]
+    #[case(0, 0, 60)]
+    fn test_stage_progress(#[case] bytes: u64, #[case] total: u64, #[case] expected: i32) {
+        assert_eq!(
+            stage_progress(bytes, total),
+            (
+                expected,
+                format!("Staging update: {} of {} bytes", bytes, total)
+            )
+        );
+    }
+
+    #[rstest]
+    fn test_download_progress() {
+        assert_eq!(
+            download_progress(250, 1000),
+            (12, "Downloading update: 250 of 1000 bytes".to_string())
+        );
+    }
+
+    #[rstest]
+    #[case(true, false, 1, false, true)]
+    #[case(false, false, 1, false, false)]
+    #[case(true, false, 2, false, false)]
+    #[case(false, true, 2, false, true)]
+    #[case(true, false, 1, true, false)]
+    #[case(false, true, 2, true, false)]
+    fn test_next_unmounted_auto_reboot(
+        #[case] auto_reboot: bool,
+        #[case] marked_for_reboot: bool,
+        #[case] iteration: usize,
+        #[case] reboot_cancelled: bool,
+        #[case] reboot: bool,
+    ) {
+        let context = Context {
+            auto_install: false,
+            auto_reboot,
+            clock_plausible: true,
+            inhibited: false,
+            outside_install_window: false,
+            reboot_cancelled,
+        };
+        let (_, actions) = next(
+            &State::Unmounting(true, iteration, marked_for_reboot),
+            State::Unmounted(true, iteration, marked_for_reboot),
+            &context,
+        );
+        assert_eq!(actions.contains(&Action::Reboot), reboot);
+        assert_eq!(
+            actions.contains(&Action::Request(State::Idle(true, iteration))),
+            !reboot
+        );
+    }
+
+    #[rstest]
+    #[case(false, true)]
+    #[case(true, false)]
+    fn test_next_updated_reboot_cancelled(#[case] reboot_cancelled: bool, #[case] reboot: bool) {
+        let context = Context {
+            reboot_cancelled,
+            ..Default::default()
+        };
+        let (_, actions) = next(
+            &State::Updating(false, 2),
+            State::Updated(false, 2, true),
+            &context,
+        );
+        assert!(actions.contains(&Action::Request(State::Unmounting(true, 2, reboot))));
+    }
+}
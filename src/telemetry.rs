@@ -0,0 +1,168 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! Export of OpenTelemetry traces of update cycles
+//!
+//! Traces are only exported, if caterpillar is built with the `otlp` feature and an `otlp_endpoint` is configured.
+//! Otherwise all [`Span`]s are no-ops.
+#[cfg(feature = "otlp")]
+use once_cell::sync::OnceCell;
+#[cfg(feature = "otlp")]
+use opentelemetry::global;
+#[cfg(feature = "otlp")]
+use opentelemetry::trace::TraceContextExt;
+#[cfg(feature = "otlp")]
+use opentelemetry::trace::Tracer;
+#[cfg(feature = "otlp")]
+use opentelemetry::Context;
+#[cfg(feature = "otlp")]
+use opentelemetry::KeyValue;
+#[cfg(feature = "otlp")]
+use opentelemetry_otlp::WithExportConfig;
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::trace::TracerProvider;
+
+use crate::error::Error;
+
+/// The provider of the installed exporter of traces
+#[cfg(feature = "otlp")]
+static PROVIDER: OnceCell<TracerProvider> = OnceCell::new();
+
+/// A span of a trace, that is ended when it is dropped
+///
+/// Spans created using [`Span::child`] are part of the same trace as their parent.
+#[derive(Debug)]
+pub struct Span {
+    #[cfg(feature = "otlp")]
+    context: Context,
+}
+
+impl Span {
+    /// Start a new Span, which is the root of a trace
+    #[cfg(feature = "otlp")]
+    pub fn root(name: &'static str) -> Self {
+        let span = global::tracer("caterpillar").start(name);
+        Span {
+            context: Context::new().with_span(span),
+        }
+    }
+
+    /// Start a new Span, which is the root of a trace
+    #[cfg(not(feature = "otlp"))]
+    pub fn root(_name: &'static str) -> Self {
+        Span {}
+    }
+
+    /// Start a new Span, which is a child of this Span
+    #[cfg(feature = "otlp")]
+    pub fn child(&self, name: &'static str) -> Self {
+        let span = global::tracer("caterpillar").start_with_context(name, &self.context);
+        Span {
+            context: self.context.with_span(span),
+        }
+    }
+
+    /// Start a new Span, which is a child of this Span
+    #[cfg(not(feature = "otlp"))]
+    pub fn child(&self, _name: &'static str) -> Self {
+        Span {}
+    }
+
+    /// Set an attribute of the Span
+    #[cfg(feature = "otlp")]
+    pub fn set_attribute(&self, key: &'static str, value: impl ToString) {
+        self.context
+            .span()
+            .set_attribute(KeyValue::new(key, value.to_string()));
+    }
+
+    /// Set an attribute of the Span
+    #[cfg(not(feature = "otlp"))]
+    pub fn set_attribute(&self, _key: &'static str, _value: impl ToString) {}
+
+    /// Record an error in the Span
+    #[cfg(feature = "otlp")]
+    pub fn set_error(&self, error: impl ToString) {
+        self.context
+            .span()
+            .set_status(opentelemetry::trace::Status::error(error.to_string()));
+    }
+
+    /// Record an error in the Span
+    #[cfg(not(feature = "otlp"))]
+    pub fn set_error(&self, _error: impl ToString) {}
+}
+
+#[cfg(feature = "otlp")]
+impl Drop for Span {
+    fn drop(&mut self) {
+        self.context.span().end();
+    }
+}
+
+/// Install the exporter of traces to an OTLP collector (e.g. "http://localhost:4317")
+///
+/// If the `endpoint` is empty, no traces are exported.
+#[cfg(feature = "otlp")]
+pub fn init(endpoint: &str) -> Result<(), Error> {
+    if endpoint.is_empty() {
+        return Ok(());
+    }
+
+    println!("Exporting traces to {}", endpoint);
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(opentelemetry_sdk::trace::config().with_resource(
+            opentelemetry_sdk::Resource::new(vec![KeyValue::new("service.name", "caterpillar")]),
+        ))
+        .install_batch(opentelemetry_sdk::runtime::Tokio)
+        .map_err(|error| Error::Telemetry(error.to_string()))?;
+    if let Some(provider) = tracer.provider() {
+        _ = PROVIDER.set(provider);
+    }
+    Ok(())
+}
+
+/// Install the exporter of traces to an OTLP collector (e.g. "http://localhost:4317")
+///
+/// Without the `otlp` feature, no traces are exported.
+#[cfg(not(feature = "otlp"))]
+pub fn init(endpoint: &str) -> Result<(), Error> {
+    if !endpoint.is_empty() {
+        eprintln!(
+            "Not exporting traces to {}, as caterpillar is built without the otlp feature",
+            endpoint
+        );
+    }
+    Ok(())
+}
+
+/// Export all pending spans, without shutting down the exporter
+#[cfg(feature = "otlp")]
+pub fn flush() {
+    if let Some(provider) = PROVIDER.get() {
+        for result in provider.force_flush() {
+            if let Err(error) = result {
+                eprintln!("Unable to export traces: {}", error);
+            }
+        }
+    }
+}
+
+/// Export all pending spans, without shutting down the exporter
+#[cfg(not(feature = "otlp"))]
+pub fn flush() {}
+
+/// Export all pending traces
+#[cfg(feature = "otlp")]
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Export all pending traces
+#[cfg(not(feature = "otlp"))]
+pub fn shutdown() {}
@@ -0,0 +1,92 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//! Support for unit tests
+//!
+//! Fakes of the D-Bus services used by the application are served on a private bus, so that code talking to them (e.g.
+//! the reboot after [`State::Unmounted`](crate::statemachine::State::Unmounted)) is covered by `cargo test` without a
+//! VM.
+use std::fs::File;
+use std::os::fd::FromRawFd;
+use std::os::fd::IntoRawFd;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use dbus_launch::BusType;
+use dbus_launch::Daemon;
+use dbus_launch::Launcher;
+use zbus::dbus_interface;
+use zbus::Connection;
+use zbus::ConnectionBuilder;
+use zvariant::OwnedFd;
+
+/// A call of a method of [`FakeLogin`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoginCall {
+    /// Reboot (with the `interactive` argument)
+    Reboot(bool),
+    /// Inhibit (with the `what`, `who`, `why` and `mode` arguments)
+    Inhibit(String, String, String, String),
+}
+
+/// A fake `org.freedesktop.login1.Manager`, that records the calls of its Reboot and Inhibit methods
+///
+/// Clones of a FakeLogin share the recorded calls, so that they can be inspected while a clone is served.
+#[derive(Clone, Debug, Default)]
+pub struct FakeLogin {
+    calls: Arc<Mutex<Vec<LoginCall>>>,
+}
+
+impl FakeLogin {
+    /// Return the calls recorded so far
+    pub fn calls(&self) -> Vec<LoginCall> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.login1.Manager")]
+impl FakeLogin {
+    #[dbus_interface(name = "Reboot")]
+    fn reboot(&self, interactive: bool) {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(LoginCall::Reboot(interactive));
+    }
+
+    /// Record the inhibitor and return a file descriptor, that represents it
+    #[dbus_interface(name = "Inhibit")]
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::fdo::Result<OwnedFd> {
+        self.calls.lock().unwrap().push(LoginCall::Inhibit(
+            what.to_string(),
+            who.to_string(),
+            why.to_string(),
+            mode.to_string(),
+        ));
+        let file = File::open("/dev/null").map_err(|x| zbus::fdo::Error::Failed(x.to_string()))?;
+        // the file descriptor is owned by the returned OwnedFd
+        Ok(unsafe { OwnedFd::from_raw_fd(file.into_raw_fd()) })
+    }
+}
+
+/// Launch a private system bus
+pub fn private_bus() -> Daemon {
+    Launcher::daemon()
+        .bus_type(BusType::System)
+        .launch()
+        .unwrap()
+}
+
+/// Serve a FakeLogin on a private bus and return the connection serving it
+///
+/// The connection owns `org.freedesktop.login1` and can be used for calling the fake as well.
+pub async fn serve_fake_login(daemon: &Daemon, login: FakeLogin) -> Connection {
+    ConnectionBuilder::address(daemon.address())
+        .unwrap()
+        .name("org.freedesktop.login1")
+        .unwrap()
+        .serve_at("/org/freedesktop/login1", login)
+        .unwrap()
+        .build()
+        .await
+        .unwrap()
+}
@@ -0,0 +1,118 @@
+// SPDX-FileCopyrightText: 2023 David Runge <dave@sleepmap.de>
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::Local;
+use chrono::NaiveTime;
+use chrono::Timelike;
+
+use crate::error::Error;
+
+/// A daily window of local time (e.g. "02:00-05:00"), in which updates are installed automatically
+///
+/// A window, that ends before it starts, spans midnight (e.g. "22:00-04:00").
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InstallWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl InstallWindow {
+    /// Create a new InstallWindow
+    pub fn new(start: NaiveTime, end: NaiveTime) -> Self {
+        InstallWindow { start, end }
+    }
+
+    /// Return whether a time is inside the InstallWindow
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// Return the Duration until the InstallWindow opens (zero if it is open) at a time
+    pub fn until_open(&self, time: NaiveTime) -> Duration {
+        if self.contains(time) {
+            return Duration::ZERO;
+        }
+        let seconds = |time: NaiveTime| i64::from(time.num_seconds_from_midnight());
+        let until = (seconds(self.start) - seconds(time)).rem_euclid(24 * 60 * 60);
+        Duration::from_secs(until as u64)
+    }
+
+    /// Return the Duration until the InstallWindow opens (zero if it is open) at the current local time
+    pub fn until_open_now(&self) -> Duration {
+        self.until_open(Local::now().time())
+    }
+}
+
+impl Display for InstallWindow {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+impl FromStr for InstallWindow {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidInstallWindow(input.to_string());
+        let (start, end) = input.trim().split_once('-').ok_or_else(invalid)?;
+        Ok(InstallWindow::new(
+            NaiveTime::parse_from_str(start.trim(), "%H:%M").map_err(|_| invalid())?,
+            NaiveTime::parse_from_str(end.trim(), "%H:%M").map_err(|_| invalid())?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn time(input: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(input, "%H:%M").unwrap()
+    }
+
+    #[rstest]
+    #[case("02:00-05:00", true)]
+    #[case(" 22:00 - 04:00 ", true)]
+    #[case("02:00", false)]
+    #[case("2am-5am", false)]
+    #[case("25:00-05:00", false)]
+    fn test_install_window_from_str(#[case] input: &str, #[case] valid: bool) {
+        assert_eq!(InstallWindow::from_str(input).is_ok(), valid);
+    }
+
+    #[rstest]
+    #[case("02:00-05:00", "03:00", true, 0)]
+    #[case("02:00-05:00", "05:00", false, 21 * 60 * 60)]
+    #[case("02:00-05:00", "01:30", false, 30 * 60)]
+    #[case("22:00-04:00", "23:00", true, 0)]
+    #[case("22:00-04:00", "03:59", true, 0)]
+    #[case("22:00-04:00", "12:00", false, 10 * 60 * 60)]
+    fn test_install_window(
+        #[case] window: &str,
+        #[case] now: &str,
+        #[case] contains: bool,
+        #[case] until_open: u64,
+    ) -> Result<(), Error> {
+        let window = InstallWindow::from_str(window)?;
+        assert_eq!(window.contains(time(now)), contains);
+        assert_eq!(
+            window.until_open(time(now)),
+            Duration::from_secs(until_open)
+        );
+        Ok(())
+    }
+}